@@ -0,0 +1,101 @@
+use crate::{
+    error::{Error, Result},
+    pitch::Key,
+    song::{Note, Song},
+};
+
+// Krumhansl-Kessler key profiles, starting from the tonic
+const MAJOR: [f64; 12] = [
+    6.35, 2.23, 3.48, 2.33, 4.38, 4.09, 2.52, 5.19, 2.39, 3.66, 2.29, 2.88,
+];
+const MINOR: [f64; 12] = [
+    6.33, 2.68, 3.52, 5.38, 2.60, 3.53, 2.54, 4.75, 3.98, 2.69, 3.34, 3.17,
+];
+
+pub const SECTION_MEASURES: usize = 8;
+
+type Histogram = [f64; 12];
+
+pub struct KeyAnalysis {
+    pub ranked: Vec<(Key, f64)>,
+    // First measure of each section with the key it leans towards
+    pub sections: Vec<(usize, Key)>,
+}
+
+// How long each pitch class sounds in each section, over all tracks
+fn histograms(song: &Song) -> Vec<Histogram> {
+    let mut sections: Vec<Histogram> = Vec::new();
+    for track in song.tracks.iter() {
+        let tuning = track.tuning();
+        let mut measure = 0;
+        for (i, beat) in track.beats.iter().enumerate() {
+            if track.measure_i[i] && i > 0 {
+                measure += 1;
+            }
+            let section = measure / SECTION_MEASURES;
+            if sections.len() <= section {
+                sections.resize(section + 1, [0.0; 12]);
+            }
+            let len = beat.dur.0 as f64 / beat.dur.1 as f64;
+            for (string, note) in beat.notes.iter() {
+                if let (Note::Fret(fret), Some(open)) = (note, tuning.get(*string as usize)) {
+                    sections[section][(*open as usize + *fret as usize) % 12] += len;
+                }
+            }
+        }
+    }
+    sections
+}
+
+fn correlation(hist: &Histogram, profile: &[f64; 12], tonic: usize) -> f64 {
+    let mean_h = hist.iter().sum::<f64>() / 12.0;
+    let mean_p = profile.iter().sum::<f64>() / 12.0;
+    let (mut cov, mut var_h, mut var_p) = (0.0, 0.0, 0.0);
+    for (pc, h) in hist.iter().enumerate() {
+        let p = profile[(pc + 12 - tonic) % 12];
+        cov += (h - mean_h) * (p - mean_p);
+        var_h += (h - mean_h).powi(2);
+        var_p += (p - mean_p).powi(2);
+    }
+    if var_h == 0.0 {
+        0.0
+    } else {
+        cov / (var_h * var_p).sqrt()
+    }
+}
+
+// All 24 major and minor keys, most likely first
+fn rank(hist: &Histogram) -> Vec<(Key, f64)> {
+    let mut ranked: Vec<(Key, f64)> = (0..12)
+        .flat_map(|tonic| {
+            [(false, &MAJOR), (true, &MINOR)].map(|(minor, profile)| {
+                let key = Key::new(tonic as u8, minor);
+                (key, correlation(hist, profile, tonic))
+            })
+        })
+        .collect();
+    ranked.sort_by(|a, b| b.1.total_cmp(&a.1));
+    ranked
+}
+
+pub fn keys(song: &Song) -> Result<KeyAnalysis> {
+    let sections = histograms(song);
+    let mut total = [0.0; 12];
+    for hist in sections.iter() {
+        for (t, h) in total.iter_mut().zip(hist) {
+            *t += h;
+        }
+    }
+    if total.iter().all(|t| *t == 0.0) {
+        return Err(Error::InvalidOp("No notes to analyze".into()));
+    }
+    Ok(KeyAnalysis {
+        ranked: rank(&total),
+        sections: sections
+            .iter()
+            .enumerate()
+            .filter(|(_, hist)| hist.iter().any(|h| *h > 0.0))
+            .map(|(i, hist)| (i * SECTION_MEASURES, rank(hist)[0].0.clone()))
+            .collect(),
+    })
+}
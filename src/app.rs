@@ -1,15 +1,61 @@
 use crate::{
-    args,
+    analyze, args,
     buffer::Buffer,
+    calc,
+    chords::ChordLib,
+    complete,
+    config::{self, Config, Recent},
     cursor::Cursor,
-    draw::Lane,
-    dur::Duration,
-    error::{Error, Result},
-    history::{Action, History},
-    song::{Note, Song},
+    draw::{self, BeatWidth, DrawOpts, Lane},
+    dur::{Duration, Offset},
+    error::{Error, Result, SResult},
+    formats::{self, text::TextOpts, TabFormat},
+    history::Action,
+    lock::{self, Lock},
+    map_io_err,
+    messages::{Level, Messages},
+    midi_input,
+    notation::{self, Notation},
+    pitch::{self, Key},
+    playback::{self, Player},
+    roundtrip, serve,
+    song::{
+        editor::Editor, Arpeggio, Beat, Change, Finger, Note, NoteEntry, Song, Stroke, TimeSig,
+        Track, Whammy, DEFAULT_VELOCITY,
+    },
     window,
 };
-use crossterm::event::{self, KeyCode, KeyModifiers};
+use crossterm::{
+    event::{self, KeyCode, KeyModifiers},
+    style::Stylize,
+};
+use std::collections::BTreeMap;
+
+const AUTOSAVE_SUFFIX: &str = ".autosave.json";
+// Redraw rate of the playback cursor
+const PLAYBACK_FRAME_MS: u64 = 30;
+// Playback speed in percent of the written tempo
+const SPEED_RANGE: std::ops::RangeInclusive<u16> = 50..=200;
+const SPEED_STEP: i16 = 10;
+// Most measures inserted at once, more than any song needs
+const MAX_INSERT_MEASURES: usize = 1000;
+
+// Polling slows to `idle_ms` once no event has arrived for `idle_after` seconds
+struct PollOpts {
+    poll_ms: u64,
+    idle_after: u64,
+    idle_ms: u64,
+}
+
+impl PollOpts {
+    fn timeout(&self, since_event: std::time::Duration) -> std::time::Duration {
+        if self.idle_after > 0 && since_event.as_secs() >= self.idle_after {
+            std::time::Duration::from_millis(self.idle_ms.max(self.poll_ms))
+        } else {
+            std::time::Duration::from_millis(self.poll_ms)
+        }
+    }
+}
 
 enum InpMode {
     None,
@@ -18,12 +64,24 @@ enum InpMode {
     Note,
     Edit,
     Duration,
+    Finger,
+    Stroke,
+    Text,
+    Arpeggio,
+    Whammy,
+    Chord,
     Command,
+    Tracks,
+    Timeline,
+    Recent,
+    Log,
+    Diagram,
 }
 
 struct InpCtrl {
     mode: InpMode,
     arg: String,
+    bell: bool,
 }
 
 impl InpCtrl {
@@ -31,9 +89,18 @@ impl InpCtrl {
         Self {
             mode: InpMode::None,
             arg: String::new(),
+            bell: false,
         }
     }
 
+    fn reject(&mut self) {
+        self.bell = true;
+    }
+
+    fn take_bell(&mut self) -> bool {
+        std::mem::take(&mut self.bell)
+    }
+
     fn push(&mut self, ch: char) {
         self.arg.push(ch);
     }
@@ -63,20 +130,62 @@ impl InpCtrl {
             InpMode::Note => format!("n:{}", self.arg),
             InpMode::Edit => format!("e:{}", self.arg),
             InpMode::Duration => format!("d:{}", self.arg),
+            InpMode::Finger => format!("f:{}", self.arg),
+            InpMode::Stroke => format!("p:{}", self.arg),
+            InpMode::Text => format!("t:{}", self.arg),
+            InpMode::Arpeggio => format!("r:{}", self.arg),
+            InpMode::Whammy => format!("W:{}", self.arg),
+            InpMode::Chord => format!("h:{}", self.arg),
             InpMode::Command => format!(":{}", self.arg),
+            InpMode::Tracks => "tracks".into(),
+            InpMode::Timeline => format!("timeline:{}", self.arg),
+            InpMode::Recent => "recent".into(),
+            InpMode::Log => "messages".into(),
+            InpMode::Diagram => "chord diagram".into(),
         }
     }
 
     fn char_valid(&self, ch: &char) -> bool {
         match self.mode {
             InpMode::Duration => ch.is_ascii_digit() || ch == &':' || ch == &'/',
-            InpMode::Edit => ch.is_ascii_digit() || ch == &'x',
+            InpMode::Edit => {
+                ch.is_ascii_digit()
+                    || matches!(ch, 'x' | 't' | 'r' | 's' | 'p' | 'v')
+                    || (*ch == '-' && self.arg.is_empty())
+            }
+            InpMode::Finger => self.arg.is_empty() && matches!(ch, '1'..='4' | 't' | 'T'),
             InpMode::Note | InpMode::Beat | InpMode::Measure => ch.is_ascii_digit(),
-            InpMode::Command => ch.is_alphabetic() || ch == &'_' || ch == &' ',
+            InpMode::Stroke
+            | InpMode::Arpeggio
+            | InpMode::Tracks
+            | InpMode::Recent
+            | InpMode::Log
+            | InpMode::Diagram => false,
+            InpMode::Whammy => ch.is_ascii_digit(),
+            InpMode::Timeline => ch.is_ascii_digit() || ch == &'/',
+            InpMode::Chord => !ch.is_control() && !ch.is_whitespace(),
+            InpMode::Text | InpMode::Command => !ch.is_control(),
             InpMode::None => false,
         }
     }
 
+    fn validate(&self, track: &Track, chords: &ChordLib) -> Result<()> {
+        if self.arg.is_empty() {
+            return Ok(());
+        }
+        match self.mode {
+            InpMode::Chord => match chords.get(&track.tuning(), &self.arg) {
+                Some(_) => Ok(()),
+                None => Err(Error::InvalidOp(format!("Unknown chord '{}'", self.arg))),
+            },
+            InpMode::Duration => self.arg.parse::<Duration>().map(|_| ()),
+            InpMode::Finger => self.arg.parse::<Finger>().map(|_| ()),
+            InpMode::Whammy => self.arg.parse::<Whammy>().map(|_| ()),
+            InpMode::Timeline if self.arg.contains('/') => self.arg.parse::<TimeSig>().map(|_| ()),
+            _ => Ok(()),
+        }
+    }
+
     fn arg_clear(&mut self) -> String {
         let temp = self.arg.clone();
         self.clear();
@@ -96,36 +205,227 @@ impl InpCtrl {
     }
 }
 
+struct Reference {
+    label: String,
+    song: Song,
+}
+
+// Ids stay with a song while others are opened and closed around it
+struct Document {
+    id: usize,
+    path: Option<String>,
+    editor: Editor,
+    dirty: bool,
+    lock: Option<Lock>,
+    read_only: bool,
+    lanes: Vec<Lane>,
+    curr_lane: usize,
+}
+
+// Autosaves go beside the song as .{name}.autosave.json, so only :save writes the song itself
+// Read-only songs belong to another instance, so their autosaves also carry this one's pid
+// Untitled songs are autosaved into the config directory, keyed by pid as well
+fn autosave_path(path: Option<&str>, id: usize, read_only: bool) -> String {
+    let pid = std::process::id();
+    let path = match path {
+        Some(path) => {
+            let path = std::path::Path::new(path);
+            let name = path.file_name().unwrap_or_default().to_string_lossy();
+            if read_only {
+                path.with_file_name(format!(".{name}.{pid}{AUTOSAVE_SUFFIX}"))
+            } else {
+                path.with_file_name(format!(".{name}{AUTOSAVE_SUFFIX}"))
+            }
+        }
+        None => {
+            let name = format!("untitled-{pid}-{id}{AUTOSAVE_SUFFIX}");
+            config::config_file(&name).unwrap_or_else(|| name.into())
+        }
+    };
+    path.to_string_lossy().into_owned()
+}
+
+fn same_file(a: &str, b: &str) -> bool {
+    let (a, b) = (std::path::Path::new(a), std::path::Path::new(b));
+    a == b
+        || matches!(
+            (std::fs::canonicalize(a), std::fs::canonicalize(b)),
+            (Ok(a), Ok(b)) if a == b
+        )
+}
+
+// Indices may be written as arithmetic, ie `16+8`
+fn parse_index(s: &str) -> Result<usize> {
+    let n = calc::eval(s)?;
+    usize::try_from(n).map_err(|_| Error::ParseError(format!("Index '{s}' is negative")))
+}
+
+fn write_song(song: &Song, path: &str) -> Result<()> {
+    formats::write_atomic(path, &formats::json::Json.write(song)?)
+}
+
+fn write_autosave(song: &Song, path: &str) -> Result<()> {
+    if let Some(dir) = std::path::Path::new(path).parent() {
+        if !dir.as_os_str().is_empty() {
+            map_io_err!(std::fs::create_dir_all(dir))?;
+        }
+    }
+    write_song(song, path)
+}
+
+// Velocity drag started on a dynamics row, changing by one step per row moved
+struct Drag {
+    cur: Cursor,
+    row: u16,
+    old: Option<u8>,
+}
+
+const VELOCITY_STEP: i16 = 8;
+
+// Keys pressed since `:record F{n}`, and where the latest top level `:` command started
+struct Recording {
+    key: String,
+    steps: Vec<String>,
+    command_at: usize,
+}
+
 pub struct App {
     args: args::Args,
     should_close: bool,
     song_path: Option<String>,
-    song: Song,
+    editor: Editor,
+    dirty: bool,
+    lock: Option<Lock>,
+    read_only: bool,
     lanes: Vec<Lane>,
     curr_lane: usize,
+    // First lane drawn, when there are more than fit on screen
+    lane_scroll: usize,
     input: InpCtrl,
-    command_res: String,
+    messages: Messages,
+    // Messages scrolled past in the log
+    log_scroll: usize,
     copy_buf: Buffer,
-    s_bwidth: usize,
+    s_bwidth: BeatWidth,
+    s_width: u16,
     s_height: u16,
-    history: History,
+    // Track picked in the track sidebar
+    track_sel: usize,
+    // Event picked in the timeline, 0 being the start of the song and n the nth change
+    change_sel: usize,
+    recent: Recent,
+    // Path picked in the recent files list
+    recent_sel: usize,
+    draw_opts: DrawOpts,
+    text_opts: TextOpts,
+    poll_opts: PollOpts,
+    last_event: std::time::Instant,
+    dyn_rows: Vec<(usize, draw::DynRow)>,
+    // Screen row of each lane's header
+    header_rows: Vec<(usize, u16)>,
+    drag: Option<Drag>,
+    // Track and beat a selection was started at, reaching to the cursor
+    select: Option<(usize, usize)>,
+    // The selection was started with V, so keys act on it at once; `select_string` limits it to
+    // the strings from there to the cursor string
+    visual: bool,
+    select_string: Option<u16>,
+    docs: Vec<Document>,
+    doc_id: usize,
+    next_doc_id: usize,
+    compare: Option<Reference>,
+    chords: ChordLib,
+    key_suggestion: Option<Key>,
+    notation: Box<dyn Notation>,
+    snap: Option<Duration>,
+    macros: BTreeMap<String, Vec<String>>,
+    recording: Option<Recording>,
+    playing: bool,
+    player: Option<Player>,
+    // Song time at which playback started
+    play_from: f64,
+    // Percent of the written tempo played at, the stored tempo is left alone
+    play_speed: u16,
+    metronome: bool,
+    // Other devices are kept in time with MIDI clock while playing
+    clock: bool,
+    // Notes entered are heard on the MIDI output, through a player of their own
+    audition: bool,
+    preview: Option<Player>,
+    // Keyboard or guitar whose notes are entered at the cursor
+    midi_in: Option<midi_input::Input>,
+    // MIDI output played on by name, the first of the system's devices when not set
+    midi_out: Option<String>,
 }
 
 impl App {
     pub fn new() -> Result<Self> {
+        // Errors are reported once the song is loaded, by `run`
+        let config = Config::load().unwrap_or_default();
         Ok(Self {
             args: clap::Parser::parse(),
             should_close: false,
             song_path: None,
-            song: Song::new(),
+            editor: Editor::new(Song::new()),
+            dirty: false,
+            lock: None,
+            read_only: false,
             lanes: Vec::new(),
             curr_lane: 0,
+            lane_scroll: 0,
             input: InpCtrl::new(),
-            command_res: String::new(),
+            messages: Messages::default(),
+            log_scroll: 0,
             copy_buf: Buffer::Empty,
-            s_bwidth: 4,
+            s_bwidth: BeatWidth {
+                beats: 4,
+                cell: draw::CELL_WIDTH,
+                scrolloff: 0,
+            },
+            s_width: 20,
             s_height: 4,
-            history: History::new(32),
+            track_sel: 0,
+            change_sel: 0,
+            recent: Recent::load(),
+            recent_sel: 0,
+            draw_opts: DrawOpts {
+                theme: config.theme,
+                glyphs: config.glyphs,
+                ..Default::default()
+            },
+            text_opts: TextOpts::default(),
+            poll_opts: PollOpts {
+                poll_ms: 100,
+                idle_after: 30,
+                idle_ms: 1000,
+            },
+            last_event: std::time::Instant::now(),
+            dyn_rows: Vec::new(),
+            header_rows: Vec::new(),
+            drag: None,
+            select: None,
+            visual: false,
+            select_string: None,
+            docs: Vec::new(),
+            doc_id: 1,
+            next_doc_id: 2,
+            compare: None,
+            chords: ChordLib::load().unwrap_or_default(),
+            key_suggestion: None,
+            notation: Box::new(notation::Fret),
+            snap: None,
+            macros: config.macros,
+            recording: None,
+            playing: false,
+            player: None,
+            play_from: 0.0,
+            play_speed: 100,
+            metronome: false,
+            clock: false,
+            audition: false,
+            preview: None,
+            midi_in: None,
+            midi_out: config.midi_out,
         })
     }
 
@@ -136,132 +436,86 @@ impl App {
     // History functions
 
     fn undo(&mut self) -> Result<String> {
-        if let Some(action) = self.history.undo() {
-            self.undo_action(action)
-        } else {
-            Err(Error::InvalidOp("Cannot undo any further".into()))
+        let res = self.editor.undo();
+        if res.is_ok() {
+            self.dirty = true;
+            self.check_lanes();
+            self.clamp_cursors();
         }
+        res
     }
 
     fn redo(&mut self) -> Result<String> {
-        if let Some(action) = self.history.redo() {
-            self.apply_action(action)
-        } else {
-            Err(Error::InvalidOp("Cannot redo any further".into()))
+        let res = self.editor.redo();
+        if res.is_ok() {
+            self.dirty = true;
+            self.check_lanes();
+            self.clamp_cursors();
         }
+        res
     }
 
-    fn apply_action(&mut self, action: std::rc::Rc<Action>) -> Result<String> {
-        match &*action {
-            Action::SetDuration { cur, new, .. } => {
-                cur.set_duration(&mut self.song, *new);
-                Ok(format!("Set duration {}/{}", new.0, new.1))
-            }
-            Action::SetNote { cur, new, .. } => {
-                if let Some(note) = new {
-                    cur.set_note(&mut self.song, note.to_owned());
-                    Ok("Set note".into())
-                } else {
-                    cur.clear_note(&mut self.song);
-                    Ok("Delete note".into())
-                }
-            }
-            Action::ClearBeat { cur, .. } => {
-                cur.clear_beat(&mut self.song);
-                Ok("Clear beat".into())
-            }
-            Action::ClearBeats { cur, old } => {
-                cur.clear_beats(&mut self.song, old.len());
-                Ok("Clear beats".into())
-            }
-            Action::DeleteBeat { cur, .. } => {
-                cur.delete_beat(&mut self.song);
-                Ok("Delete beat".into())
-            }
-            Action::DeleteBeats { cur, old } => {
-                cur.delete_beats(&mut self.song, old.len());
-                Ok("Undo delete beat".into())
-            }
-            Action::PasteNote { cur, buf, .. } => {
-                cur.set_note(&mut self.song, buf.clone());
-                Ok("Paste note".into())
-            }
-            Action::PasteBeat { cur, old, buf } => {
-                cur.insert_beat(&mut self.song, old.is_some(), buf.clone());
-                Ok("Paste beat".into())
-            }
-            Action::PasteBeats { cur, old, buf } => {
-                cur.insert_beats(&mut self.song, old.is_some(), buf.clone());
-                Ok("Paste beats".into())
-            }
+    // Undoing appended beats can leave cursors past the end of their tracks
+    fn clamp_cursors(&mut self) {
+        let song = &self.editor.song;
+        for lane in self.lanes.iter_mut() {
+            let len = lane.cur.beats(song).len();
+            lane.cur.beat = lane.cur.beat.min(len - 1);
         }
     }
 
-    fn undo_action(&mut self, action: std::rc::Rc<Action>) -> Result<String> {
-        match &*action {
-            Action::SetDuration { cur, old, .. } => {
-                cur.set_duration(&mut self.song, *old);
-                Ok("Undo set duration".into())
-            }
-            Action::SetNote { cur, old, new } => {
-                if let Some(note) = old {
-                    cur.set_note(&mut self.song, note.clone());
-                } else {
-                    cur.clear_note(&mut self.song);
-                }
-                if new.is_none() {
-                    Ok("Undo delete note".into())
-                } else {
-                    Ok("Undo set note".into())
-                }
-            }
-            Action::ClearBeat { cur, old } => {
-                cur.set_notes(&mut self.song, old.clone());
-                Ok("Undo clear beat".into())
-            }
-            Action::ClearBeats { cur, old } => {
-                cur.replace_beats(&mut self.song, old.clone());
-                Ok("Undo clear beats".into())
-            }
-            Action::DeleteBeat { cur, old } => {
-                cur.insert_beat(&mut self.song, false, old.clone());
-                Ok("Undo delete beat".into())
-            }
-            Action::DeleteBeats { cur, old } => {
-                cur.insert_beats(&mut self.song, false, old.clone());
-                Ok("Undo delete beat".into())
-            }
-            Action::PasteNote { cur, old, .. } => {
-                if let Some(note) = old {
-                    cur.set_note(&mut self.song, note.clone());
+    // Settings edits with what each changed, numbered by how many undo steps back they are
+    fn settings_history(&self) -> Result<String> {
+        let entries: Vec<String> = self
+            .editor
+            .settings_history()
+            .into_iter()
+            .map(|(n, label, old, new)| {
+                let diff = old.settings_diff(new);
+                if diff.is_empty() {
+                    format!("{n}. {label}")
                 } else {
-                    cur.clear_note(&mut self.song);
+                    format!("{n}. {label}: {}", diff.join(", "))
                 }
-                Ok("Undo paste note".into())
-            }
-            Action::PasteBeat { cur, old, .. } => {
-                if let Some(beat) = old {
-                    cur.insert_beat(&mut self.song, true, beat.clone());
-                } else {
-                    cur.delete_beat(&mut self.song);
-                }
-                Ok("Undo paste beat".into())
-            }
-            Action::PasteBeats { cur, old, buf } => {
-                cur.delete_beats(&mut self.song, buf.len());
-                if let Some(beat) = old {
-                    cur.insert_beat(&mut self.song, false, beat.clone());
-                }
-                Ok("Undo paste beats".into())
-            }
+            })
+            .collect();
+        if entries.is_empty() {
+            return Ok("Settings history : (none)".into());
+        }
+        Ok(format!("Settings history : {}", entries.join(" | ")))
+    }
+
+    // Reverts a single settings edit of the history as a new edit, keeping everything since
+    fn revert_settings(&mut self, arg: &str) -> Result<String> {
+        let n = parse_index(arg)?;
+        let (label, old, new) = self
+            .editor
+            .settings_history()
+            .into_iter()
+            .find(|(i, ..)| *i == n)
+            .map(|(_, label, old, new)| (label.to_owned(), old.clone(), new.clone()))
+            .ok_or_else(|| Error::InvalidOp(format!("No settings edit {n} steps back")))?;
+        self.edit_song(&format!("revert {label}"), |song| {
+            song.revert_settings(&old, &new);
+            Ok(())
+        })?;
+        self.check_lanes();
+        Ok(format!("Reverted {label}"))
+    }
+
+    // Changes of the song outside of its beats, as one undo step
+    fn edit_song<R>(&mut self, label: &str, f: impl FnOnce(&mut Song) -> Result<R>) -> Result<R> {
+        let res = self.editor.edit(label, f);
+        if res.is_ok() {
+            self.dirty = true;
         }
+        res
     }
 
     fn push_action(&mut self, action: Action) -> Result<String> {
-        let action = std::rc::Rc::new(action);
-        let res = self.apply_action(action.clone());
+        let res = self.editor.apply(action);
         if res.is_ok() {
-            self.history.push(action);
+            self.dirty = true;
         }
         res
     }
@@ -273,15 +527,94 @@ impl App {
 
     // IO functions
 
+    // Holds the lock of the default location, falling back to read-only if another instance has it
+    fn relock(&mut self) -> Option<String> {
+        if self.lock.as_ref().map(|l| l.file()) == self.song_path.as_deref() {
+            return None;
+        }
+        self.lock = None;
+        self.read_only = false;
+        let path = self.song_path.clone()?;
+        match Lock::acquire(&path) {
+            Ok(Some(lock)) => self.lock = Some(lock),
+            Ok(None) => {
+                self.read_only = true;
+                return Some(format!(
+                    "{path} is open in another instance, opened read-only (remove {} if not)",
+                    lock::lock_path(&path).display()
+                ));
+            }
+            // Locking is best effort, an unwritable directory should not stop editing
+            Err(_) => {}
+        }
+        None
+    }
+
     fn save_file(&mut self, path: String) -> Result<String> {
-        let s = serde_json::to_string(&self.song).unwrap();
-        std::fs::write(&path, s).unwrap();
+        let registry = self.formats();
+        let format = registry.for_path(&path)?;
+        if !format.lossless() {
+            // Other formats keep only part of the song, so they are written like an export
+            registry.write_file(format, &self.editor.song.without_archived()?, &path)?;
+            return Ok(format!(
+                "Exported to {path} as {}, only .json keeps the whole song",
+                format.name()
+            ));
+        }
+        if self.song_path.as_deref() == Some(path.as_str()) {
+            if self.read_only {
+                return Err(Error::FileError(format!(
+                    "{path} is open in another instance (use :save {{path}} to save elsewhere)"
+                )));
+            }
+        } else if let Ok(None) = Lock::acquire(&path) {
+            return Err(Error::FileError(format!(
+                "{path} is open in another instance"
+            )));
+        }
+        registry.write_file(format, &self.editor.song, &path)?;
         self.song_path = Some(path.clone());
-        Ok(format!("Saved to {path}"))
+        self.relock();
+        self.dirty = false;
+        if self.editor.song.exports.is_empty() {
+            return Ok(format!("Saved to {path}"));
+        }
+        // The song itself is saved even if exports fail, and every export is tried
+        let exports = self.editor.song.exports.clone();
+        let failed: Vec<String> = exports
+            .iter()
+            .filter_map(|format| {
+                let res = self.export_file(format, false);
+                res.err().map(|e| format!("{format}: {e}"))
+            })
+            .collect();
+        if !failed.is_empty() {
+            return Err(Error::FileError(format!(
+                "Saved to {path}, but {}",
+                failed.join("; ")
+            )));
+        }
+        Ok(format!("Saved to {path} and {}", exports.join(", ")))
+    }
+
+    // Songs stay dirty until saved with :save, autosaves only guard against losing the changes
+    fn autosave(&mut self) {
+        for doc in self.docs.iter().filter(|d| d.dirty) {
+            let _ = write_autosave(
+                &doc.editor.song,
+                &autosave_path(doc.path.as_deref(), doc.id, doc.read_only),
+            );
+        }
+        if !self.dirty {
+            return;
+        }
+        let path = autosave_path(self.song_path.as_deref(), self.doc_id, self.read_only);
+        let res = write_autosave(&self.editor.song, &path).map(|_| format!("Autosaved to {path}"));
+        self.set_command_res(res);
     }
 
-    fn do_save_file(&mut self, inp: Option<String>) {
-        let res = if let Some(path) = inp {
+    fn do_save_file(&mut self, inp: Option<String>) -> Result<String> {
+        if let Some(path) = inp {
             self.save_file(path)
         } else {
             if let Some(path) = self.song_path.clone() {
@@ -289,74 +622,2380 @@ impl App {
             } else {
                 Err(Error::FileError("No default file to save to".into()))
             }
+        }
+    }
+
+    // Songs imported from other formats are saved as json beside the original
+    fn load_file(&mut self, path: String) -> Result<String> {
+        let formats = self.formats();
+        self.editor = Editor::new(formats.read_file(&path)?);
+        self.song_path = Some(if formats.for_path(&path)?.lossless() {
+            path.clone()
+        } else {
+            std::path::Path::new(&path)
+                .with_extension("json")
+                .to_string_lossy()
+                .into_owned()
+        });
+        self.dirty = false;
+        // Songs driven by --serve are not the user's own, so they stay out of the list
+        if !self.args.serve {
+            self.recent.add(&path);
+            // Failing to remember the song is not worth interrupting its loading
+            let _ = self.recent.save();
+        }
+        let unknown = self.editor.song.unknown_fields();
+        let loaded = if unknown.is_empty() {
+            format!("Loaded {path}")
+        } else {
+            format!(
+                "Loaded {path}, keeping fields unknown to this version: {}",
+                unknown.join(", ")
+            )
+        };
+        match self.relock() {
+            Some(warning) => Ok(format!("{warning} | {loaded}")),
+            None => Ok(loaded),
+        }
+    }
+
+    fn do_load_file(&mut self) {
+        let res = match self.song_path.clone() {
+            Some(path) => self.load_file(path),
+            None => Err(Error::FileError("No default file to load".into())),
         };
+        // The song is only replaced once the file is read, so on failure the lanes stay as they were
+        if res.is_ok() {
+            self.reset_lanes();
+        }
         self.set_command_res(res);
     }
 
-    fn load_file(&mut self, path: String) -> Result<String> {
-        if let Ok(data) = std::fs::read_to_string(&path) {
-            self.song = serde_json::from_str(data.as_str()).unwrap();
-            for track in self.song.tracks.iter_mut() {
-                track.update_measures();
+    // Replaces the song in this buffer, its history and cursors start over with the new one
+    fn open_file(&mut self, path: &str, force: bool) -> Result<String> {
+        if self.dirty && !force {
+            return Err(Error::InvalidOp(
+                "Song has unsaved changes (use :open! to discard)".into(),
+            ));
+        }
+        let res = self.load_file(path.to_owned())?;
+        self.reset_lanes();
+        self.track_sel = 0;
+        self.change_sel = 0;
+        Ok(res)
+    }
+
+    fn open_recent(&mut self) -> Result<String> {
+        let path = self
+            .recent
+            .paths
+            .get(self.recent_sel)
+            .cloned()
+            .ok_or_else(|| Error::InvalidOp("No recent file picked".into()))?;
+        let res = self.open_file(&path, false)?;
+        self.input.clear();
+        Ok(res)
+    }
+
+    fn forget_recent(&mut self) -> Result<String> {
+        if self.recent_sel >= self.recent.paths.len() {
+            return Err(Error::InvalidOp("No recent file picked".into()));
+        }
+        let path = self.recent.paths.remove(self.recent_sel);
+        self.recent_sel = self
+            .recent_sel
+            .min(self.recent.paths.len().saturating_sub(1));
+        if self.recent.paths.is_empty() {
+            self.input.clear();
+        }
+        self.recent.save()?;
+        Ok(format!("Removed {path} from recent files"))
+    }
+
+    fn show_recent(&mut self) -> Result<String> {
+        if self.recent.paths.is_empty() {
+            return Err(Error::InvalidOp("No recent files".into()));
+        }
+        self.recent_sel = 0;
+        self.input.mode = InpMode::Recent;
+        Ok("Recent files".into())
+    }
+
+    // Measures are numbered from 1 and both ends are exported, in the format of the path's extension
+    fn export_range(&mut self, arg: &str, archived: bool) -> Result<String> {
+        let mut args = arg.splitn(3, ' ');
+        let (Some(from), Some(to), Some(path)) = (args.next(), args.next(), args.next()) else {
+            return Err(Error::InvalidOp(
+                "Usage is :export-range {from} {to} {path}".into(),
+            ));
+        };
+        let (from, to) = (parse_index(from)?, parse_index(to)?);
+        if from == 0 || to < from {
+            return Err(Error::InvalidOp(format!(
+                "Bad measure range {from} to {to}"
+            )));
+        }
+        let registry = self.formats();
+        let path = self.export_path(Some(path.trim()), "json")?;
+        let format = registry.for_path(&path)?;
+        let song = if archived {
+            self.editor.song.measure_range(from - 1, to - 1)?
+        } else {
+            self.editor
+                .song
+                .without_archived()?
+                .measure_range(from - 1, to - 1)?
+        };
+        registry.write_file(format, &song, &path)?;
+        Ok(format!("Exported measures {from} to {to} to {path}"))
+    }
+
+    // Exports never replace the song they are made from
+    fn export_path(&self, inp: Option<&str>, ext: &str) -> Result<String> {
+        let path = match (inp, &self.song_path) {
+            (Some(path), _) => path.to_owned(),
+            (None, Some(path)) => std::path::Path::new(path)
+                .with_extension(ext)
+                .to_string_lossy()
+                .into_owned(),
+            (None, None) => return Err(Error::FileError("No default file to export to".into())),
+        };
+        if self
+            .song_path
+            .as_deref()
+            .is_some_and(|song| same_file(song, &path))
+        {
+            return Err(Error::FileError(format!(
+                "Cannot export over the song itself ({path})"
+            )));
+        }
+        Ok(path)
+    }
+
+    // --convert reads `input` and writes it to `output`, each in the format of its extension
+    // unless --from or --format names it, - being standard input or output
+    fn convert(
+        &mut self,
+        input: &str,
+        output: &str,
+        from: Option<&str>,
+        format: Option<&str>,
+    ) -> Result<String> {
+        let registry = self.formats();
+        let song = match (input, from) {
+            ("-", from) => {
+                use std::io::Read;
+                let mut data = Vec::new();
+                map_io_err!(std::io::stdin().read_to_end(&mut data))?;
+                let format = registry.find(from.unwrap_or("json"))?;
+                format.read(&data).map_err(|e| match e {
+                    Error::ParseError(e) => {
+                        Error::ParseError(format!("Cannot parse standard input: {e}"))
+                    }
+                    e => e,
+                })?
+            }
+            (_, Some(from)) => {
+                let data = std::fs::read(input)
+                    .map_err(|e| Error::FileError(format!("Cannot read file '{input}': {e}")))?;
+                registry.find(from)?.read(&data)?
+            }
+            (_, None) => registry.read_file(input)?,
+        };
+        self.editor = Editor::new(song);
+        if input != "-" {
+            self.song_path = Some(input.to_owned());
+        }
+        let registry = self.formats();
+        let format = match format {
+            Some(name) => registry.find(name)?,
+            None if output == "-" => {
+                return Err(Error::InvalidOp(
+                    "Writing to standard output needs --format".into(),
+                ))
             }
-            Ok(format!("Loaded {path}"))
+            None => registry.for_path(output)?,
+        };
+        let filtered = if format.lossless() {
+            None
+        } else {
+            Some(self.editor.song.without_archived()?)
+        };
+        let song = filtered.as_ref().unwrap_or(&self.editor.song);
+        if output == "-" {
+            use std::io::Write;
+            map_io_err!(std::io::stdout().write_all(&format.write(song)?))?;
         } else {
-            Err(Error::InvalidOp(format!("Cannot read file '{path}'")))
+            registry.write_file(format, song, output)?;
         }
+        Ok(format!("Converted {input} to {output}"))
     }
 
-    fn do_load_file(&mut self, inp: Option<&&str>) {
-        let res = if let Some(path) = inp {
-            self.load_file(path.to_string())
+    fn song_title(&self) -> String {
+        if let Some(title) = &self.editor.song.meta.title {
+            return title.clone();
+        }
+        self.song_path
+            .as_deref()
+            .and_then(|p| std::path::Path::new(p).file_stem())
+            .map(|s| s.to_string_lossy().into_owned())
+            .unwrap_or_else(|| "Untitled".into())
+    }
+
+    fn formats(&self) -> formats::Registry {
+        formats::Registry::builtin(&self.song_title(), &self.text_opts)
+    }
+
+    fn export_file(&mut self, arg: &str, archived: bool) -> Result<String> {
+        let (format, path) = match arg.split_once(' ') {
+            Some((f, p)) => (f, Some(p)),
+            None => (arg, None),
+        };
+        let registry = self.formats();
+        let format = registry.find(format)?;
+        let path = self.export_path(path, format.extensions()[0])?;
+        if archived {
+            registry.write_file(format, &self.editor.song, &path)?;
         } else {
-            if let Some(path) = self.song_path.clone() {
-                self.load_file(path)
-            } else {
-                Err(Error::FileError("No default file to save to".into()))
+            registry.write_file(format, &self.editor.song.without_archived()?, &path)?;
+        }
+        Ok(format!("Exported to {path}"))
+    }
+
+    fn set_auto_export(&mut self, arg: Option<&str>) -> Result<String> {
+        let names: Vec<String> = arg
+            .unwrap_or("")
+            .split_whitespace()
+            .map(String::from)
+            .collect();
+        let registry = self.formats();
+        for name in &names {
+            let format = registry.find(name)?;
+            if !format.can_write() || format.name() == "json" {
+                return Err(Error::InvalidOp(format!("Cannot export {name} on save")));
+            }
+        }
+        self.edit_song("set exports", |song| {
+            song.exports = names;
+            Ok(())
+        })?;
+        if self.editor.song.exports.is_empty() {
+            Ok("Cleared exports on save".into())
+        } else {
+            Ok(format!(
+                "Will export {} on save",
+                self.editor.song.exports.join(", ")
+            ))
+        }
+    }
+
+    fn set_option(&mut self, arg: &str) -> Result<String> {
+        let (key, value) = arg
+            .split_once(' ')
+            .ok_or_else(|| Error::InvalidOp("Usage: set {option} {value}".into()))?;
+        match key {
+            "pagelen" => self.text_opts.page_len = parse_index(value)?,
+            "width" => self.text_opts.width = parse_index(value)?,
+            "measures" => self.text_opts.measures = parse_index(value)?,
+            "poll" => self.poll_opts.poll_ms = parse_index(value)?.max(1) as u64,
+            "idle" => self.poll_opts.idle_after = parse_index(value)? as u64,
+            "idlepoll" => self.poll_opts.idle_ms = parse_index(value)? as u64,
+            "empty" | "dead" | "barline" | "separator" | "double" | "end" => {
+                self.draw_opts.glyphs.set(key, value)?;
+                self.store_glyphs()?;
+            }
+            "zoom" => self.set_zoom(value.trim())?,
+            "theme" => {
+                self.draw_opts.theme = value.trim().parse()?;
+                let mut config = Config::load()?;
+                config.theme = self.draw_opts.theme;
+                config.save()?;
+            }
+            "scrolloff" => {
+                self.s_bwidth.scrolloff = match value.trim() {
+                    "center" => usize::MAX,
+                    n => parse_index(n)?,
+                };
+                for lane in &mut self.lanes {
+                    lane.cur.scroll_to_cursor(&self.editor.song, self.s_bwidth);
+                }
+            }
+            "entry" => self.notation = notation::by_name(value.trim())?,
+            "snap" => {
+                self.snap = match value.trim() {
+                    "off" => None,
+                    grid => Some(grid.parse()?).filter(|d| *d > Duration::zero()),
+                }
+            }
+            _ => return Err(Error::InvalidOp(format!("Unknown option '{key}'"))),
+        }
+        Ok(format!("Set {key} to {}", value.trim()))
+    }
+
+    // Cells from 2 columns, fitting two digit frets with nothing around them, up to 9
+    fn set_zoom(&mut self, value: &str) -> Result<()> {
+        let cell = match value.parse::<usize>() {
+            Ok(cell @ 2..=9) => cell,
+            _ => {
+                return Err(Error::InvalidOp(
+                    "Zoom must be a cell width from 2 to 9".into(),
+                ))
+            }
+        };
+        self.s_bwidth.cell = cell;
+        self.s_bwidth.fit(self.s_width);
+        for lane in &mut self.lanes {
+            lane.cur.scroll_to_cursor(&self.editor.song, self.s_bwidth);
+        }
+        Ok(())
+    }
+
+    // Glyphs are written back to config.json like macros
+    fn store_glyphs(&self) -> Result<()> {
+        let mut config = Config::load()?;
+        config.glyphs = self.draw_opts.glyphs.clone();
+        config.save()
+    }
+
+    // Workspace functions
+
+    fn park_doc(&mut self) -> Document {
+        Document {
+            id: self.doc_id,
+            path: self.song_path.take(),
+            editor: std::mem::replace(&mut self.editor, Editor::new(Song::new())),
+            dirty: std::mem::take(&mut self.dirty),
+            lock: self.lock.take(),
+            read_only: std::mem::take(&mut self.read_only),
+            lanes: std::mem::take(&mut self.lanes),
+            curr_lane: std::mem::take(&mut self.curr_lane),
+        }
+    }
+
+    fn unpark_doc(&mut self, doc: Document) {
+        self.doc_id = doc.id;
+        self.song_path = doc.path;
+        self.editor = doc.editor;
+        self.dirty = doc.dirty;
+        self.lock = doc.lock;
+        self.read_only = doc.read_only;
+        self.lanes = doc.lanes;
+        self.curr_lane = doc.curr_lane;
+    }
+
+    // Lanes show every track that is neither archived nor in a collapsed group,
+    // or the first track if there are none
+    fn lane_tracks(&self) -> Vec<usize> {
+        let song = &self.editor.song;
+        let visible: Vec<usize> = song
+            .visible_tracks()
+            .filter(|t| !song.is_collapsed(*t))
+            .collect();
+        if visible.is_empty() {
+            return vec![0];
+        }
+        visible
+    }
+
+    fn reset_lanes(&mut self) {
+        self.curr_lane = 0;
+        self.end_visual();
+        self.lanes = self.lane_tracks().into_iter().map(Lane::new_t).collect();
+    }
+
+    // Archiving, collapsing or undoing them can change which tracks have lanes,
+    // lanes that stay keep their cursors and new ones join at the current position;
+    // the split view stays last while its track is there
+    fn check_lanes(&mut self) {
+        let tracks = self.lane_tracks();
+        if self
+            .lanes
+            .iter()
+            .filter(|l| !l.split)
+            .map(|l| l.cur.track)
+            .eq(tracks.iter().copied())
+        {
+            return;
+        }
+        let song = &self.editor.song;
+        // Undoing an imported track can take away the track of the current lane
+        let offset = if self.cursor().track < song.tracks.len() {
+            self.cursor().calc_offset(song)
+        } else {
+            Offset::zero()
+        };
+        let (track, in_split) = (self.cursor().track, self.lanes[self.curr_lane].split);
+        let mut old = std::mem::take(&mut self.lanes);
+        let split = old
+            .iter()
+            .position(|l| l.split)
+            .map(|i| old.remove(i))
+            .filter(|l| l.cur.track < song.tracks.len());
+        for t in tracks {
+            match old.iter().position(|l| l.cur.track == t) {
+                Some(i) => self.lanes.push(old.remove(i)),
+                None => {
+                    let mut lane = Lane::new_t(t);
+                    lane.cur.transfer_seek(offset, song, self.s_bwidth);
+                    self.lanes.push(lane);
+                }
+            }
+        }
+        self.lanes.extend(split);
+        self.curr_lane = self
+            .lanes
+            .iter()
+            .position(|l| l.cur.track == track && l.split == in_split)
+            .unwrap_or(0);
+    }
+
+    // A second view of a track below the lanes, moving apart from them, for keeping
+    // one part of the song in sight while working on another; (Up)/(Down) go between them
+    fn toggle_split(&mut self, arg: Option<&str>) -> Result<String> {
+        if let Some(i) = self.lanes.iter().position(|l| l.split) {
+            if arg.is_none() {
+                self.lanes.remove(i);
+                self.curr_lane = self.curr_lane.min(self.lanes.len() - 1);
+                return Ok("Closed split view".into());
+            }
+            self.lanes.remove(i);
+        }
+        let track = match arg {
+            Some(n) => {
+                let t = parse_index(n)?.wrapping_sub(1);
+                if t >= self.editor.song.tracks.len() {
+                    return Err(Error::InvalidOp(format!("No track {}", n.trim())));
+                }
+                t
+            }
+            None => self.cursor().track,
+        };
+        let offset = self.cursor().calc_offset(&self.editor.song);
+        let mut lane = Lane::new_t(track);
+        lane.split = true;
+        lane.cur
+            .transfer_seek(offset, &self.editor.song, self.s_bwidth);
+        self.lanes.push(lane);
+        self.curr_lane = self.lanes.len() - 1;
+        Ok(format!("Split view of Track {}", track + 1))
+    }
+
+    // Position of the current song among the open ones in the order they were opened
+    fn doc_pos(&self) -> (usize, usize) {
+        let before = self.docs.iter().filter(|d| d.id < self.doc_id).count();
+        (before + 1, self.docs.len() + 1)
+    }
+
+    fn doc_name(&self) -> String {
+        self.song_path
+            .clone()
+            .unwrap_or_else(|| "[untitled]".into())
+    }
+
+    fn buf_add(&mut self, path: String) -> Result<String> {
+        let doc = self.park_doc();
+        self.docs.push(doc);
+        self.doc_id = self.next_doc_id;
+        self.next_doc_id += 1;
+        self.song_path = Some(path.clone());
+        if std::path::Path::new(&path).exists() {
+            if let Err(e) = self.load_file(path) {
+                let doc = self.docs.pop().unwrap();
+                self.unpark_doc(doc);
+                return Err(e);
+            }
+        }
+        let warning = self.relock();
+        self.reset_lanes();
+        if let Some(warning) = warning {
+            return Ok(warning);
+        }
+        let (pos, count) = self.doc_pos();
+        Ok(format!("Opened {} [{pos}/{count}]", self.doc_name()))
+    }
+
+    fn buf_next(&mut self) -> Result<String> {
+        if self.docs.is_empty() {
+            return Err(Error::InvalidOp("No other songs open".into()));
+        }
+        let doc = self.park_doc();
+        self.docs.push(doc);
+        let doc = self.docs.remove(0);
+        self.unpark_doc(doc);
+        Ok(format!("Switched to {}", self.doc_name()))
+    }
+
+    fn buf_prev(&mut self) -> Result<String> {
+        if self.docs.is_empty() {
+            return Err(Error::InvalidOp("No other songs open".into()));
+        }
+        let doc = self.park_doc();
+        self.docs.insert(0, doc);
+        let doc = self.docs.pop().unwrap();
+        self.unpark_doc(doc);
+        Ok(format!("Switched to {}", self.doc_name()))
+    }
+
+    fn buf_delete(&mut self, force: bool) -> Result<String> {
+        if self.docs.is_empty() {
+            return Err(Error::InvalidOp("Cannot close the only song".into()));
+        }
+        if self.dirty && !force {
+            return Err(Error::InvalidOp(
+                "Song has unsaved changes (use :bdelete! to discard)".into(),
+            ));
+        }
+        let name = self.doc_name();
+        let doc = self.docs.remove(0);
+        self.unpark_doc(doc);
+        Ok(format!("Closed {name}"))
+    }
+
+    fn buf_list(&self) -> String {
+        let mut names = vec![(self.doc_id, format!("*{}", self.doc_name()))];
+        for doc in self.docs.iter() {
+            let dirty = if doc.dirty { "+" } else { "" };
+            names.push((
+                doc.id,
+                format!("{}{dirty}", doc.path.as_deref().unwrap_or("[untitled]")),
+            ));
+        }
+        names.sort_by_key(|(id, _)| *id);
+        let names: Vec<String> = names.into_iter().map(|(_, name)| name).collect();
+        names.join("  ")
+    }
+
+    // Compare functions
+
+    fn undo_snapshot(&mut self, steps: usize) -> Result<Song> {
+        self.editor.snapshot(steps)
+    }
+
+    fn start_compare(&mut self, arg: Option<&str>) -> Result<String> {
+        let reference = match arg.map(|a| a.split_once(' ').unwrap_or((a, ""))) {
+            None => {
+                self.compare = None;
+                return Ok("Compare off".into());
+            }
+            Some(("undo", steps)) => {
+                let steps = if steps.is_empty() {
+                    1
+                } else {
+                    parse_index(steps)?
+                };
+                Reference {
+                    label: format!("{steps} undo step(s) ago"),
+                    song: self.undo_snapshot(steps)?,
+                }
+            }
+            Some(_) => {
+                let path = arg.unwrap();
+                Reference {
+                    label: path.to_owned(),
+                    song: self.formats().read_file(path)?,
+                }
+            }
+        };
+        let msg = format!("Comparing against {}", reference.label);
+        self.compare = Some(reference);
+        Ok(msg)
+    }
+
+    // Song settings functions
+
+    fn set_time_sig(&mut self, arg: &str) -> Result<String> {
+        let sig = arg.parse()?;
+        self.edit_song("set time signature", |song| {
+            song.time_sig = sig;
+            song.update_all_measures();
+            Ok(())
+        })?;
+        Ok(format!("Set time signature {}", self.editor.song.time_sig))
+    }
+
+    fn set_meta(&mut self, key: &str, arg: Option<&str>) -> Result<String> {
+        let value = arg
+            .map(str::trim)
+            .filter(|a| !a.is_empty())
+            .map(String::from);
+        let msg = match &value {
+            Some(value) => format!("Set {key} to '{value}'"),
+            None => format!("Cleared {key}"),
+        };
+        self.edit_song(&format!("set {key}"), |song| {
+            let field = song
+                .meta
+                .field_mut(key)
+                .ok_or_else(|| Error::InvalidOp(format!("Unknown song field '{key}'")))?;
+            *field = value;
+            Ok(msg)
+        })
+    }
+
+    fn set_swing(&mut self, arg: &str) -> Result<String> {
+        let swing = match arg {
+            "on" => Some(67),
+            "off" => None,
+            _ => {
+                let s = parse_index(arg.trim_end_matches('%'))?;
+                let out_of_range = || Error::InvalidOp(format!("Swing of {s}% is outside 50-80%"));
+                Some(u16::try_from(s).map_err(|_| out_of_range())?)
+            }
+        };
+        self.edit_song("set swing", |song| song.set_swing(swing))?;
+        match self.editor.song.swing {
+            Some(s) => Ok(format!("Set swing {s}%")),
+            None => Ok("Set straight feel".into()),
+        }
+    }
+
+    fn set_tempo(&mut self, arg: Option<&str>) -> Result<String> {
+        let tempo = arg
+            .map(|a| parse_index(a.trim_end_matches("bpm")))
+            .transpose()?;
+        let tempo = tempo.map(|t| t.min(u16::MAX as usize) as u16);
+        self.edit_song("set tempo", |song| song.set_tempo(tempo))?;
+        Ok(format!("Set tempo {} bpm", self.editor.song.tempo()))
+    }
+
+    fn set_key(&mut self, arg: Option<&str>) -> Result<String> {
+        let key = arg.map(str::parse).transpose()?;
+        self.edit_song("set key", |song| {
+            song.key = key;
+            Ok(())
+        })?;
+        match &self.editor.song.key {
+            Some(key) => Ok(format!("Set key {key}")),
+            None => Ok("Cleared key".into()),
+        }
+    }
+
+    // The suggestion is only written to the song once confirmed with `apply`
+    fn analyze(&mut self, arg: &str) -> Result<String> {
+        match arg.split_whitespace().collect::<Vec<_>>()[..] {
+            ["key"] => {
+                let analysis = analyze::keys(&self.editor.song)?;
+                let (best, _) = &analysis.ranked[0];
+                let (next, _) = &analysis.ranked[1];
+                let sections: Vec<String> = analysis
+                    .sections
+                    .iter()
+                    .map(|(m, key)| format!("m{} {key}", m + 1))
+                    .collect();
+                self.key_suggestion = Some(best.clone());
+                Ok(format!(
+                    "Likely {best} (or {next}) | {} | :analyze key apply to set",
+                    sections.join(", ")
+                ))
+            }
+            ["key", "apply"] => {
+                let key = self
+                    .key_suggestion
+                    .take()
+                    .ok_or_else(|| Error::InvalidOp("Run :analyze key first".into()))?;
+                self.set_key(Some(&key.to_string()))
+            }
+            _ => Err(Error::InvalidOp("Usage: analyze key [apply]".into())),
+        }
+    }
+
+    fn set_beaming(&mut self, arg: Option<&str>) -> Result<String> {
+        let groups = match arg {
+            Some(arg) => Some(
+                arg.split('+')
+                    .map(|g| {
+                        u16::try_from(parse_index(g)?)
+                            .map_err(|_| Error::InvalidOp(format!("Group '{g}' is too long")))
+                    })
+                    .collect::<Result<Vec<_>>>()?,
+            ),
+            None => None,
+        };
+        self.edit_song("set beaming", |song| song.set_beam_groups(groups))?;
+        let groups: Vec<_> = self
+            .editor
+            .song
+            .beam_groups()
+            .iter()
+            .map(|g| g.to_string())
+            .collect();
+        Ok(format!(
+            "Beaming {} as {}",
+            self.editor.song.time_sig,
+            groups.join("+")
+        ))
+    }
+
+    fn list_tracks(&self) -> Result<String> {
+        let tracks: Vec<String> = self
+            .editor
+            .song
+            .tracks
+            .iter()
+            .enumerate()
+            .map(|(i, t)| {
+                let archived = if t.archived { ", archived" } else { "" };
+                format!("{} ({} strings{archived})", i + 1, t.string_count)
+            })
+            .collect();
+        Ok(format!("Tracks : {}", tracks.join(" ")))
+    }
+
+    // Without a number this is the track of the current lane
+    fn track_arg(&self, arg: Option<&str>) -> Result<usize> {
+        match arg {
+            Some(n) => parse_index(n)?
+                .checked_sub(1)
+                .filter(|t| *t < self.editor.song.tracks.len())
+                .ok_or_else(|| Error::InvalidOp(format!("No track '{n}'"))),
+            None => Ok(self.cursor().track),
+        }
+    }
+
+    fn toggle_archived(&mut self, arg: Option<&str>) -> Result<String> {
+        let t = self.track_arg(arg)?;
+        self.toggle_track_archived(t)
+    }
+
+    fn toggle_track_archived(&mut self, t: usize) -> Result<String> {
+        let archived = self.edit_song("toggle archived", |song| {
+            let track = &mut song.tracks[t];
+            track.archived = !track.archived;
+            Ok(track.archived)
+        })?;
+        let msg = if archived { "Archived" } else { "Restored" };
+        self.check_lanes();
+        Ok(format!("{msg} track {}", t + 1))
+    }
+
+    fn toggle_muted(&mut self, t: usize) -> Result<String> {
+        let muted = self.edit_song("toggle muted", |song| {
+            let track = &mut song.tracks[t];
+            track.muted = !track.muted;
+            Ok(track.muted)
+        })?;
+        self.restart_playback()?;
+        let msg = if muted { "Muted" } else { "Unmuted" };
+        Ok(format!("{msg} track {}", t + 1))
+    }
+
+    fn toggle_solo(&mut self, t: usize) -> Result<String> {
+        let solo = self.edit_song("toggle solo", |song| {
+            let track = &mut song.tracks[t];
+            track.solo = !track.solo;
+            Ok(track.solo)
+        })?;
+        self.restart_playback()?;
+        let msg = if solo { "Soloed" } else { "Unsoloed" };
+        Ok(format!("{msg} track {}", t + 1))
+    }
+
+    fn set_group(&mut self, arg: Option<&str>) -> Result<String> {
+        let t = self.cursor().track;
+        let group = arg
+            .map(str::trim)
+            .filter(|a| !a.is_empty())
+            .map(String::from);
+        let msg = match &group {
+            Some(name) => format!("Put track {} in group '{name}'", t + 1),
+            None => format!("Took track {} out of its group", t + 1),
+        };
+        self.edit_song("set group", |song| {
+            song.tracks[t].group = group;
+            Ok(())
+        })?;
+        self.check_lanes();
+        Ok(msg)
+    }
+
+    fn set_track_name(&mut self, arg: Option<&str>) -> Result<String> {
+        let t = self.cursor().track;
+        let name = arg
+            .map(str::trim)
+            .filter(|a| !a.is_empty())
+            .map(String::from);
+        let msg = match &name {
+            Some(name) => format!("Named track {} '{name}'", t + 1),
+            None => format!("Cleared the name of track {}", t + 1),
+        };
+        self.edit_song("set track name", |song| {
+            song.tracks[t].name = name;
+            Ok(())
+        })?;
+        Ok(msg)
+    }
+
+    fn list_groups(&self) -> Result<String> {
+        let song = &self.editor.song;
+        let groups: Vec<String> = song
+            .groups()
+            .iter()
+            .map(|(name, tracks)| {
+                let tracks: Vec<String> = tracks.iter().map(|t| (t + 1).to_string()).collect();
+                let collapsed = if song.collapsed.iter().any(|c| c == name) {
+                    ", collapsed"
+                } else {
+                    ""
+                };
+                format!("{name} ({}{collapsed})", tracks.join(" "))
+            })
+            .collect();
+        if groups.is_empty() {
+            return Ok("Groups : (none)".into());
+        }
+        Ok(format!("Groups : {}", groups.join(" ")))
+    }
+
+    fn set_collapsed(&mut self, name: &str, collapsed: bool) -> Result<String> {
+        if !self.editor.song.groups().iter().any(|(n, _)| *n == name) {
+            return Err(Error::InvalidOp(format!("No group '{name}'")));
+        }
+        let label = if collapsed {
+            "collapse group"
+        } else {
+            "expand group"
+        };
+        self.edit_song(label, |song| {
+            song.collapsed.retain(|c| c != name);
+            if collapsed {
+                song.collapsed.push(name.to_owned());
+            }
+            Ok(())
+        })?;
+        self.check_lanes();
+        let msg = if collapsed { "Collapsed" } else { "Expanded" };
+        Ok(format!("{msg} group '{name}'"))
+    }
+
+    // Without a name this collapses the group of the track of the current lane
+    fn collapse_group(&mut self, arg: Option<&str>) -> Result<String> {
+        let name = match arg {
+            Some(name) => name.trim().to_owned(),
+            None => self
+                .cursor()
+                .track(&self.editor.song)
+                .group
+                .clone()
+                .ok_or_else(|| {
+                    Error::InvalidOp(format!("Track {} is in no group", self.cursor().track + 1))
+                })?,
+        };
+        self.set_collapsed(&name, true)
+    }
+
+    // Without a name this expands every group
+    fn expand_group(&mut self, arg: Option<&str>) -> Result<String> {
+        if let Some(name) = arg {
+            return self.set_collapsed(name.trim(), false);
+        }
+        self.edit_song("expand groups", |song| {
+            song.collapsed.clear();
+            Ok(())
+        })?;
+        self.check_lanes();
+        Ok("Expanded all groups".into())
+    }
+
+    // Toggles the group of a track picked in the track sidebar
+    fn toggle_collapsed(&mut self, t: usize) -> Result<String> {
+        let track = &self.editor.song.tracks[t];
+        let name = track
+            .group
+            .clone()
+            .ok_or_else(|| Error::InvalidOp(format!("Track {} is in no group", t + 1)))?;
+        let collapsed = self.editor.song.is_collapsed(t);
+        self.set_collapsed(&name, !collapsed)
+    }
+
+    fn set_capo(&mut self, arg: &str) -> Result<String> {
+        let t = self.cursor().track;
+        let fret_count = self.editor.song.tracks[t].fret_count;
+        let capo = u16::try_from(parse_index(arg)?)
+            .ok()
+            .filter(|c| *c < fret_count)
+            .ok_or_else(|| {
+                Error::InvalidOp(format!("Capo {arg} is past the track's {fret_count} frets"))
+            })?;
+        self.edit_song("set capo", |song| {
+            song.tracks[t].capo = capo;
+            Ok(())
+        })?;
+        Ok(format!("Set capo to {capo}"))
+    }
+
+    // MIDI settings of the track of the current lane, auto leaves them to the player
+    fn set_program(&mut self, arg: &str) -> Result<String> {
+        let program = match arg.trim() {
+            "auto" => None,
+            n => match parse_index(n)? {
+                p @ 0..=127 => Some(p as u8),
+                _ => return Err(Error::InvalidOp("Programs go from 0 to 127".into())),
+            },
+        };
+        let t = self.cursor().track;
+        self.edit_song("set program", |song| {
+            song.tracks[t].program = program;
+            Ok(())
+        })?;
+        self.restart_playback()?;
+        Ok(match program {
+            Some(p) => format!("Track {} plays program {p}", t + 1),
+            None => format!("Track {} plays the output's own program", t + 1),
+        })
+    }
+
+    fn set_channel(&mut self, arg: &str) -> Result<String> {
+        let channel = match arg.trim() {
+            "auto" => None,
+            n => match parse_index(n)? {
+                c @ 1..=16 => Some(c as u8),
+                _ => return Err(Error::InvalidOp("Channels go from 1 to 16".into())),
+            },
+        };
+        let t = self.cursor().track;
+        self.edit_song("set channel", |song| {
+            song.tracks[t].channel = channel;
+            Ok(())
+        })?;
+        self.restart_playback()?;
+        Ok(match channel {
+            Some(c) => format!("Track {} plays on channel {c}", t + 1),
+            None => format!("Track {} plays on a channel of its own", t + 1),
+        })
+    }
+
+    fn set_fret_count(&mut self, arg: &str) -> Result<String> {
+        let frets = u16::try_from(parse_index(arg)?)
+            .map_err(|_| Error::InvalidOp(format!("Too many frets '{arg}'")))?;
+        let t = self.cursor().track;
+        self.edit_song("set fret count", |song| {
+            song.tracks[t].set_fret_count(frets)
+        })?;
+        Ok(format!("Set fret count to {frets}"))
+    }
+
+    fn set_tuning(&mut self, arg: Option<&str>) -> Result<String> {
+        let t = self.cursor().track;
+        if let Some(arg) = arg {
+            // Written low string first, stored top (highest) string first
+            let mut tuning = arg
+                .split_whitespace()
+                .map(pitch::parse)
+                .collect::<Result<Vec<_>>>()?;
+            tuning.reverse();
+            self.edit_song("set tuning", |song| {
+                let track = &mut song.tracks[t];
+                if tuning.len() != track.string_count as usize {
+                    return Err(Error::InvalidOp(format!(
+                        "Tuning needs {} pitches",
+                        track.string_count
+                    )));
+                }
+                track.tuning = tuning;
+                Ok(())
+            })?;
+        }
+        let track = &self.editor.song.tracks[t];
+        let names: Vec<_> = track
+            .tuning()
+            .iter()
+            .rev()
+            .map(|p| pitch::name(*p))
+            .collect();
+        Ok(format!("Tuning : {}", names.join(" ")))
+    }
+
+    // The track is numbered from 1, the first one being taken when none is given
+    fn import_track(&mut self, arg: &str) -> Result<String> {
+        let (path, n) = match arg.trim().rsplit_once(' ') {
+            Some((path, n)) if parse_index(n).is_ok() => (path.trim(), parse_index(n)?),
+            _ => (arg.trim(), 1),
+        };
+        let mut song = self.formats().read_file(path)?;
+        if n == 0 || n > song.tracks.len() {
+            return Err(Error::InvalidOp(format!(
+                "'{path}' has {} tracks",
+                song.tracks.len()
+            )));
+        }
+        let track = song.tracks.swap_remove(n - 1);
+        let t = self.append_track(track)?;
+        Ok(format!("Imported track {n} of {path} as track {}", t + 1))
+    }
+
+    // Strings are in standard tuning, and the track starts as a measure of rests
+    fn add_track(&mut self, arg: Option<&str>) -> Result<String> {
+        let strings = arg.map_or(Ok(6), parse_index)?;
+        if !(4..=8).contains(&strings) {
+            return Err(Error::InvalidOp("Tracks have 4 to 8 strings".into()));
+        }
+        let sig = self.editor.song.time_sig_at(0);
+        let mut track = Track::new();
+        track.string_count = strings as u16;
+        track.beats = vec![Beat::new(Duration::new(1, sig.den)); sig.num as usize];
+        let t = self.append_track(track)?;
+        Ok(format!("Added track {} with {strings} strings", t + 1))
+    }
+
+    // Every new track gets a lane, which becomes the current one
+    fn append_track(&mut self, track: Track) -> Result<usize> {
+        self.push_action(Action::add_track(track))?;
+        self.check_lanes();
+        let t = self.editor.song.tracks.len() - 1;
+        if let Some(lane) = self.lanes.iter().position(|l| l.cur.track == t) {
+            self.curr_lane = lane;
+        }
+        Ok(t)
+    }
+
+    // Chord library functions
+
+    fn chord_save(&mut self, name: &str) -> Result<String> {
+        let track = self.cursor().track(&self.editor.song);
+        let shape = track.notes_shape(&self.cursor().clone_chord(&self.editor.song))?;
+        if shape.is_empty() {
+            return Err(Error::InvalidOp("No notes to save as chord".into()));
+        }
+        // Reloaded so that a library which failed to load is not saved over
+        self.chords = ChordLib::load()?;
+        self.chords.insert(&track.tuning(), name, shape);
+        self.chords.save()?;
+        Ok(format!("Saved chord '{name}'"))
+    }
+
+    fn chord_del(&mut self, name: &str) -> Result<String> {
+        let tuning = self.cursor().track(&self.editor.song).tuning();
+        self.chords = ChordLib::load()?;
+        self.chords.remove(&tuning, name)?;
+        self.chords.save()?;
+        Ok(format!("Removed chord '{name}'"))
+    }
+
+    // Name of the saved or built in chord played at the cursor, if any
+    fn chord_name(&self) -> Option<String> {
+        let track = self.cursor().track(&self.editor.song);
+        let mut shape = track
+            .notes_shape(&self.cursor().clone_chord(&self.editor.song))
+            .ok()?;
+        shape.sort_by_key(|(s, _)| *s);
+        let tuning = track.tuning();
+        self.chords.names(&tuning).into_iter().find(|name| {
+            self.chords.get(&tuning, name).is_some_and(|mut saved| {
+                saved.sort_by_key(|(s, _)| *s);
+                saved == shape
+            })
+        })
+    }
+
+    fn chord_cmd(&mut self, arg: Option<&str>) -> Result<String> {
+        match arg.map(|a| a.split_once(' ').unwrap_or((a, ""))) {
+            Some(("save", name)) if !name.is_empty() => self.chord_save(name.trim()),
+            Some(("del", name)) if !name.is_empty() => self.chord_del(name.trim()),
+            None => {
+                let tuning = self.cursor().track(&self.editor.song).tuning();
+                Ok(format!("Chords : {}", self.chords.names(&tuning).join(" ")))
+            }
+            _ => Err(Error::InvalidOp("Usage: chord [save|del {name}]".into())),
+        }
+    }
+
+    // Song TODO functions
+
+    fn todo_add(&mut self, text: &str) -> Result<String> {
+        let measure = self.cursor().measure(&self.editor.song);
+        let n = self.edit_song("add TODO", |song| {
+            Ok(song.add_todo(measure, text.trim().to_owned()))
+        })?;
+        Ok(format!("Added TODO {n} at measure {}", measure + 1))
+    }
+
+    fn todo_done(&mut self, n: &str) -> Result<String> {
+        let n = parse_index(n)?;
+        let done = self.edit_song("toggle TODO", |song| {
+            let todo = song.todo_mut(n)?;
+            todo.done = !todo.done;
+            Ok(todo.done)
+        })?;
+        let msg = if done { "Completed" } else { "Reopened" };
+        Ok(format!("{msg} TODO {n}"))
+    }
+
+    fn todo_del(&mut self, n: &str) -> Result<String> {
+        let n = parse_index(n)?;
+        self.edit_song("remove TODO", |song| song.del_todo(n))?;
+        Ok(format!("Removed TODO {n}"))
+    }
+
+    fn todo_jump(&mut self, n: &str) -> Result<String> {
+        let n = parse_index(n)?;
+        let measure = self.editor.song.todo_mut(n)?.measure;
+        self.goto_measure(measure)
+    }
+
+    // Timeline functions
+
+    // Measure of the picked event, which undo may have taken away
+    fn picked_change(&mut self) -> usize {
+        self.change_sel = self.change_sel.min(self.editor.song.changes.len());
+        match self.change_sel {
+            0 => 0,
+            n => self.editor.song.changes[n - 1].measure,
+        }
+    }
+
+    fn pick_change_at(&mut self, measure: usize) {
+        let changes = &self.editor.song.changes;
+        self.change_sel = changes
+            .iter()
+            .position(|c| c.measure == measure)
+            .map_or(0, |i| i + 1);
+    }
+
+    fn add_change(&mut self) -> Result<String> {
+        let measure = self.cursor().measure(&self.editor.song);
+        if self.editor.song.change_at(measure).is_some() {
+            return Err(Error::InvalidOp(format!(
+                "Measure {} already has a change",
+                measure + 1
+            )));
+        }
+        // Starts out keeping the tempo, for the time signature or tempo to be typed in
+        let change = Change {
+            measure,
+            time_sig: None,
+            tempo: Some(self.editor.song.tempo_at(measure)),
+        };
+        self.edit_song("add change", |song| song.set_change(change))?;
+        self.pick_change_at(measure);
+        Ok(format!("Added change at measure {}", measure + 1))
+    }
+
+    fn set_change(&mut self, arg: &str) -> Result<String> {
+        let measure = self.picked_change();
+        if measure == 0 {
+            if arg.contains('/') {
+                return self.set_time_sig(arg);
+            }
+            return self.set_tempo(Some(arg));
+        }
+        let (sig, tempo) = if arg.contains('/') {
+            (Some(arg.parse::<TimeSig>()?), None)
+        } else {
+            (None, Some(parse_index(arg)?.min(u16::MAX as usize) as u16))
+        };
+        let mut change = self.editor.song.change_at(measure).unwrap().clone();
+        change.time_sig = sig.or(change.time_sig);
+        change.tempo = tempo.or(change.tempo);
+        self.edit_song("set change", |song| song.set_change(change))?;
+        Ok(format!("Set change at measure {} to {arg}", measure + 1))
+    }
+
+    fn move_change(&mut self, steps: isize) -> Result<String> {
+        let from = self.picked_change();
+        if from == 0 {
+            return Err(Error::InvalidOp(
+                "The start of the song does not move".into(),
+            ));
+        }
+        let to = from
+            .saturating_add_signed(steps)
+            .clamp(1, self.editor.song.last_measure().max(1));
+        if to == from {
+            return Ok(format!("Change stays at measure {}", from + 1));
+        }
+        self.edit_song("move change", |song| song.move_change(from, to))?;
+        self.pick_change_at(to);
+        Ok(format!("Moved change to measure {}", to + 1))
+    }
+
+    fn del_change(&mut self) -> Result<String> {
+        let measure = self.picked_change();
+        if measure == 0 {
+            return Err(Error::InvalidOp(
+                "The start of the song cannot be removed".into(),
+            ));
+        }
+        self.edit_song("remove change", |song| song.del_change(measure))?;
+        self.change_sel -= 1;
+        Ok(format!("Removed change at measure {}", measure + 1))
+    }
+
+    // Measure functions
+
+    fn goto_measure(&mut self, measure: usize) -> Result<String> {
+        if self.lanes[self.curr_lane]
+            .cur
+            .seek_measure(&self.editor.song, measure, self.s_bwidth)
+        {
+            self.sync_cursors();
+            Ok(format!("Jumped to measure {}", measure + 1))
+        } else {
+            Err(Error::InvalidOp(format!(
+                "Measure {} is past the end of the track",
+                measure + 1
+            )))
+        }
+    }
+
+    fn goto(&mut self, arg: &str) -> Result<String> {
+        match parse_index(arg)? {
+            0 => Err(Error::InvalidOp("Measures are numbered from 1".into())),
+            n => self.goto_measure(n - 1),
+        }
+    }
+
+    // Measures line up across tracks, so they are deleted from every track that reaches them
+    fn delete_measures(&mut self, arg: &str) -> Result<String> {
+        let count = parse_index(arg)?;
+        if count == 0 {
+            return Err(Error::InvalidOp("No measures to delete".into()));
+        }
+        let song = &self.editor.song;
+        let measure = self.cursor().measure(song);
+        let mut actions = Vec::new();
+        let mut deleted = 0;
+        for (t, track) in song.tracks.iter().enumerate() {
+            let Some(start) = track.measure_start(measure) else {
+                continue;
+            };
+            let end = track
+                .measure_start(measure + count)
+                .unwrap_or(track.beats.len());
+            if start == 0 && end == track.beats.len() {
+                return Err(Error::InvalidOp(format!(
+                    "Cannot delete every measure of track {}",
+                    t + 1
+                )));
+            }
+            let mut cur = self.cursor().clone();
+            cur.track = t;
+            cur.beat = start;
+            actions.push(Action::delete_beats(cur, track.beats[start..end].to_vec()));
+            deleted = deleted.max(track.measure_of(end - 1) - measure + 1);
+        }
+        let start = self.cursor().track(song).measure_start(measure).unwrap();
+        // TODOs and changes follow their measures, and are restored along with the beats on undo
+        self.editor.begin();
+        let res = actions
+            .into_iter()
+            .try_for_each(|action| self.editor.apply(action).map(|_| ()))
+            .and_then(|_| {
+                self.editor.edit("shift TODOs", |song| {
+                    song.shift_todos(measure, deleted);
+                    song.shift_changes(measure, deleted);
+                    Ok(())
+                })
+            });
+        if let Err(e) = res {
+            self.editor.rollback();
+            return Err(e);
+        }
+        self.editor.commit("delete measures");
+        self.dirty = true;
+        let len = self.cursor().beats(&self.editor.song).len();
+        let cur = &mut self.lanes[self.curr_lane].cur;
+        cur.beat = start.min(len - 1);
+        cur.scroll_to_cursor(&self.editor.song, self.s_bwidth);
+        self.sync_cursors();
+        Ok(format!(
+            "Deleted {deleted} measures from measure {}",
+            measure + 1
+        ))
+    }
+
+    // Empty measures before the one at cursor, in the current track or in every track reaching it,
+    // each filled with beats of its time signature's note value
+    fn insert_measures(&mut self, count: usize, all: bool) -> Result<String> {
+        if count == 0 {
+            return Err(Error::InvalidOp("No measures to insert".into()));
+        }
+        if count > MAX_INSERT_MEASURES {
+            return Err(Error::InvalidOp(format!(
+                "Cannot insert more than {MAX_INSERT_MEASURES} measures at once"
+            )));
+        }
+        let song = &self.editor.song;
+        let measure = self.cursor().measure(song);
+        let sig = song.time_sig_at(measure);
+        let len = (sig.num as usize)
+            .checked_mul(count)
+            .ok_or_else(|| Error::InvalidOp(format!("Too many beats in {count} measures")))?;
+        let beats = vec![Beat::new(Duration::new(1, sig.den)); len];
+        let mut actions = Vec::new();
+        for (t, track) in song.tracks.iter().enumerate() {
+            if t != self.cursor().track && !all {
+                continue;
+            }
+            let Some(start) = track.measure_start(measure) else {
+                continue;
+            };
+            let mut cur = self.cursor().clone();
+            cur.track = t;
+            cur.beat = start;
+            actions.push(Action::insert_beats(cur, beats.clone()));
+        }
+        let start = self.cursor().track(song).measure_start(measure).unwrap();
+        self.editor.begin();
+        let res = actions
+            .into_iter()
+            .try_for_each(|action| self.editor.apply(action).map(|_| ()))
+            .and_then(|_| {
+                if !all {
+                    return Ok(());
+                }
+                self.editor.edit("shift TODOs", |song| {
+                    song.open_measures(measure, count);
+                    Ok(())
+                })
+            });
+        if let Err(e) = res {
+            self.editor.rollback();
+            return Err(e);
+        }
+        self.editor.commit("insert measures");
+        self.dirty = true;
+        let cur = &mut self.lanes[self.curr_lane].cur;
+        cur.beat = start;
+        cur.scroll_to_cursor(&self.editor.song, self.s_bwidth);
+        self.sync_cursors();
+        Ok(format!(
+            "Inserted {count} measures of {sig} at measure {}{}",
+            measure + 1,
+            if all { " in every track" } else { "" }
+        ))
+    }
+
+    // Completes the last measure of the track and adds one more, with rests as long as the beat at cursor
+    fn append_measure(&mut self) -> Result<String> {
+        let song = &self.editor.song;
+        let track = self.cursor().track(song);
+        let beat = self.cursor().beat(song).copy_duration();
+        let len = track.beats.len();
+        let last = track.measure_of(len - 1);
+        let used = track.beats[track.measure_start(last).unwrap()..]
+            .iter()
+            .fold(Duration::zero(), |total, b| total + b.dur);
+        let last_len = song.time_sig_at(last).measure_len();
+        let rest = if used < last_len {
+            last_len - used
+        } else {
+            Duration::zero()
+        };
+        let sig = song.time_sig_at(last + 1);
+        let count = match (
+            beat.dur.count_in(rest),
+            beat.dur.count_in(sig.measure_len()),
+        ) {
+            (Some(rest), Some(full)) => rest + full,
+            _ => {
+                return Err(Error::InvalidOp(format!(
+                    "Beats of {} do not fill a measure of {sig}",
+                    beat.dur.dur_icon().trim()
+                )))
+            }
+        };
+        let mut cur = self.cursor().clone();
+        cur.beat = len;
+        self.push_action(Action::paste_beats(cur, None, vec![beat; count]))?;
+        let cur = &mut self.lanes[self.curr_lane].cur;
+        cur.beat = len;
+        cur.scroll_to_cursor(&self.editor.song, self.s_bwidth);
+        self.sync_cursors();
+        Ok(format!("Appended measure {}", last + 2))
+    }
+
+    // Selection functions
+
+    // Starts a selection at the cursor, or drops the one there is
+    fn toggle_selection(&mut self) {
+        self.select = match self.select {
+            Some(_) => None,
+            None => Some((self.cursor().track, self.cursor().beat)),
+        };
+        self.visual = false;
+    }
+
+    // V starts a visual selection at the cursor, and once started limits it to strings or lifts that
+    fn toggle_visual(&mut self) {
+        if self.visual && self.select.is_some() {
+            self.select_string = match self.select_string {
+                Some(_) => None,
+                None => Some(self.cursor().string),
+            };
+            return;
+        }
+        self.select = Some((self.cursor().track, self.cursor().beat));
+        self.visual = true;
+        self.select_string = None;
+    }
+
+    fn end_visual(&mut self) {
+        self.select = None;
+        self.visual = false;
+        self.select_string = None;
+    }
+
+    fn visual_selection(&self) -> Option<std::ops::Range<usize>> {
+        self.visual.then(|| self.selection()).flatten()
+    }
+
+    // Cursors on the notes of the visual selection, on its strings
+    fn selected_notes(&self) -> Vec<Cursor> {
+        self.visual_selection()
+            .map_or(Vec::new(), |range| self.notes_in(range))
+    }
+
+    // Cursors on the notes of the beats in `range`, limited to the strings of a visual selection
+    fn notes_in(&self, range: std::ops::Range<usize>) -> Vec<Cursor> {
+        let cur = self.cursor();
+        let song = &self.editor.song;
+        let strings = match self.select_string {
+            Some(s) => s.min(cur.string)..=s.max(cur.string),
+            None => 0..=cur.track(song).string_count - 1,
+        };
+        let mut out = Vec::new();
+        for beat in range {
+            for string in strings.clone() {
+                let mut at = cur.clone();
+                at.beat = beat;
+                at.string = string;
+                if at.clone_note(song).is_some() {
+                    out.push(at);
+                }
+            }
+        }
+        out
+    }
+
+    // Each note of the visual selection replaced as one undo step, ending the selection
+    fn edit_selected_notes(
+        &mut self,
+        label: &str,
+        f: impl FnMut(&NoteEntry) -> Result<Option<NoteEntry>>,
+    ) -> Result<String> {
+        let notes = self.selected_notes();
+        self.edit_notes(notes, label, f)
+    }
+
+    fn edit_notes(
+        &mut self,
+        notes: Vec<Cursor>,
+        label: &str,
+        mut f: impl FnMut(&NoteEntry) -> Result<Option<NoteEntry>>,
+    ) -> Result<String> {
+        let song = &self.editor.song;
+        let track = self.cursor().track(song);
+        let mut actions = Vec::new();
+        for cur in notes {
+            let old = cur.clone_note(song).unwrap();
+            let new = f(&old)?;
+            if let Some(new) = &new {
+                track.check_entry(new)?;
+            }
+            actions.push(Action::set_note(cur, Some(old), new));
+        }
+        if actions.is_empty() {
+            return Err(Error::InvalidOp("No notes selected".into()));
+        }
+        self.end_visual();
+        self.push_action(Action::group(label, actions))
+    }
+
+    // Frets (and trills) of the selected beats, or of the whole track, moved by `arg` semitones
+    fn transpose(&mut self, arg: &str) -> Result<()> {
+        // A leading + reads as a shift up, which the calculator has no unary form for
+        let steps = calc::eval(arg.trim().trim_start_matches('+'))?;
+        let steps = i32::try_from(steps)
+            .map_err(|_| Error::InvalidOp(format!("Cannot transpose by {steps} semitones")))?;
+        let range = self
+            .selection()
+            .unwrap_or(0..self.cursor().track(&self.editor.song).beats.len());
+        let notes = self.notes_in(range);
+        self.do_transpose(notes, steps);
+        Ok(())
+    }
+
+    // Dead notes stay as they are, and frets that would go behind the capo or past the last fret
+    // are kept at them with a warning
+    fn do_transpose(&mut self, notes: Vec<Cursor>, steps: i32) {
+        let count = notes.len();
+        let track = self.cursor().track(&self.editor.song);
+        let (capo, fret_count) = (track.capo, track.fret_count);
+        let (mut low, mut high) = (0, 0);
+        let mut shift = |fret: u16| {
+            let to = fret as i32 + steps;
+            if to < capo as i32 {
+                low += 1;
+            } else if to > fret_count as i32 {
+                high += 1;
+            }
+            to.clamp(capo as i32, fret_count as i32) as u16
+        };
+        let res = self.edit_notes(notes, "transpose", |old| {
+            let mut new = old.clone();
+            if let Note::Fret(f) = old.note {
+                new.note = Note::Fret(shift(f));
+            }
+            new.marks.trill = old.marks.trill.map(&mut shift);
+            Ok(Some(new))
+        });
+        if let Err(e) = res {
+            return self.set_command_err(e);
+        }
+        let plural = if count == 1 { "" } else { "s" };
+        self.messages.push(
+            Level::Info,
+            format!("Transposed {count} note{plural} by {steps:+} semitones"),
+        );
+        if low > 0 {
+            let floor = if capo == 0 {
+                "the open string"
+            } else {
+                "the capo"
+            };
+            self.messages.push(
+                Level::Warn,
+                format!("{low} frets would go below {floor}, kept at {capo}"),
+            );
+        }
+        if high > 0 {
+            self.messages.push(
+                Level::Warn,
+                format!("{high} frets would go past fret {fret_count}, kept at {fret_count}"),
+            );
+        }
+    }
+
+    // Every beat of the visual selection set to `dur` as one undo step
+    fn set_selected_durations(&mut self, range: std::ops::Range<usize>, dur: Duration) {
+        let song = &self.editor.song;
+        let actions = range
+            .map(|beat| {
+                let mut cur = self.cursor().clone();
+                cur.beat = beat;
+                Action::set_duration(cur.clone(), cur.beat(song).dur, dur)
+            })
+            .collect();
+        self.end_visual();
+        self.new_action(Action::group("set durations", actions));
+    }
+
+    // Marks of the entry syntax (ie t, s, tr7) added to the selected notes, or with none
+    // the techniques cleared from them
+    fn mark_selected_notes(&mut self, marks: &str) -> Result<String> {
+        let marks = if marks.is_empty() {
+            None
+        } else {
+            Some(format!("0{marks}").parse::<NoteEntry>()?.marks)
+        };
+        self.edit_selected_notes("set techniques", |old| {
+            let mut new = old.clone();
+            match &marks {
+                Some(m) => {
+                    new.marks.tap |= m.tap;
+                    new.marks.swell |= m.swell;
+                    new.marks.pluck = m.pluck.or(old.marks.pluck);
+                    new.marks.trill = m.trill.or(old.marks.trill);
+                }
+                None => {
+                    new.marks.tap = false;
+                    new.marks.swell = false;
+                    new.marks.pluck = None;
+                    new.marks.trill = None;
+                }
+            }
+            Ok(Some(new))
+        })
+    }
+
+    // Keys acting on a visual selection, false for those left to their usual meaning
+    fn visual_key(&mut self, key: KeyCode) -> bool {
+        match key {
+            KeyCode::Char('V') => self.toggle_visual(),
+            KeyCode::Char('c') => {
+                if let Some(n) = self.take_selection() {
+                    self.do_copy_beats(n);
+                }
+            }
+            KeyCode::Char('x') => {
+                if let Some(n) = self.take_selection() {
+                    self.do_delete_beats(n);
+                }
+            }
+            KeyCode::Char('k') if self.select_string.is_some() => {
+                let res = self.edit_selected_notes("clear notes", |_| Ok(None));
+                self.set_command_res(res);
+            }
+            KeyCode::Char('k') => {
+                if let Some(n) = self.take_selection() {
+                    self.do_clear_beats(n);
+                }
+            }
+            KeyCode::Char('>') | KeyCode::Char('<') => {
+                let notes = self.selected_notes();
+                self.do_transpose(notes, if key == KeyCode::Char('>') { 1 } else { -1 });
+            }
+            KeyCode::Char('l') => self.input.mode = InpMode::Duration,
+            KeyCode::Char('e') => self.input.mode = InpMode::Edit,
+            _ => return false,
+        }
+        true
+    }
+
+    // Beats from where the selection started to the cursor, while the current lane is on its track
+    fn selection(&self) -> Option<std::ops::Range<usize>> {
+        let (track, anchor) = self.select?;
+        let cur = self.cursor();
+        if cur.track != track {
+            return None;
+        }
+        let anchor = anchor.min(cur.beats(&self.editor.song).len() - 1);
+        Some(anchor.min(cur.beat)..anchor.max(cur.beat) + 1)
+    }
+
+    // Ends the selection with the cursor at its start, giving the number of beats in it
+    fn take_selection(&mut self) -> Option<usize> {
+        let sel = self.selection()?;
+        self.end_visual();
+        let cur = &mut self.lanes[self.curr_lane].cur;
+        cur.beat = sel.start;
+        cur.scroll_to_cursor(&self.editor.song, self.s_bwidth);
+        self.sync_cursors();
+        Some(sel.len())
+    }
+
+    // Playback functions
+
+    // Plays every track that is not archived from the beat at cursor
+    fn toggle_playback(&mut self) -> Result<String> {
+        if let Some(player) = self.player.take() {
+            if player.is_playing() {
+                return Ok("Stopped playback".into());
+            }
+        }
+        let song = &self.editor.song;
+        let start = song.beat_times(self.cursor().track)[self.cursor().beat];
+        let measure = self.cursor().measure(song);
+        let device = self.start_playback(start)?;
+        Ok(format!(
+            "Playing from measure {} on {}",
+            measure + 1,
+            device.display()
+        ))
+    }
+
+    fn midi_device(&self) -> Result<std::path::PathBuf> {
+        match &self.midi_out {
+            Some(name) => playback::device_by_name(name),
+            None => playback::find_device().ok_or_else(|| {
+                Error::FileError("No MIDI output found (use :midi-out {path})".into())
+            }),
+        }
+    }
+
+    fn start_playback(&mut self, start: f64) -> Result<std::path::PathBuf> {
+        let device = self.midi_device()?;
+        let song = &self.editor.song;
+        let mut events = playback::schedule(song, start);
+        if self.metronome {
+            events.extend(playback::clicks(song, self.cursor().track, start));
+        }
+        if self.clock {
+            events.extend(playback::clock(song, self.cursor().track, start));
+        }
+        playback::sort(&mut events);
+        let speed = self.play_speed as f64 / 100.0;
+        for event in &mut events {
+            event.time /= speed;
+        }
+        // Old players are stopped first, so that they let go of the device
+        self.player = None;
+        self.preview = None;
+        self.player = Some(Player::start(&device, events)?);
+        self.play_from = start;
+        Ok(device)
+    }
+
+    // Song time being played, while playback runs
+    fn play_time(&self) -> Option<f64> {
+        let player = self.player.as_ref().filter(|p| p.is_playing())?;
+        Some(self.play_from + player.elapsed() * self.play_speed as f64 / 100.0)
+    }
+
+    // Playback that is running picks changes up from where it is
+    fn restart_playback(&mut self) -> Result<()> {
+        if let Some(time) = self.play_time() {
+            self.start_playback(time)?;
+        }
+        Ok(())
+    }
+
+    fn set_speed(&mut self, speed: u16) -> Result<String> {
+        if !SPEED_RANGE.contains(&speed) {
+            return Err(Error::InvalidOp(format!(
+                "Speed must be {}% to {}%",
+                SPEED_RANGE.start(),
+                SPEED_RANGE.end()
+            )));
+        }
+        // Taken at the old speed, before the new one applies
+        let time = self.play_time();
+        self.play_speed = speed;
+        if let Some(time) = time {
+            self.start_playback(time)?;
+        }
+        Ok(format!("Playing at {speed}% speed"))
+    }
+
+    fn step_speed(&mut self, dire: i16) -> Result<String> {
+        let speed = (self.play_speed as i16 + dire * SPEED_STEP)
+            .clamp(*SPEED_RANGE.start() as i16, *SPEED_RANGE.end() as i16);
+        self.set_speed(speed as u16)
+    }
+
+    fn toggle_audition(&mut self) -> Result<String> {
+        if self.audition {
+            self.audition = false;
+            self.preview = None;
+            return Ok("Audition off".into());
+        }
+        let device = self.midi_device()?;
+        self.audition = true;
+        Ok(format!("Notes entered are heard on {}", device.display()))
+    }
+
+    // Plays the note at cursor alone, unless the song is playing over it
+    fn audition_note(&mut self) -> Result<()> {
+        if !self.audition || self.player.as_ref().is_some_and(|p| p.is_playing()) {
+            return Ok(());
+        }
+        let track = self.cursor().track(&self.editor.song);
+        let beat = self.cursor().beat(&self.editor.song);
+        let Some(Note::Fret(fret)) = beat.get_note(self.cursor().string) else {
+            return Ok(());
+        };
+        let pitch = track.tuning()[self.cursor().string as usize] as u16 + fret;
+        let Some(pitch) = u8::try_from(pitch).ok().filter(|p| *p <= 127) else {
+            return Ok(());
+        };
+        let velocity = beat.velocity().min(127);
+        let device = self.midi_device()?;
+        self.preview = None;
+        self.preview = Some(Player::start(&device, playback::preview(pitch, velocity))?);
+        Ok(())
+    }
+
+    fn toggle_clock(&mut self) -> Result<String> {
+        self.clock = !self.clock;
+        self.restart_playback()?;
+        Ok(format!(
+            "MIDI clock {}",
+            if self.clock { "on" } else { "off" }
+        ))
+    }
+
+    fn toggle_metronome(&mut self) -> Result<String> {
+        self.metronome = !self.metronome;
+        self.restart_playback()?;
+        Ok(format!(
+            "Metronome {}",
+            if self.metronome { "on" } else { "off" }
+        ))
+    }
+
+    // Moves the playback cursor of every lane to the beat being played, turning the view
+    // to the next page when it leaves it, returns whether anything needs redrawing
+    fn follow_playback(&mut self) -> bool {
+        // Once the song has played out, input is waited for as usual again
+        if self.player.as_ref().is_some_and(|p| !p.is_playing()) {
+            self.player = None;
+        }
+        let time = self.play_time();
+        let song = &self.editor.song;
+        let mut changed = false;
+        for lane in &mut self.lanes {
+            let play = time.map(|time| {
+                let times = song.beat_times(lane.cur.track);
+                let last = lane.cur.beats(song).len() - 1;
+                times
+                    .partition_point(|t| *t <= time + 1e-6)
+                    .clamp(1, last + 1)
+                    - 1
+            });
+            if play == lane.play {
+                continue;
+            }
+            lane.play = play;
+            changed = true;
+            if let Some(beat) = play {
+                if !lane.cur.visible(song, self.s_bwidth).contains(&beat) {
+                    lane.cur.scroll_to(song, beat, self.s_bwidth);
+                }
+            }
+        }
+        changed
+    }
+
+    // Without a name this lists the outputs, the choice is kept in config.json
+    fn set_midi_out(&mut self, arg: Option<&str>) -> Result<String> {
+        let Some(name) = arg.map(str::trim) else {
+            let devices: Vec<String> = playback::devices().iter().map(|d| d.to_string()).collect();
+            let current = self.midi_out.as_deref().unwrap_or("auto");
+            if devices.is_empty() {
+                return Ok(format!("No MIDI outputs found | playing on {current}"));
+            }
+            return Ok(format!(
+                "MIDI outputs : {} | playing on {current}",
+                devices.join(", ")
+            ));
+        };
+        let msg = if name == "auto" {
+            self.midi_out = None;
+            "Playing on the first MIDI output found".to_owned()
+        } else {
+            let path = playback::device_by_name(name)?;
+            self.midi_out = Some(name.to_owned());
+            format!("Playing on {}", path.display())
+        };
+        let mut config = Config::load()?;
+        config.midi_out = self.midi_out.clone();
+        config.save()?;
+        self.restart_playback()?;
+        Ok(msg)
+    }
+
+    // Without a path the first raw MIDI device found is read
+    fn toggle_midi_in(&mut self, arg: Option<&str>) -> Result<String> {
+        if self.midi_in.take().is_some() && arg.is_none() {
+            return Ok("Stopped MIDI step entry".into());
+        }
+        let device = match arg {
+            Some(path) => path.trim().into(),
+            None => playback::find_device().ok_or_else(|| {
+                Error::FileError("No MIDI input found (use :midi-in {path})".into())
+            })?,
+        };
+        self.midi_in = Some(midi_input::Input::open(&device)?);
+        Ok(format!(
+            "Entering notes played on {} (:midi-in again stops)",
+            device.display()
+        ))
+    }
+
+    // Each chord played is written over the beat at cursor, which then moves on a beat,
+    // returns whether anything needs redrawing
+    fn step_entry(&mut self) -> bool {
+        let Some(chord) = self.midi_in.as_mut().and_then(|input| input.chord()) else {
+            return false;
+        };
+        let track = self.cursor().track(&self.editor.song);
+        match midi_input::place(track, &chord) {
+            Ok(notes) => {
+                self.new_action(Action::set_chord(
+                    self.cursor().clone(),
+                    self.cursor().clone_chord(&self.editor.song),
+                    notes,
+                ));
+                self.cur_seek_beat(1);
+            }
+            Err(e) => self.set_command_err(e),
+        }
+        true
+    }
+
+    // Macro functions
+
+    fn macro_key(arg: &str) -> Result<String> {
+        let key = arg.trim().to_ascii_uppercase();
+        match serve::function_key(&key) {
+            Some(_) => Ok(key),
+            None => Err(Error::InvalidOp(format!("'{}' is not F1-F12", arg.trim()))),
+        }
+    }
+
+    // Bindings are written back to config.json so they outlive the session
+    fn store_macros(&self) -> Result<()> {
+        let mut config = Config::load()?;
+        config.macros = self.macros.clone();
+        config.save()
+    }
+
+    fn record(&mut self, arg: Option<&str>) -> Result<String> {
+        match (arg, self.recording.take()) {
+            (Some(arg), None) => {
+                let key = Self::macro_key(arg)?;
+                let msg = format!("Recording {key} (press {key} or :record to stop)");
+                self.recording = Some(Recording {
+                    key,
+                    steps: Vec::new(),
+                    command_at: 0,
+                });
+                Ok(msg)
+            }
+            (None, Some(mut rec)) => {
+                rec.steps.truncate(rec.command_at);
+                self.finish_recording(rec)
+            }
+            (Some(_), Some(rec)) => {
+                let key = rec.key.clone();
+                self.recording = Some(rec);
+                Err(Error::InvalidOp(format!("Already recording {key}")))
+            }
+            (None, None) => Err(Error::InvalidOp("Not recording".into())),
+        }
+    }
+
+    fn finish_recording(&mut self, rec: Recording) -> Result<String> {
+        if rec.steps.is_empty() {
+            return Err(Error::InvalidOp(format!(
+                "Nothing recorded for {}",
+                rec.key
+            )));
+        }
+        let count = rec.steps.len();
+        self.macros.insert(rec.key.clone(), rec.steps);
+        self.store_macros()?;
+        Ok(format!("Recorded {count} keys to {}", rec.key))
+    }
+
+    fn record_key(&mut self, code: KeyCode, modi: KeyModifiers) {
+        let top_level = self.input.is_none();
+        let Some(rec) = self.recording.as_mut() else {
+            return;
+        };
+        if top_level && code == KeyCode::Char(':') {
+            rec.command_at = rec.steps.len();
+        }
+        if let Some(name) = serve::key_name(code, modi) {
+            rec.steps.push(name);
+        }
+    }
+
+    // Commands are separated by ';', ie `:map F5 goto 1; export ascii`
+    fn map_macro(&mut self, arg: &str) -> Result<String> {
+        let (key, cmds) = arg.split_once(' ').unwrap_or((arg, ""));
+        let key = Self::macro_key(key)?;
+        let steps: Vec<String> = cmds
+            .split(';')
+            .map(|c| c.trim().trim_start_matches(':'))
+            .filter(|c| !c.is_empty())
+            .map(|c| format!(":{c}"))
+            .collect();
+        if steps.is_empty() {
+            return Err(Error::InvalidOp(
+                "Usage: map F{n} {command}[; {command}..]".into(),
+            ));
+        }
+        self.macros.insert(key.clone(), steps);
+        self.store_macros()?;
+        Ok(format!("Mapped {key}"))
+    }
+
+    fn unmap_macro(&mut self, arg: &str) -> Result<String> {
+        let key = Self::macro_key(arg)?;
+        if self.macros.remove(&key).is_none() {
+            return Err(Error::InvalidOp(format!("Nothing mapped to {key}")));
+        }
+        self.store_macros()?;
+        Ok(format!("Unmapped {key}"))
+    }
+
+    fn list_macros(&self) -> Result<String> {
+        if self.macros.is_empty() {
+            return Err(Error::InvalidOp(
+                "No macros (use :record F{n} or :map)".into(),
+            ));
+        }
+        Ok(self
+            .macros
+            .iter()
+            .map(|(key, steps)| format!("{key}: {}", steps.join(" ")))
+            .collect::<Vec<_>>()
+            .join(" | "))
+    }
+
+    // Steps starting with ':' are whole commands, the rest are single keys
+    fn play_macro(&mut self, n: u8) -> Result<String> {
+        let key = format!("F{n}");
+        if self.recording.as_ref().is_some_and(|r| r.key == key) {
+            let rec = self.recording.take().unwrap();
+            return self.finish_recording(rec);
+        }
+        if self.playing {
+            return Err(Error::InvalidOp("Macros cannot run other macros".into()));
+        }
+        let steps = self
+            .macros
+            .get(&key)
+            .cloned()
+            .ok_or_else(|| Error::InvalidOp(format!("Nothing mapped to {key}")))?;
+        self.playing = true;
+        let res = steps.iter().try_for_each(|step| {
+            match step.strip_prefix(':').filter(|c| !c.is_empty()) {
+                Some(cmd) => {
+                    self.input.clear();
+                    self.input.mode = InpMode::Command;
+                    self.input.arg = cmd.to_owned();
+                    self.input_command();
+                }
+                None => {
+                    let (code, modi) = serve::parse_key(step)?;
+                    self.key_event(code, modi);
+                }
             }
-        };
-        self.set_command_res(res);
+            Ok(())
+        });
+        self.playing = false;
+        res?;
+        Ok(self.messages.text().to_owned())
     }
 
     // Draw functions
 
     fn reset_sdim(&mut self, (w, h): (u16, u16)) {
-        self.s_bwidth = ((w - 4) / 4) as usize;
+        self.s_bwidth.fit(w);
+        self.s_width = w;
         self.s_height = h;
     }
 
-    fn gen_status_msg(&self) -> String {
+    // Screen lines of one row of a lane's tab
+    fn row_height(&self, lane: &Lane) -> usize {
+        let track = lane.cur.track(&self.editor.song);
+        track.has_text() as usize
+            + track.has_decorations() as usize
+            + self.draw_opts.fingering as usize * track.string_count as usize
+            + self.draw_opts.dynamics as usize
+            + self.draw_opts.staff as usize * draw::STAFF_ROWS
+            + self.draw_opts.ruler as usize
+            + self.draw_opts.rhythm as usize
+            + track.string_count as usize
+            + 3
+    }
+
+    // Screen lines of a lane apart from its rows of tab
+    fn lane_fixed_height(&self, lane: &Lane) -> usize {
+        let strings = lane.cur.track(&self.editor.song).string_count as usize;
+        1 + lane.split as usize + self.compare.as_ref().map_or(0, |_| strings + 3)
+    }
+
+    // Screen lines left for the lanes
+    fn lanes_height(&self) -> usize {
+        let song = &self.editor.song;
+        // Meta line, minimap, status bar and message line
+        let mut fixed = 2 + !song.meta.is_empty() as usize + self.draw_opts.minimap as usize;
+        fixed += 2 * song
+            .groups()
+            .iter()
+            .filter(|(name, _)| song.collapsed.iter().any(|c| c == name))
+            .count();
+        (self.s_height as usize).saturating_sub(fixed)
+    }
+
+    // Gives every lane of the wrapped view as many rows as fit on screen, all the same number
+    fn fit_wrap(&mut self) {
+        if !self.draw_opts.wrap {
+            for lane in &mut self.lanes {
+                if lane.cur.rows.take().is_some() {
+                    lane.cur.scroll_to_cursor(&self.editor.song, self.s_bwidth);
+                }
+            }
+            return;
+        }
+        let fixed: usize = self.lanes.iter().map(|l| self.lane_fixed_height(l)).sum();
+        let row_height: usize = self.lanes.iter().map(|l| self.row_height(l)).sum();
+        let rows = self.lanes_height().saturating_sub(fixed) / row_height.max(1);
+        for lane in &mut self.lanes {
+            lane.cur.rows = Some(rows.max(1));
+            lane.cur.scroll_to_cursor(&self.editor.song, self.s_bwidth);
+        }
+    }
+
+    // Lanes that fit on screen, scrolled to keep the current lane among them
+    fn fit_lanes(&mut self) -> std::ops::Range<usize> {
+        let heights: Vec<usize> = self
+            .lanes
+            .iter()
+            .map(|l| self.lane_fixed_height(l) + l.cur.rows.unwrap_or(1) * self.row_height(l))
+            .collect();
+        let space = self.lanes_height();
+        self.lane_scroll = self.lane_scroll.min(self.curr_lane);
+        while self.lane_scroll < self.curr_lane
+            && heights[self.lane_scroll..=self.curr_lane]
+                .iter()
+                .sum::<usize>()
+                > space
+        {
+            self.lane_scroll += 1;
+        }
+        let mut used = 0;
+        let mut end = self.lane_scroll;
+        while end < heights.len() && (end == self.lane_scroll || used + heights[end] <= space) {
+            used += heights[end];
+            end += 1;
+        }
+        self.lane_scroll..end
+    }
+
+    // Where the cursor is and what is on, then the last message or the input being typed
+    fn draw_status(&self, win: &mut window::Window, shown: std::ops::Range<usize>) -> Result<()> {
+        let song = &self.editor.song;
+        let cur = self.cursor();
+        let track = cur.track(song);
+        let file = self
+            .song_path
+            .as_deref()
+            .and_then(|p| std::path::Path::new(p).file_name())
+            .map_or("Untitled".into(), |f| f.to_string_lossy().into_owned());
+        win.print_styled(file.bold())?;
+        if self.dirty {
+            win.print(" [+]")?;
+        }
+        win.print(format!(" | Track {}", cur.track + 1))?;
+        if let Some(name) = &track.name {
+            win.print(format!(" {name}"))?;
+        }
+        let measure = cur.measure(song);
+        let first = track.measure_start(measure).unwrap_or(0);
+        win.print(format!(
+            " | {}:{} of {}",
+            measure + 1,
+            cur.beat - first + 1,
+            track.measure_of(track.beats.len() - 1) + 1
+        ))?
+        .print(format!(" | string {}", cur.string + 1))?
+        .print(format!(" | {}", cur.beat(song).dur.dur_icon().trim()))?;
+        // Length of the measure so far against its time signature, counted in the signature's
+        // notes unless they cannot count it (as with triplets)
+        let end = track
+            .measure_start(measure + 1)
+            .unwrap_or(track.beats.len());
+        let total = track.beats[first..end]
+            .iter()
+            .fold(Duration::zero(), |total, b| total + b.dur);
+        let sig = song.time_sig_at(measure);
+        let den = (1..=total.1)
+            .map(|k| sig.den * k)
+            .find(|d| d % total.1 == 0)
+            .unwrap_or(total.1);
+        let fill = format!(" | {}/{den} of {sig}", total.0 * (den / total.1));
+        match total.cmp(&sig.measure_len()) {
+            std::cmp::Ordering::Less => win.print(fill)?,
+            std::cmp::Ordering::Equal => win.print_styled(fill.green())?,
+            std::cmp::Ordering::Greater => win.print_styled(fill.red())?,
+        };
+        win.print(format!(" | buffer {:?}", self.copy_buf))?;
+        if let Some(sel) = self.selection() {
+            let plural = if sel.len() == 1 { "" } else { "s" };
+            let strings = match self.select_string {
+                Some(s) => format!(
+                    " on strings {}-{}",
+                    s.min(cur.string) + 1,
+                    s.max(cur.string) + 1
+                ),
+                None => String::new(),
+            };
+            let visual = if self.visual { "visual, " } else { "" };
+            win.print_styled(
+                format!(" | {visual}selected {} beat{plural}{strings}", sel.len()).blue(),
+            )?;
+        }
+        if shown.len() < self.lanes.len() {
+            win.print(format!(
+                " | lanes {}-{}/{}",
+                shown.start + 1,
+                shown.end,
+                self.lanes.len()
+            ))?;
+        }
+        if let Some(tempo) = song.tempo {
+            win.print(format!(" | {tempo} bpm"))?;
+        }
+        if let Some(swing) = song.swing {
+            win.print(format!(" | swing {swing}%"))?;
+        }
+        if self.play_speed != 100 {
+            win.print(format!(" | speed {}%", self.play_speed))?;
+        }
+        if let Some(key) = &song.key {
+            win.print(format!(" | key {key}"))?;
+        }
+        if self.notation.name() != "fret" {
+            win.print(format!(" | entry {}", self.notation.name()))?;
+        }
+        if let Some(Duration(num, den)) = self.snap {
+            win.print(format!(" | snap {num}/{den}"))?;
+        }
+        if !self.docs.is_empty() {
+            let (pos, count) = self.doc_pos();
+            win.print(format!(" | song {pos}/{count}"))?;
+        }
+        if self.read_only {
+            win.print_styled(" | read-only".yellow())?;
+        }
+        if let Some(rec) = &self.recording {
+            win.print_styled(format!(" | recording {}", rec.key).red())?;
+        }
+        win.next_line()?;
         if self.input.is_none() {
-            format!("{} | buffer : {:?}", self.command_res, self.copy_buf)
+            if let Some(msg) = self.messages.current() {
+                win.print_styled(match msg.level {
+                    Level::Info => msg.text.as_str().stylize(),
+                    Level::Warn => msg.text.as_str().yellow(),
+                    Level::Error => msg.text.as_str().red(),
+                })?;
+            }
         } else {
-            format!(">{}< | buffer : {:?}", self.input.display(), self.copy_buf)
+            let inp = format!(">{}<", self.input.display());
+            // Entries are read through the entry notation, as when they are written
+            let valid = match self.input.mode {
+                InpMode::Edit if !self.input.arg.is_empty() => {
+                    self.parse_entry(&self.input.arg).map(|_| ())
+                }
+                _ => self
+                    .input
+                    .validate(self.cursor().track(&self.editor.song), &self.chords),
+            };
+            match valid {
+                Ok(()) => win.print(inp)?,
+                Err(e) => win
+                    .print_styled(inp.red())?
+                    .print_styled(format!(" {e}").red())?,
+            };
+        }
+        // Candidates for (Tab) while typing a command
+        if let InpMode::Command = self.input.mode {
+            let (_, names) = complete::complete(&self.input.arg);
+            let room = (self.s_width as usize).saturating_sub(self.input.display().len() + 2);
+            let line: String = format!("  {}", names.join("  "))
+                .chars()
+                .take(room)
+                .collect();
+            win.print_styled(line.dark_grey())?;
         }
+        Ok(())
     }
 
     fn set_command_res<T: Into<String>>(&mut self, res: Result<T>) {
-        if let Err(e) = res {
-            self.command_res = format!("{e}");
-        } else {
-            self.command_res = res.unwrap().into();
+        match res {
+            Ok(msg) => self.messages.push(Level::Info, msg),
+            Err(e) => self.set_command_err(e),
         }
     }
 
     fn set_command_err(&mut self, err: Error) {
-        self.command_res = format!("{err}");
+        self.messages.push(Level::Error, format!("{err}"));
     }
 
-    fn draw(&self, win: &mut window::Window) -> Result<()> {
+    fn draw(&mut self, win: &mut window::Window) -> Result<()> {
         let t0 = std::time::Instant::now();
-        win.moveto(0, 0)?;
-        for (i, lane) in self.lanes.iter().enumerate() {
-            lane.draw(win, self.s_bwidth, &self.song, i == self.curr_lane)?;
+        self.fit_wrap();
+        let shown = self.fit_lanes();
+        // Whatever does not fit is cut off above the status line
+        let status_row = self.s_height.saturating_sub(2);
+        let meta = &self.editor.song.meta;
+        // Lane headers take clicks while playing, when muting and soloing matter most
+        win.set_title(&format!("Tab - {}", self.song_title()))?
+            .set_mouse_capture(self.draw_opts.dynamics || self.player.is_some())?
+            .set_height(status_row)
+            .moveto(0, 0)?;
+        if !meta.is_empty() {
+            win.print_styled(meta.header().bold())?.next_line()?;
+        }
+        if self.draw_opts.minimap {
+            let cur = self.cursor();
+            let view = cur.visible(&self.editor.song, self.s_bwidth);
+            draw::draw_minimap(win, &self.editor.song, cur, view, self.s_width as usize)?;
+        }
+        let sel = self.selection();
+        let counting = matches!(self.input.mode, InpMode::Beat);
+        let shade = self.cursor().measure(&self.editor.song);
+        for (i, lane) in self.lanes.iter_mut().enumerate() {
+            lane.shade = Some(shade);
+            lane.sel = sel.clone().filter(|_| i == self.curr_lane);
+            lane.counting = counting && i == self.curr_lane;
+        }
+        let mut dyn_rows = Vec::new();
+        self.header_rows.clear();
+        for (i, lane) in self
+            .lanes
+            .iter()
+            .enumerate()
+            .skip(shown.start)
+            .take(shown.len())
+        {
+            if lane.split {
+                let line = "─".repeat(self.s_width as usize);
+                win.print_styled(line.dark_grey())?.next_line()?;
+            }
+            self.header_rows.push((i, win.row()));
+            let rows = lane.draw(
+                win,
+                self.s_bwidth,
+                &self.editor.song,
+                i == self.curr_lane,
+                &self.draw_opts,
+            )?;
+            dyn_rows.extend(rows.into_iter().map(|row| (i, row)));
+            if let Some(cmp) = &self.compare {
+                lane.draw_reference(
+                    win,
+                    self.s_bwidth,
+                    &self.editor.song,
+                    &cmp.song,
+                    &cmp.label,
+                    &self.draw_opts,
+                )?;
+            }
+        }
+        self.dyn_rows = dyn_rows;
+        let song = &self.editor.song;
+        for (name, tracks) in song.groups() {
+            if song.collapsed.iter().any(|c| c == name) {
+                self.lanes[self.curr_lane].draw_group(
+                    win,
+                    self.s_bwidth,
+                    song,
+                    name,
+                    &tracks,
+                    &self.draw_opts.glyphs,
+                )?;
+            }
         }
-        win.print(self.gen_status_msg())?;
+        if self.draw_opts.todos {
+            draw::draw_todos(win, &self.editor.song)?;
+        }
+        if win.row() > status_row {
+            win.moveto(0, status_row)?;
+        }
+        win.set_height(self.s_height);
+        self.draw_status(win, shown)?;
         let dur = std::time::Instant::now().duration_since(t0).as_secs_f32() * 1000.0;
         if self.args.draw_timer {
             win.print(format!("     -> ({dur:.2}ms)"))?;
         }
-        win.clear_eoline()?.update()?;
+        win.clear_below()?;
+        if let InpMode::Tracks = self.input.mode {
+            let lanes: Vec<usize> = self.lanes.iter().map(|l| l.cur.track).collect();
+            draw::draw_tracks(win, &self.editor.song, self.s_width, self.track_sel, &lanes)?;
+        }
+        if let InpMode::Timeline = self.input.mode {
+            let picked = self.picked_change();
+            draw::draw_timeline(win, &self.editor.song, self.s_width, picked)?;
+        }
+        if let InpMode::Recent = self.input.mode {
+            draw::draw_recent(win, &self.recent.paths, self.s_width, self.recent_sel)?;
+        }
+        if let InpMode::Diagram = self.input.mode {
+            let song = &self.editor.song;
+            let name = self.chord_name();
+            let (track, beat) = (self.cursor().track(song), self.cursor().beat(song));
+            draw::draw_diagram(win, track, beat, name.as_deref(), self.s_width)?;
+        }
+        if let InpMode::Log = self.input.mode {
+            let size = (self.s_width, self.s_height);
+            draw::draw_log(win, &self.messages, size, self.log_scroll)?;
+        }
+        win.update()?;
         Ok(())
     }
 
@@ -365,37 +3004,118 @@ impl App {
     fn do_set_duration(&mut self, dur: Duration) {
         self.new_action(Action::set_duration(
             self.cursor().clone(),
-            self.cursor().beat(&self.song).dur.clone(),
+            self.cursor().beat(&self.editor.song).dur.clone(),
             dur,
         ));
     }
 
-    fn do_set_note(&mut self, note: Option<Note>) {
+    fn do_set_note(&mut self, note: Option<NoteEntry>) {
         self.new_action(Action::set_note(
             self.cursor().clone(),
-            self.cursor()
-                .beat(&self.song)
-                .copy_note(self.cursor().string),
+            self.cursor().clone_note(&self.editor.song),
             note,
         ));
     }
 
+    fn do_set_finger(&mut self, finger: Option<Finger>) {
+        if self.cursor().clone_note(&self.editor.song).is_none() {
+            self.set_command_err(Error::InvalidOp("No note to finger".into()));
+            return;
+        }
+        self.new_action(Action::set_finger(
+            self.cursor().clone(),
+            self.cursor()
+                .beat(&self.editor.song)
+                .get_finger(self.cursor().string),
+            finger,
+        ));
+    }
+
+    fn do_set_color(&mut self, arg: Option<&str>) -> Result<String> {
+        if self.cursor().clone_note(&self.editor.song).is_none() {
+            return Err(Error::InvalidOp("No note to color".into()));
+        }
+        let color = arg.map(str::parse).transpose()?;
+        let old = self
+            .cursor()
+            .beat(&self.editor.song)
+            .get_color(self.cursor().string);
+        self.push_action(Action::set_color(self.cursor().clone(), old, color))
+    }
+
+    fn do_set_stroke(&mut self, stroke: Option<Stroke>) {
+        self.new_action(Action::set_stroke(
+            self.cursor().clone(),
+            self.cursor().beat(&self.editor.song).stroke,
+            stroke,
+        ));
+    }
+
+    fn do_adjust_velocity(&mut self, steps: i16) {
+        let beat = self.cursor().beat(&self.editor.song);
+        let velocity = (beat.velocity() as i16 + steps * VELOCITY_STEP).clamp(1, 127) as u8;
+        self.new_action(Action::set_velocity(
+            self.cursor().clone(),
+            beat.velocity,
+            Some(velocity),
+        ));
+    }
+
+    fn do_set_text(&mut self, text: Option<String>) {
+        self.new_action(Action::set_text(
+            self.cursor().clone(),
+            self.cursor().beat(&self.editor.song).text.clone(),
+            text,
+        ));
+    }
+
+    fn do_set_arpeggio(&mut self, arpeggio: Option<Arpeggio>) {
+        self.new_action(Action::set_arpeggio(
+            self.cursor().clone(),
+            self.cursor().beat(&self.editor.song).arpeggio,
+            arpeggio,
+        ));
+    }
+
+    fn do_set_whammy(&mut self, whammy: Option<Whammy>) {
+        self.new_action(Action::set_whammy(
+            self.cursor().clone(),
+            self.cursor().beat(&self.editor.song).whammy,
+            whammy,
+        ));
+    }
+
+    fn do_set_chord(&mut self, name: &str) {
+        let track = self.cursor().track(&self.editor.song);
+        match self.chords.get(&track.tuning(), name) {
+            Some(shape) => match track.shape_notes(&shape) {
+                Ok(notes) => self.new_action(Action::set_chord(
+                    self.cursor().clone(),
+                    self.cursor().clone_chord(&self.editor.song),
+                    notes,
+                )),
+                Err(e) => self.set_command_err(e),
+            },
+            None => self.set_command_err(Error::InvalidOp(format!("Unknown chord '{name}'"))),
+        }
+    }
+
     fn do_copy_note(&mut self) {
-        self.copy_buf = self.cursor().copy_note(&self.song);
+        self.copy_buf = self.cursor().copy_note(&self.editor.song);
         if matches!(self.copy_buf, Buffer::Note(_)) {
             self.set_command_res(Ok("Copied Note"));
         }
     }
 
     fn do_copy_beat(&mut self) {
-        self.copy_buf = self.cursor().copy_beat(&self.song);
+        self.copy_buf = self.cursor().copy_beat(&self.editor.song);
         if matches!(self.copy_buf, Buffer::Beat(_)) {
             self.set_command_res(Ok("Copied Beat"));
         }
     }
 
     fn do_copy_beats(&mut self, count: usize) {
-        self.copy_buf = self.cursor().copy_beats(&self.song, count);
+        self.copy_buf = self.cursor().copy_beats(&self.editor.song, count);
         if let Buffer::Beats(b) = &self.copy_buf {
             let msg = format!("Copied {} beats", b.len());
             self.set_command_res(Ok(msg));
@@ -403,7 +3123,7 @@ impl App {
     }
 
     fn do_delete_beats(&mut self, count: usize) {
-        if let Some(b) = self.cursor().clone_beats_slice(&self.song, count) {
+        if let Some(b) = self.cursor().clone_beats_slice(&self.editor.song, count) {
             self.new_action(Action::delete_beats(self.cursor().clone(), b))
         } else {
             self.set_command_err(Error::InvalidOp("Tried to delete out of bounds".into()));
@@ -413,12 +3133,23 @@ impl App {
     fn do_delete_beat(&mut self) {
         self.new_action(Action::delete_beat(
             self.cursor().clone(),
-            self.cursor().clone_beat(&self.song),
+            self.cursor().clone_beat(&self.editor.song),
         ));
     }
 
+    // Empty beats of the duration at the cursor, after it moving the cursor onto the first of them
+    fn do_insert_beats(&mut self, count: usize, after: bool) {
+        let dur = self.cursor().beat(&self.editor.song).dur;
+        let mut cur = self.cursor().clone();
+        cur.beat += after as usize;
+        self.new_action(Action::insert_beats(cur, vec![Beat::new(dur); count]));
+        if after {
+            self.cur_seek_beat(1);
+        }
+    }
+
     fn do_clear_beats(&mut self, count: usize) {
-        if let Some(b) = self.cursor().clone_beats_slice(&self.song, count) {
+        if let Some(b) = self.cursor().clone_beats_slice(&self.editor.song, count) {
             self.new_action(Action::clear_beats(self.cursor().clone(), b))
         } else {
             self.set_command_err(Error::InvalidOp("Tried to delete out of bounds".into()));
@@ -428,7 +3159,7 @@ impl App {
     fn do_clear_beat(&mut self) {
         self.new_action(Action::clear_beat(
             self.cursor().clone(),
-            self.cursor().clone_chord(&self.song),
+            self.cursor().clone_chord(&self.editor.song),
         ));
     }
 
@@ -436,13 +3167,13 @@ impl App {
         match self.copy_buf.clone() {
             Buffer::Note(note) => self.new_action(Action::paste_note(
                 self.cursor().clone(),
-                self.cursor().clone_note(&self.song),
+                self.cursor().clone_note(&self.editor.song),
                 note,
             )),
             Buffer::Beat(beat) => self.new_action(Action::paste_beat(
                 self.cursor().clone(),
                 if in_place {
-                    Some(self.cursor().clone_beat(&self.song))
+                    Some(self.cursor().clone_beat(&self.editor.song))
                 } else {
                     None
                 },
@@ -451,7 +3182,7 @@ impl App {
             Buffer::Beats(beats) => self.new_action(Action::paste_beats(
                 self.cursor().clone(),
                 if in_place {
-                    Some(self.cursor().clone_beat(&self.song))
+                    Some(self.cursor().clone_beat(&self.editor.song))
                 } else {
                     None
                 },
@@ -464,10 +3195,14 @@ impl App {
     // Cursor functions
 
     fn sync_cursors(&mut self) {
-        let dur = self.lanes[self.curr_lane].cur.calc_duration(&self.song);
+        let offset = self.lanes[self.curr_lane]
+            .cur
+            .calc_offset(&self.editor.song);
+        let split = self.lanes[self.curr_lane].split;
         for (i, lane) in self.lanes.iter_mut().enumerate() {
-            if i != self.curr_lane {
-                lane.cur.transfer_seek(dur, &self.song, self.s_bwidth);
+            if i != self.curr_lane && lane.split == split {
+                lane.cur
+                    .transfer_seek(offset, &self.editor.song, self.s_bwidth);
             }
         }
     }
@@ -475,28 +3210,42 @@ impl App {
     fn cur_seek_beat(&mut self, dire: isize) {
         self.lanes[self.curr_lane]
             .cur
-            .seek_beat(&mut self.song, dire, self.s_bwidth);
+            .seek_beat(&mut self.editor.song, dire, self.s_bwidth);
+        self.sync_cursors();
+    }
+
+    // Navigation keys land only on beats of the :set snap grid
+    fn cur_seek_beat_snapped(&mut self, dire: isize) {
+        let Some(grid) = self.snap else {
+            return self.cur_seek_beat(dire);
+        };
+        self.lanes[self.curr_lane].cur.seek_beat_snapped(
+            &mut self.editor.song,
+            dire,
+            grid,
+            self.s_bwidth,
+        );
         self.sync_cursors();
     }
 
     fn cur_seek_next_measure(&mut self) {
         self.lanes[self.curr_lane]
             .cur
-            .seek_next_measure(&self.song, self.s_bwidth);
+            .seek_next_measure(&self.editor.song, self.s_bwidth);
         self.sync_cursors();
     }
 
     fn cur_seek_prev_measure(&mut self) {
         self.lanes[self.curr_lane]
             .cur
-            .seek_prev_measure(&self.song, self.s_bwidth);
+            .seek_prev_measure(&self.editor.song, self.s_bwidth);
         self.sync_cursors();
     }
 
     fn cur_seek_end(&mut self) {
         self.lanes[self.curr_lane]
             .cur
-            .seek_end(&self.song, self.s_bwidth);
+            .seek_end(&self.editor.song, self.s_bwidth);
         self.sync_cursors();
     }
 
@@ -506,16 +3255,33 @@ impl App {
     }
 
     fn cur_seek_scroll(&mut self, dire: isize) {
-        self.lanes[self.curr_lane]
-            .cur
-            .seek_scroll(&mut self.song, dire, self.s_bwidth);
+        match self.snap {
+            Some(grid) => self.lanes[self.curr_lane].cur.seek_scroll_snapped(
+                &self.editor.song,
+                dire,
+                grid,
+                self.s_bwidth,
+            ),
+            None => {
+                self.lanes[self.curr_lane]
+                    .cur
+                    .seek_scroll(&self.editor.song, dire, self.s_bwidth)
+            }
+        }
         self.sync_cursors();
     }
 
+    // Past the outer strings of a wrapped view the cursor goes on to the next row
     fn cur_seek_string(&mut self, dire: i16) {
-        self.lanes[self.curr_lane]
-            .cur
-            .seek_string(&mut self.song, dire);
+        let cur = &mut self.lanes[self.curr_lane].cur;
+        let last = cur.track(&self.editor.song).string_count - 1;
+        let edge = if dire < 0 { 0 } else { last };
+        if cur.string == edge && cur.seek_row(&self.editor.song, dire as isize, self.s_bwidth) {
+            cur.string = last - edge;
+            self.sync_cursors();
+            return;
+        }
+        cur.seek_string(&mut self.editor.song, dire);
     }
 
     fn cur_next_lane(&mut self) {
@@ -536,13 +3302,18 @@ impl App {
 
     fn key_press(&mut self, key: KeyCode, modi: KeyModifiers) {
         let shift = modi.contains(KeyModifiers::SHIFT);
+        if self.visual_selection().is_some() && self.visual_key(key) {
+            return;
+        }
         match key {
+            KeyCode::Esc if self.select.is_some() => self.end_visual(),
             KeyCode::Esc => self.should_close = true,
+            KeyCode::Char('S') => self.toggle_selection(),
 
             KeyCode::Char('D') => self.cur_seek_next_measure(),
             KeyCode::Char('A') => self.cur_seek_prev_measure(),
-            KeyCode::Char('d') => self.cur_seek_beat(1),
-            KeyCode::Char('a') => self.cur_seek_beat(-1),
+            KeyCode::Char('d') => self.cur_seek_beat_snapped(1),
+            KeyCode::Char('a') => self.cur_seek_beat_snapped(-1),
             KeyCode::End => self.cur_seek_end(),
             KeyCode::Home => self.cur_seek_start(),
 
@@ -565,49 +3336,276 @@ impl App {
             }
 
             KeyCode::Char('v') => self.do_paste(false),
-            KeyCode::Char('V') => self.do_paste(false),
+            KeyCode::Char('V') => self.toggle_visual(),
             KeyCode::Char('c') => {
                 self.set_command_err(Error::InvalidOp("Specify copy type first".into()))
             }
 
+            KeyCode::Char('F') => {
+                self.draw_opts.fingering = !self.draw_opts.fingering;
+            }
+            KeyCode::Char('K') => {
+                self.draw_opts.dynamics = !self.draw_opts.dynamics;
+            }
+            KeyCode::Char('k') => self.do_adjust_velocity(1),
+            KeyCode::Char('j') => self.do_adjust_velocity(-1),
             KeyCode::Char('l') => self.input.mode = InpMode::Duration,
+            KeyCode::Char('f') => self.input.mode = InpMode::Finger,
+            KeyCode::Char('p') => self.input.mode = InpMode::Stroke,
+            KeyCode::Char('t') => {
+                self.input.mode = InpMode::Text;
+                self.input.arg = self
+                    .cursor()
+                    .beat(&self.editor.song)
+                    .text
+                    .clone()
+                    .unwrap_or_default();
+            }
+            KeyCode::Char('r') => self.input.mode = InpMode::Arpeggio,
+            KeyCode::Char('W') => self.input.mode = InpMode::Whammy,
+            KeyCode::Char('h') => self.input.mode = InpMode::Chord,
             KeyCode::Char('e') => self.input.mode = InpMode::Edit,
             KeyCode::Char('n') => self.input.mode = InpMode::Note,
             KeyCode::Char('b') => self.input.mode = InpMode::Beat,
             KeyCode::Char('m') => self.input.mode = InpMode::Measure,
             KeyCode::Char(':') => self.input.mode = InpMode::Command,
+            KeyCode::Char('T') => {
+                self.track_sel = self.cursor().track;
+                self.input.mode = InpMode::Tracks;
+            }
+            KeyCode::Char('L') => {
+                self.log_scroll = 0;
+                self.input.mode = InpMode::Log;
+            }
+            KeyCode::Char('C') => {
+                let song = &self.editor.song;
+                if self.cursor().beat(song).notes.len() < 2 {
+                    self.set_command_err(Error::InvalidOp("No chord at cursor".into()));
+                } else {
+                    self.input.mode = InpMode::Diagram;
+                }
+            }
+            KeyCode::Char('M') => {
+                let measure = self.cursor().measure(&self.editor.song);
+                self.pick_change_at(measure);
+                self.input.mode = InpMode::Timeline;
+            }
+            KeyCode::F(n) => {
+                let res = self.play_macro(n);
+                self.set_command_res(res);
+            }
+            KeyCode::Char(' ') => {
+                let res = self.toggle_playback();
+                self.set_command_res(res);
+            }
+            KeyCode::Char('-') => {
+                let res = self.step_speed(-1);
+                self.set_command_res(res);
+            }
+            KeyCode::Char('+') | KeyCode::Char('=') => {
+                let res = self.step_speed(1);
+                self.set_command_res(res);
+            }
             _ => {}
         }
     }
 
     fn input_duration(&mut self) {
         match self.input.parse_arg_clear() {
-            Ok(dur) => self.do_set_duration(dur),
+            Ok(dur) => match self.visual_selection() {
+                Some(range) => self.set_selected_durations(range, dur),
+                None => self.do_set_duration(dur),
+            },
             Err(e) => self.set_command_err(e),
         };
     }
 
+    // The leading number is read by the entry notation, so it may be negative
+    fn parse_entry(&self, arg: &str) -> Result<NoteEntry> {
+        let (sign, arg) = match arg.strip_prefix('-') {
+            Some(rest) => (-1, rest),
+            None => (1, arg),
+        };
+        let mut note: NoteEntry = arg.parse()?;
+        let cur = self.cursor();
+        let track = cur.track(&self.editor.song);
+        match note.note {
+            Note::Fret(n) => {
+                let ctx = notation::Context {
+                    track,
+                    string: cur.string,
+                    key: self.editor.song.key.as_ref(),
+                    prev: notation::previous_pitch(track, cur.beat, cur.string),
+                };
+                note.note = Note::Fret(self.notation.fret(sign * n as i32, &ctx)?);
+            }
+            Note::X if sign < 0 => {
+                return Err(Error::InvalidOp("Dead notes cannot be negative".into()))
+            }
+            Note::X => (),
+        }
+        track.check_entry(&note)?;
+        Ok(note)
+    }
+
     fn input_edit(&mut self) {
+        let arg = self.input.arg_clear();
+        if self.visual_selection().is_some() {
+            let res = self.mark_selected_notes(&arg);
+            return self.set_command_res(res);
+        }
+        let res = self.parse_entry(&arg);
+        match res {
+            Ok(mut note) => {
+                // Marks without entry syntax carry over from the note being replaced
+                if let Some(old) = self.cursor().clone_note(&self.editor.song) {
+                    note.marks.finger = old.marks.finger;
+                }
+                self.do_set_note(Some(note));
+                if let Err(e) = self.audition_note() {
+                    self.set_command_err(e);
+                }
+            }
+            Err(e) => self.set_command_err(e),
+        }
+    }
+
+    fn input_finger(&mut self) {
+        match self.input.parse_arg_clear() {
+            Ok(finger) => self.do_set_finger(Some(finger)),
+            Err(e) => self.set_command_err(e),
+        }
+    }
+
+    fn input_whammy(&mut self) {
         match self.input.parse_arg_clear() {
-            Ok(note) => self.do_set_note(Some(note)),
+            Ok(whammy) => self.do_set_whammy(Some(whammy)),
             Err(e) => self.set_command_err(e),
         }
     }
 
     fn input_command(&mut self) {
         let arg = self.input.arg_clear();
+        match self.command(&arg) {
+            // Toggling a view has nothing to report, the last message stays shown
+            Ok(msg) if msg.is_empty() => {}
+            res => self.set_command_res(res),
+        }
+    }
+
+    fn command(&mut self, arg: &str) -> Result<String> {
         let cmd = if let Some((a, b)) = arg.split_once(' ') {
             (a, Some(b))
         } else {
-            (arg.as_str(), None)
+            (arg, None)
         };
         match cmd {
-            ("save", Some(path)) => {
-                let path = Some(path.to_owned());
-                self.do_save_file(path);
-            }
+            ("save", Some(path)) => self.do_save_file(Some(path.to_owned())),
             ("save", None) => self.do_save_file(None),
-            _ => {}
+            ("open" | "e" | "load", Some(path)) => self.open_file(path, false),
+            ("open!" | "e!" | "load!", Some(path)) => self.open_file(path, true),
+            ("badd", Some(path)) => self.buf_add(path.to_owned()),
+            ("bnext" | "bn", None) => self.buf_next(),
+            ("bprev" | "bp", None) => self.buf_prev(),
+            ("bdelete" | "bd", None) => self.buf_delete(false),
+            ("bdelete!" | "bd!", None) => self.buf_delete(true),
+            ("ls" | "buffers", None) => Ok(self.buf_list()),
+            ("export", Some(arg)) => self.export_file(arg, false),
+            ("export!", Some(arg)) => self.export_file(arg, true),
+            ("import-track", Some(arg)) => self.import_track(arg),
+            ("export-range", Some(arg)) => self.export_range(arg, false),
+            ("export-range!", Some(arg)) => self.export_range(arg, true),
+            ("color", arg) => self.do_set_color(arg),
+            ("add-track", arg) => self.add_track(arg),
+            ("tracks", None) => self.list_tracks(),
+            ("archive", arg) => self.toggle_archived(arg),
+            ("mute", arg) => self.track_arg(arg).and_then(|t| self.toggle_muted(t)),
+            ("solo", arg) => self.track_arg(arg).and_then(|t| self.toggle_solo(t)),
+            ("group", arg) => self.set_group(arg),
+            ("name", arg) => self.set_track_name(arg),
+            ("groups", None) => self.list_groups(),
+            ("collapse", arg) => self.collapse_group(arg),
+            ("expand", arg) => self.expand_group(arg),
+            ("set", Some(arg)) => self.set_option(arg),
+            ("compare", arg) => self.start_compare(arg),
+            ("chord" | "chords", arg) => self.chord_cmd(arg),
+            ("program", Some(arg)) => self.set_program(arg),
+            ("channel", Some(arg)) => self.set_channel(arg),
+            ("capo", Some(arg)) => self.set_capo(arg),
+            ("frets", Some(arg)) => self.set_fret_count(arg),
+            ("tuning", arg) => self.set_tuning(arg),
+            ("time", Some(arg)) => self.set_time_sig(arg),
+            ("title" | "artist" | "album" | "transcriber", arg) => self.set_meta(cmd.0, arg),
+            ("autoexport", arg) => self.set_auto_export(arg),
+            ("tempo", arg) => self.set_tempo(arg),
+            ("swing", Some(arg)) => self.set_swing(arg),
+            ("key", arg) => self.set_key(arg),
+            ("analyze", Some(arg)) => self.analyze(arg),
+            ("beam", arg) => self.set_beaming(arg),
+            ("todo", Some(text)) => self.todo_add(text),
+            ("todo" | "todos", None) => {
+                self.draw_opts.todos = !self.draw_opts.todos;
+                Ok(String::new())
+            }
+            ("pitch-names", None) => {
+                self.draw_opts.pitches = !self.draw_opts.pitches;
+                Ok(String::new())
+            }
+            ("split", arg) => self.toggle_split(arg),
+            ("ruler", None) => {
+                self.draw_opts.ruler = !self.draw_opts.ruler;
+                Ok(String::new())
+            }
+            ("rhythm", None) => {
+                self.draw_opts.rhythm = !self.draw_opts.rhythm;
+                Ok(String::new())
+            }
+            ("techniques", None) => {
+                self.draw_opts.techniques = !self.draw_opts.techniques;
+                Ok(String::new())
+            }
+            ("minimap", None) => {
+                self.draw_opts.minimap = !self.draw_opts.minimap;
+                Ok(String::new())
+            }
+            ("staff", None) => {
+                self.draw_opts.staff = !self.draw_opts.staff;
+                Ok(String::new())
+            }
+            ("wrap", None) => {
+                self.draw_opts.wrap = !self.draw_opts.wrap;
+                self.fit_wrap();
+                Ok(String::new())
+            }
+            ("tdone", Some(n)) => self.todo_done(n),
+            ("tdel", Some(n)) => self.todo_del(n),
+            ("record", arg) => self.record(arg),
+            ("map", Some(arg)) => self.map_macro(arg),
+            ("unmap", Some(arg)) => self.unmap_macro(arg),
+            ("macros", None) => self.list_macros(),
+            ("goto", Some(arg)) => self.goto(arg),
+            ("delete-measures", Some(arg)) => self.delete_measures(arg),
+            ("speed", Some(arg)) => {
+                parse_index(arg).and_then(|n| self.set_speed(n.try_into().unwrap_or(u16::MAX)))
+            }
+            ("midi-out", arg) => self.set_midi_out(arg),
+            ("midi-in", arg) => self.toggle_midi_in(arg),
+            ("audition", None) => self.toggle_audition(),
+            ("clock", None) => self.toggle_clock(),
+            ("metronome", None) => self.toggle_metronome(),
+            ("insert-measures", arg) => arg
+                .map_or(Ok(1), parse_index)
+                .and_then(|n| self.insert_measures(n, true)),
+            ("append-measure", None) => self.append_measure(),
+            ("history", Some("settings")) => self.settings_history(),
+            ("transpose", Some(arg)) => self.transpose(arg).map(|()| String::new()),
+            ("revert", Some(n)) => self.revert_settings(n),
+            ("recent", None) => self.show_recent(),
+            ("tjump", Some(n)) => self.todo_jump(n),
+            ("", None) => Ok(String::new()),
+            (name, _) => Err(Error::InvalidOp(format!(
+                "Unknown command '{name}' or bad arguments"
+            ))),
         }
     }
 
@@ -624,7 +3622,7 @@ impl App {
                         self.cur_seek_beat(1);
                         self.input.mode = InpMode::Duration;
                     }
-                    _ => {}
+                    _ => self.input.reject(),
                 },
                 InpMode::Edit => match key {
                     KeyCode::Enter => self.input_edit(),
@@ -633,8 +3631,136 @@ impl App {
                         self.cur_seek_beat(1);
                         self.input.mode = InpMode::Edit;
                     }
-                    _ => {}
+                    _ => self.input.reject(),
+                },
+                InpMode::Finger => match key {
+                    KeyCode::Enter => self.input_finger(),
+                    KeyCode::Char('k') | KeyCode::Char('x') => {
+                        self.do_set_finger(None);
+                        self.input.clear();
+                    }
+                    _ => self.input.reject(),
+                },
+                InpMode::Text => match key {
+                    KeyCode::Enter => {
+                        let text = self.input.arg_clear();
+                        let text = text.trim();
+                        self.do_set_text((!text.is_empty()).then(|| text.to_owned()));
+                    }
+                    _ => self.input.reject(),
+                },
+                InpMode::Arpeggio => {
+                    match key {
+                        KeyCode::Char('u') => self.do_set_arpeggio(Some(Arpeggio::Up)),
+                        KeyCode::Char('d') => self.do_set_arpeggio(Some(Arpeggio::Down)),
+                        KeyCode::Char('k') | KeyCode::Char('x') => self.do_set_arpeggio(None),
+                        _ => return self.input.reject(),
+                    }
+                    self.input.clear();
+                }
+                InpMode::Whammy => match key {
+                    KeyCode::Enter => self.input_whammy(),
+                    KeyCode::Char('r') if self.input.arg.is_empty() => {
+                        self.do_set_whammy(Some(Whammy::Return));
+                        self.input.clear();
+                    }
+                    KeyCode::Char('k') | KeyCode::Char('x') => {
+                        self.do_set_whammy(None);
+                        self.input.clear();
+                    }
+                    _ => self.input.reject(),
+                },
+                InpMode::Chord => match key {
+                    KeyCode::Enter => {
+                        let name = self.input.arg_clear();
+                        self.do_set_chord(&name);
+                    }
+                    _ => self.input.reject(),
+                },
+                InpMode::Stroke => {
+                    match key {
+                        KeyCode::Char('d') => self.do_set_stroke(Some(Stroke::Down)),
+                        KeyCode::Char('u') => self.do_set_stroke(Some(Stroke::Up)),
+                        KeyCode::Char('k') | KeyCode::Char('x') => self.do_set_stroke(None),
+                        _ => return self.input.reject(),
+                    }
+                    self.input.clear();
+                }
+                InpMode::Diagram => match key {
+                    KeyCode::Enter | KeyCode::Char('C') => self.input.clear(),
+                    _ => self.input.reject(),
+                },
+                InpMode::Log => match key {
+                    KeyCode::Up | KeyCode::Char('w') => {
+                        self.log_scroll = self.log_scroll.saturating_sub(1);
+                    }
+                    KeyCode::Down | KeyCode::Char('s') => {
+                        let last = self.messages.log().count().saturating_sub(1);
+                        self.log_scroll = (self.log_scroll + 1).min(last);
+                    }
+                    KeyCode::Enter | KeyCode::Char('L') => self.input.clear(),
+                    _ => self.input.reject(),
+                },
+                InpMode::Tracks => match key {
+                    KeyCode::Up | KeyCode::Char('w') => {
+                        self.track_sel = self.track_sel.saturating_sub(1);
+                    }
+                    KeyCode::Down | KeyCode::Char('s') => {
+                        let last = self.editor.song.tracks.len() - 1;
+                        self.track_sel = (self.track_sel + 1).min(last);
+                    }
+                    KeyCode::Char('x') | KeyCode::Char(' ') => {
+                        let res = self.toggle_track_archived(self.track_sel);
+                        self.set_command_res(res);
+                    }
+                    KeyCode::Char('g') => {
+                        let res = self.toggle_collapsed(self.track_sel);
+                        self.set_command_res(res);
+                    }
+                    KeyCode::Enter | KeyCode::Char('T') => self.input.clear(),
+                    _ => self.input.reject(),
                 },
+                InpMode::Timeline => {
+                    let res = match key {
+                        KeyCode::Up | KeyCode::Char('w') => {
+                            self.change_sel = self.change_sel.saturating_sub(1);
+                            return;
+                        }
+                        KeyCode::Down | KeyCode::Char('s') => {
+                            let last = self.editor.song.changes.len();
+                            self.change_sel = (self.change_sel + 1).min(last);
+                            return;
+                        }
+                        KeyCode::Left | KeyCode::Char('a') => self.move_change(-1),
+                        KeyCode::Right | KeyCode::Char('d') => self.move_change(1),
+                        KeyCode::Char('n') => self.add_change(),
+                        KeyCode::Char('x') => self.del_change(),
+                        KeyCode::Enter if !self.input.arg.is_empty() => {
+                            let arg = std::mem::take(&mut self.input.arg);
+                            self.set_change(&arg)
+                        }
+                        KeyCode::Enter | KeyCode::Char('M') => return self.input.clear(),
+                        _ => return self.input.reject(),
+                    };
+                    self.set_command_res(res);
+                }
+                InpMode::Recent => {
+                    let res = match key {
+                        KeyCode::Up | KeyCode::Char('w') => {
+                            self.recent_sel = self.recent_sel.saturating_sub(1);
+                            return;
+                        }
+                        KeyCode::Down | KeyCode::Char('s') => {
+                            let last = self.recent.paths.len().saturating_sub(1);
+                            self.recent_sel = (self.recent_sel + 1).min(last);
+                            return;
+                        }
+                        KeyCode::Enter => self.open_recent(),
+                        KeyCode::Char('x') => self.forget_recent(),
+                        _ => return self.input.reject(),
+                    };
+                    self.set_command_res(res);
+                }
                 InpMode::Note => match key {
                     KeyCode::Char('c') => {
                         self.do_copy_note();
@@ -644,47 +3770,170 @@ impl App {
                         self.do_set_note(None);
                         self.input.clear();
                     }
-                    _ => {}
+                    _ => self.input.reject(),
                 },
                 InpMode::Beat => match key {
-                    KeyCode::Char('c') => match self.input.parse_arg_opt_clear() {
+                    // Without a count the selection is taken, when there is one
+                    KeyCode::Char('c') => match self
+                        .input
+                        .parse_arg_opt_clear()
+                        .or_else(|| self.take_selection())
+                    {
                         Some(n) => self.do_copy_beats(n),
                         None => self.do_copy_beat(),
                     },
-                    KeyCode::Char('x') => match self.input.parse_arg_opt_clear() {
+                    KeyCode::Char('x') => match self
+                        .input
+                        .parse_arg_opt_clear()
+                        .or_else(|| self.take_selection())
+                    {
                         Some(n) => self.do_delete_beats(n),
                         None => self.do_delete_beat(),
                     },
-                    KeyCode::Char('k') => match self.input.parse_arg_opt_clear::<usize>() {
+                    KeyCode::Char('k') => match self
+                        .input
+                        .parse_arg_opt_clear::<usize>()
+                        .or_else(|| self.take_selection())
+                    {
                         Some(n) => self.do_clear_beats(n),
                         None => self.do_clear_beat(),
                     },
-                    _ => {}
+                    KeyCode::Char('i') | KeyCode::Char('o') => {
+                        let count = self.input.parse_arg_opt_clear().unwrap_or(1).max(1);
+                        self.do_insert_beats(count, key == KeyCode::Char('o'));
+                    }
+                    _ => self.input.reject(),
+                },
+                InpMode::Measure => match key {
+                    KeyCode::Char('i') => {
+                        let count = self.input.parse_arg_opt_clear().unwrap_or(1);
+                        let res = self.insert_measures(count, false);
+                        self.set_command_res(res);
+                    }
+                    _ => self.input.reject(),
                 },
                 InpMode::Command => match key {
                     KeyCode::Enter => self.input_command(),
-                    _ => {}
+                    KeyCode::Tab => {
+                        let (line, _) = complete::complete(&self.input.arg);
+                        if line == self.input.arg {
+                            self.input.reject();
+                        }
+                        self.input.arg = line;
+                    }
+                    _ => self.input.reject(),
                 },
-                _ => {}
+                _ => self.input.reject(),
             },
         }
     }
 
+    fn key_event(&mut self, code: KeyCode, modifiers: KeyModifiers) {
+        // Keys are their own undo steps, even when the mouse button was never released
+        self.end_drag();
+        if !self.playing && !matches!(code, KeyCode::F(_)) {
+            self.record_key(code, modifiers);
+        }
+        if self.input.is_none() {
+            self.key_press(code, modifiers);
+        } else {
+            self.key_input(code);
+        }
+    }
+
+    fn end_drag(&mut self) -> bool {
+        if self.drag.take().is_none() {
+            return false;
+        }
+        self.editor.commit("set velocity");
+        true
+    }
+
+    fn mouse_event(&mut self, e: event::MouseEvent) -> bool {
+        use event::{MouseButton, MouseEventKind};
+        match e.kind {
+            MouseEventKind::Down(MouseButton::Left) => {
+                if let Some((lane, _)) = self.header_rows.iter().find(|(_, row)| *row == e.row) {
+                    let t = self.lanes[*lane].cur.track;
+                    let res = if draw::MUTE_COLS.contains(&e.column) {
+                        self.toggle_muted(t)
+                    } else if draw::SOLO_COLS.contains(&e.column) {
+                        self.toggle_solo(t)
+                    } else {
+                        return false;
+                    };
+                    self.set_command_res(res);
+                    return true;
+                }
+                let hit = self.dyn_rows.iter().find_map(|(lane, row)| {
+                    let (_, beat) = row
+                        .cells
+                        .iter()
+                        .find(|(cols, _)| row.row == e.row && cols.contains(&e.column))?;
+                    Some((*lane, *beat))
+                });
+                let Some((lane, beat)) = hit else {
+                    return false;
+                };
+                self.curr_lane = lane;
+                self.lanes[lane].cur.beat = beat;
+                self.end_drag();
+                // Each step of the drag is applied as an action, and the whole drag undone at once
+                self.editor.begin();
+                self.drag = Some(Drag {
+                    cur: self.cursor().clone(),
+                    row: e.row,
+                    old: self.cursor().beat(&self.editor.song).velocity,
+                });
+                true
+            }
+            MouseEventKind::Drag(MouseButton::Left) => {
+                let Some(drag) = &self.drag else {
+                    return false;
+                };
+                let base = drag.old.unwrap_or(DEFAULT_VELOCITY) as i16;
+                let steps = drag.row as i16 - e.row as i16;
+                let velocity = Some((base + steps * VELOCITY_STEP).clamp(1, 127) as u8);
+                let cur = drag.cur.clone();
+                let old = cur.beat(&self.editor.song).velocity;
+                if old != velocity {
+                    self.new_action(Action::set_velocity(cur, old, velocity));
+                }
+                true
+            }
+            MouseEventKind::Up(MouseButton::Left) => self.end_drag(),
+            _ => false,
+        }
+    }
+
     fn proc_event(&mut self, win: &mut window::Window) -> Result<bool> {
-        match win.get_event() {
+        let mut timeout = self.poll_opts.timeout(self.last_event.elapsed());
+        // Playback and MIDI input move on without key presses, so waiting for them is kept short
+        if self.player.is_some() || self.midi_in.is_some() {
+            timeout = timeout.min(std::time::Duration::from_millis(PLAYBACK_FRAME_MS));
+        }
+        let event = win.get_event(timeout);
+        if event.is_ok() {
+            self.last_event = std::time::Instant::now();
+        }
+        match event {
             Ok(e) => match e {
                 event::Event::Key(e) => match e {
                     event::KeyEvent {
                         code, modifiers, ..
                     } => {
-                        if self.input.is_none() {
-                            self.key_press(code, modifiers);
-                        } else {
-                            self.key_input(code);
+                        self.key_event(code, modifiers);
+                        if self.input.take_bell() {
+                            win.bell()?;
                         }
                         Ok(true)
                     }
                 },
+                event::Event::Mouse(e) => Ok(self.mouse_event(e)),
+                event::Event::FocusLost => {
+                    self.autosave();
+                    Ok(true)
+                }
                 event::Event::Resize(..) => {
                     win.moveto(0, 0)?.clear()?;
                     self.reset_sdim(crossterm::terminal::size().unwrap());
@@ -697,23 +3946,174 @@ impl App {
         }
     }
 
+    // Headless control
+
+    fn serve_state(&self) -> serve::State<'_> {
+        serve::State {
+            song: &self.editor.song,
+            path: self.song_path.as_deref(),
+            dirty: self.dirty,
+            cursor: serve::CursorState::new(self.cursor()),
+            input: self.input.display(),
+            status: self.messages.text(),
+        }
+    }
+
+    fn serve_keys(&mut self, keys: &[serde_json::Value]) -> Result<()> {
+        for key in keys {
+            let name = key.as_str().unwrap_or_default();
+            let (code, modifiers) = serve::parse_key(name)?;
+            self.key_event(code, modifiers);
+            if self.input.take_bell() {
+                return Err(Error::InvalidOp(format!("Key '{name}' rejected")));
+            }
+        }
+        Ok(())
+    }
+
+    fn serve_request(&mut self, req: &serve::Request) -> SResult<(), serve::ErrorState> {
+        match (req.method.as_str(), &req.params) {
+            ("keys", serde_json::Value::Array(keys)) => {
+                // A request is undone as a whole, and leaves the song untouched if it fails
+                self.editor.begin();
+                let res = self.serve_keys(keys);
+                if res.is_ok() {
+                    self.editor.commit("keys");
+                } else {
+                    self.editor.rollback();
+                }
+                Ok(res?)
+            }
+            // Undo steps are whole requests, so they are taken outside of "keys"
+            ("undo", _) => {
+                let msg = self.undo()?;
+                self.set_command_res(Ok(msg));
+                Ok(())
+            }
+            ("redo", _) => {
+                let msg = self.redo()?;
+                self.set_command_res(Ok(msg));
+                Ok(())
+            }
+            ("command", serde_json::Value::String(cmd)) => {
+                self.input.clear();
+                let msg = self.command(cmd)?;
+                if !msg.is_empty() {
+                    self.set_command_res(Ok(msg));
+                }
+                Ok(())
+            }
+            ("state", _) => Ok(()),
+            ("quit", _) => {
+                self.should_close = true;
+                Ok(())
+            }
+            (method @ ("keys" | "command"), _) => Err(serve::ErrorState::new(
+                serve::INVALID_PARAMS,
+                Error::InvalidOp(format!("Bad params for '{method}'")),
+            )),
+            (method, _) => Err(serve::ErrorState::new(
+                serve::METHOD_NOT_FOUND,
+                Error::InvalidOp(format!("Unknown method '{method}'")),
+            )),
+        }
+    }
+
+    fn serve(mut self) -> Result<()> {
+        use std::io::{BufRead, Write};
+        self.reset_lanes();
+        let stdin = std::io::stdin();
+        let mut stdout = std::io::stdout();
+        for line in stdin.lock().lines() {
+            let line = map_io_err!(line)?;
+            if line.trim().is_empty() {
+                continue;
+            }
+            let res = match serde_json::from_str::<serve::Request>(&line) {
+                Ok(req) => match self.serve_request(&req) {
+                    Ok(()) => serve::Response::ok(req.id, self.serve_state()),
+                    Err(e) => serve::Response::err(req.id, e),
+                },
+                Err(e) => {
+                    let err = serve::ErrorState::new(
+                        serve::PARSE_ERROR,
+                        Error::ParseError(e.to_string()),
+                    );
+                    serve::Response::err(serde_json::Value::Null, err)
+                }
+            };
+            let text = serde_json::to_string(&res).unwrap();
+            map_io_err!(writeln!(stdout, "{text}"))?;
+            map_io_err!(stdout.flush())?;
+            if self.should_close {
+                break;
+            }
+        }
+        Ok(())
+    }
+
     // Main loop
 
     pub fn run(mut self) -> Result<()> {
+        if let Some(count) = self.args.roundtrip {
+            println!("{}", roundtrip::run(count, self.args.seed)?);
+            return Ok(());
+        }
+        if let Some([input, output]) = self.args.convert.clone().as_deref() {
+            let (from, format) = (self.args.from.clone(), self.args.format.clone());
+            let msg = self.convert(input, output, from.as_deref(), format.as_deref())?;
+            // Standard output only carries the song, to be piped on
+            if output != "-" {
+                println!("{msg}");
+            }
+            return Ok(());
+        }
         self.song_path = self.args.path.clone();
-        let _ = self.do_load_file(None);
+        self.poll_opts.poll_ms = self.args.poll_ms.max(1);
+        self.do_load_file();
+        // Loading locks the song, this covers songs that do not exist yet
+        if !self.read_only {
+            if let Some(warning) = self.relock() {
+                self.messages.push(Level::Warn, warning);
+            }
+        }
+        if let Err(e) = Config::load() {
+            self.messages
+                .push(Level::Warn, format!("{e}, using default settings"));
+        }
+        if let Err(e) = ChordLib::load() {
+            self.messages.push(
+                Level::Warn,
+                format!("{e}, only built in chords are available"),
+            );
+        }
+        // Started without a song, offer the ones opened last instead of an empty one
+        if self.song_path.is_none() && !self.args.serve && !self.recent.paths.is_empty() {
+            self.input.mode = InpMode::Recent;
+        }
+        if self.args.serve {
+            return self.serve();
+        }
 
         let mut win = window::Window::new()?;
-        win.clear()?;
+        win.set_ascii(self.args.ascii || Config::load().is_ok_and(|c| c.ascii))
+            .clear()?;
         self.reset_sdim(crossterm::terminal::size().unwrap());
         let mut do_redraw = true;
-        self.lanes.push(Lane::new());
-        self.lanes.push(Lane::new_t(1));
+        self.reset_lanes();
         while !self.should_close {
+            if win.close_requested() {
+                // The terminal is restored when `win` is dropped, if it is still there
+                self.autosave();
+                return Ok(());
+            }
             if do_redraw {
                 self.draw(&mut win)?;
             }
             do_redraw = self.proc_event(&mut win)?;
+            do_redraw |= self.follow_playback();
+            do_redraw |= self.step_entry();
+            do_redraw |= self.messages.expire();
         }
         win.clear()?.update()
     }
@@ -6,4 +6,20 @@ pub struct Args {
     pub path: Option<String>,
     #[clap(short, long, action)]
     pub draw_timer: bool,
+    #[clap(long, value_parser, default_value_t = 100)]
+    pub poll_ms: u64,
+    #[clap(long, action)]
+    pub ascii: bool,
+    #[clap(long, action)]
+    pub serve: bool,
+    #[clap(long, value_parser)]
+    pub roundtrip: Option<u64>,
+    #[clap(long, value_parser, default_value_t = 1)]
+    pub seed: u64,
+    #[clap(long, value_parser, number_of_values = 2, value_names = &["IN", "OUT"])]
+    pub convert: Option<Vec<String>>,
+    #[clap(long, value_parser, requires = "convert")]
+    pub format: Option<String>,
+    #[clap(long, value_parser, requires = "convert")]
+    pub from: Option<String>,
 }
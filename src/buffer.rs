@@ -1,10 +1,10 @@
-use crate::song::{Beat, Note};
+use crate::song::{Beat, NoteEntry};
 use std::fmt;
 
 #[derive(Clone)]
 pub enum Buffer {
     Empty,
-    Note(Note),
+    Note(NoteEntry),
     Beat(Beat),
     Beats(Vec<Beat>),
 }
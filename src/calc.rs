@@ -0,0 +1,133 @@
+use crate::error::{Error, Result};
+
+// Integer arithmetic for command arguments, ie `16+8` or `(4+4)*2`
+// Operators are + - * / with the usual precedence, and / must divide evenly
+
+struct Parser<'a> {
+    src: &'a [u8],
+    pos: usize,
+}
+
+impl<'a> Parser<'a> {
+    fn peek(&mut self) -> Option<u8> {
+        while self.src.get(self.pos) == Some(&b' ') {
+            self.pos += 1;
+        }
+        self.src.get(self.pos).copied()
+    }
+
+    fn err(&self) -> Error {
+        Error::ParseError(format!(
+            "Unable to evaluate '{}'",
+            String::from_utf8_lossy(self.src)
+        ))
+    }
+
+    fn expr(&mut self) -> Result<i64> {
+        let mut acc = self.term()?;
+        while let Some(op @ (b'+' | b'-')) = self.peek() {
+            self.pos += 1;
+            let rhs = self.term()?;
+            acc = if op == b'+' {
+                acc.checked_add(rhs)
+            } else {
+                acc.checked_sub(rhs)
+            }
+            .ok_or_else(|| self.err())?;
+        }
+        Ok(acc)
+    }
+
+    fn term(&mut self) -> Result<i64> {
+        let mut acc = self.atom()?;
+        while let Some(op @ (b'*' | b'/')) = self.peek() {
+            self.pos += 1;
+            let rhs = self.atom()?;
+            acc = match op {
+                b'*' => acc.checked_mul(rhs),
+                _ if acc.checked_rem(rhs) == Some(0) => acc.checked_div(rhs),
+                _ => None,
+            }
+            .ok_or_else(|| self.err())?;
+        }
+        Ok(acc)
+    }
+
+    fn atom(&mut self) -> Result<i64> {
+        match self.peek() {
+            Some(b'(') => {
+                self.pos += 1;
+                let value = self.expr()?;
+                if self.peek() != Some(b')') {
+                    return Err(self.err());
+                }
+                self.pos += 1;
+                Ok(value)
+            }
+            Some(b'-') => {
+                self.pos += 1;
+                let value = self.atom()?;
+                value.checked_neg().ok_or_else(|| self.err())
+            }
+            Some(b'0'..=b'9') => {
+                let start = self.pos;
+                while matches!(self.src.get(self.pos), Some(b'0'..=b'9')) {
+                    self.pos += 1;
+                }
+                std::str::from_utf8(&self.src[start..self.pos])
+                    .unwrap()
+                    .parse()
+                    .map_err(|_| self.err())
+            }
+            _ => Err(self.err()),
+        }
+    }
+}
+
+pub fn eval(expr: &str) -> Result<i64> {
+    let mut parser = Parser {
+        src: expr.trim().as_bytes(),
+        pos: 0,
+    };
+    let value = parser.expr()?;
+    if parser.peek().is_some() {
+        return Err(parser.err());
+    }
+    Ok(value)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn precedence_and_parentheses() {
+        assert_eq!(eval("16+8").unwrap(), 24);
+        assert_eq!(eval(" 2 + 3 * 4 ").unwrap(), 14);
+        assert_eq!(eval("(4+4)*2").unwrap(), 16);
+        assert_eq!(eval("-(2-5)").unwrap(), 3);
+        assert_eq!(eval("12/4/3").unwrap(), 1);
+    }
+
+    #[test]
+    fn rejects_bad_input() {
+        for expr in ["", "1+", "(1", "1)", "7/2", "1/0", "2 3", "x"] {
+            assert!(eval(expr).is_err(), "{expr}");
+        }
+    }
+
+    #[test]
+    fn overflow_is_an_error() {
+        let max = i64::MAX;
+        for expr in [
+            format!("{max}+1"),
+            format!("{max}*2"),
+            format!("(-{max}-1)/-1"),
+            format!("-(-{max}-1)"),
+            "99999999999999999999".into(),
+        ] {
+            assert!(eval(&expr).is_err(), "{expr}");
+        }
+        assert_eq!(eval(&format!("-{max}-1")).unwrap(), i64::MIN);
+    }
+}
@@ -0,0 +1,161 @@
+use crate::{
+    config,
+    error::{Error, Result},
+    map_io_err, pitch,
+    song::Note,
+};
+use serde::{Deserialize, Serialize};
+use std::{collections::BTreeMap, path::Path};
+
+// Frets are stored relative to the capo so shapes can be reused at any position
+pub type Shape = Vec<(u16, Note)>;
+
+const LIBRARY_FILE: &str = "chords.json";
+
+// Open shapes for standard 6-string tuning, written low string first
+const BUILTIN: &[(&str, &str)] = &[
+    ("A", "x02220"),
+    ("A7", "x02020"),
+    ("Am", "x02210"),
+    ("Am7", "x02010"),
+    ("B7", "x21202"),
+    ("C", "x32010"),
+    ("C7", "x32310"),
+    ("D", "xx0232"),
+    ("D7", "xx0212"),
+    ("Dm", "xx0231"),
+    ("E", "022100"),
+    ("E7", "020100"),
+    ("Em", "022000"),
+    ("Em7", "022030"),
+    ("F", "133211"),
+    ("G", "320003"),
+    ("G7", "320001"),
+];
+
+fn tuning_key(tuning: &[u8]) -> String {
+    let names: Vec<_> = tuning.iter().map(|p| pitch::name(*p)).collect();
+    names.join(" ")
+}
+
+fn builtin(tuning: &[u8], name: &str) -> Option<Shape> {
+    if tuning != pitch::standard_tuning(6).as_slice() {
+        return None;
+    }
+    let (_, frets) = BUILTIN.iter().find(|(n, _)| *n == name)?;
+    Some(
+        frets
+            .chars()
+            .rev()
+            .enumerate()
+            .filter_map(|(s, f)| Some((s as u16, Note::Fret(f.to_digit(10)? as u16))))
+            .collect(),
+    )
+}
+
+#[derive(Default, Serialize, Deserialize)]
+pub struct ChordLib {
+    shapes: BTreeMap<String, BTreeMap<String, Shape>>,
+}
+
+impl ChordLib {
+    // A missing library is empty, but one that cannot be read is an error, so that it is not
+    // saved over
+    pub fn load() -> Result<Self> {
+        match config::config_file(LIBRARY_FILE) {
+            Some(path) => Self::read(&path),
+            None => Ok(Self::default()),
+        }
+    }
+
+    fn read(path: &Path) -> Result<Self> {
+        let data = match std::fs::read_to_string(path) {
+            Ok(data) => data,
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => return Ok(Self::default()),
+            Err(e) => {
+                return Err(Error::FileError(format!(
+                    "Cannot read {}: {e}",
+                    path.display()
+                )))
+            }
+        };
+        serde_json::from_str(&data)
+            .map_err(|e| Error::FileError(format!("Bad chord library {}: {e}", path.display())))
+    }
+
+    pub fn save(&self) -> Result<()> {
+        let path = config::config_file(LIBRARY_FILE)
+            .ok_or_else(|| Error::FileError("No config directory".into()))?;
+        if let Some(dir) = path.parent() {
+            map_io_err!(std::fs::create_dir_all(dir))?;
+        }
+        let data =
+            serde_json::to_string_pretty(self).map_err(|e| Error::FileError(format!("{e}")))?;
+        map_io_err!(std::fs::write(path, data))
+    }
+
+    pub fn get(&self, tuning: &[u8], name: &str) -> Option<Shape> {
+        self.shapes
+            .get(&tuning_key(tuning))
+            .and_then(|lib| lib.get(name))
+            .cloned()
+            .or_else(|| builtin(tuning, name))
+    }
+
+    pub fn insert(&mut self, tuning: &[u8], name: &str, shape: Shape) {
+        self.shapes
+            .entry(tuning_key(tuning))
+            .or_default()
+            .insert(name.to_owned(), shape);
+    }
+
+    pub fn remove(&mut self, tuning: &[u8], name: &str) -> Result<()> {
+        self.shapes
+            .get_mut(&tuning_key(tuning))
+            .and_then(|lib| lib.remove(name))
+            .map(|_| ())
+            .ok_or_else(|| Error::InvalidOp(format!("No saved chord '{name}'")))
+    }
+
+    pub fn names(&self, tuning: &[u8]) -> Vec<String> {
+        let mut names: Vec<String> = self
+            .shapes
+            .get(&tuning_key(tuning))
+            .map(|lib| lib.keys().cloned().collect())
+            .unwrap_or_default();
+        if builtin(tuning, "E").is_some() {
+            for (n, _) in BUILTIN {
+                if !names.iter().any(|i| i == n) {
+                    names.push(n.to_string());
+                }
+            }
+        }
+        names
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn read_library() {
+        let dir = std::env::temp_dir().join(format!("rust-tab-chords-{}", std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+        let path = dir.join(LIBRARY_FILE);
+
+        // Missing is empty
+        assert!(ChordLib::read(&path).unwrap().shapes.is_empty());
+
+        let mut lib = ChordLib::default();
+        lib.insert(&[40, 45], "X", vec![(0, Note::Fret(2))]);
+        std::fs::write(&path, serde_json::to_string(&lib).unwrap()).unwrap();
+        assert_eq!(ChordLib::read(&path).unwrap().names(&[40, 45]), ["X"]);
+
+        // Corrupt is an error, not an empty library to save over
+        std::fs::write(&path, "{\"shapes\": [").unwrap();
+        assert!(matches!(ChordLib::read(&path), Err(Error::FileError(_))));
+
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+}
@@ -0,0 +1,192 @@
+use std::path::Path;
+
+// Names of the :commands, as matched by App::input_command
+const COMMANDS: &[&str] = &[
+    "add-track",
+    "album",
+    "analyze",
+    "append-measure",
+    "archive",
+    "artist",
+    "audition",
+    "autoexport",
+    "badd",
+    "bdelete",
+    "bdelete!",
+    "beam",
+    "bnext",
+    "bprev",
+    "buffers",
+    "capo",
+    "channel",
+    "chord",
+    "clock",
+    "collapse",
+    "color",
+    "compare",
+    "delete-measures",
+    "expand",
+    "export",
+    "export!",
+    "export-range",
+    "export-range!",
+    "frets",
+    "goto",
+    "group",
+    "groups",
+    "history",
+    "import-track",
+    "insert-measures",
+    "key",
+    "macros",
+    "map",
+    "metronome",
+    "minimap",
+    "midi-in",
+    "midi-out",
+    "mute",
+    "name",
+    "open",
+    "open!",
+    "pitch-names",
+    "program",
+    "recent",
+    "record",
+    "revert",
+    "rhythm",
+    "ruler",
+    "save",
+    "set",
+    "solo",
+    "speed",
+    "split",
+    "staff",
+    "swing",
+    "tdel",
+    "tdone",
+    "techniques",
+    "tempo",
+    "time",
+    "title",
+    "tjump",
+    "todo",
+    "tracks",
+    "transcriber",
+    "transpose",
+    "tuning",
+    "unmap",
+    "wrap",
+];
+
+// Commands whose last argument is a file path
+const PATH_COMMANDS: &[&str] = &[
+    "badd",
+    "compare",
+    "e",
+    "e!",
+    "export",
+    "export!",
+    "export-range",
+    "export-range!",
+    "import-track",
+    "load",
+    "load!",
+    "open",
+    "open!",
+    "save",
+];
+
+fn common_prefix(words: &[String]) -> String {
+    let Some(first) = words.first() else {
+        return String::new();
+    };
+    let mut len = first.len();
+    for word in &words[1..] {
+        len = first
+            .char_indices()
+            .zip(word.chars())
+            .take_while(|((_, a), b)| a == b)
+            .last()
+            .map_or(0, |((i, a), _)| i + a.len_utf8())
+            .min(len);
+    }
+    first[..len].to_string()
+}
+
+// Entries of the directory in `word` starting with its file name, directories ending in '/'
+// Hidden entries are only listed once the name starts with '.'
+fn paths(word: &str) -> Vec<String> {
+    let (dir, name) = match word.rfind('/') {
+        Some(i) => (&word[..=i], &word[i + 1..]),
+        None => ("", word),
+    };
+    let Ok(entries) = std::fs::read_dir(if dir.is_empty() { "." } else { dir }) else {
+        return Vec::new();
+    };
+    let mut out: Vec<String> = entries
+        .filter_map(|e| e.ok())
+        .filter_map(|e| {
+            let file = e.file_name().to_string_lossy().into_owned();
+            if !file.starts_with(name) || (file.starts_with('.') && !name.starts_with('.')) {
+                return None;
+            }
+            let is_dir = Path::new(dir).join(&file).is_dir();
+            Some(if is_dir { format!("{file}/") } else { file })
+        })
+        .collect();
+    out.sort();
+    out
+}
+
+// The command line carried on as far as every candidate for its last word agrees,
+// and the candidates: command names, or file names for the path of a command taking one
+pub fn complete(line: &str) -> (String, Vec<String>) {
+    let Some((cmd, args)) = line.split_once(' ') else {
+        let names: Vec<String> = COMMANDS
+            .iter()
+            .filter(|c| c.starts_with(line))
+            .map(|c| c.to_string())
+            .collect();
+        let prefix = common_prefix(&names);
+        return (
+            if prefix.len() > line.len() {
+                prefix
+            } else {
+                line.into()
+            },
+            names,
+        );
+    };
+    if !PATH_COMMANDS.contains(&cmd) {
+        return (line.into(), Vec::new());
+    }
+    let word = args.rsplit(' ').next().unwrap_or_default();
+    let files = paths(word);
+    let name = &word[word.rfind('/').map_or(0, |i| i + 1)..];
+    let prefix = common_prefix(&files);
+    let line = match prefix.strip_prefix(name) {
+        Some(rest) => format!("{line}{rest}"),
+        None => line.into(),
+    };
+    (line, files)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn commands_complete_as_far_as_they_agree() {
+        let (line, names) = complete("mid");
+        assert_eq!(line, "midi-");
+        assert_eq!(names, ["midi-in", "midi-out"]);
+        assert_eq!(complete("wr").0, "wrap");
+        assert_eq!(complete("zz"), ("zz".into(), Vec::new()));
+        // Only paths are completed after the command
+        assert_eq!(complete("tempo 1").1, Vec::<String>::new());
+        assert_eq!(
+            common_prefix(&["exä".into(), "exäm".into(), "exö".into()]),
+            "ex"
+        );
+    }
+}
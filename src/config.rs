@@ -0,0 +1,289 @@
+use crate::{
+    error::{Error, Result},
+    map_io_err,
+};
+use serde::{Deserialize, Serialize};
+use std::{collections::BTreeMap, path::PathBuf};
+
+const CONFIG_FILE: &str = "config.json";
+const RECENT_FILE: &str = "recent.json";
+const RECENT_LEN: usize = 10;
+
+pub fn config_dir() -> Option<PathBuf> {
+    if let Some(dir) = std::env::var_os("XDG_CONFIG_HOME") {
+        return Some(PathBuf::from(dir).join("rust-tab"));
+    }
+    if let Some(dir) = std::env::var_os("APPDATA") {
+        return Some(PathBuf::from(dir).join("rust-tab"));
+    }
+    std::env::var_os("HOME").map(|home| PathBuf::from(home).join(".config").join("rust-tab"))
+}
+
+pub fn config_file(name: &str) -> Option<PathBuf> {
+    config_dir().map(|dir| dir.join(name))
+}
+
+// Characters drawn for parts of the tab grid, each a single character wide
+#[derive(Clone, Serialize, Deserialize)]
+#[serde(default)]
+pub struct Glyphs {
+    pub empty: char,
+    pub dead: char,
+    pub barline: char,
+    pub separator: char,
+    // Barline where the time signature changes
+    pub double: char,
+    // Barline closing the last measure
+    pub end: char,
+}
+
+impl Default for Glyphs {
+    fn default() -> Self {
+        Self {
+            empty: '―',
+            dead: 'X',
+            barline: '|',
+            separator: '―',
+            double: '║',
+            end: '┃',
+        }
+    }
+}
+
+// Stand-ins for the characters drawn outside of ASCII, one column each like those they replace,
+// for terminals and fonts that show them badly; other characters, as in names, are kept
+const ASCII_GLYPHS: &[(char, char)] = &[
+    ('―', '-'),
+    ('─', '-'),
+    ('│', '|'),
+    ('║', '"'),
+    ('┃', ']'),
+    ('•', '.'),
+    ('·', '.'),
+    ('…', '.'),
+    ('⅓', 't'),
+    ('●', 'o'),
+    ('○', 'O'),
+    ('┌', '.'),
+    ('┬', '.'),
+    ('┐', '.'),
+    ('╮', '\\'),
+    ('╗', '\\'),
+    ('╌', '-'),
+    ('━', '='),
+    ('↑', '^'),
+    ('↓', 'v'),
+    ('≀', '~'),
+    ('∏', 'n'),
+    ('▸', '>'),
+    ('▬', '='),
+    ('░', '.'),
+    ('▁', '_'),
+    ('▂', '.'),
+    ('▃', ','),
+    ('▄', '-'),
+    ('▅', '~'),
+    ('▆', '='),
+    ('▇', '*'),
+    ('█', '#'),
+];
+
+pub fn to_ascii(text: &str) -> String {
+    text.chars()
+        .map(|c| {
+            ASCII_GLYPHS
+                .iter()
+                .find(|(glyph, _)| *glyph == c)
+                .map_or(c, |(_, ascii)| *ascii)
+        })
+        .collect()
+}
+
+// Terminal columns taken by a character, close enough to tell single column ones apart
+pub fn char_width(c: char) -> usize {
+    match c as u32 {
+        0..=0x1f | 0x7f..=0x9f => 0,
+        0x300..=0x36f | 0x200b..=0x200f | 0x20d0..=0x20ff | 0xfe00..=0xfe0f | 0xfe20..=0xfe2f => 0,
+        0x1100..=0x115f
+        | 0x2e80..=0x303e
+        | 0x3041..=0xa4cf
+        | 0xac00..=0xd7a3
+        | 0xf900..=0xfaff
+        | 0xfe30..=0xfe4f
+        | 0xff00..=0xff60
+        | 0xffe0..=0xffe6
+        | 0x1f300..=0x1f64f
+        | 0x1f900..=0x1f9ff
+        | 0x20000..=0x3fffd => 2,
+        _ => 1,
+    }
+}
+
+fn check_glyph(c: char) -> Result<char> {
+    match char_width(c) {
+        1 => Ok(c),
+        _ => Err(Error::InvalidOp(format!(
+            "Glyph '{c}' is not one column wide"
+        ))),
+    }
+}
+
+impl Glyphs {
+    // Hand edited config files may hold glyphs that would break the grid
+    fn too_wide(&self) -> Option<char> {
+        [
+            self.empty,
+            self.dead,
+            self.barline,
+            self.separator,
+            self.double,
+            self.end,
+        ]
+        .into_iter()
+        .find(|c| char_width(*c) != 1)
+    }
+
+    pub fn set(&mut self, key: &str, value: &str) -> Result<()> {
+        let mut chars = value.trim().chars();
+        let glyph = match (chars.next(), chars.next()) {
+            (Some(c), None) => check_glyph(c)?,
+            _ => {
+                return Err(Error::InvalidOp(format!(
+                    "Glyph '{}' must be a single character",
+                    value.trim()
+                )))
+            }
+        };
+        match key {
+            "empty" => self.empty = glyph,
+            "dead" => self.dead = glyph,
+            "barline" => self.barline = glyph,
+            "separator" => self.separator = glyph,
+            "double" => self.double = glyph,
+            "end" => self.end = glyph,
+            _ => return Err(Error::InvalidOp(format!("Unknown glyph '{key}'"))),
+        }
+        Ok(())
+    }
+}
+
+// Colours of the cursor, selection and marks, with built in palettes for low contrast
+// displays and for colour blindness
+#[derive(Clone, Copy, Default, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Theme {
+    #[default]
+    Default,
+    Contrast,
+    Colorblind,
+}
+
+impl std::str::FromStr for Theme {
+    type Err = Error;
+
+    fn from_str(s: &str) -> Result<Self> {
+        match s {
+            "default" => Ok(Self::Default),
+            "contrast" => Ok(Self::Contrast),
+            "colorblind" => Ok(Self::Colorblind),
+            _ => Err(Error::InvalidOp(format!(
+                "Unknown theme '{s}', use default, contrast or colorblind"
+            ))),
+        }
+    }
+}
+
+// Missing settings fall back to their defaults
+#[derive(Default, Serialize, Deserialize)]
+#[serde(default)]
+pub struct Config {
+    pub glyphs: Glyphs,
+    // Function key name to the keys (named as in --serve requests) or :commands it replays
+    #[serde(skip_serializing_if = "BTreeMap::is_empty")]
+    pub macros: BTreeMap<String, Vec<String>>,
+    // Name of the MIDI output played on, the first one found when not set
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub midi_out: Option<String>,
+    // Draw with ASCII stand-ins for the box and music characters (also --ascii)
+    #[serde(skip_serializing_if = "std::ops::Not::not")]
+    pub ascii: bool,
+    #[serde(skip_serializing_if = "is_default_theme")]
+    pub theme: Theme,
+}
+
+fn is_default_theme(theme: &Theme) -> bool {
+    *theme == Theme::Default
+}
+
+impl Config {
+    // A missing file is an empty config, but one that cannot be read is an error,
+    // so that it is not saved over
+    pub fn load() -> Result<Self> {
+        let Some(path) = config_file(CONFIG_FILE) else {
+            return Ok(Self::default());
+        };
+        let data = match std::fs::read_to_string(&path) {
+            Ok(data) => data,
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => return Ok(Self::default()),
+            Err(e) => {
+                return Err(Error::FileError(format!(
+                    "Cannot read {}: {e}",
+                    path.display()
+                )))
+            }
+        };
+        let config: Self = serde_json::from_str(&data)
+            .map_err(|e| Error::FileError(format!("Bad config {}: {e}", path.display())))?;
+        if let Some(c) = config.glyphs.too_wide() {
+            return Err(Error::FileError(format!(
+                "Bad config {}: glyph '{c}' is not one column wide",
+                path.display()
+            )));
+        }
+        Ok(config)
+    }
+
+    pub fn save(&self) -> Result<()> {
+        save_file(CONFIG_FILE, self)
+    }
+}
+
+fn save_file(name: &str, value: &impl Serialize) -> Result<()> {
+    let path = config_file(name).ok_or_else(|| Error::FileError("No config directory".into()))?;
+    if let Some(dir) = path.parent() {
+        map_io_err!(std::fs::create_dir_all(dir))?;
+    }
+    let data = serde_json::to_string_pretty(value).map_err(|e| Error::FileError(format!("{e}")))?;
+    map_io_err!(std::fs::write(path, data))
+}
+
+// Songs opened last, most recent first
+#[derive(Default, Serialize, Deserialize)]
+#[serde(default)]
+pub struct Recent {
+    pub paths: Vec<String>,
+}
+
+impl Recent {
+    // The list is only a convenience, so one that cannot be read starts over
+    pub fn load() -> Self {
+        config_file(RECENT_FILE)
+            .and_then(|path| std::fs::read_to_string(path).ok())
+            .and_then(|data| serde_json::from_str(&data).ok())
+            .unwrap_or_default()
+    }
+
+    pub fn save(&self) -> Result<()> {
+        save_file(RECENT_FILE, self)
+    }
+
+    // Paths are kept absolute, so that songs open the same from any directory
+    pub fn add(&mut self, path: &str) {
+        let path = std::fs::canonicalize(path)
+            .map(|p| p.to_string_lossy().into_owned())
+            .unwrap_or_else(|_| path.to_owned());
+        self.paths.retain(|p| *p != path);
+        self.paths.insert(0, path);
+        self.paths.truncate(RECENT_LEN);
+    }
+}
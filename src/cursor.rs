@@ -1,7 +1,8 @@
 use crate::{
     buffer::Buffer,
-    dur::Duration,
-    song::{Beat, Note, Song, Track},
+    draw::{self, BeatWidth},
+    dur::{Duration, Offset},
+    song::{Arpeggio, Beat, Color, Finger, Note, NoteEntry, Song, Stroke, Track, Whammy},
 };
 
 #[derive(Clone)]
@@ -10,6 +11,13 @@ pub struct Cursor {
     pub track: usize,
     pub beat: usize,
     pub string: u16,
+    // Rows in view when the tab is wrapped into rows of whole measures
+    pub rows: Option<usize>,
+}
+
+// Row of the wrapped view holding `beat`, from the row starts
+fn row_of(starts: &[usize], beat: usize) -> usize {
+    starts.partition_point(|s| *s <= beat) - 1
 }
 
 impl Cursor {
@@ -19,6 +27,7 @@ impl Cursor {
             track: 0,
             beat: 0,
             string: 0,
+            rows: None,
         }
     }
 
@@ -71,8 +80,8 @@ impl Cursor {
         song.tracks[self.track].beats[self.beat].notes.clone()
     }
 
-    pub fn clone_note(&self, song: &Song) -> Option<Note> {
-        song.tracks[self.track].beats[self.beat].copy_note(self.string)
+    pub fn clone_note(&self, song: &Song) -> Option<NoteEntry> {
+        song.tracks[self.track].beats[self.beat].copy_entry(self.string)
     }
 
     // Seek cursor
@@ -82,7 +91,7 @@ impl Cursor {
         self.string = new.clamp(0, self.track(song).string_count as i16 - 1) as u16;
     }
 
-    pub fn seek_beat(&mut self, song: &mut Song, dire: isize, s_bwidth: usize) {
+    pub fn seek_beat(&mut self, song: &mut Song, dire: isize, s_bwidth: BeatWidth) {
         self.beat = (self.beat as isize + dire).max(0) as usize;
         if let Some(diff) = (self.beat + 1).checked_sub(self.beats(song).len()) {
             let beat = self.beats(song).last().unwrap().copy_duration();
@@ -90,8 +99,8 @@ impl Cursor {
                 self.append_beat(song, beat.clone());
             }
         }
-        self.scroll_to_cursor(s_bwidth);
-        self.track_mut(song).update_measures();
+        self.scroll_to_cursor(song, s_bwidth);
+        song.update_measures(self.track);
     }
 
     pub fn seek_start(&mut self) {
@@ -99,12 +108,12 @@ impl Cursor {
         self.scroll = 0;
     }
 
-    pub fn seek_end(&mut self, song: &Song, s_bwidth: usize) {
+    pub fn seek_end(&mut self, song: &Song, s_bwidth: BeatWidth) {
         self.beat = self.track(song).beats.len() - 1;
-        self.scroll_to_cursor(s_bwidth);
+        self.scroll_to_cursor(song, s_bwidth);
     }
 
-    pub fn seek_next_measure(&mut self, song: &Song, s_bwidth: usize) {
+    pub fn seek_next_measure(&mut self, song: &Song, s_bwidth: BeatWidth) {
         let l = &self.track(song).measure_i;
         let m = l.len() - 1;
         if self.beat < m {
@@ -113,10 +122,10 @@ impl Cursor {
                 self.beat += 1;
             }
         }
-        self.scroll_to_cursor(s_bwidth);
+        self.scroll_to_cursor(song, s_bwidth);
     }
 
-    pub fn seek_prev_measure(&mut self, song: &Song, s_bwidth: usize) {
+    pub fn seek_prev_measure(&mut self, song: &Song, s_bwidth: BeatWidth) {
         let l = &self.track(song).measure_i;
         if self.beat > 0 {
             self.beat -= 1;
@@ -124,32 +133,200 @@ impl Cursor {
                 self.beat -= 1;
             }
         }
-        self.scroll_to_cursor(s_bwidth);
+        self.scroll_to_cursor(song, s_bwidth);
+    }
+
+    pub fn measure(&self, song: &Song) -> usize {
+        self.track(song).measure_of(self.beat)
+    }
+
+    pub fn seek_measure(&mut self, song: &Song, measure: usize, s_bwidth: BeatWidth) -> bool {
+        if let Some(beat) = self.track(song).measure_start(measure) {
+            self.beat = beat;
+            self.scroll_to_cursor(song, s_bwidth);
+            true
+        } else {
+            false
+        }
     }
 
-    pub fn scroll_to_cursor(&mut self, s_bwidth: usize) {
-        if self.scroll > self.beat {
-            self.scroll = self.beat;
+    // Beats in view, fewer than `s_bwidth` when some of them are drawn wider
+    pub fn visible(&self, song: &Song, s_bwidth: BeatWidth) -> std::ops::Range<usize> {
+        let rows = self.view_rows(song, s_bwidth);
+        rows[0].start..rows.last().unwrap().end
+    }
+
+    // Beats of each row in view, from the row holding the scroll beat when wrapped
+    pub fn view_rows(&self, song: &Song, s_bwidth: BeatWidth) -> Vec<std::ops::Range<usize>> {
+        let beats = self.beats(song);
+        let Some(rows) = self.rows else {
+            return vec![draw::fit_range(beats, self.scroll, s_bwidth)];
+        };
+        let starts = draw::wrap_rows(self.track(song), s_bwidth);
+        let top = row_of(&starts, self.scroll);
+        (top..starts.len())
+            .take(rows)
+            .map(|r| starts[r]..starts.get(r + 1).copied().unwrap_or(beats.len()))
+            .collect()
+    }
+
+    pub fn scroll_to_cursor(&mut self, song: &Song, s_bwidth: BeatWidth) {
+        if let Some(rows) = self.rows {
+            // Wrapped views scroll by whole rows
+            let starts = draw::wrap_rows(self.track(song), s_bwidth);
+            let (top, row) = (row_of(&starts, self.scroll), row_of(&starts, self.beat));
+            self.scroll = starts[top.clamp((row + 1).saturating_sub(rows), row)];
+            return;
+        }
+        // Up to half the view is kept on either side, which centres the cursor
+        let beats = self.beats(song);
+        let half = self.visible(song, s_bwidth).len().saturating_sub(1) / 2;
+        let off = s_bwidth.scrolloff.min(half);
+        let (first, last) = (
+            self.beat.saturating_sub(off),
+            (self.beat + off).min(beats.len() - 1),
+        );
+        if self.scroll > first {
+            self.scroll = first;
+        }
+        if self.visible(song, s_bwidth).end <= last {
+            // The latest scroll that still shows the beats after the cursor
+            let mut scroll = last;
+            while scroll > self.scroll && draw::fit_range(beats, scroll - 1, s_bwidth).end > last {
+                scroll -= 1;
+            }
+            self.scroll = scroll;
         }
-        if self.scroll + s_bwidth - 1 < self.beat {
-            self.scroll = self.beat - (s_bwidth - 1);
+    }
+
+    // Scrolls to show `beat` first, or its row when wrapped
+    pub fn scroll_to(&mut self, song: &Song, beat: usize, s_bwidth: BeatWidth) {
+        self.scroll = match self.rows {
+            Some(_) => {
+                let starts = draw::wrap_rows(self.track(song), s_bwidth);
+                starts[row_of(&starts, beat)]
+            }
+            None => beat,
+        };
+    }
+
+    pub fn seek_scroll(&mut self, song: &Song, dire: isize, s_bwidth: BeatWidth) {
+        if self.rows.is_some() {
+            let starts = draw::wrap_rows(self.track(song), s_bwidth);
+            let top = row_of(&starts, self.scroll) as isize + dire;
+            self.scroll = starts[top.clamp(0, starts.len() as isize - 1) as usize];
+        } else {
+            let new = (self.scroll as isize + dire).max(0) as usize;
+            self.scroll = new.min(self.beats(song).len() - 1);
         }
+        self.cursor_to_scroll(song, s_bwidth);
     }
 
-    pub fn seek_scroll(&mut self, song: &Song, dire: isize, s_bwidth: usize) {
-        let new = (self.scroll as isize + dire).max(0) as usize;
-        self.scroll = new.min(self.beats(song).len() - 1);
-        self.cursor_to_scroll(s_bwidth);
+    // Moves to the beat drawn closest above or below the cursor in the next wrapped row,
+    // false when there is none
+    pub fn seek_row(&mut self, song: &Song, dire: isize, s_bwidth: BeatWidth) -> bool {
+        if self.rows.is_none() {
+            return false;
+        }
+        let beats = self.beats(song);
+        let starts = draw::wrap_rows(self.track(song), s_bwidth);
+        let row = row_of(&starts, self.beat) as isize + dire;
+        if row < 0 || row as usize >= starts.len() {
+            return false;
+        }
+        let width = |range: std::ops::Range<usize>| -> usize {
+            range
+                .map(|i| draw::cell_width(&beats[i], s_bwidth.cell) + 1)
+                .sum()
+        };
+        let col = width(starts[row_of(&starts, self.beat)]..self.beat);
+        let start = starts[row as usize];
+        let end = starts.get(row as usize + 1).copied().unwrap_or(beats.len());
+        self.beat = (start..end)
+            .take_while(|i| width(start..*i) <= col)
+            .last()
+            .unwrap_or(start);
+        self.scroll_to_cursor(song, s_bwidth);
+        true
+    }
+
+    pub fn cursor_to_scroll(&mut self, song: &Song, s_bwidth: BeatWidth) {
+        let view = self.visible(song, s_bwidth);
+        self.beat = self.beat.clamp(view.start, view.end - 1);
+    }
+
+    // Beats starting on a multiple of `grid` from the start of their measure
+    fn grid_beats(&self, song: &Song, grid: Duration) -> Vec<bool> {
+        let track = self.track(song);
+        let mut offset = Duration::zero();
+        let mut out = Vec::with_capacity(track.beats.len());
+        for (beat, start) in track.beats.iter().zip(&track.measure_i) {
+            if *start {
+                offset = Duration::zero();
+            }
+            let (a, b) = (
+                offset.0 as u64 * grid.1 as u64,
+                offset.1 as u64 * grid.0 as u64,
+            );
+            out.push(a % b == 0);
+            offset = offset + beat.dur;
+        }
+        out
+    }
+
+    // Like seek_beat, skipping beats off the grid, and extending the track by a beat once none are left
+    pub fn seek_beat_snapped(
+        &mut self,
+        song: &mut Song,
+        dire: isize,
+        grid: Duration,
+        s_bwidth: BeatWidth,
+    ) {
+        let on_grid = self.grid_beats(song, grid);
+        let mut beat = self.beat;
+        for _ in 0..dire.unsigned_abs() {
+            let next = if dire > 0 {
+                (beat + 1..on_grid.len()).find(|i| on_grid[*i])
+            } else {
+                (0..beat).rev().find(|i| on_grid[*i])
+            };
+            match next {
+                Some(next) => beat = next,
+                None => break,
+            }
+        }
+        if beat == self.beat && dire > 0 {
+            self.beat = on_grid.len() - 1;
+            self.seek_beat(song, 1, s_bwidth);
+        } else {
+            self.beat = beat;
+            self.scroll_to_cursor(song, s_bwidth);
+        }
     }
 
-    pub fn cursor_to_scroll(&mut self, s_bwidth: usize) {
-        self.beat = self.beat.clamp(self.scroll, self.scroll + s_bwidth - 1);
+    // Like seek_scroll, then moving the cursor onto the closest beat on the grid in view
+    pub fn seek_scroll_snapped(
+        &mut self,
+        song: &Song,
+        dire: isize,
+        grid: Duration,
+        s_bwidth: BeatWidth,
+    ) {
+        self.seek_scroll(song, dire, s_bwidth);
+        let on_grid = self.grid_beats(song, grid);
+        if let Some(beat) = self
+            .visible(song, s_bwidth)
+            .filter(|i| on_grid[*i])
+            .min_by_key(|i| i.abs_diff(self.beat))
+        {
+            self.beat = beat;
+        }
     }
 
     // -- Cursor syncronisation
 
-    pub fn calc_duration(&self, song: &Song) -> Duration {
-        let mut sum = Duration::new(0, 1);
+    pub fn calc_offset(&self, song: &Song) -> Offset {
+        let mut sum = Offset::zero();
         for (i, beat) in self.beats(song).iter().enumerate() {
             if i == self.beat {
                 return sum;
@@ -159,13 +336,13 @@ impl Cursor {
         sum
     }
 
-    pub fn transfer_seek(&mut self, dur: Duration, song: &Song, s_bwidth: usize) {
-        let mut sum = Duration::new(0, 1);
+    pub fn transfer_seek(&mut self, offset: Offset, song: &Song, s_bwidth: BeatWidth) {
+        let mut sum = Offset::zero();
         for (i, beat) in self.beats(song).iter().enumerate() {
             sum = sum + beat.dur;
-            if sum > dur {
+            if sum > offset {
                 self.beat = i;
-                self.scroll_to_cursor(s_bwidth);
+                self.scroll_to_cursor(song, s_bwidth);
                 return;
             }
         }
@@ -175,19 +352,47 @@ impl Cursor {
 
     pub fn set_duration(&self, song: &mut Song, dur: Duration) {
         self.beat_mut(song).dur = dur;
-        self.track_mut(song).update_measures();
+        song.update_measures(self.track);
     }
 
-    pub fn set_note(&self, song: &mut Song, note: Note) {
-        self.beat_mut(song).set_note(self.string, note);
+    pub fn set_note(&self, song: &mut Song, entry: NoteEntry) {
+        self.beat_mut(song).set_entry(self.string, entry);
     }
 
     pub fn set_notes(&self, song: &mut Song, notes: Vec<(u16, Note)>) {
         self.beat_mut(song).notes = notes;
     }
 
+    pub fn set_finger(&self, song: &mut Song, finger: Option<Finger>) {
+        self.beat_mut(song).set_finger(self.string, finger);
+    }
+
+    pub fn set_color(&self, song: &mut Song, color: Option<Color>) {
+        self.beat_mut(song).set_color(self.string, color);
+    }
+
+    pub fn set_stroke(&self, song: &mut Song, stroke: Option<Stroke>) {
+        self.beat_mut(song).stroke = stroke;
+    }
+
+    pub fn set_velocity(&self, song: &mut Song, velocity: Option<u8>) {
+        self.beat_mut(song).velocity = velocity;
+    }
+
+    pub fn set_text(&self, song: &mut Song, text: Option<String>) {
+        self.beat_mut(song).text = text;
+    }
+
+    pub fn set_arpeggio(&self, song: &mut Song, arpeggio: Option<Arpeggio>) {
+        self.beat_mut(song).arpeggio = arpeggio;
+    }
+
+    pub fn set_whammy(&self, song: &mut Song, whammy: Option<Whammy>) {
+        self.beat_mut(song).whammy = whammy;
+    }
+
     pub fn clear_note(&self, song: &mut Song) {
-        self.beat_mut(song).del_note(self.string);
+        self.beat_mut(song).del_entry(self.string);
     }
 
     pub fn clear_beat(&self, song: &mut Song) {
@@ -202,17 +407,17 @@ impl Cursor {
 
     pub fn delete_beat(&self, song: &mut Song) {
         self.beats_mut(song).remove(self.beat);
-        self.track_mut(song).update_measures();
+        song.update_measures(self.track);
     }
 
     pub fn delete_beats(&self, song: &mut Song, count: usize) {
         self.beats_mut(song)
             .splice(self.beat..self.beat + count, []);
-        self.track_mut(song).update_measures();
+        song.update_measures(self.track);
     }
 
     pub fn copy_note(&self, song: &Song) -> Buffer {
-        if let Some(note) = self.beat(song).copy_note(self.string) {
+        if let Some(note) = self.beat(song).copy_entry(self.string) {
             Buffer::Note(note)
         } else {
             Buffer::Empty
@@ -237,7 +442,7 @@ impl Cursor {
         } else {
             self.beats_mut(song).insert(self.beat, beat);
         }
-        self.track_mut(song).update_measures();
+        song.update_measures(self.track);
     }
 
     pub fn insert_beats(&self, song: &mut Song, in_place: bool, src: Vec<Beat>) {
@@ -248,13 +453,13 @@ impl Cursor {
         let after = dest.split_off(self.beat);
         dest.extend(src);
         dest.extend(after);
-        self.track_mut(song).update_measures();
+        song.update_measures(self.track);
     }
 
     pub fn replace_beats(&self, song: &mut Song, src: Vec<Beat>) {
         self.beats_mut(song)
             .splice(self.beat..self.beat + src.len(), src);
-        self.track_mut(song).update_measures();
+        song.update_measures(self.track);
     }
 
     pub fn append_beat(&self, song: &mut Song, beat: Beat) {
@@ -1,84 +1,1388 @@
 use crate::{
+    config::{Glyphs, Theme},
     cursor::Cursor,
+    dur::Duration,
     error::Result,
-    song::{Note, Song},
+    messages::{Level, Messages},
+    pitch,
+    song::{Beat, Color, Note, Song, Track},
     window,
 };
-use crossterm::style::Stylize;
+use crossterm::style::{Color as Term, StyledContent, Stylize};
+
+#[derive(Default)]
+pub struct DrawOpts {
+    pub fingering: bool,
+    pub todos: bool,
+    pub dynamics: bool,
+    pub wrap: bool,
+    // Note names in place of fret numbers
+    pub pitches: bool,
+    pub staff: bool,
+    pub minimap: bool,
+    // Notes coloured by how they are played
+    pub techniques: bool,
+    pub ruler: bool,
+    // Durations as stems, beams and heads in place of numbers
+    pub rhythm: bool,
+    pub theme: Theme,
+    pub glyphs: Glyphs,
+}
+
+// Screen row of a lane's dynamics bars and the columns covered by each beat
+pub struct DynRow {
+    pub row: u16,
+    pub cells: Vec<(std::ops::Range<u16>, usize)>,
+}
+
+// Columns of the mute and solo toggles at the start of a lane's header
+pub const MUTE_COLS: std::ops::Range<u16> = 0..3;
+pub const SOLO_COLS: std::ops::Range<u16> = 4..7;
+
+// Staff positions drawn, from the space over the top line (G5 as written) down to the space
+// under the bottom line (D4), counted in steps of the C major scale from C-1
+const STAFF_TOP: usize = 46;
+pub const STAFF_ROWS: usize = 11;
+
+const BARS: [char; 8] = ['▁', '▂', '▃', '▄', '▅', '▆', '▇', '█'];
+
+fn velocity_bar(velocity: u8) -> char {
+    BARS[(velocity as usize * BARS.len() / 128).min(BARS.len() - 1)]
+}
+
+pub fn draw_todos(win: &mut window::Window, song: &Song) -> Result<()> {
+    if song.todos.is_empty() {
+        win.print("TODO : (empty)")?.next_line()?;
+    }
+    for (i, todo) in song.todos.iter().enumerate() {
+        let line = format!(
+            "{: >2}. [{}] m{: <4} {}",
+            i + 1,
+            if todo.done { "x" } else { " " },
+            todo.measure + 1,
+            todo.text
+        );
+        if todo.done {
+            win.print_styled(line.dark_grey())?;
+        } else {
+            win.print(line)?;
+        }
+        win.next_line()?;
+    }
+    win.next_line()?;
+    Ok(())
+}
+
+const SIDEBAR_WIDTH: u16 = 26;
+
+// The whole song on one line, a block per measure (or per run of measures when they do not fit),
+// filled where any track plays, with annotations of the cursor's track over the measures they are in;
+// the measures in view are shaded and the one at cursor lit
+pub fn draw_minimap(
+    win: &mut window::Window,
+    song: &Song,
+    cur: &Cursor,
+    view: std::ops::Range<usize>,
+    width: usize,
+) -> Result<()> {
+    let count = song.last_measure() + 1;
+    let per = count.div_ceil(width.max(1));
+    let mut playing = vec![false; count];
+    for t in song.visible_tracks() {
+        let track = &song.tracks[t];
+        let mut measure = 0;
+        for (i, beat) in track.beats.iter().enumerate() {
+            if track.measure_i[i] && i > 0 {
+                measure += 1;
+            }
+            playing[measure] |= !beat.notes.is_empty();
+        }
+    }
+    let mut cells: Vec<char> = playing
+        .chunks(per)
+        .map(|c| if c.iter().any(|p| *p) { '█' } else { '░' })
+        .collect();
+    let track = cur.track(song);
+    let mut free = 0;
+    for (i, beat) in track.beats.iter().enumerate() {
+        let Some(text) = &beat.text else {
+            continue;
+        };
+        let start = (track.measure_of(i) / per).max(free);
+        for (k, c) in text.chars().enumerate() {
+            match cells.get_mut(start + k) {
+                Some(cell) => *cell = c,
+                None => break,
+            }
+        }
+        free = start + text.chars().count() + 1;
+    }
+    let at = cur.measure(song) / per;
+    let shown = track.measure_of(view.start) / per..=track.measure_of(view.end - 1) / per;
+    for (i, c) in cells.into_iter().enumerate() {
+        if i == at {
+            win.print_styled(c.yellow().on_dark_grey())?;
+        } else if shown.contains(&i) {
+            win.print_styled(c.grey().on_dark_grey())?;
+        } else {
+            win.print_styled(c.dark_cyan())?;
+        }
+    }
+    win.next_line()?;
+    Ok(())
+}
+
+// Track sidebar along the right edge, over whatever the lanes drew there
+// `>` marks the picked track, `*` the tracks shown in lanes and `-` those of collapsed groups
+pub fn draw_tracks(
+    win: &mut window::Window,
+    song: &Song,
+    s_width: u16,
+    selected: usize,
+    lanes: &[usize],
+) -> Result<()> {
+    let x = s_width.saturating_sub(SIDEBAR_WIDTH);
+    let width = (s_width - x) as usize - 2;
+    win.moveto(x, 0)?
+        .print(format!("│{: <width$}", " Tracks"))?;
+    for (i, track) in song.tracks.iter().enumerate() {
+        let pick = if i == selected { '>' } else { ' ' };
+        let lane = if lanes.contains(&i) {
+            '*'
+        } else if song.is_collapsed(i) {
+            '-'
+        } else {
+            ' '
+        };
+        let mut line = format!(
+            "{pick}{lane}{: >2} {}{} strings{}",
+            i + 1,
+            track
+                .name
+                .as_ref()
+                .map_or(String::new(), |n| format!("{n}, ")),
+            track.string_count,
+            if track.archived { " (archived)" } else { "" }
+        );
+        if let Some(group) = &track.group {
+            line.push_str(&format!(" [{group}]"));
+        }
+        let line: String = format!("{line: <width$}").chars().take(width).collect();
+        win.moveto(x, i as u16 + 1)?.print("│")?;
+        if track.archived {
+            win.print_styled(line.dark_grey())?;
+        } else {
+            win.print(line)?;
+        }
+    }
+    let row = song.tracks.len() as u16 + 1;
+    win.moveto(x, row)?
+        .print(format!("│{: <width$}", " w/s pick, x archive"))?;
+    win.moveto(x, row + 1)?
+        .print(format!("│{: <width$}", " g collapse group"))?;
+    Ok(())
+}
+
+// Timeline sidebar along the right edge, a ruler of the measures around the picked event
+// marking changes of time signature (S), tempo (T) or both (*), over a list of the events
+pub fn draw_timeline(
+    win: &mut window::Window,
+    song: &Song,
+    s_width: u16,
+    picked: usize,
+) -> Result<()> {
+    let x = s_width.saturating_sub(SIDEBAR_WIDTH);
+    let width = (s_width - x) as usize - 2;
+    win.moveto(x, 0)?
+        .print(format!("│{: <width$}", " Timeline"))?;
+    let last = song
+        .changes
+        .iter()
+        .map(|c| c.measure)
+        .fold(song.last_measure(), usize::max);
+    let start = picked
+        .saturating_sub(width / 2)
+        .min((last + 2).saturating_sub(width));
+    let ruler: String = (start..(start + width - 1).min(last + 1))
+        .map(|m| match song.change_at(m) {
+            _ if m == 0 => '|',
+            Some(c) if c.time_sig.is_some() && c.tempo.is_some() => '*',
+            Some(c) if c.time_sig.is_some() => 'S',
+            Some(_) => 'T',
+            None => '·',
+        })
+        .collect();
+    let caret = format!("{: >1$}", '^', picked - start + 1);
+    win.moveto(x, 1)?
+        .print(format!("│ {: <1$}", ruler, width - 1))?;
+    win.moveto(x, 2)?
+        .print(format!("│ {: <1$}", caret, width - 1))?;
+    let first = [(0, Some(song.time_sig), Some(song.tempo()))];
+    let events = song
+        .changes
+        .iter()
+        .map(|c| (c.measure, c.time_sig, c.tempo));
+    let mut row = 3;
+    for (measure, sig, tempo) in first.into_iter().chain(events) {
+        let line = format!(
+            "{}m{: <4}{: >6}{: >9}",
+            if measure == picked { '>' } else { ' ' },
+            measure + 1,
+            sig.map(|s| s.to_string()).unwrap_or_default(),
+            tempo.map(|t| format!("{t} bpm")).unwrap_or_default()
+        );
+        win.moveto(x, row)?.print(format!("│{line: <width$}"))?;
+        row += 1;
+    }
+    for help in [
+        " w/s pick, a/d move",
+        " n new at cursor, x del",
+        " type 3/4 or 90, Enter",
+    ] {
+        win.moveto(x, row)?.print(format!("│{help: <width$}"))?;
+        row += 1;
+    }
+    Ok(())
+}
+
+// Chord of a beat on the neck along the right edge, lowest string on the left: x over strings
+// left unplayed, o over open ones, and the finger on each fretted note where one is marked;
+// frets are counted from the capo, with the nut drawn when the chord starts at the first fret
+pub fn draw_diagram(
+    win: &mut window::Window,
+    track: &Track,
+    beat: &Beat,
+    name: Option<&str>,
+    s_width: u16,
+) -> Result<()> {
+    let x = s_width.saturating_sub(SIDEBAR_WIDTH);
+    let width = (s_width - x) as usize - 2;
+    let strings: Vec<u16> = (0..track.string_count).rev().collect();
+    let fret = |s: u16| match beat.get_note(s) {
+        Some(Note::Fret(f)) => Some(f.saturating_sub(track.capo)),
+        _ => None,
+    };
+    let fretted: Vec<u16> = strings
+        .iter()
+        .filter_map(|s| fret(*s))
+        .filter(|f| *f > 0)
+        .collect();
+    let high = fretted.iter().copied().max().unwrap_or(0);
+    let base = if high <= 4 {
+        1
+    } else {
+        fretted.iter().copied().min().unwrap_or(1)
+    };
+    let rows = (high.saturating_sub(base) + 1).clamp(4, 12);
+    let mut lines = vec![format!(" {}", name.unwrap_or("Chord"))];
+    if track.capo > 0 {
+        lines[0].push_str(&format!(" (capo {})", track.capo));
+    }
+    let tuning = track.tuning();
+    lines.push(
+        strings
+            .iter()
+            .map(|s| {
+                format!(
+                    " {}",
+                    pitch::class_name(tuning[*s as usize])
+                        .chars()
+                        .next()
+                        .unwrap_or(' ')
+                )
+            })
+            .collect(),
+    );
+    lines.push(
+        strings
+            .iter()
+            .map(|s| match fret(*s) {
+                Some(0) => " o",
+                Some(_) => "  ",
+                None => " x",
+            })
+            .collect(),
+    );
+    lines.push(if base == 1 {
+        format!(" {}", "═".repeat(strings.len() * 2 - 1))
+    } else {
+        String::new()
+    });
+    for f in base..base + rows {
+        let mut line: String = strings
+            .iter()
+            .map(|s| match fret(*s) {
+                Some(n) if n == f => {
+                    let finger = beat.get_finger(*s).map_or('●', |f| f.glyph());
+                    format!(" {finger}")
+                }
+                _ => " │".into(),
+            })
+            .collect();
+        if f == base && base > 1 || f % 12 == 0 {
+            line.push_str(&format!("  {f}fr"));
+        }
+        lines.push(line);
+    }
+    lines.push(" (Enter) or (Esc) close".into());
+    for (row, line) in lines.iter().enumerate() {
+        let line: String = line.chars().take(width).collect();
+        win.moveto(x, row as u16)?
+            .print(format!("│{line: <width$}"))?;
+    }
+    Ok(())
+}
+
+// Recent files over the whole width, long paths keep their end where the file name is
+pub fn draw_recent(
+    win: &mut window::Window,
+    paths: &[String],
+    s_width: u16,
+    selected: usize,
+) -> Result<()> {
+    let width = s_width as usize;
+    win.moveto(0, 0)?
+        .print(format!("{: <width$}", " Recent files"))?;
+    let mut row = 1;
+    for (i, path) in paths.iter().enumerate() {
+        let missing = !std::path::Path::new(path).exists();
+        let mut line = format!(
+            "{}{: >2}. {path}{}",
+            if i == selected { '>' } else { ' ' },
+            i + 1,
+            if missing { " (missing)" } else { "" }
+        );
+        let len = line.chars().count();
+        if len > width {
+            line = format!(
+                "…{}",
+                line.chars().skip(len - width + 1).collect::<String>()
+            );
+        }
+        win.moveto(0, row)?;
+        if missing {
+            win.print_styled(format!("{line: <width$}").dark_grey())?;
+        } else {
+            win.print(format!("{line: <width$}"))?;
+        }
+        row += 1;
+    }
+    win.moveto(0, row)?.print(format!(
+        "{: <width$}",
+        " w/s pick, Enter open, x forget, Esc new song"
+    ))?;
+    Ok(())
+}
+
+// Messages over the whole width, newest first from `scroll`, each with its age and level
+pub fn draw_log(
+    win: &mut window::Window,
+    messages: &Messages,
+    (s_width, s_height): (u16, u16),
+    scroll: usize,
+) -> Result<()> {
+    let width = s_width as usize;
+    win.moveto(0, 0)?
+        .print(format!("{: <width$}", " Messages"))?;
+    let rows = s_height.saturating_sub(3);
+    for (row, msg) in (1..=rows).zip(messages.log().skip(scroll)) {
+        let age = msg.at.elapsed().as_secs();
+        let age = match age {
+            0..=59 => format!("{age}s"),
+            60..=3599 => format!("{}m", age / 60),
+            _ => format!("{}h", age / 3600),
+        };
+        let line: String = format!(" {age: >4} {: <5} {}", msg.level.name(), msg.text)
+            .chars()
+            .take(width)
+            .collect();
+        let line = format!("{line: <width$}");
+        win.moveto(0, row)?.print_styled(match msg.level {
+            Level::Info => line.stylize(),
+            Level::Warn => line.yellow(),
+            Level::Error => line.red(),
+        })?;
+    }
+    let shown = messages.log().skip(scroll).count().min(rows as usize);
+    win.moveto(0, shown as u16 + 1)?
+        .print(format!("{: <width$}", " w/s scroll, Enter or Esc close"))?;
+    Ok(())
+}
+
+// Columns of a beat cell when not zoomed
+pub const CELL_WIDTH: usize = 3;
+
+// Beats that fit across the screen in cells `cell` columns wide, the width :set zoom changes,
+// and the beats kept in view on either side of the cursor when scrolling, as :set scrolloff
+#[derive(Clone, Copy)]
+pub struct BeatWidth {
+    pub beats: usize,
+    pub cell: usize,
+    pub scrolloff: usize,
+}
+
+impl BeatWidth {
+    // Four columns are kept for the margin, and each cell is followed by a separator
+    pub fn fit(&mut self, s_width: u16) {
+        self.beats = (s_width as usize).saturating_sub(4) / (self.cell + 1);
+    }
+}
+
+// Beats holding frets of three or more digits get wider cells so they stay apart
+pub fn cell_width(beat: &Beat, cell: usize) -> usize {
+    beat.notes
+        .iter()
+        .map(|(_, note)| match note {
+            Note::Fret(fret) if *fret >= 100 => (fret.to_string().len() + 2).max(cell),
+            _ => cell,
+        })
+        .max()
+        .unwrap_or(cell)
+}
+
+fn term_color(color: Color) -> Term {
+    match color {
+        Color::Red => Term::Red,
+        Color::Green => Term::Green,
+        Color::Yellow => Term::Yellow,
+        Color::Blue => Term::Blue,
+        Color::Magenta => Term::Magenta,
+        Color::Cyan => Term::Cyan,
+    }
+}
+
+// Dead notes dimmed, and the first of tapped, slapped or popped, trilled, swelled or under a whammy
+// in a colour of its own, plain where colours set on notes are bold
+fn technique_color(beat: &Beat, string: u16) -> Option<Term> {
+    if let Some(Note::X) = beat.get_note(string) {
+        return Some(Term::DarkGrey);
+    }
+    let marks = beat.get_marks(string);
+    if marks.is_some_and(|m| m.tap) {
+        Some(Term::Magenta)
+    } else if marks.is_some_and(|m| m.pluck.is_some()) {
+        Some(Term::Yellow)
+    } else if marks.is_some_and(|m| m.trill.is_some()) {
+        Some(Term::Cyan)
+    } else if marks.is_some_and(|m| m.swell) {
+        Some(Term::Green)
+    } else if beat.whammy.is_some() && beat.get_note(string).is_some() {
+        Some(Term::Blue)
+    } else {
+        None
+    }
+}
+
+// Backgrounds of the cursor and of highlighted beats. Themes other than the default also mark
+// the cursor and selection with bold and underline, so they can be told apart without
+// telling shades apart; the colour blind one keeps to blues and oranges
+struct Palette {
+    // Note at the cursor, the rest of its beat, and the cursor of the lanes not in use
+    cursor: Term,
+    cursor_beat: Term,
+    cursor_idle: Term,
+    play: Term,
+    select: Term,
+    // Measure at the cursor
+    shade: Term,
+    // Durations of measures that do not add up
+    wrong: Term,
+    marked: bool,
+}
+
+fn palette(theme: Theme) -> Palette {
+    match theme {
+        Theme::Default => Palette {
+            cursor: Term::White,
+            cursor_beat: Term::Grey,
+            cursor_idle: Term::DarkGrey,
+            play: Term::DarkGreen,
+            select: Term::DarkBlue,
+            shade: Term::AnsiValue(236),
+            wrong: Term::Red,
+            marked: false,
+        },
+        Theme::Contrast => Palette {
+            cursor: Term::White,
+            cursor_beat: Term::Yellow,
+            cursor_idle: Term::Grey,
+            play: Term::Green,
+            select: Term::Cyan,
+            shade: Term::AnsiValue(239),
+            wrong: Term::Magenta,
+            marked: true,
+        },
+        Theme::Colorblind => Palette {
+            cursor: Term::White,
+            cursor_beat: Term::AnsiValue(214),
+            cursor_idle: Term::Grey,
+            play: Term::AnsiValue(117),
+            select: Term::AnsiValue(25),
+            shade: Term::AnsiValue(238),
+            wrong: Term::AnsiValue(208),
+            marked: true,
+        },
+    }
+}
+
+fn shade_if<D: std::fmt::Display>(
+    content: StyledContent<D>,
+    shade: Option<Term>,
+) -> StyledContent<D> {
+    match shade {
+        Some(color) => content.on(color),
+        None => content,
+    }
+}
+
+// Frets are shown as the names of the notes they sound when given the track's tuning
+fn note_cell(
+    beat: &Beat,
+    string: u16,
+    width: usize,
+    glyphs: &Glyphs,
+    tuning: Option<&[u8]>,
+) -> String {
+    match beat.get_note(string) {
+        // Frets too high to name a pitch are shown as they are
+        Some(Note::Fret(fret)) => match tuning
+            .and_then(|tuning| tuning.get(string as usize))
+            .and_then(|open| fret.checked_add(*open as u16))
+        {
+            Some(pitch) => format!("{: ^width$}", pitch::class_name(pitch.min(127) as u8)),
+            None => format!("{: ^width$}", fret),
+        },
+        Some(Note::X) => format!("{: ^width$}", glyphs.dead),
+        None => glyphs.empty.to_string().repeat(width),
+    }
+}
+
+// Lines before the beats of `range` and the one closing it: barlines at measure starts, doubled
+// where the time signature changes, and the end of the track drawn as the final bar
+fn lines_of(
+    song: &Song,
+    track: &Track,
+    range: std::ops::Range<usize>,
+    glyphs: &Glyphs,
+) -> Vec<char> {
+    let mut measure = track.measure_of(range.start);
+    let mut lines: Vec<char> = range
+        .clone()
+        .map(|i| {
+            if !track.measure_i[i] {
+                return glyphs.separator;
+            }
+            if i > range.start {
+                measure += 1;
+            }
+            match song.change_at(measure) {
+                Some(change) if change.time_sig.is_some() => glyphs.double,
+                _ => glyphs.barline,
+            }
+        })
+        .collect();
+    lines.push(if range.end == track.beats.len() {
+        glyphs.end
+    } else {
+        glyphs.separator
+    });
+    lines
+}
+
+// Icons are three columns, cells narrower than that lose the dot or triplet mark
+fn dur_cell(dur: Duration, width: usize) -> String {
+    let icon = dur.dur_icon();
+    if width < icon.chars().count() {
+        centred(icon.trim(), width)
+    } else {
+        centred(icon, width)
+    }
+}
+
+// Beams over a note value, a dashed bracket for those without any
+fn beam_glyph(value: u16) -> char {
+    match value {
+        0..=4 => '╌',
+        8 => '─',
+        _ => '━',
+    }
+}
+
+// Durations of the beats in `range` written as notes, each as its separator and cell on the
+// stem row and on the head row: beamed beats have their stems joined, triplets are bracketed
+// in threes of the same duration with the 3 on the middle stem, and others are flagged
+fn rhythm_cells(track: &Track, range: std::ops::Range<usize>, cell: usize) -> Vec<[String; 2]> {
+    let notes: Vec<_> = track.beats.iter().map(|b| b.dur.notation()).collect();
+    // Place of each beat in its group of three triplets, for those in a whole group
+    let mut triplet = vec![None; notes.len()];
+    let mut run = 0;
+    for i in 0..notes.len() {
+        let is_triplet = matches!(notes[i], Some((_, _, true)));
+        if is_triplet && i > 0 && run > 0 && track.beats[i].dur == track.beats[i - 1].dur {
+            run += 1;
+        } else {
+            run = is_triplet as usize;
+        }
+        if run % 3 == 0 && run > 0 {
+            for (k, t) in triplet[i - 2..=i].iter_mut().enumerate() {
+                *t = Some(k);
+            }
+        }
+    }
+    let joined = |i: usize| match triplet[i] {
+        Some(k) => k > 0,
+        None => track.beam_i[i] && triplet[i - 1].is_none(),
+    };
+    let value = |i: usize| notes[i].map_or(4, |n| n.0);
+    range
+        .map(|i| {
+            let w = cell_width(&track.beats[i], cell);
+            let head = (w - 1) / 2;
+            let prev = (i > 0 && joined(i)).then(|| beam_glyph(value(i).min(value(i - 1))));
+            let next = (i + 1 < notes.len() && joined(i + 1))
+                .then(|| beam_glyph(value(i).min(value(i + 1))));
+            let stem = match (triplet[i], prev, next) {
+                (Some(1), _, _) => '3',
+                (_, Some(_), Some(_)) => '┬',
+                (_, None, Some(_)) => '┌',
+                (_, Some(_), None) => '┐',
+                _ => match value(i) {
+                    1 => ' ',
+                    2 | 4 => '│',
+                    8 => '╮',
+                    _ => '╗',
+                },
+            };
+            let mut stems = String::from(prev.unwrap_or(' '));
+            stems.extend((0..w).map(|c| match c.cmp(&head) {
+                std::cmp::Ordering::Less => prev.unwrap_or(' '),
+                std::cmp::Ordering::Equal => stem,
+                std::cmp::Ordering::Greater => next.unwrap_or(' '),
+            }));
+            let mut heads = vec![' '; w + 1];
+            heads[head + 1] = match notes[i] {
+                Some((v, _, _)) if v <= 2 => '○',
+                Some(_) => '●',
+                None => '?',
+            };
+            if matches!(notes[i], Some((_, true, _))) && head + 1 < w {
+                heads[head + 2] = '•';
+            }
+            [stems, heads.into_iter().collect()]
+        })
+        .collect()
+}
+
+// Centred in `width` columns, cutting off what does not fit
+fn centred(text: &str, width: usize) -> String {
+    let text: String = text.chars().take(width).collect();
+    format!("{: ^width$}", text)
+}
+
+// As many beats from `start` as fit in `cols` columns, the beat at `i` taking `width(i)` of them
+pub fn fit_columns(
+    len: usize,
+    start: usize,
+    cols: usize,
+    width: impl Fn(usize) -> usize,
+) -> std::ops::Range<usize> {
+    let mut used = 0;
+    let mut end = start;
+    while end < len {
+        used += width(end);
+        if used > cols && end > start {
+            break;
+        }
+        end += 1;
+    }
+    start..end
+}
+
+// As many beats from `start` as fit in the columns of `s_bwidth` standard cells
+pub fn fit_range(beats: &[Beat], start: usize, s_bwidth: BeatWidth) -> std::ops::Range<usize> {
+    let cols = s_bwidth.beats * (s_bwidth.cell + 1);
+    fit_columns(beats.len(), start, cols, |i| {
+        cell_width(&beats[i], s_bwidth.cell) + 1
+    })
+}
+
+// Starts of the rows a track wraps into, each holding as many whole measures as fit in the
+// columns of `s_bwidth` standard cells, with measures too long for a row split over several
+pub fn wrap_rows(track: &Track, s_bwidth: BeatWidth) -> Vec<usize> {
+    let mut starts = vec![0];
+    loop {
+        let start = *starts.last().unwrap();
+        let row = fit_range(&track.beats, start, s_bwidth);
+        if row.end >= track.beats.len() {
+            return starts;
+        }
+        let next = (start + 1..=row.end)
+            .rev()
+            .find(|i| track.measure_i[*i])
+            .unwrap_or(row.end);
+        starts.push(next);
+    }
+}
 
 pub struct Lane {
     pub cur: Cursor,
+    // Beat being played, while playback runs
+    pub play: Option<usize>,
+    // Beats picked for the next copy, clear or delete
+    pub sel: Option<std::ops::Range<usize>>,
+    // A count is being typed for b: mode, which takes beats from the cursor
+    pub counting: bool,
+    // Measure at the cursor of the current lane, shaded in every lane to line the tracks up
+    pub shade: Option<usize>,
+    // Lane of the split view, which scrolls apart from the others
+    pub split: bool,
 }
 
 impl Lane {
-    pub fn new() -> Self {
-        Self { cur: Cursor::new() }
-    }
-
     pub fn new_t(track: usize) -> Self {
         let mut cur = Cursor::new();
         cur.track = track;
-        Self { cur }
+        Self {
+            cur,
+            play: None,
+            sel: None,
+            counting: false,
+            shade: None,
+            split: false,
+        }
     }
 
-    fn draw_durations(
+    // Track number with its mute and solo toggles, greyed out when it is not played
+    fn draw_header(&self, win: &mut window::Window, song: &Song, is_curr: bool) -> Result<()> {
+        let t = self.cur.track;
+        let track = &song.tracks[t];
+        win.print_styled(if track.muted {
+            "[M]".on_red().black()
+        } else {
+            "[M]".dark_grey()
+        })?
+        .print(" ")?
+        .print_styled(if track.solo {
+            "[S]".on_yellow().black()
+        } else {
+            "[S]".dark_grey()
+        })?;
+        let mut title = format!("Track {}", t + 1);
+        if let Some(name) = &track.name {
+            title.push_str(&format!(" {name}"));
+        }
+        // String letters from the lowest string up
+        let strings: String = track
+            .tuning()
+            .iter()
+            .rev()
+            .map(|p| pitch::class_name(*p))
+            .collect();
+        title.push_str(&format!(" {strings}"));
+        if track.capo > 0 {
+            title.push_str(&format!(" capo {}", track.capo));
+        }
+        if let Some(group) = &track.group {
+            title.push_str(&format!(" [{group}]"));
+        }
+        win.print(" ")?;
+        let title = if song.is_audible(t) {
+            title.stylize()
+        } else {
+            title.dark_grey()
+        };
+        if is_curr {
+            win.print_styled(title.bold().reverse())?;
+        } else {
+            win.print_styled(title)?;
+        }
+        win.next_line()?;
+        Ok(())
+    }
+
+    // Number of each measure over its barline, and of the one in progress at the start of the row,
+    // left out where the number before it runs on
+    fn draw_measures(
+        &self,
+        win: &mut window::Window,
+        range: std::ops::Range<usize>,
+        song: &Song,
+        cell: usize,
+    ) -> Result<()> {
+        let track = self.cur.track(song);
+        let mut measure = track.measure_of(range.start);
+        let mut line = String::new();
+        let mut col = 0;
+        for i in range.clone() {
+            if track.measure_i[i] && i > range.start {
+                measure += 1;
+            }
+            let len = line.chars().count();
+            if (track.measure_i[i] || i == range.start) && len <= col {
+                line.extend(std::iter::repeat_n(' ', col - len));
+                line.push_str(&(measure + 1).to_string());
+            }
+            col += cell_width(&track.beats[i], cell) + 1;
+        }
+        win.print_styled(line.dark_grey())?.next_line()?;
+        Ok(())
+    }
+
+    // Notes on a treble staff, written an octave above their sound as for guitar, sharps marked
+    // before the head; notes outside the staff are drawn octaves in, with an arrow pointing
+    // to where they belong
+    fn draw_staff(
+        &self,
+        win: &mut window::Window,
+        range: std::ops::Range<usize>,
+        song: &Song,
+        cell: usize,
+    ) -> Result<()> {
+        const STEPS: [(usize, bool); 12] = [
+            (0, false),
+            (0, true),
+            (1, false),
+            (1, true),
+            (2, false),
+            (3, false),
+            (3, true),
+            (4, false),
+            (4, true),
+            (5, false),
+            (5, true),
+            (6, false),
+        ];
+        let track = self.cur.track(song);
+        let tuning = track.tuning();
+        // Row, sharp and octaves moved in by of every note of each beat
+        let heads: Vec<Vec<(usize, bool, isize)>> = range
+            .clone()
+            .map(|i| {
+                track.beats[i]
+                    .notes
+                    .iter()
+                    .filter_map(|(s, note)| match note {
+                        Note::Fret(fret) => tuning
+                            .get(*s as usize)
+                            .and_then(|open| fret.checked_add(*open as u16 + 12))
+                            .map(usize::from),
+                        Note::X => None,
+                    })
+                    .map(|pitch| {
+                        let (step, sharp) = STEPS[pitch % 12];
+                        let mut pos = pitch / 12 * 7 + step;
+                        let mut moved = 0;
+                        while pos > STAFF_TOP {
+                            pos -= 7;
+                            moved += 1;
+                        }
+                        while pos + STAFF_ROWS <= STAFF_TOP {
+                            pos += 7;
+                            moved -= 1;
+                        }
+                        (STAFF_TOP - pos, sharp, moved)
+                    })
+                    .collect()
+            })
+            .collect();
+        for row in 0..STAFF_ROWS {
+            let on_line = row % 2 == 1;
+            let fill = if on_line { '─' } else { ' ' };
+            for (k, i) in range.clone().enumerate() {
+                let w = cell_width(&track.beats[i], cell);
+                let barline = track.measure_i[i] && (1..STAFF_ROWS - 1).contains(&row);
+                let mut cell: Vec<char> = std::iter::once(if barline { '│' } else { fill })
+                    .chain(std::iter::repeat_n(fill, w))
+                    .collect();
+                let mid = 1 + w / 2;
+                for (r, sharp, moved) in &heads[k] {
+                    if *r != row {
+                        continue;
+                    }
+                    cell[mid] = match moved {
+                        0 => '●',
+                        m if *m > 0 => '↑',
+                        _ => '↓',
+                    };
+                    if *sharp {
+                        cell[mid - 1] = '#';
+                    }
+                }
+                let cell: String = cell.into_iter().collect();
+                if self.cur.beat == i {
+                    win.print_styled(cell.yellow())?;
+                } else {
+                    win.print(cell)?;
+                }
+            }
+            win.print(fill)?.next_line()?;
+        }
+        Ok(())
+    }
+
+    // Each annotation starts over its beat and is cut short by the next one
+    fn draw_text(
+        &self,
+        win: &mut window::Window,
+        range: std::ops::Range<usize>,
+        song: &Song,
+        cell: usize,
+    ) -> Result<()> {
+        let track = self.cur.track(song);
+        let mut starts = vec![0];
+        for i in range.clone() {
+            starts.push(starts.last().unwrap() + cell_width(&track.beats[i], cell) + 1);
+        }
+        let width = *starts.last().unwrap();
+        let mut line = String::new();
+        for i in range.clone() {
+            let Some(text) = &track.beats[i].text else {
+                continue;
+            };
+            let col = starts[i - range.start] + 1;
+            let end = (i + 1..range.end)
+                .find(|j| track.beats[*j].text.is_some())
+                .map_or(width, |j| starts[j - range.start]);
+            let len = line.chars().count();
+            line.extend(std::iter::repeat_n(' ', col.saturating_sub(len)));
+            line.extend(text.chars().take(end.saturating_sub(col + 1)));
+        }
+        win.print_styled(line.italic())?.next_line()?;
+        Ok(())
+    }
+
+    fn draw_decorations(
         &self,
         win: &mut window::Window,
         range: std::ops::Range<usize>,
         song: &Song,
+        cell: usize,
     ) -> Result<()> {
         let track = self.cur.track(song);
         for i in range {
-            win.print("~")?.print(track.beats[i].dur.dur_icon())?;
+            let beat = &track.beats[i];
+            let mut deco = String::from(beat.stroke.map(|s| s.glyph()).unwrap_or(""));
+            if let Some(arp) = beat.arpeggio {
+                deco.push(arp.glyph());
+            }
+            if beat.has_swell() {
+                deco.push('<');
+            }
+            if beat.has_tap() {
+                deco.push('T');
+            }
+            if let Some(pluck) = beat.pluck() {
+                deco.push(pluck.glyph());
+            }
+            if let Some(fret) = beat.trill() {
+                deco.push_str(&format!("tr{fret}"));
+            }
+            if let Some(whammy) = beat.whammy {
+                deco.push_str(&whammy.glyph());
+            }
+            win.print(format!(" {}", centred(&deco, cell_width(beat, cell))))?;
         }
-        win.print("~")?.next_line()?;
+        win.next_line()?;
         Ok(())
     }
 
-    fn draw_string(
+    // Number of each beat in its measure, or while counting the count from the cursor
+    // that takes in each beat after it
+    fn draw_ruler(
+        &self,
+        win: &mut window::Window,
+        range: std::ops::Range<usize>,
+        song: &Song,
+        cell: usize,
+    ) -> Result<()> {
+        let track = self.cur.track(song);
+        let first = (0..=range.start)
+            .rev()
+            .find(|i| track.measure_i[*i])
+            .unwrap_or(0);
+        let mut in_measure = range.start - first;
+        for i in range {
+            if track.measure_i[i] {
+                in_measure = 0;
+            }
+            in_measure += 1;
+            let w = cell_width(&track.beats[i], cell);
+            win.print(" ")?;
+            if self.counting && i >= self.cur.beat {
+                win.print_styled(centred(&(i - self.cur.beat + 1).to_string(), w).yellow())?;
+            } else {
+                win.print_styled(centred(&in_measure.to_string(), w).dark_grey())?;
+            }
+        }
+        win.next_line()?;
+        Ok(())
+    }
+
+    fn draw_durations(
+        &self,
+        win: &mut window::Window,
+        range: std::ops::Range<usize>,
+        song: &Song,
+        cell: usize,
+        opts: &DrawOpts,
+    ) -> Result<()> {
+        let track = self.cur.track(song);
+        let rhythm = opts.rhythm;
+        // Measures that do not add up to their time signature are marked red
+        let fit = song.measure_fit(self.cur.track);
+        let first = track.measure_of(range.start);
+        let measures: Vec<usize> = range
+            .clone()
+            .scan(first, |measure, i| {
+                if track.measure_i[i] && i > range.start {
+                    *measure += 1;
+                }
+                Some(*measure)
+            })
+            .collect();
+        let cells: Vec<Vec<String>> = if rhythm {
+            rhythm_cells(track, range.clone(), cell)
+                .into_iter()
+                .map(Vec::from)
+                .collect()
+        } else {
+            range
+                .clone()
+                .map(|i| {
+                    let beam = if track.beam_i[i] { "~" } else { " " };
+                    let icon = dur_cell(track.beats[i].dur, cell_width(&track.beats[i], cell));
+                    vec![format!("{beam}{icon}")]
+                })
+                .collect()
+        };
+        for row in 0..1 + rhythm as usize {
+            for (k, c) in cells.iter().enumerate() {
+                if fit[measures[k]] == std::cmp::Ordering::Equal {
+                    win.print(&c[row])?;
+                } else {
+                    win.print_styled(c[row].clone().with(palette(opts.theme).wrong))?;
+                }
+            }
+            win.next_line()?;
+        }
+        Ok(())
+    }
+
+    fn draw_strings(
         &self,
         win: &mut window::Window,
-        string: u16,
         range: std::ops::Range<usize>,
         song: &Song,
         is_curr: bool,
+        cell: usize,
+        opts: &DrawOpts,
+    ) -> Result<()> {
+        let track = self.cur.track(song);
+        let glyphs = &opts.glyphs;
+        let tuning = opts.pitches.then(|| track.tuning());
+        let colors = palette(opts.theme);
+        let selected = |i: usize| self.sel.as_ref().is_some_and(|sel| sel.contains(&i));
+        let lines = lines_of(song, track, range.clone(), glyphs);
+        let mut measure = track.measure_of(range.start);
+        let shaded: Vec<Option<Term>> = range
+            .clone()
+            .map(|i| {
+                if track.measure_i[i] && i > range.start {
+                    measure += 1;
+                }
+                (self.shade == Some(measure)).then_some(colors.shade)
+            })
+            .collect();
+        for string in 0..track.string_count {
+            for i in range.clone() {
+                let rolled = track.beats[i]
+                    .arpeggio_span()
+                    .is_some_and(|span| span.contains(&string));
+                // The roll line wins over the barline, so rolls at measure starts stay visible
+                let line = if rolled {
+                    '≀'
+                } else {
+                    lines[i - range.start]
+                };
+                // Lines between selected beats are filled in too,
+                // so the selection reads as one block
+                if selected(i) && i > 0 && selected(i - 1) {
+                    win.print_styled(line.on(colors.select))?;
+                } else if let (Some(shade), false) = (shaded[i - range.start], track.measure_i[i]) {
+                    win.print_styled(line.on(shade))?;
+                } else {
+                    win.print(line)?;
+                }
+                let beat = &track.beats[i];
+                let inner = note_cell(
+                    beat,
+                    string,
+                    cell_width(beat, cell),
+                    glyphs,
+                    tuning.as_deref(),
+                );
+                if self.cur.beat == i {
+                    let on_string = self.cur.string == string;
+                    let styled = inner.as_str().black().on(match (is_curr, on_string) {
+                        (true, true) => colors.cursor,
+                        (true, false) => colors.cursor_beat,
+                        _ => colors.cursor_idle,
+                    });
+                    win.print_styled(match (colors.marked, is_curr && on_string) {
+                        (true, true) => styled.bold().underlined(),
+                        (true, false) => styled.bold(),
+                        _ => styled,
+                    })?;
+                } else if self.play == Some(i) {
+                    win.print_styled(inner.as_str().on(colors.play).black())?;
+                } else if selected(i) {
+                    let styled = inner.as_str().on(colors.select);
+                    win.print_styled(if colors.marked {
+                        styled.underlined()
+                    } else {
+                        styled
+                    })?;
+                } else if let Some(color) = track.beats[i].get_color(string) {
+                    win.print_styled(shade_if(
+                        inner.as_str().with(term_color(color)).bold(),
+                        shaded[i - range.start],
+                    ))?;
+                } else if let Some(color) = opts
+                    .techniques
+                    .then(|| technique_color(beat, string))
+                    .flatten()
+                {
+                    win.print_styled(shade_if(
+                        inner.as_str().with(color),
+                        shaded[i - range.start],
+                    ))?;
+                } else {
+                    win.print_styled(shade_if(inner.as_str().stylize(), shaded[i - range.start]))?;
+                }
+            }
+            win.print(lines[range.len()])?.next_line()?;
+        }
+        Ok(())
+    }
+
+    // Fingers of the notes, a row for each string in the order of the tab above
+    fn draw_fingering(
+        &self,
+        win: &mut window::Window,
+        range: std::ops::Range<usize>,
+        song: &Song,
+        cell: usize,
     ) -> Result<()> {
         let track = self.cur.track(song);
+        for string in 0..track.string_count {
+            for i in range.clone() {
+                let beat = &track.beats[i];
+                let finger = beat
+                    .get_note(string)
+                    .and_then(|_| beat.get_finger(string))
+                    .map_or(' ', |f| f.glyph());
+                let text = centred(&finger.to_string(), cell_width(beat, cell));
+                if self.cur.beat == i && self.cur.string == string {
+                    win.print(" ")?.print_styled(text.yellow())?;
+                } else {
+                    win.print(format!(" {text}"))?;
+                }
+            }
+            win.next_line()?;
+        }
+        Ok(())
+    }
+
+    fn draw_dynamics(
+        &self,
+        win: &mut window::Window,
+        range: std::ops::Range<usize>,
+        song: &Song,
+        cell: usize,
+    ) -> Result<DynRow> {
+        let track = self.cur.track(song);
+        let mut row = DynRow {
+            row: win.row(),
+            cells: Vec::new(),
+        };
+        let mut col = 0;
         for i in range {
-            win.print(if track.measure_i[i] { "|" } else { "―" })?;
-            let inner = match track.beats[i].get_note(string) {
-                Some(Note::Fret(fret)) if fret > &999 => "###".into(),
-                Some(Note::Fret(fret)) => format!("{: ^3}", fret),
-                Some(Note::X) => " X ".into(),
-                None => "―――".into(),
+            let beat = &track.beats[i];
+            let w = cell_width(beat, cell);
+            let bars = if beat.notes.is_empty() {
+                " ".repeat(w)
+            } else {
+                velocity_bar(beat.velocity()).to_string().repeat(w)
             };
+            win.print(" ")?;
             if self.cur.beat == i {
-                win.print_styled(match (is_curr, self.cur.string == string) {
-                    (true, true) => inner.as_str().on_white().black(),
-                    (true, false) => inner.as_str().on_grey().black(),
-                    _ => inner.as_str().on_dark_grey().black(),
-                })?;
+                win.print_styled(bars.yellow())?;
             } else {
-                win.print(inner)?;
+                win.print_styled(bars.dark_cyan())?;
             }
+            row.cells.push((col + 1..col + 1 + w as u16, i));
+            col += 1 + w as u16;
         }
-        win.print("―")?.next_line()?;
-        Ok(())
+        win.next_line()?;
+        Ok(row)
     }
 
     pub fn draw(
         &self,
         win: &mut window::Window,
-        s_bwidth: usize,
+        s_bwidth: BeatWidth,
         song: &Song,
         is_curr: bool,
+        opts: &DrawOpts,
+    ) -> Result<Vec<DynRow>> {
+        let track = self.cur.track(song);
+        self.draw_header(win, song, is_curr)?;
+        let mut dyn_rows = Vec::new();
+        for range in self.cur.view_rows(song, s_bwidth) {
+            self.draw_measures(win, range.clone(), song, s_bwidth.cell)?;
+            if opts.staff {
+                self.draw_staff(win, range.clone(), song, s_bwidth.cell)?;
+            }
+            if track.has_text() {
+                self.draw_text(win, range.clone(), song, s_bwidth.cell)?;
+            }
+            if track.has_decorations() {
+                self.draw_decorations(win, range.clone(), song, s_bwidth.cell)?;
+            }
+            if opts.ruler {
+                self.draw_ruler(win, range.clone(), song, s_bwidth.cell)?;
+            }
+            self.draw_durations(win, range.clone(), song, s_bwidth.cell, opts)?;
+            self.draw_strings(win, range.clone(), song, is_curr, s_bwidth.cell, opts)?;
+            if opts.fingering {
+                self.draw_fingering(win, range.clone(), song, s_bwidth.cell)?;
+            }
+            if opts.dynamics {
+                dyn_rows.push(self.draw_dynamics(win, range, song, s_bwidth.cell)?);
+            }
+            win.next_line()?;
+        }
+        Ok(dyn_rows)
+    }
+
+    // Collapsed group as a single lane, marking the measures in view of this lane
+    // in which any of its tracks play
+    pub fn draw_group(
+        &self,
+        win: &mut window::Window,
+        s_bwidth: BeatWidth,
+        song: &Song,
+        name: &str,
+        tracks: &[usize],
+        glyphs: &Glyphs,
+    ) -> Result<()> {
+        let mut playing = std::collections::BTreeSet::new();
+        for t in tracks {
+            let track = &song.tracks[*t];
+            let mut measure = 0;
+            for (i, beat) in track.beats.iter().enumerate() {
+                if track.measure_i[i] && i > 0 {
+                    measure += 1;
+                }
+                if !beat.notes.is_empty() {
+                    playing.insert(measure);
+                }
+            }
+        }
+        let plural = if tracks.len() == 1 { "" } else { "s" };
+        let title = format!("▸ {name} ({} track{plural}, :expand {name})", tracks.len());
+        win.print_styled(title.bold())?.next_line()?;
+        let track = self.cur.track(song);
+        let range = fit_range(&track.beats, self.cur.scroll, s_bwidth);
+        let mut measure = track.measure_of(range.start);
+        for i in range.clone() {
+            if track.measure_i[i] && i > range.start {
+                measure += 1;
+            }
+            let fill = if playing.contains(&measure) {
+                '▬'
+            } else {
+                ' '
+            };
+            win.print(if track.measure_i[i] {
+                glyphs.barline
+            } else {
+                fill
+            })?;
+            let cells = fill
+                .to_string()
+                .repeat(cell_width(&track.beats[i], s_bwidth.cell));
+            win.print_styled(cells.dark_cyan())?;
+        }
+        win.next_line()?.next_line()?;
+        Ok(())
+    }
+
+    // Draws the same time range of `other` below this lane, marking cells that differ in red
+    pub fn draw_reference(
+        &self,
+        win: &mut window::Window,
+        s_bwidth: BeatWidth,
+        song: &Song,
+        other: &Song,
+        label: &str,
+        opts: &DrawOpts,
     ) -> Result<()> {
+        let glyphs = &opts.glyphs;
+        win.print_styled(format!("[{label}]").dark_grey())?
+            .next_line()?;
         let track = self.cur.track(song);
-        let num_beats = track.beats.len();
-        let range = self.cur.scroll..(self.cur.scroll + s_bwidth).min(num_beats);
-        self.draw_durations(win, range.clone(), song)?;
-        for i in 0..track.string_count {
-            self.draw_string(win, i, range.clone(), song, is_curr)?;
+        let rtrack = match other.tracks.get(self.cur.track) {
+            Some(t) => t,
+            None => {
+                win.print("(track missing)")?.next_line()?.next_line()?;
+                return Ok(());
+            }
+        };
+        let tuning = opts.pitches.then(|| rtrack.tuning());
+        let offsets = track.offsets();
+        let roffsets = rtrack.offsets();
+        let start = offsets[self.cur.scroll];
+        let rstart = roffsets.partition_point(|o| *o < start);
+        let range = fit_range(&rtrack.beats, rstart, s_bwidth);
+        let matched: Vec<Option<&Beat>> = range
+            .clone()
+            .map(|j| {
+                offsets
+                    .binary_search(&roffsets[j])
+                    .ok()
+                    .and_then(|i| track.beats.get(i))
+            })
+            .collect();
+
+        for (k, j) in range.clone().enumerate() {
+            let rbeat = &rtrack.beats[j];
+            let icon = dur_cell(rbeat.dur, cell_width(rbeat, s_bwidth.cell));
+            win.print(if rtrack.beam_i[j] { "~" } else { " " })?;
+            match matched[k] {
+                Some(b) if b.dur == rbeat.dur => win.print(icon)?,
+                _ => win.print_styled(icon.red())?,
+            };
+        }
+        win.next_line()?;
+        let lines = lines_of(other, rtrack, range.clone(), glyphs);
+        for string in 0..rtrack.string_count {
+            for (k, j) in range.clone().enumerate() {
+                let rbeat = &rtrack.beats[j];
+                win.print(lines[k])?;
+                let inner = note_cell(
+                    rbeat,
+                    string,
+                    cell_width(rbeat, s_bwidth.cell),
+                    glyphs,
+                    tuning.as_deref(),
+                );
+                match matched[k] {
+                    Some(b) if b.get_note(string) == rbeat.get_note(string) => win.print(inner)?,
+                    _ => win.print_styled(inner.on_dark_red())?,
+                };
+            }
+            win.print(lines[range.len()])?.next_line()?;
         }
         win.next_line()?;
         Ok(())
@@ -92,6 +92,27 @@ impl Duration {
         Self(self.0 * rhs.1 + rhs.0 * self.1, self.1 * rhs.1)
     }
 
+    // How many of this duration exactly fill `space`
+    pub fn count_in(&self, space: Self) -> Option<usize> {
+        let whole = space.0 as u32 * self.1 as u32;
+        let part = space.1 as u32 * self.0 as u32;
+        (part != 0 && whole.is_multiple_of(part)).then(|| (whole / part) as usize)
+    }
+
+    pub fn beamable(&self) -> bool {
+        *self < Self(1, 4)
+    }
+
+    // Note value as written (1 whole, 2 half, ...), whether it is dotted and whether it is a triplet
+    pub fn notation(&self) -> Option<(u16, bool, bool)> {
+        match *self {
+            Self(1, d) if d.is_power_of_two() => Some((d, false, false)),
+            Self(3, d) if d.is_power_of_two() && d >= 2 => Some((d / 2, true, false)),
+            Self(1, d) if d % 3 == 0 && (d / 3).is_power_of_two() => Some((d / 3 * 2, false, true)),
+            _ => None,
+        }
+    }
+
     pub fn dur_icon(&self) -> &'static str {
         match self {
             Self(1, 1) => " 1 ",
@@ -231,3 +252,58 @@ impl PartialEq for Duration {
 }
 
 impl Eq for Duration {}
+
+// Position from the start of a track in whole notes, as a reduced u64 fraction since
+// summing the Durations of a track (ie a run of triplets) overflows their u16 parts
+#[derive(Debug, Clone, Copy)]
+pub struct Offset(pub u64, pub u64);
+
+impl Offset {
+    pub fn zero() -> Self {
+        Self(0, 1)
+    }
+
+    pub fn quarters(&self) -> f64 {
+        self.0 as f64 * 4.0 / self.1 as f64
+    }
+}
+
+fn gcd(mut a: u64, mut b: u64) -> u64 {
+    while b != 0 {
+        (a, b) = (b, a % b);
+    }
+    a
+}
+
+impl std::ops::Add<Duration> for Offset {
+    type Output = Self;
+
+    fn add(self, rhs: Duration) -> Self::Output {
+        let num = self.0 * rhs.1 as u64 + rhs.0 as u64 * self.1;
+        let den = self.1 * rhs.1 as u64;
+        let div = gcd(num, den);
+        Self(num / div, den / div)
+    }
+}
+
+impl Ord for Offset {
+    fn cmp(&self, other: &Self) -> std::cmp::Ordering {
+        let a = self.0 as u128 * other.1 as u128;
+        let b = other.0 as u128 * self.1 as u128;
+        a.cmp(&b)
+    }
+}
+
+impl PartialOrd for Offset {
+    fn partial_cmp(&self, other: &Self) -> Option<std::cmp::Ordering> {
+        Some(self.cmp(other))
+    }
+}
+
+impl PartialEq for Offset {
+    fn eq(&self, other: &Self) -> bool {
+        self.cmp(other).is_eq()
+    }
+}
+
+impl Eq for Offset {}
@@ -0,0 +1,190 @@
+use crate::{
+    error::Result,
+    formats::{dynamic, gp5, quote, TabFormat},
+    pitch,
+    song::{Arpeggio, Beat, Finger, Note, Pluck, Song, Stroke, Track, Whammy},
+};
+
+// Markup read by alphaTab, for showing tabs on web pages
+pub struct AlphaTex;
+
+fn finger(finger: Finger) -> u8 {
+    match finger {
+        Finger::Thumb => 1,
+        Finger::Index => 2,
+        Finger::Middle => 3,
+        Finger::Ring => 4,
+        Finger::Pinky => 5,
+    }
+}
+
+// Strings are numbered from 1 for the highest, as here but one based
+fn write_note(out: &mut String, beat: &Beat, string: u16, note: &Note, tied: bool) {
+    match note {
+        _ if tied => out.push('-'),
+        Note::Fret(fret) => out.push_str(&fret.to_string()),
+        Note::X => out.push('x'),
+    }
+    out.push_str(&format!(".{}", string + 1));
+    let mut effects = Vec::new();
+    if let Some(marks) = beat.get_marks(string).filter(|_| !tied) {
+        if let Some(f) = marks.finger {
+            effects.push(format!("lf {}", finger(f)));
+        }
+        if let Some(trill) = marks.trill {
+            effects.push(format!("tr {trill} 16"));
+        }
+    }
+    if !effects.is_empty() {
+        out.push_str(&format!("{{{}}}", effects.join(" ")));
+    }
+}
+
+fn beat_effects(beat: &Beat, dur: &gp5::GpDuration, tied: bool) -> Vec<String> {
+    let mut effects = Vec::new();
+    if dur.dotted {
+        effects.push("d".into());
+    }
+    if let Some(tuplet) = dur.tuplet {
+        effects.push(format!("tu {tuplet}"));
+    }
+    if tied {
+        return effects;
+    }
+    if let Some(velocity) = beat.velocity {
+        effects.push(format!("dy {}", dynamic(velocity)));
+    }
+    if let Some(text) = &beat.text {
+        effects.push(format!("txt {}", quote(text)));
+    }
+    match beat.stroke {
+        Some(Stroke::Up) => effects.push("spu".into()),
+        Some(Stroke::Down) => effects.push("spd".into()),
+        None => (),
+    }
+    match beat.arpeggio {
+        Some(Arpeggio::Up) => effects.push("au".into()),
+        Some(Arpeggio::Down) => effects.push("ad".into()),
+        None => (),
+    }
+    if beat.has_swell() {
+        effects.push("f".into());
+    }
+    if beat.has_tap() {
+        effects.push("tt".into());
+    }
+    match beat.pluck() {
+        Some(Pluck::Slap) => effects.push("s".into()),
+        Some(Pluck::Pop) => effects.push("p".into()),
+        None => (),
+    }
+    // Whammy points are in quarter tones
+    match beat.whammy {
+        Some(Whammy::Dive(steps)) => effects.push(format!("tb (0 -{})", steps * 2)),
+        Some(Whammy::Return) => effects.push("tb (-4 0)".into()),
+        None => (),
+    }
+    effects
+}
+
+// Lengths with no single note value are split into tied parts, as in gp5 exports
+fn write_beat(out: &mut String, beat: &Beat, track: &Track) -> Result<()> {
+    for (i, dur) in gp5::gp_durations(beat.dur)?.iter().enumerate() {
+        let tied = i > 0;
+        let notes: Vec<(u16, &Note)> = (0..track.string_count)
+            .filter_map(|s| beat.get_note(s).map(|n| (s, n)))
+            .collect();
+        match notes.as_slice() {
+            [] => out.push('r'),
+            [(string, note)] => write_note(out, beat, *string, note, tied),
+            _ => {
+                out.push('(');
+                for (i, (string, note)) in notes.iter().enumerate() {
+                    if i > 0 {
+                        out.push(' ');
+                    }
+                    write_note(out, beat, *string, note, tied);
+                }
+                out.push(')');
+            }
+        }
+        out.push_str(&format!(".{}", dur.value));
+        let effects = beat_effects(beat, dur, tied);
+        if !effects.is_empty() {
+            out.push_str(&format!(" {{{}}}", effects.join(" ")));
+        }
+        out.push(' ');
+    }
+    Ok(())
+}
+
+fn write_track(out: &mut String, song: &Song, t: usize) -> Result<()> {
+    let track = &song.tracks[t];
+    out.push_str(&format!("\\track {}\n", quote(&format!("Track {}", t + 1))));
+    out.push_str("\\staff {tabs}\n");
+    let tuning: Vec<String> = track
+        .tuning()
+        .iter()
+        .map(|p| pitch::name(*p).to_lowercase())
+        .collect();
+    out.push_str(&format!("\\tuning {}\n", tuning.join(" ")));
+    if track.capo > 0 {
+        out.push_str(&format!("\\capo {}\n", track.capo));
+    }
+    out.push_str(&format!(
+        "\\ts {} {}\n",
+        song.time_sig.num, song.time_sig.den
+    ));
+    let mut measure = 0;
+    for (i, beat) in track.beats.iter().enumerate() {
+        if track.measure_i[i] && i > 0 {
+            out.push_str("|\n");
+            measure += 1;
+            if let Some(change) = song.change_at(measure) {
+                if let Some(sig) = change.time_sig {
+                    out.push_str(&format!("\\ts {} {} ", sig.num, sig.den));
+                }
+                if let Some(tempo) = change.tempo {
+                    out.push_str(&format!("\\tempo {tempo} "));
+                }
+            }
+        }
+        write_beat(out, beat, track)?;
+    }
+    out.push_str("|\n");
+    Ok(())
+}
+
+pub fn render(song: &Song) -> Result<String> {
+    let mut out = String::new();
+    let meta = &song.meta;
+    for (tag, value) in [
+        ("title", &meta.title),
+        ("artist", &meta.artist),
+        ("album", &meta.album),
+        ("tab", &meta.transcriber),
+    ] {
+        if let Some(value) = value {
+            out.push_str(&format!("\\{tag} {}\n", quote(value)));
+        }
+    }
+    out.push_str(&format!("\\tempo {}\n.\n", song.tempo()));
+    for t in 0..song.tracks.len() {
+        write_track(&mut out, song, t)?;
+    }
+    Ok(out)
+}
+
+impl TabFormat for AlphaTex {
+    fn name(&self) -> &'static str {
+        "alphatex"
+    }
+
+    fn extensions(&self) -> &'static [&'static str] {
+        &["atex", "alphatex"]
+    }
+
+    fn write(&self, song: &Song) -> Result<Vec<u8>> {
+        Ok(render(song)?.into_bytes())
+    }
+}
@@ -0,0 +1,375 @@
+use crate::{
+    dur::Duration,
+    error::{Error, Result},
+    formats::{text, TabFormat},
+    pitch,
+    song::{Beat, Note, Song, TimeSig, Track},
+};
+use std::collections::BTreeMap;
+
+// Longer runs of tab lines are taken as a broken file rather than one track
+const MAX_STRINGS: usize = 12;
+
+pub struct Ascii {
+    title: String,
+    width: usize,
+}
+
+impl Ascii {
+    pub fn new(title: String, width: usize) -> Self {
+        Self { title, width }
+    }
+}
+
+fn label_width(track: &Track) -> usize {
+    track
+        .tuning()
+        .iter()
+        .map(|p| pitch::class_name(*p).len())
+        .max()
+        .unwrap_or(1)
+}
+
+// The highest string is written in lower case, as is usual for plain tabs
+fn string_label(track: &Track, string: u16, width: usize) -> String {
+    let name = pitch::class_name(track.tuning()[string as usize]);
+    let name = if string == 0 {
+        name.to_lowercase()
+    } else {
+        name.to_owned()
+    };
+    format!("{name:<width$}")
+}
+
+pub fn fret_text(note: Option<&Note>) -> String {
+    match note {
+        Some(Note::Fret(fret)) => fret.to_string(),
+        Some(Note::X) => "x".into(),
+        None => String::new(),
+    }
+}
+
+// Columns are only as wide as the widest fret played on that beat
+fn column_width(track: &Track, beat: usize) -> usize {
+    (0..track.string_count)
+        .map(|s| fret_text(track.beats[beat].get_note(s)).len())
+        .max()
+        .unwrap_or(0)
+        .max(1)
+}
+
+fn column_widths(track: &Track, range: std::ops::Range<usize>) -> Vec<usize> {
+    range.map(|i| column_width(track, i)).collect()
+}
+
+// Columns taken by a beat in a line from `render_line`, with its barline and trailing dash
+pub fn beat_width(track: &Track, beat: usize) -> usize {
+    column_width(track, beat) + 2
+}
+
+// Character column of the frets of each beat in a line from `render_line`
+pub fn beat_columns(track: &Track, range: std::ops::Range<usize>) -> Vec<usize> {
+    let mut col = label_width(track) + 1;
+    column_widths(track, range)
+        .into_iter()
+        .map(|w| {
+            let start = col;
+            col += w + 2;
+            start
+        })
+        .collect()
+}
+
+pub fn render_line(track: &Track, range: std::ops::Range<usize>) -> Vec<String> {
+    let label_w = label_width(track);
+    let widths = column_widths(track, range.clone());
+    (0..track.string_count)
+        .map(|string| {
+            let mut row = string_label(track, string, label_w);
+            for (i, w) in range.clone().zip(widths.iter()) {
+                row.push(if track.measure_i[i] { '|' } else { '-' });
+                let fret = fret_text(track.beats[i].get_note(string));
+                row.push_str(&format!("{fret:-<w$}-"));
+            }
+            row.push('|');
+            row
+        })
+        .collect()
+}
+
+pub fn line_width(track: &Track, width: usize) -> usize {
+    width.saturating_sub(label_width(track))
+}
+
+// ie "Track 1 (E A D G B E), capo 2"
+pub fn track_heading(t: usize, track: &Track) -> String {
+    let tuning: Vec<_> = track
+        .tuning()
+        .iter()
+        .rev()
+        .map(|p| pitch::class_name(*p))
+        .collect();
+    let mut out = format!("Track {} ({})", t + 1, tuning.join(" "));
+    if track.capo > 0 {
+        out.push_str(&format!(", capo {}", track.capo));
+    }
+    out
+}
+
+pub fn render(song: &Song, title: &str, width: usize) -> String {
+    let mut out = String::new();
+    if song.meta.is_empty() {
+        out.push_str(title);
+    } else {
+        out.push_str(&song.meta.header());
+    }
+    out.push_str("\n\n");
+    for (t, track) in song.tracks.iter().enumerate() {
+        out.push_str(&track_heading(t, track));
+        out.push_str("\n\n");
+        for range in text::split_lines(track, line_width(track, width), |i| beat_width(track, i)) {
+            for row in render_line(track, range) {
+                out.push_str(&row);
+                out.push('\n');
+            }
+            out.push('\n');
+        }
+    }
+    out
+}
+
+// Import
+
+struct TabLine {
+    label: Option<String>,
+    body: Vec<char>,
+}
+
+// Accepts lines like "e|--3--5--|" or "|--3--5--|", with or without a string name
+fn parse_line(line: &str) -> Option<TabLine> {
+    let line = line.trim();
+    let body_at = line.find(['|', '-'])?;
+    let (label, body) = line.split_at(body_at);
+    let label = label.trim();
+    let valid_label =
+        label.is_empty() || (label.len() <= 2 && pitch::parse(&format!("{label}4")).is_ok());
+    let dashes = body.chars().filter(|c| *c == '-').count();
+    let bars = body.chars().filter(|c| *c == '|').count();
+    // Short lines pass when closed by barlines, like those of a single beat
+    let valid_body = (dashes >= 3 || (dashes >= 2 && bars >= 1) || (dashes >= 1 && bars >= 2))
+        && body
+            .chars()
+            .all(|c| c.is_ascii_digit() || "-|xXhpbrstvPSHT/\\~()<>.^*= ".contains(c));
+    (valid_label && valid_body).then(|| TabLine {
+        label: (!label.is_empty()).then(|| label.to_owned()),
+        body: body.chars().collect(),
+    })
+}
+
+// Notes by starting column, with the width each was written in
+type Events = BTreeMap<usize, Vec<(u16, Note, usize)>>;
+
+type Notes = Vec<(u16, Note)>;
+
+// Bend and release targets are skipped as they are not struck
+fn note_events(lines: &[TabLine]) -> Events {
+    let mut events = Events::new();
+    for (string, line) in lines.iter().enumerate() {
+        let body = &line.body;
+        let mut c = 0;
+        while c < body.len() {
+            let struck = c == 0 || !matches!(body[c - 1], 'b' | 'r');
+            if body[c].is_ascii_digit() {
+                let end = (c..body.len())
+                    .find(|i| !body[*i].is_ascii_digit())
+                    .unwrap_or(body.len());
+                if struck {
+                    let fret: String = body[c..end].iter().collect();
+                    if let Ok(fret) = fret.parse() {
+                        events.entry(c).or_default().push((
+                            string as u16,
+                            Note::Fret(fret),
+                            end - c,
+                        ));
+                    }
+                }
+                c = end;
+                continue;
+            }
+            if matches!(body[c], 'x' | 'X') {
+                events
+                    .entry(c)
+                    .or_default()
+                    .push((string as u16, Note::X, 1));
+            }
+            c += 1;
+        }
+    }
+    events
+}
+
+// Notes starting inside a wider fret number on another string belong to the same beat
+fn merge_events(events: Events) -> Vec<(usize, Notes)> {
+    let mut merged: Vec<(usize, usize, Notes)> = Vec::new();
+    for (col, notes) in events {
+        let end = col + notes.iter().map(|n| n.2).max().unwrap_or(1);
+        if let Some((_, prev_end, prev)) = merged.last_mut() {
+            let disjoint = notes.iter().all(|n| prev.iter().all(|p| p.0 != n.0));
+            if col < *prev_end && disjoint {
+                prev.extend(notes.into_iter().map(|n| (n.0, n.1)));
+                *prev_end = (*prev_end).max(end);
+                continue;
+            }
+        }
+        merged.push((col, end, notes.into_iter().map(|n| (n.0, n.1)).collect()));
+    }
+    merged.into_iter().map(|(c, _, n)| (c, n)).collect()
+}
+
+// Picks the closest of the power of two durations to a fraction of a whole note
+fn nearest_duration(whole: f64) -> Duration {
+    let den = (1.0 / whole.max(1.0 / 64.0)).log2().round().clamp(0.0, 5.0);
+    Duration::new(1, 2u16.pow(den as u32))
+}
+
+// Durations are guessed from how far apart the notes are written within each measure
+fn parse_block(lines: &[TabLine], sig: &TimeSig) -> Vec<Beat> {
+    let width = lines.iter().map(|l| l.body.len()).max().unwrap_or(0);
+    let mut bars: Vec<usize> = (0..width)
+        .filter(|c| lines.iter().any(|l| l.body.get(*c) == Some(&'|')))
+        .collect();
+    if bars.len() < 2 {
+        bars = vec![0, width];
+    }
+    // A line that does not open with a barline carries on the measure of the line before
+    if bars[0] > 0 {
+        bars.insert(0, 0);
+    }
+    let events = merge_events(note_events(lines));
+    let measure = sig.num as f64 / sig.den as f64;
+    let mut beats = Vec::new();
+    for bar in bars.windows(2) {
+        let (start, end) = (bar[0], bar[1]);
+        if end <= start + 1 {
+            continue;
+        }
+        let inside: Vec<_> = events
+            .iter()
+            .filter(|(c, _)| *c > start && *c < end)
+            .collect();
+        if inside.is_empty() {
+            beats.push(Beat::new(nearest_duration(measure)));
+            continue;
+        }
+        // Padding before the first note is assumed to be repeated after the last
+        let span = (end - start - 1) as f64;
+        let last_end = end + inside[0].0 - start - 1;
+        for (i, (col, notes)) in inside.iter().enumerate() {
+            let next = inside.get(i + 1).map_or(last_end, |n| n.0);
+            let mut beat = Beat::new(nearest_duration((next - col) as f64 / span * measure));
+            for (string, note) in notes {
+                beat.set_note(*string, note.clone());
+            }
+            beats.push(beat);
+        }
+    }
+    beats
+}
+
+// Octaves are not written in tabs, so each string takes the nearest octave to standard tuning
+fn infer_tuning(lines: &[TabLine]) -> Vec<u8> {
+    let standard = pitch::standard_tuning(lines.len() as u16);
+    let tuning: Option<Vec<u8>> = lines
+        .iter()
+        .zip(standard.iter())
+        .map(|(line, std_pitch)| {
+            let class = pitch::parse(&format!("{}4", line.label.as_ref()?)).ok()? % 12;
+            let below = *std_pitch as i16 - ((std_pitch % 12 + 12 - class) % 12) as i16;
+            // Low strings past the bottom of the range keep to the octave above
+            Some(if *std_pitch as i16 - below > 6 || below < 0 {
+                below + 12
+            } else {
+                below
+            } as u8)
+        })
+        .collect();
+    match tuning {
+        Some(tuning) if tuning != standard => tuning,
+        _ => Vec::new(),
+    }
+}
+
+pub fn parse(text: &str) -> Result<Song> {
+    let mut song = Song::new();
+    song.tracks.clear();
+    let mut block: Vec<TabLine> = Vec::new();
+    // Blocks with the same number of strings carry on a track, unless a heading as written by
+    // `track_heading` comes between them
+    let mut heading = false;
+    let mut lines = text
+        .lines()
+        .map(|l| (l.trim_start().starts_with("Track "), parse_line(l)))
+        .peekable();
+    while let Some((is_heading, line)) = lines.next() {
+        match line {
+            Some(line) => block.push(line),
+            None => heading |= is_heading,
+        }
+        if block.is_empty() || matches!(lines.peek(), Some((_, Some(_)))) {
+            continue;
+        }
+        if block.len() > MAX_STRINGS {
+            return Err(Error::ParseError(format!(
+                "{} tab lines in a row, tracks have at most {MAX_STRINGS} strings",
+                block.len()
+            )));
+        }
+        let beats = parse_block(&block, &song.time_sig);
+        let string_count = block.len() as u16;
+        match song.tracks.last_mut() {
+            Some(track) if track.string_count == string_count && !heading => {
+                track.beats.extend(beats)
+            }
+            _ => {
+                let mut track = Track::new();
+                track.string_count = string_count;
+                track.tuning = infer_tuning(&block);
+                track.beats = beats;
+                song.tracks.push(track);
+            }
+        }
+        heading = false;
+        block.clear();
+    }
+    if song.tracks.is_empty() {
+        return Err(Error::ParseError("No tab lines found".into()));
+    }
+    song.update_all_measures();
+    Ok(song)
+}
+
+impl TabFormat for Ascii {
+    fn name(&self) -> &'static str {
+        "ascii"
+    }
+
+    fn extensions(&self) -> &'static [&'static str] {
+        &["tab", "txt"]
+    }
+
+    fn can_read(&self) -> bool {
+        true
+    }
+
+    fn keeps_rhythm(&self) -> bool {
+        false
+    }
+
+    fn read(&self, data: &[u8]) -> Result<Song> {
+        parse(&String::from_utf8_lossy(data))
+    }
+
+    fn write(&self, song: &Song) -> Result<Vec<u8>> {
+        Ok(render(song, &self.title, self.width).into_bytes())
+    }
+}
@@ -0,0 +1,1027 @@
+use crate::{
+    dur::Duration,
+    error::{Error, Result},
+    formats::TabFormat,
+    pitch,
+    song::{Arpeggio, Beat, Finger, Note, NoteMarks, Pluck, Song, Stroke, TimeSig, Track, Whammy},
+};
+
+pub struct Gp5;
+
+// Steel string guitar, for tracks without a program
+const DEFAULT_PROGRAM: i32 = 25;
+
+// Little endian reader over the whole file, failing on truncation instead of panicking
+struct Reader<'a> {
+    data: &'a [u8],
+    pos: usize,
+}
+
+impl<'a> Reader<'a> {
+    fn new(data: &'a [u8]) -> Self {
+        Self { data, pos: 0 }
+    }
+
+    fn bytes(&mut self, n: usize) -> Result<&'a [u8]> {
+        let bytes = self
+            .data
+            .get(self.pos..self.pos + n)
+            .ok_or_else(|| Error::ParseError("Unexpected end of gp5 file".into()))?;
+        self.pos += n;
+        Ok(bytes)
+    }
+
+    fn skip(&mut self, n: usize) -> Result<()> {
+        self.bytes(n).map(|_| ())
+    }
+
+    fn u8(&mut self) -> Result<u8> {
+        Ok(self.bytes(1)?[0])
+    }
+
+    fn i8(&mut self) -> Result<i8> {
+        Ok(self.u8()? as i8)
+    }
+
+    fn i16(&mut self) -> Result<i16> {
+        let b = self.bytes(2)?;
+        Ok(i16::from_le_bytes([b[0], b[1]]))
+    }
+
+    fn i32(&mut self) -> Result<i32> {
+        let b = self.bytes(4)?;
+        Ok(i32::from_le_bytes([b[0], b[1], b[2], b[3]]))
+    }
+
+    fn count(&mut self) -> Result<usize> {
+        usize::try_from(self.i32()?)
+            .map_err(|_| Error::ParseError("Negative count in gp5 file".into()))
+    }
+
+    // A length byte followed by a field of `size` bytes
+    fn byte_string(&mut self, size: usize) -> Result<String> {
+        let len = self.u8()? as usize;
+        let field = self.bytes(size)?;
+        Ok(String::from_utf8_lossy(&field[..len.min(field.len())]).into_owned())
+    }
+
+    // An int holding the length plus one, followed by a byte sized string
+    fn int_byte_string(&mut self) -> Result<String> {
+        let size = self.count()?;
+        let len = self.u8()? as usize;
+        let field = self.bytes(size.saturating_sub(1))?;
+        Ok(String::from_utf8_lossy(&field[..len.min(field.len())]).into_owned())
+    }
+
+    fn int_string(&mut self) -> Result<String> {
+        let len = self.count()?;
+        Ok(String::from_utf8_lossy(self.bytes(len)?).into_owned())
+    }
+}
+
+struct Header {
+    time_sig: TimeSig,
+    triplet_feel: bool,
+}
+
+fn non_empty(s: String) -> Option<String> {
+    let s = s.trim().to_owned();
+    (!s.is_empty()).then_some(s)
+}
+
+fn read_song_info(r: &mut Reader, song: &mut Song) -> Result<()> {
+    song.meta.title = non_empty(r.int_byte_string()?);
+    r.int_byte_string()?; // subtitle
+    song.meta.artist = non_empty(r.int_byte_string()?);
+    song.meta.album = non_empty(r.int_byte_string()?);
+    r.int_byte_string()?; // words
+    r.int_byte_string()?; // music
+    r.int_byte_string()?; // copyright
+    song.meta.transcriber = non_empty(r.int_byte_string()?);
+    r.int_byte_string()?; // instructions
+    for _ in 0..r.count()? {
+        r.int_byte_string()?;
+    }
+    Ok(())
+}
+
+// Lyrics, master effects, page setup, tempo, key, midi channels and directions, keeping the tempo
+// and the program of each channel
+fn read_song_settings(r: &mut Reader, v51: bool) -> Result<(i32, Vec<i32>)> {
+    r.i32()?;
+    for _ in 0..5 {
+        r.i32()?;
+        r.int_string()?;
+    }
+    if v51 {
+        r.skip(19)?;
+    }
+    r.skip(30)?;
+    for _ in 0..11 {
+        r.int_byte_string()?;
+    }
+    let tempo = r.i32()?;
+    if v51 {
+        r.skip(1)?;
+    }
+    r.skip(5)?;
+    let mut programs = Vec::with_capacity(64);
+    for _ in 0..64 {
+        programs.push(r.i32()?);
+        r.skip(8)?;
+    }
+    r.skip(42)?;
+    Ok((tempo, programs))
+}
+
+fn read_headers(r: &mut Reader, count: usize) -> Result<Vec<Header>> {
+    // The count is not trusted to size anything, a bad one runs out of file instead
+    let mut headers = Vec::new();
+    let mut time_sig = TimeSig { num: 4, den: 4 };
+    for i in 0..count {
+        if i > 0 {
+            r.skip(1)?;
+        }
+        let flags = r.u8()?;
+        if flags & 0x01 != 0 {
+            time_sig.num = u16::try_from(r.i8()?)
+                .ok()
+                .filter(|num| *num > 0)
+                .ok_or_else(|| {
+                    Error::ParseError(format!("Bad time signature in measure {}", i + 1))
+                })?;
+        }
+        if flags & 0x02 != 0 {
+            time_sig.den = u16::try_from(r.i8()?)
+                .ok()
+                .filter(|den| den.is_power_of_two())
+                .ok_or_else(|| {
+                    Error::ParseError(format!("Bad time signature in measure {}", i + 1))
+                })?;
+        }
+        if flags & 0x08 != 0 {
+            r.skip(1)?; // repeat close
+        }
+        if flags & 0x20 != 0 {
+            r.int_byte_string()?; // marker
+            r.skip(4)?;
+        }
+        if flags & 0x10 != 0 {
+            r.skip(1)?; // alternate ending
+        }
+        if flags & 0x40 != 0 {
+            r.skip(2)?; // key signature
+        }
+        if flags & 0x03 != 0 {
+            r.skip(4)?; // beaming
+        }
+        if flags & 0x10 == 0 {
+            r.skip(1)?;
+        }
+        let triplet_feel = r.u8()? != 0;
+        headers.push(Header {
+            time_sig,
+            triplet_feel,
+        });
+    }
+    Ok(headers)
+}
+
+// Strings are listed top first as midi pitches, as they are here
+// Guitar Pro files always give the channel and program of a track, so they are kept
+fn read_track(r: &mut Reader, first: bool, programs: &[i32], v51: bool) -> Result<Track> {
+    if first || !v51 {
+        r.skip(1)?;
+    }
+    r.skip(1)?; // flags
+    let name = r.byte_string(40)?;
+    let string_count = r.count()?.clamp(1, 7) as u16;
+    let mut tuning = Vec::new();
+    for s in 0..7 {
+        let pitch = r.i32()?;
+        if s < string_count {
+            tuning.push(pitch.clamp(0, 127) as u8);
+        }
+    }
+    let port = r.i32()?.clamp(1, 4) as usize;
+    let channel = r.i32()?.clamp(1, 16) as usize - 1;
+    r.skip(4)?; // effects channel
+    let fret_count = r.i32()?.clamp(1, 99) as u16;
+    let capo = r.i32()?.clamp(0, 24) as u16;
+    r.skip(4)?; // color
+    r.skip(if v51 { 49 } else { 44 })?;
+    if v51 {
+        r.int_byte_string()?;
+        r.int_byte_string()?;
+    }
+    let mut track = Track::new();
+    track.name = Some(name.trim().to_string()).filter(|n| !n.is_empty());
+    track.string_count = string_count;
+    track.fret_count = fret_count;
+    track.capo = capo;
+    track.channel = Some(channel as u8 + 1);
+    track.program = Some(programs[(port - 1) * 16 + channel].clamp(0, 127) as u8);
+    track.tuning = if tuning == pitch::standard_tuning(string_count) {
+        Vec::new()
+    } else {
+        tuning
+    };
+    track.beats.clear();
+    Ok(track)
+}
+
+fn read_duration(r: &mut Reader, flags: u8) -> Result<Duration> {
+    let den = 1u16 << (r.i8()? as i32 + 2).clamp(0, 6);
+    let mut dur = Duration::new(1, den);
+    if flags & 0x01 != 0 {
+        dur = dur.dotted();
+    }
+    if flags & 0x20 != 0 {
+        // n notes in the time of the largest power of two below n
+        let n = r.i32()?.clamp(1, 13) as u16;
+        let base = 1 << (15 - n.leading_zeros());
+        dur = Duration::new(dur.0 * base, dur.1 * n);
+    }
+    Ok(dur)
+}
+
+fn skip_bend(r: &mut Reader) -> Result<Vec<i32>> {
+    r.skip(5)?;
+    let mut points = Vec::new();
+    for _ in 0..r.count()? {
+        r.i32()?;
+        points.push(r.i32()?);
+        r.skip(1)?;
+    }
+    Ok(points)
+}
+
+fn skip_mix_change(r: &mut Reader, v51: bool) -> Result<()> {
+    r.skip(1 + 16)?;
+    let values = r.bytes(6)?.to_vec();
+    r.int_byte_string()?;
+    let tempo = r.i32()?;
+    let durations = values.iter().filter(|v| (**v as i8) >= 0).count();
+    r.skip(durations)?;
+    if tempo >= 0 {
+        r.skip(if v51 { 2 } else { 1 })?;
+    }
+    r.skip(2)?;
+    if v51 {
+        r.int_byte_string()?;
+        r.int_byte_string()?;
+    }
+    Ok(())
+}
+
+// Effects that apply to every note of the beat
+#[derive(Default)]
+struct BeatEffects {
+    swell: bool,
+    tap: bool,
+    pluck: Option<Pluck>,
+}
+
+fn read_beat_effects(r: &mut Reader, beat: &mut Beat) -> Result<BeatEffects> {
+    let flags1 = r.u8()?;
+    let flags2 = r.u8()?;
+    let mut effects = BeatEffects {
+        swell: flags1 & 0x10 != 0,
+        ..Default::default()
+    };
+    if flags1 & 0x20 != 0 {
+        match r.u8()? {
+            1 => effects.tap = true,
+            2 => effects.pluck = Some(Pluck::Slap),
+            3 => effects.pluck = Some(Pluck::Pop),
+            _ => (),
+        }
+    }
+    if flags2 & 0x04 != 0 {
+        // Bar values are 25 per half step, and a bar rising back to 0 is a return
+        let points = skip_bend(r)?;
+        let dip = points.iter().copied().min().unwrap_or(0);
+        beat.whammy = match (points.first(), points.last()) {
+            (Some(first), Some(0)) if *first < 0 => Some(Whammy::Return),
+            _ if dip < 0 => Some(Whammy::Dive((dip.saturating_neg() / 25).clamp(1, 24) as u16)),
+            _ => None,
+        };
+    }
+    if flags1 & 0x40 != 0 {
+        let up = r.i8()?;
+        let down = r.i8()?;
+        if up > 0 {
+            beat.arpeggio = Some(Arpeggio::Up);
+        } else if down > 0 {
+            beat.arpeggio = Some(Arpeggio::Down);
+        }
+    }
+    if flags2 & 0x02 != 0 {
+        match r.u8()? {
+            1 => beat.stroke = Some(Stroke::Up),
+            2 => beat.stroke = Some(Stroke::Down),
+            _ => (),
+        }
+    }
+    Ok(effects)
+}
+
+fn read_note_effects(r: &mut Reader, marks: &mut NoteMarks) -> Result<()> {
+    let flags1 = r.u8()?;
+    let flags2 = r.u8()?;
+    if flags1 & 0x01 != 0 {
+        skip_bend(r)?;
+    }
+    if flags1 & 0x10 != 0 {
+        r.skip(5)?; // grace note
+    }
+    if flags2 & 0x04 != 0 {
+        r.skip(1)?; // tremolo picking
+    }
+    if flags2 & 0x08 != 0 {
+        r.skip(1)?; // slide
+    }
+    if flags2 & 0x10 != 0 {
+        match r.u8()? {
+            2 => r.skip(3)?,
+            3 => r.skip(1)?,
+            _ => (),
+        }
+    }
+    if flags2 & 0x20 != 0 {
+        marks.trill = Some(r.i8()?.max(0) as u16);
+        r.skip(1)?;
+    }
+    Ok(())
+}
+
+// Notes on strings the track does not have are read and dropped
+fn read_note(r: &mut Reader, beat: &mut Beat, string: u16, track: &Track) -> Result<()> {
+    let flags = r.u8()?;
+    let mut dead = false;
+    if flags & 0x20 != 0 {
+        dead = r.u8()? == 3;
+    }
+    if flags & 0x10 != 0 {
+        let dynamic = r.u8()?.clamp(1, 8);
+        if dynamic != 6 {
+            let velocity = 15 + 16 * (dynamic - 1);
+            beat.velocity = Some(beat.velocity.map_or(velocity, |v| v.max(velocity)));
+        }
+    }
+    let fret = if flags & 0x20 != 0 { r.i8()? } else { 0 };
+    let mut marks = NoteMarks::default();
+    if flags & 0x80 != 0 {
+        marks.finger = match r.i8()? {
+            0 => Some(Finger::Thumb),
+            1 => Some(Finger::Index),
+            2 => Some(Finger::Middle),
+            3 => Some(Finger::Ring),
+            4 => Some(Finger::Pinky),
+            _ => None,
+        };
+        r.skip(1)?;
+    }
+    if flags & 0x01 != 0 {
+        r.skip(8)?;
+    }
+    r.skip(1)?;
+    if flags & 0x08 != 0 {
+        read_note_effects(r, &mut marks)?;
+    }
+    if string >= track.string_count {
+        return Ok(());
+    }
+    let note = if dead {
+        Note::X
+    } else {
+        Note::Fret(fret.max(0) as u16 + track.capo)
+    };
+    beat.set_note(string, note);
+    if !marks.is_empty() {
+        beat.marks.push((string, marks));
+    }
+    Ok(())
+}
+
+// Returns None for the empty placeholder beats Guitar Pro leaves in unused voices
+fn read_beat(r: &mut Reader, track: &Track, v51: bool) -> Result<Option<Beat>> {
+    let flags = r.u8()?;
+    let empty = flags & 0x40 != 0 && r.u8()? & 0x02 == 0;
+    let mut beat = Beat::new(read_duration(r, flags)?);
+    if flags & 0x02 != 0 {
+        r.skip(17)?;
+        r.byte_string(21)?;
+        r.skip(4 + 4 + 7 * 4 + 32)?;
+    }
+    if flags & 0x04 != 0 {
+        beat.text = non_empty(r.int_byte_string()?);
+    }
+    let effects = if flags & 0x08 != 0 {
+        read_beat_effects(r, &mut beat)?
+    } else {
+        BeatEffects::default()
+    };
+    if flags & 0x10 != 0 {
+        skip_mix_change(r, v51)?;
+    }
+    let strings = r.u8()?;
+    for string in 0..7u16 {
+        if strings & (1 << (6 - string)) != 0 {
+            read_note(r, &mut beat, string, track)?;
+        }
+    }
+    let flags2 = r.i16()?;
+    if flags2 & 0x0800 != 0 {
+        r.skip(1)?;
+    }
+    if effects.swell || effects.tap || effects.pluck.is_some() {
+        for (string, _) in beat.notes.clone() {
+            let i = match beat.marks.iter().position(|m| m.0 == string) {
+                Some(i) => i,
+                None => {
+                    beat.marks.push((string, NoteMarks::default()));
+                    beat.marks.len() - 1
+                }
+            };
+            let marks = &mut beat.marks[i].1;
+            marks.swell |= effects.swell;
+            marks.tap |= effects.tap;
+            marks.pluck = marks.pluck.or(effects.pluck);
+        }
+    }
+    Ok((!empty).then_some(beat))
+}
+
+// Only the first voice is kept, and measures it leaves empty become a whole measure rest
+fn read_measure(r: &mut Reader, track: &mut Track, header: &Header, v51: bool) -> Result<()> {
+    for voice in 0..2 {
+        let mut beats = Vec::new();
+        for _ in 0..r.count()? {
+            if let Some(beat) = read_beat(r, track, v51)? {
+                beats.push(beat);
+            }
+        }
+        if voice == 0 {
+            if beats.is_empty() {
+                let TimeSig { num, den } = header.time_sig;
+                beats.push(Beat::new(Duration::new(num, den)));
+            }
+            track.beats.extend(beats);
+        }
+    }
+    r.skip(1)
+}
+
+pub fn parse(data: &[u8]) -> Result<Song> {
+    let mut r = Reader::new(data);
+    let version = r.byte_string(30)?;
+    if !version.starts_with("FICHIER GUITAR PRO v5") {
+        return Err(Error::ParseError(format!(
+            "Unsupported Guitar Pro version '{version}'"
+        )));
+    }
+    let v51 = !version.ends_with("5.00");
+    let mut song = Song::new();
+    read_song_info(&mut r, &mut song)?;
+    let (tempo, programs) = read_song_settings(&mut r, v51)?;
+    let _ = song.set_tempo(u16::try_from(tempo).ok());
+    let measure_count = r.count()?;
+    let track_count = r.count()?;
+    let headers = read_headers(&mut r, measure_count)?;
+    song.tracks = (0..track_count)
+        .map(|t| read_track(&mut r, t == 0, &programs, v51))
+        .collect::<Result<_>>()?;
+    r.skip(if v51 { 1 } else { 2 })?;
+    for header in &headers {
+        for track in song.tracks.iter_mut() {
+            read_measure(&mut r, track, header, v51)?;
+        }
+    }
+    // Triplet feel is a 2:1 swing
+    if let Some(header) = headers.first() {
+        song.time_sig = header.time_sig;
+        song.swing = header.triplet_feel.then_some(67);
+    }
+    for track in song.tracks.iter_mut() {
+        if track.beats.is_empty() {
+            track.beats.push(Beat::new(Duration::new(1, 1)));
+        }
+    }
+    if song.tracks.is_empty() {
+        return Err(Error::ParseError("No tracks in gp5 file".into()));
+    }
+    song.update_all_measures();
+    Ok(song)
+}
+
+// Export
+
+const VERSION: &str = "FICHIER GUITAR PRO v5.00";
+
+const PAGE_TEMPLATES: [&str; 10] = [
+    "%TITLE%",
+    "%SUBTITLE%",
+    "%ARTIST%",
+    "%ALBUM%",
+    "Words by %WORDS%",
+    "Music by %MUSIC%",
+    "Words & Music by %WORDSMUSIC%",
+    "Copyright %COPYRIGHT%",
+    "All Rights Reserved - International Copyright Secured",
+    "Page %N%/%P%",
+];
+
+#[derive(Default)]
+struct Writer {
+    data: Vec<u8>,
+}
+
+impl Writer {
+    fn u8(&mut self, v: u8) {
+        self.data.push(v);
+    }
+
+    fn i8(&mut self, v: i8) {
+        self.data.push(v as u8);
+    }
+
+    fn i16(&mut self, v: i16) {
+        self.data.extend(v.to_le_bytes());
+    }
+
+    fn i32(&mut self, v: i32) {
+        self.data.extend(v.to_le_bytes());
+    }
+
+    fn zeros(&mut self, n: usize) {
+        self.data.extend(std::iter::repeat_n(0, n));
+    }
+
+    fn byte_string(&mut self, s: &str, size: usize) {
+        let bytes = &s.as_bytes()[..s.len().min(size).min(255)];
+        self.u8(bytes.len() as u8);
+        self.data.extend(bytes);
+        self.zeros(size - bytes.len());
+    }
+
+    fn int_byte_string(&mut self, s: &str) {
+        let bytes = &s.as_bytes()[..s.len().min(255)];
+        self.i32(bytes.len() as i32 + 1);
+        self.u8(bytes.len() as u8);
+        self.data.extend(bytes);
+    }
+
+    fn int_string(&mut self, s: &str) {
+        self.i32(s.len() as i32);
+        self.data.extend(s.as_bytes());
+    }
+}
+
+// Notes of an n-tuplet take the time of this many plain ones, the power of two below n,
+// except for duplets which take the time of three
+pub(super) fn tuplet_base(n: u16) -> u16 {
+    match n {
+        2 => 3,
+        n => 1 << (15 - n.leading_zeros()),
+    }
+}
+
+// A note value as Guitar Pro writes it, 1 for whole down to 64
+#[derive(Clone, Copy)]
+pub(super) struct GpDuration {
+    pub value: u16,
+    pub dotted: bool,
+    pub tuplet: Option<u16>,
+}
+
+fn gp_duration(dur: Duration) -> Option<GpDuration> {
+    let Duration(num, den) = dur;
+    let value = |v: u32| (v.is_power_of_two() && v <= 64).then_some(v as u16);
+    let plain = |value| GpDuration {
+        value,
+        dotted: false,
+        tuplet: None,
+    };
+    if num == 1 {
+        if let Some(v) = value(den as u32) {
+            return Some(plain(v));
+        }
+    }
+    if num == 3 && den % 2 == 0 {
+        if let Some(v) = value(den as u32 / 2) {
+            return Some(GpDuration {
+                dotted: true,
+                ..plain(v)
+            });
+        }
+    }
+    for n in [3u16, 5, 6, 7, 9, 10, 11, 12, 13] {
+        let (p, q) = (tuplet_base(n) as u32 * den as u32, n as u32 * num as u32);
+        if let Some(v) = (p % q == 0).then(|| value(p / q)).flatten() {
+            return Some(GpDuration {
+                tuplet: Some(n),
+                ..plain(v)
+            });
+        }
+    }
+    None
+}
+
+// Lengths with no single note value are written as several tied ones
+pub(super) fn gp_durations(dur: Duration) -> Result<Vec<GpDuration>> {
+    let mut parts = Vec::new();
+    let mut rest = dur;
+    while rest > Duration::zero() {
+        if let Some(d) = gp_duration(rest) {
+            parts.push(d);
+            break;
+        }
+        let Some(value) = [1, 2, 4, 8, 16, 32, 64]
+            .into_iter()
+            .find(|v| Duration::new(1, *v) <= rest)
+        else {
+            return Err(Error::InvalidOp(format!(
+                "Length {}/{} has a part shorter than a 64th",
+                dur.0, dur.1
+            )));
+        };
+        parts.push(GpDuration {
+            value,
+            dotted: false,
+            tuplet: None,
+        });
+        rest = rest - Duration::new(1, value);
+    }
+    Ok(parts)
+}
+
+fn write_header(w: &mut Writer, song: &Song) {
+    w.byte_string(VERSION, 30);
+    for field in [
+        &song.meta.title,
+        &None,
+        &song.meta.artist,
+        &song.meta.album,
+        &None,
+        &None,
+        &None,
+        &song.meta.transcriber,
+        &None,
+    ] {
+        w.int_byte_string(field.as_deref().unwrap_or(""));
+    }
+    w.i32(0);
+    // Lyrics
+    w.i32(0);
+    for _ in 0..5 {
+        w.i32(0);
+        w.int_string("");
+    }
+    // Page setup in millimetres
+    for v in [210, 297, 10, 10, 15, 10, 100] {
+        w.i32(v);
+    }
+    w.i16(0x1ff);
+    for template in PAGE_TEMPLATES {
+        w.int_byte_string(template);
+    }
+    w.int_byte_string("Moderate");
+    w.i32(song.tempo() as i32);
+    w.i8(0);
+    w.i32(0);
+    let tracks: Vec<usize> = (0..song.tracks.len()).collect();
+    let channels = song.midi_channels(&tracks);
+    for channel in 0..64 {
+        let program = channels
+            .iter()
+            .position(|c| *c as usize == channel)
+            .and_then(|t| song.tracks[t].program);
+        w.i32(match program {
+            Some(p) => p as i32,
+            None if channel % 16 == 9 => 0,
+            None => DEFAULT_PROGRAM,
+        });
+        for v in [13, 8, 0, 0, 0, 0, 0, 0] {
+            w.u8(v);
+        }
+    }
+    for _ in 0..19 {
+        w.i16(-1);
+    }
+    w.i32(0);
+}
+
+fn write_measure_headers(w: &mut Writer, song: &Song, count: usize) {
+    for i in 0..count {
+        let sig = match i {
+            0 => Some(song.time_sig),
+            _ => song.change_at(i).and_then(|c| c.time_sig),
+        };
+        if i > 0 {
+            w.u8(0);
+        }
+        if let Some(sig) = sig {
+            w.u8(0x03);
+            w.i8(sig.num as i8);
+            w.i8(sig.den as i8);
+            w.data.extend([2, 2, 2, 2]);
+        } else {
+            w.u8(0);
+        }
+        w.u8(0);
+        w.u8(song.swing.is_some() as u8);
+    }
+}
+
+fn write_track(w: &mut Writer, track: &Track, t: usize, channel: u8) {
+    let strings = track.string_count.min(7);
+    w.u8(0);
+    w.u8(0);
+    let name = track
+        .name
+        .clone()
+        .unwrap_or_else(|| format!("Track {}", t + 1));
+    w.byte_string(&name, 40);
+    w.i32(strings as i32);
+    let tuning = track.tuning();
+    for s in 0..7 {
+        w.i32(
+            tuning
+                .get(s)
+                .filter(|_| s < strings as usize)
+                .map_or(0, |p| *p as i32),
+        );
+    }
+    w.i32(1);
+    w.i32(channel as i32 + 1);
+    w.i32(channel as i32 + 1);
+    w.i32(track.fret_count as i32);
+    w.i32(track.capo as i32);
+    w.data.extend([255, 0, 0, 0]);
+    w.i16(0x03);
+    w.zeros(3 + 12 + 12);
+    w.i32(-1);
+    w.i32(-1);
+    w.i32(-1);
+    w.i16(-1);
+    w.u8(0);
+}
+
+fn write_duration(w: &mut Writer, dur: &GpDuration) {
+    w.i8(dur.value.trailing_zeros() as i8 - 2);
+    if let Some(n) = dur.tuplet {
+        w.i32(n as i32);
+    }
+}
+
+// `dive` is the depth of the latest dive, which a return rises from
+fn write_beat_effects(w: &mut Writer, beat: &Beat, dive: u16) {
+    let tap = beat.has_tap();
+    let pluck = beat.pluck();
+    let mut flags1 = 0;
+    let mut flags2 = 0;
+    if beat.has_swell() {
+        flags1 |= 0x10;
+    }
+    if tap || pluck.is_some() {
+        flags1 |= 0x20;
+    }
+    if beat.arpeggio.is_some() {
+        flags1 |= 0x40;
+    }
+    if beat.whammy.is_some() {
+        flags2 |= 0x04;
+    }
+    if beat.stroke.is_some() {
+        flags2 |= 0x02;
+    }
+    w.u8(flags1);
+    w.u8(flags2);
+    if flags1 & 0x20 != 0 {
+        w.u8(match pluck {
+            _ if tap => 1,
+            Some(Pluck::Slap) => 2,
+            _ => 3,
+        });
+    }
+    if let Some(whammy) = beat.whammy {
+        let points = match whammy {
+            Whammy::Dive(depth) => [0, -25 * depth as i32],
+            Whammy::Return => [-25 * dive.max(1) as i32, 0],
+        };
+        w.u8(6);
+        w.i32(points[0].min(points[1]));
+        w.i32(2);
+        for (position, value) in [0, 60].into_iter().zip(points) {
+            w.i32(position);
+            w.i32(value);
+            w.u8(0);
+        }
+    }
+    match beat.arpeggio {
+        Some(Arpeggio::Up) => w.data.extend([2, 0]),
+        Some(Arpeggio::Down) => w.data.extend([0, 2]),
+        None => (),
+    }
+    match beat.stroke {
+        Some(Stroke::Up) => w.u8(1),
+        Some(Stroke::Down) => w.u8(2),
+        None => (),
+    }
+}
+
+// Later parts of a split beat only carry its notes on, tied
+// Changes nothing but the tempo, at once
+fn write_mix_change(w: &mut Writer, tempo: u16) {
+    w.i8(-1);
+    w.zeros(16);
+    for _ in 0..6 {
+        w.i8(-1);
+    }
+    w.int_byte_string("");
+    w.i32(tempo as i32);
+    w.u8(0);
+    w.u8(0);
+    w.i8(-1);
+}
+
+fn write_beat(
+    w: &mut Writer,
+    beat: &Beat,
+    dur: &GpDuration,
+    track: &Track,
+    tied: bool,
+    dive: u16,
+    tempo: Option<u16>,
+) {
+    let rest = beat.notes.is_empty();
+    let effects = !tied && beat.has_decoration();
+    let text = beat.text.as_deref().filter(|_| !tied);
+    let mut flags = 0;
+    if dur.dotted {
+        flags |= 0x01;
+    }
+    if text.is_some() {
+        flags |= 0x04;
+    }
+    if effects {
+        flags |= 0x08;
+    }
+    if tempo.is_some() {
+        flags |= 0x10;
+    }
+    if dur.tuplet.is_some() {
+        flags |= 0x20;
+    }
+    if rest {
+        flags |= 0x40;
+    }
+    w.u8(flags);
+    if rest {
+        w.u8(0x02);
+    }
+    write_duration(w, dur);
+    if let Some(text) = text {
+        w.int_byte_string(text);
+    }
+    if effects {
+        write_beat_effects(w, beat, dive);
+    }
+    if let Some(tempo) = tempo {
+        write_mix_change(w, tempo);
+    }
+    let strings = (0..track.string_count.min(7))
+        .filter(|s| beat.get_note(*s).is_some())
+        .fold(0u8, |f, s| f | 1 << (6 - s));
+    w.u8(strings);
+    for string in 0..track.string_count.min(7) {
+        if let Some(note) = beat.get_note(string) {
+            write_note(w, beat, string, note, track, tied);
+        }
+    }
+    w.i16(0);
+}
+
+fn write_note(w: &mut Writer, beat: &Beat, string: u16, note: &Note, track: &Track, tied: bool) {
+    let marks = beat.marks.iter().find(|m| m.0 == string).map(|m| &m.1);
+    let finger = marks.and_then(|m| m.finger).filter(|_| !tied);
+    let trill = marks.and_then(|m| m.trill).filter(|_| !tied);
+    let mut flags = 0x20;
+    if beat.velocity.is_some() {
+        flags |= 0x10;
+    }
+    if trill.is_some() {
+        flags |= 0x08;
+    }
+    if finger.is_some() {
+        flags |= 0x80;
+    }
+    w.u8(flags);
+    w.u8(match note {
+        Note::X => 3,
+        _ if tied => 2,
+        _ => 1,
+    });
+    if let Some(velocity) = beat.velocity {
+        w.u8(((velocity.max(15) - 15 + 8) / 16 + 1).min(8));
+    }
+    w.i8(match note {
+        Note::Fret(fret) => fret.saturating_sub(track.capo).min(99) as i8,
+        Note::X => 0,
+    });
+    if let Some(finger) = finger {
+        w.i8(match finger {
+            Finger::Thumb => 0,
+            Finger::Index => 1,
+            Finger::Middle => 2,
+            Finger::Ring => 3,
+            Finger::Pinky => 4,
+        });
+        w.i8(-1);
+    }
+    w.u8(0);
+    if let Some(trill) = trill {
+        w.u8(0);
+        w.u8(0x20);
+        w.i8(trill.min(99) as i8);
+        w.i8(1);
+    }
+}
+
+// Tracks are cut into measures where they start here, padding shorter ones with empty measures
+fn render(song: &Song) -> Result<Vec<u8>> {
+    let measures: Vec<Vec<&[Beat]>> = song
+        .tracks
+        .iter()
+        .map(|track| {
+            let starts: Vec<usize> = (0..track.beats.len())
+                .filter(|i| track.measure_i[*i])
+                .chain([track.beats.len()])
+                .collect();
+            starts
+                .windows(2)
+                .map(|m| &track.beats[m[0]..m[1]])
+                .collect()
+        })
+        .collect();
+    let count = measures.iter().map(|m| m.len()).max().unwrap_or(0);
+    let mut w = Writer::default();
+    write_header(&mut w, song);
+    w.i32(count as i32);
+    w.i32(song.tracks.len() as i32);
+    write_measure_headers(&mut w, song, count);
+    let tracks: Vec<usize> = (0..song.tracks.len()).collect();
+    for (t, channel) in song.midi_channels(&tracks).into_iter().enumerate() {
+        write_track(&mut w, &song.tracks[t], t, channel);
+    }
+    w.zeros(2);
+    let mut dives = vec![0; song.tracks.len()];
+    for m in 0..count {
+        for (t, track) in song.tracks.iter().enumerate() {
+            let beats = measures[t].get(m).copied().unwrap_or_default();
+            let mut parts = Vec::new();
+            for beat in beats {
+                for (i, dur) in gp_durations(beat.dur)?.into_iter().enumerate() {
+                    parts.push((beat, dur, i > 0));
+                }
+            }
+            w.i32(parts.len() as i32);
+            // Tempo changes go on the first beat of the measure in the first track
+            let mut tempo = song.change_at(m).and_then(|c| c.tempo).filter(|_| t == 0);
+            for (beat, dur, tied) in parts {
+                write_beat(&mut w, beat, &dur, track, tied, dives[t], tempo.take());
+                match beat.whammy {
+                    Some(Whammy::Dive(depth)) => dives[t] = depth,
+                    Some(Whammy::Return) => dives[t] = 0,
+                    None => (),
+                }
+            }
+            w.i32(0);
+            w.u8(0);
+        }
+    }
+    Ok(w.data)
+}
+
+impl TabFormat for Gp5 {
+    fn name(&self) -> &'static str {
+        "gp5"
+    }
+
+    fn extensions(&self) -> &'static [&'static str] {
+        &["gp5"]
+    }
+
+    fn can_read(&self) -> bool {
+        true
+    }
+
+    fn read(&self, data: &[u8]) -> Result<Song> {
+        parse(data)
+    }
+
+    fn write(&self, song: &Song) -> Result<Vec<u8>> {
+        render(song)
+    }
+}
@@ -0,0 +1,436 @@
+use crate::{
+    dur::Duration,
+    error::{Error, Result},
+    formats::{
+        self,
+        xml::{self, Elem},
+        TabFormat,
+    },
+    pitch::{self, Key},
+    song::{Arpeggio, Beat, Finger, Note, NoteMarks, Pluck, Song, Stroke, TimeSig, Track, Whammy},
+};
+use std::collections::HashMap;
+use std::io::Read;
+
+const SCORE_PATH: &str = "Content/score.gpif";
+
+pub struct Gp7;
+
+fn parse_xml(xml: &str) -> Result<Elem> {
+    xml::parse(xml)?
+        .children
+        .into_iter()
+        .find(|c| c.name == "GPIF")
+        .ok_or_else(|| Error::ParseError("No GPIF score in gp file".into()))
+}
+
+fn read_score(data: &[u8]) -> Result<String> {
+    let err = |e: zip::result::ZipError| Error::ParseError(format!("Bad gp archive: {e}"));
+    let mut archive = zip::ZipArchive::new(std::io::Cursor::new(data)).map_err(err)?;
+    let mut file = archive.by_name(SCORE_PATH).map_err(err)?;
+    let mut xml = String::new();
+    file.read_to_string(&mut xml)
+        .map_err(|e| Error::ParseError(format!("Bad gp archive: {e}")))?;
+    Ok(xml)
+}
+
+// Elements are referenced by id from the level above
+fn by_id<'a>(gpif: &'a Elem, list: &str, item: &'a str) -> HashMap<i64, &'a Elem> {
+    gpif.child(list)
+        .map(|l| {
+            l.children(item)
+                .filter_map(|e| Some((e.attr("id")?.parse().ok()?, e)))
+                .collect()
+        })
+        .unwrap_or_default()
+}
+
+fn read_duration(rhythm: &Elem) -> Duration {
+    let den = match rhythm.text_of("NoteValue").unwrap_or("Quarter") {
+        "Whole" => 1,
+        "Half" => 2,
+        "Eighth" => 8,
+        "16th" => 16,
+        "32nd" => 32,
+        "64th" | "128th" => 64,
+        _ => 4,
+    };
+    let mut dur = Duration::new(1, den);
+    let dots = rhythm
+        .child("AugmentationDot")
+        .and_then(|d| d.attr("count")?.parse().ok())
+        .unwrap_or(0);
+    for _ in 0..dots {
+        dur = dur + Duration::new(dur.0, dur.1 * 2);
+    }
+    if let Some(tuplet) = rhythm.child("PrimaryTuplet") {
+        let num: u16 = tuplet.attr("num").and_then(|n| n.parse().ok()).unwrap_or(1);
+        let den: u16 = tuplet.attr("den").and_then(|n| n.parse().ok()).unwrap_or(1);
+        dur = Duration::new(dur.0 * den.max(1), dur.1 * num.max(1));
+    }
+    dur
+}
+
+struct Ctx<'a> {
+    notes: HashMap<i64, &'a Elem>,
+    rhythms: HashMap<i64, &'a Elem>,
+}
+
+// String numbers count up from the lowest string, unlike here
+fn read_note(ctx: &Ctx, beat: &mut Beat, id: i64, track: &Track) -> Option<()> {
+    let note = ctx.notes.get(&id)?;
+    let string: u16 = note.property_value("String")?.parse().ok()?;
+    let string = track.string_count.checked_sub(string + 1)?;
+    let fret: u16 = note.property_value("Fret")?.parse().ok()?;
+    if note.property("Muted").is_some() {
+        beat.set_note(string, Note::X);
+    } else {
+        beat.set_note(string, Note::Fret(fret + track.capo));
+    }
+    let marks = NoteMarks {
+        finger: match note.text_of("LeftFingering") {
+            Some("P") => Some(Finger::Thumb),
+            Some("I") => Some(Finger::Index),
+            Some("M") => Some(Finger::Middle),
+            Some("A") => Some(Finger::Ring),
+            Some("C") => Some(Finger::Pinky),
+            _ => None,
+        },
+        trill: note.text_of("Trill").and_then(|t| t.parse().ok()),
+        tap: note.property("Tapped").is_some(),
+        pluck: if note.property("Slapped").is_some() {
+            Some(Pluck::Slap)
+        } else if note.property("Popped").is_some() {
+            Some(Pluck::Pop)
+        } else {
+            None
+        },
+        ..Default::default()
+    };
+    if !marks.is_empty() {
+        beat.marks.push((string, marks));
+    }
+    Some(())
+}
+
+// Whammy values are in hundredths of a whole tone, and a bar rising back to 0 is a return
+fn read_whammy(beat: &Elem) -> Option<Whammy> {
+    beat.property("WhammyBar")?;
+    let value = |name| {
+        beat.property_value(name)
+            .and_then(|v| v.parse::<f64>().ok())
+            .unwrap_or(0.0)
+    };
+    let origin = value("WhammyBarOriginValue");
+    let middle = value("WhammyBarMiddleValue");
+    let dest = value("WhammyBarDestinationValue");
+    let dip = origin.min(middle).min(dest);
+    if origin < 0.0 && dest == 0.0 {
+        Some(Whammy::Return)
+    } else if dip < 0.0 {
+        Some(Whammy::Dive((-dip / 50.0).round().clamp(1.0, 24.0) as u16))
+    } else {
+        None
+    }
+}
+
+fn read_beat(ctx: &Ctx, elem: &Elem, track: &Track) -> Beat {
+    let dur = elem
+        .child("Rhythm")
+        .and_then(|r| r.attr("ref")?.parse().ok())
+        .and_then(|id| ctx.rhythms.get(&id))
+        .map_or(Duration::new(1, 4), |r| read_duration(r));
+    let mut beat = Beat::new(dur);
+    for id in elem.ids("Notes") {
+        read_note(ctx, &mut beat, id, track);
+    }
+    beat.text = elem.text_of("FreeText").map(str::to_owned);
+    beat.velocity = elem.text_of("Dynamic").and_then(formats::velocity);
+    beat.arpeggio = match elem
+        .text_of("Arpeggio")
+        .or_else(|| elem.property("Brush")?.text_of("Direction"))
+    {
+        Some("Up") => Some(Arpeggio::Up),
+        Some("Down") => Some(Arpeggio::Down),
+        _ => None,
+    };
+    beat.stroke = match elem
+        .property("PickStroke")
+        .and_then(|p| p.text_of("Direction"))
+    {
+        Some("Up") => Some(Stroke::Up),
+        Some("Down") => Some(Stroke::Down),
+        _ => None,
+    };
+    beat.whammy = read_whammy(elem);
+    if elem.text_of("Fadding") == Some("FadeIn") {
+        for (string, _) in beat.notes.clone() {
+            match beat.marks.iter_mut().find(|m| m.0 == string) {
+                Some((_, marks)) => marks.swell = true,
+                None => beat.marks.push((
+                    string,
+                    NoteMarks {
+                        swell: true,
+                        ..Default::default()
+                    },
+                )),
+            }
+        }
+    }
+    beat
+}
+
+// Guitar Pro 7 keeps tuning on the staff, older scores on the track itself
+fn read_track(elem: &Elem) -> Track {
+    let props = elem
+        .path(&["Staves", "Staff"])
+        .filter(|s| s.child("Properties").is_some())
+        .unwrap_or(elem);
+    let mut tuning: Vec<u8> = props
+        .property("Tuning")
+        .and_then(|p| p.text_of("Pitches"))
+        .map(|t| {
+            t.split_whitespace()
+                .filter_map(|p| p.parse().ok())
+                .collect()
+        })
+        .unwrap_or_default();
+    tuning.reverse();
+    let mut track = Track::new();
+    if !tuning.is_empty() {
+        track.string_count = tuning.len() as u16;
+        if tuning != pitch::standard_tuning(track.string_count) {
+            track.tuning = tuning;
+        }
+    }
+    track.capo = props
+        .property("CapoFret")
+        .and_then(|p| p.text_of("Fret")?.parse().ok())
+        .unwrap_or(0);
+    track.beats.clear();
+    track
+}
+
+// Key signatures are counted in sharps (or flats when negative) from C major
+fn read_key(bar: &Elem) -> Option<Key> {
+    let key = bar.child("Key")?;
+    let fifths: i32 = key.text_of("AccidentalCount")?.parse().ok()?;
+    let minor = key.text_of("Mode") == Some("Minor");
+    let major = (fifths * 7).rem_euclid(12) as u8;
+    Some(Key::new(
+        if minor { (major + 9) % 12 } else { major },
+        minor,
+    ))
+}
+
+// Signatures the editor cannot hold, such as 4/0, make the file unreadable
+fn read_time_sig(bar: &Elem) -> Result<Option<TimeSig>> {
+    bar.text_of("Time").map(str::parse).transpose()
+}
+
+// Only the first voice of the first staff of each track is kept
+pub fn parse(data: &[u8]) -> Result<Song> {
+    let gpif = parse_xml(&read_score(data)?)?;
+    let ctx = Ctx {
+        notes: by_id(&gpif, "Notes", "Note"),
+        rhythms: by_id(&gpif, "Rhythms", "Rhythm"),
+    };
+    let bars = by_id(&gpif, "Bars", "Bar");
+    let voices = by_id(&gpif, "Voices", "Voice");
+    let beats = by_id(&gpif, "Beats", "Beat");
+
+    let mut song = Song::new();
+    if let Some(score) = gpif.child("Score") {
+        let field = |name| score.text_of(name).map(str::to_owned);
+        song.meta.title = field("Title");
+        song.meta.artist = field("Artist");
+        song.meta.album = field("Album");
+        song.meta.transcriber = field("Tabber");
+    }
+    let track_elems: Vec<&Elem> = gpif
+        .child("Tracks")
+        .map(|t| t.children("Track").collect())
+        .unwrap_or_default();
+    song.tracks = track_elems.iter().map(|t| read_track(t)).collect();
+    if song.tracks.is_empty() {
+        return Err(Error::ParseError("No tracks in gp file".into()));
+    }
+    // Bars of a master bar are listed staff by staff
+    let mut first_staff = Vec::new();
+    let mut staff = 0;
+    for elem in track_elems.iter() {
+        first_staff.push(staff);
+        staff += elem
+            .child("Staves")
+            .map_or(1, |s| s.children("Staff").count().max(1));
+    }
+
+    let master_bars: Vec<&Elem> = gpif
+        .child("MasterBars")
+        .map(|m| m.children("MasterBar").collect())
+        .unwrap_or_default();
+    if let Some(first) = master_bars.first() {
+        song.time_sig = read_time_sig(first)?.unwrap_or_default();
+        song.key = read_key(first);
+        song.swing = first.child("TripletFeel").map(|_| 67);
+    }
+    let mut time_sig = song.time_sig;
+    for master in master_bars.iter() {
+        time_sig = read_time_sig(master)?.unwrap_or(time_sig);
+        let bar_ids = master.ids("Bars");
+        for (t, track) in song.tracks.iter_mut().enumerate() {
+            let before = track.beats.len();
+            let voice = bar_ids
+                .get(first_staff[t])
+                .and_then(|id| bars.get(id))
+                .and_then(|bar| bar.ids("Voices").into_iter().find(|v| *v >= 0))
+                .and_then(|id| voices.get(&id));
+            for id in voice.map(|v| v.ids("Beats")).unwrap_or_default() {
+                if let Some(elem) = beats.get(&id) {
+                    let beat = read_beat(&ctx, elem, track);
+                    track.beats.push(beat);
+                }
+            }
+            if track.beats.len() == before {
+                track
+                    .beats
+                    .push(Beat::new(Duration::new(time_sig.num, time_sig.den)));
+            }
+        }
+    }
+    for track in song.tracks.iter_mut() {
+        if track.beats.is_empty() {
+            track.beats.push(Beat::new(Duration::new(1, 1)));
+        }
+    }
+    song.update_all_measures();
+    Ok(song)
+}
+
+impl TabFormat for Gp7 {
+    fn name(&self) -> &'static str {
+        "gp"
+    }
+
+    fn extensions(&self) -> &'static [&'static str] {
+        &["gp"]
+    }
+
+    fn can_read(&self) -> bool {
+        true
+    }
+
+    fn can_write(&self) -> bool {
+        false
+    }
+
+    fn read(&self, data: &[u8]) -> Result<Song> {
+        parse(data)
+    }
+
+    fn write(&self, _song: &Song) -> Result<Vec<u8>> {
+        Err(Error::InvalidOp("Cannot export to gp files".into()))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::io::Write;
+
+    // One dropped D track with a capo, over a bar of 3/4 and an empty one
+    const GPIF: &str = r#"<?xml version="1.0" encoding="utf-8"?>
+<GPIF>
+  <Score><Title>Gp Test</Title><Artist>Someone</Artist></Score>
+  <MasterTrack><Tracks>0</Tracks></MasterTrack>
+  <Tracks>
+    <Track id="0">
+      <Name>Guitar</Name>
+      <Staves><Staff><Properties>
+        <Property name="Tuning"><Pitches>38 45 50 55 59 64</Pitches></Property>
+        <Property name="CapoFret"><Fret>1</Fret></Property>
+      </Properties></Staff></Staves>
+    </Track>
+  </Tracks>
+  <MasterBars>
+    <MasterBar><Key><AccidentalCount>2</AccidentalCount><Mode>Major</Mode></Key><Time>3/4</Time><Bars>0</Bars></MasterBar>
+    <MasterBar><Time>3/4</Time><Bars>1</Bars></MasterBar>
+  </MasterBars>
+  <Bars>
+    <Bar id="0"><Voices>0 -1 -1 -1</Voices></Bar>
+    <Bar id="1"><Voices>-1 -1 -1 -1</Voices></Bar>
+  </Bars>
+  <Voices><Voice id="0"><Beats>0 1</Beats></Voice></Voices>
+  <Beats>
+    <Beat id="0"><Rhythm ref="0"/><Notes>0 1</Notes><FreeText>let ring</FreeText></Beat>
+    <Beat id="1"><Rhythm ref="1"/><Notes>2</Notes></Beat>
+  </Beats>
+  <Notes>
+    <Note id="0"><Properties>
+      <Property name="String"><String>0</String></Property>
+      <Property name="Fret"><Fret>2</Fret></Property>
+    </Properties></Note>
+    <Note id="1"><LeftFingering>A</LeftFingering><Properties>
+      <Property name="String"><String>1</String></Property>
+      <Property name="Fret"><Fret>4</Fret></Property>
+    </Properties></Note>
+    <Note id="2"><Properties>
+      <Property name="String"><String>5</String></Property>
+      <Property name="Fret"><Fret>0</Fret></Property>
+      <Property name="Muted"><Enable/></Property>
+    </Properties></Note>
+  </Notes>
+  <Rhythms>
+    <Rhythm id="0"><NoteValue>Half</NoteValue></Rhythm>
+    <Rhythm id="1"><NoteValue>Eighth</NoteValue><AugmentationDot count="1"/></Rhythm>
+  </Rhythms>
+</GPIF>"#;
+
+    fn archive(gpif: &str) -> Vec<u8> {
+        let mut zip = zip::ZipWriter::new(std::io::Cursor::new(Vec::new()));
+        zip.start_file(SCORE_PATH, zip::write::FileOptions::default())
+            .unwrap();
+        zip.write_all(gpif.as_bytes()).unwrap();
+        zip.finish().unwrap().into_inner()
+    }
+
+    #[test]
+    fn reads_score() {
+        let song = parse(&archive(GPIF)).unwrap();
+        assert_eq!(song.meta.title.as_deref(), Some("Gp Test"));
+        assert_eq!((song.time_sig.num, song.time_sig.den), (3, 4));
+        assert!(song.key == Some(Key::new(2, false)));
+        let track = &song.tracks[0];
+        assert_eq!(track.string_count, 6);
+        assert_eq!(track.capo, 1);
+        assert_eq!(track.tuning(), vec![64, 59, 55, 50, 45, 38]);
+        let beats = &track.beats;
+        assert_eq!(beats.len(), 3);
+        assert!(beats[0].get_note(5) == Some(&Note::Fret(3)));
+        assert!(beats[0].get_note(4) == Some(&Note::Fret(5)));
+        assert!(matches!(
+            beats[0].marks.as_slice(),
+            [(
+                4,
+                NoteMarks {
+                    finger: Some(Finger::Ring),
+                    ..
+                }
+            )]
+        ));
+        assert_eq!(beats[0].text.as_deref(), Some("let ring"));
+        assert!(beats[1].get_note(0) == Some(&Note::X));
+        assert_eq!((beats[1].dur.0, beats[1].dur.1), (3, 16));
+        // The bar with no voices is filled with a rest the length of the measure
+        assert_eq!((beats[2].dur.0, beats[2].dur.1), (3, 4));
+        assert!(beats[2].notes.is_empty());
+    }
+
+    #[test]
+    fn zero_denominator_refused() {
+        let gpif = GPIF.replacen("<Time>3/4</Time>", "<Time>4/0</Time>", 1);
+        assert!(matches!(parse(&archive(&gpif)), Err(Error::ParseError(_))));
+        let gpif = GPIF.replace("<Time>3/4</Time><Bars>1", "<Time>4/0</Time><Bars>1");
+        assert!(matches!(parse(&archive(&gpif)), Err(Error::ParseError(_))));
+    }
+}
@@ -0,0 +1,86 @@
+use crate::{
+    error::{Error, Result},
+    formats::TabFormat,
+    song::Song,
+};
+use flate2::{read::GzDecoder, write::GzEncoder, Compression};
+use std::io::{Read, Write};
+
+// Gzip streams start with these bytes, so compressed songs load whatever their extension
+const GZIP_MAGIC: [u8; 2] = [0x1f, 0x8b];
+
+fn decompress(data: &[u8]) -> Result<Vec<u8>> {
+    let mut out = Vec::new();
+    GzDecoder::new(data)
+        .read_to_end(&mut out)
+        .map_err(|e| Error::ParseError(format!("Bad gzip data: {e}")))?;
+    Ok(out)
+}
+
+pub struct Json;
+
+impl TabFormat for Json {
+    fn name(&self) -> &'static str {
+        "json"
+    }
+
+    fn extensions(&self) -> &'static [&'static str] {
+        &["json"]
+    }
+
+    fn can_read(&self) -> bool {
+        true
+    }
+
+    fn lossless(&self) -> bool {
+        true
+    }
+
+    fn read(&self, data: &[u8]) -> Result<Song> {
+        let mut song: Song = if data.starts_with(&GZIP_MAGIC) {
+            serde_json::from_slice(&decompress(data)?)
+        } else {
+            serde_json::from_slice(data)
+        }
+        .map_err(|e| Error::ParseError(e.to_string()))?;
+        song.update_all_measures();
+        Ok(song)
+    }
+
+    fn write(&self, song: &Song) -> Result<Vec<u8>> {
+        serde_json::to_vec(song).map_err(|e| Error::FileError(e.to_string()))
+    }
+}
+
+// The json song compressed with gzip, for long songs with many tracks
+pub struct JsonGz;
+
+impl TabFormat for JsonGz {
+    fn name(&self) -> &'static str {
+        "tab.gz"
+    }
+
+    fn extensions(&self) -> &'static [&'static str] {
+        &["tab.gz", "gz"]
+    }
+
+    fn can_read(&self) -> bool {
+        true
+    }
+
+    fn lossless(&self) -> bool {
+        true
+    }
+
+    fn read(&self, data: &[u8]) -> Result<Song> {
+        Json.read(data)
+    }
+
+    fn write(&self, song: &Song) -> Result<Vec<u8>> {
+        let mut encoder = GzEncoder::new(Vec::new(), Compression::default());
+        encoder
+            .write_all(&Json.write(song)?)
+            .and_then(|_| encoder.finish())
+            .map_err(|e| Error::FileError(e.to_string()))
+    }
+}
@@ -0,0 +1,346 @@
+use crate::{
+    error::{Error, Result},
+    formats::{ascii, text, TabFormat},
+    song::{Color, Song},
+};
+use serde::Serialize;
+
+// ASCII tab lines with the time each beat is played, for players that scroll along with the music
+pub struct Karaoke {
+    title: String,
+    width: usize,
+}
+
+impl Karaoke {
+    pub fn new(title: String, width: usize) -> Self {
+        Self { title, width }
+    }
+}
+
+#[derive(Serialize)]
+struct Cue {
+    time: f64,
+    column: usize,
+    measure: usize,
+}
+
+// Characters of a row taken by a colored fret
+#[derive(Serialize)]
+struct Emphasis {
+    row: usize,
+    column: usize,
+    width: usize,
+    color: Color,
+}
+
+#[derive(Serialize)]
+struct Line {
+    track: usize,
+    start: f64,
+    end: f64,
+    rows: Vec<String>,
+    beats: Vec<Cue>,
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    colors: Vec<Emphasis>,
+}
+
+#[derive(Serialize)]
+struct Timed<'a> {
+    title: &'a str,
+    tempo: u16,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    swing: Option<u16>,
+    length: f64,
+    lines: Vec<Line>,
+}
+
+// Times are in seconds, rounded to milliseconds
+fn ms(time: f64) -> f64 {
+    (time * 1000.0).round() / 1000.0
+}
+
+fn render<'a>(song: &'a Song, title: &'a str, width: usize) -> Timed<'a> {
+    let mut lines = Vec::new();
+    let mut length: f64 = 0.0;
+    for (t, track) in song.tracks.iter().enumerate() {
+        let times = song.beat_times(t);
+        length = length.max(*times.last().unwrap());
+        let ranges = text::split_lines(track, ascii::line_width(track, width), |i| {
+            ascii::beat_width(track, i)
+        });
+        for range in ranges {
+            let columns = ascii::beat_columns(track, range.clone());
+            let mut colors = Vec::new();
+            for (i, column) in range.clone().zip(columns.iter().copied()) {
+                let beat = &track.beats[i];
+                for (string, note) in &beat.notes {
+                    if let Some(color) = beat.get_color(*string) {
+                        colors.push(Emphasis {
+                            row: *string as usize,
+                            column,
+                            width: ascii::fret_text(Some(note)).len(),
+                            color,
+                        });
+                    }
+                }
+            }
+            let beats = range
+                .clone()
+                .zip(columns)
+                .map(|(i, column)| Cue {
+                    time: ms(times[i]),
+                    column,
+                    measure: track.measure_of(i) + 1,
+                })
+                .collect();
+            lines.push(Line {
+                track: t + 1,
+                start: ms(times[range.start]),
+                end: ms(times[range.end]),
+                rows: ascii::render_line(track, range),
+                beats,
+                colors,
+            });
+        }
+    }
+    Timed {
+        title: song.meta.title.as_deref().unwrap_or(title),
+        tempo: song.tempo(),
+        swing: song.swing,
+        length: ms(length),
+        lines,
+    }
+}
+
+// The same lines and times in a web page that plays them back, scrolling each line into view
+// and marking the beat being played
+pub struct KaraokeHtml {
+    title: String,
+    width: usize,
+}
+
+impl KaraokeHtml {
+    pub fn new(title: String, width: usize) -> Self {
+        Self { title, width }
+    }
+}
+
+fn escape(text: &str) -> String {
+    text.replace('&', "&amp;")
+        .replace('<', "&lt;")
+        .replace('>', "&gt;")
+        .replace('"', "&quot;")
+}
+
+const SCRIPT: &str = r#"
+const cues = JSON.parse(document.getElementById("cues").textContent);
+const lines = document.querySelectorAll(".line");
+const button = document.getElementById("play");
+let start = null;
+let shown = -1;
+
+function frame(now) {
+  if (start === null) return;
+  const time = (now - start) / 1000;
+  const i = cues.lines.findIndex((l) => time >= l.start && time < l.end);
+  if (i !== shown) {
+    if (shown >= 0) lines[shown].classList.remove("now");
+    if (i >= 0) {
+      lines[i].classList.add("now");
+      lines[i].scrollIntoView({ behavior: "smooth", block: "center" });
+    }
+    shown = i;
+  }
+  if (i >= 0) {
+    const beats = cues.lines[i].beats.filter((b) => b.time <= time);
+    const column = beats.length ? beats[beats.length - 1].column : 0;
+    lines[i].querySelector(".mark").textContent = " ".repeat(column) + "^";
+  }
+  if (time > cues.length) return stop();
+  requestAnimationFrame(frame);
+}
+
+function stop() {
+  start = null;
+  button.textContent = "Play";
+  document.querySelectorAll(".mark").forEach((m) => (m.textContent = ""));
+  if (shown >= 0) lines[shown].classList.remove("now");
+  shown = -1;
+}
+
+button.onclick = () => {
+  if (start !== null) return stop();
+  start = performance.now();
+  button.textContent = "Stop";
+  requestAnimationFrame(frame);
+};
+"#;
+
+// Escaped rows of a line, with colored frets in spans of their color
+fn html_rows(line: &Line) -> String {
+    let rows: Vec<String> = line
+        .rows
+        .iter()
+        .enumerate()
+        .map(|(r, row)| {
+            let chars: Vec<char> = row.chars().collect();
+            let mut colors: Vec<&Emphasis> = line.colors.iter().filter(|c| c.row == r).collect();
+            colors.sort_by_key(|c| c.column);
+            let mut out = String::new();
+            let mut col = 0;
+            for c in colors {
+                let text: String = chars[col..c.column].iter().collect();
+                let fret: String = chars[c.column..c.column + c.width].iter().collect();
+                out.push_str(&escape(&text));
+                out.push_str(&format!(
+                    "<span class=\"{}\">{}</span>",
+                    c.color.name(),
+                    escape(&fret)
+                ));
+                col = c.column + c.width;
+            }
+            let rest: String = chars[col..].iter().collect();
+            out.push_str(&escape(&rest));
+            out
+        })
+        .collect();
+    rows.join("\n")
+}
+
+fn render_html(song: &Song, title: &str, width: usize) -> Result<String> {
+    let timed = render(song, title, width);
+    // Kept from closing the script element it is embedded in
+    let cues = serde_json::to_string(&timed)
+        .map_err(|e| Error::FileError(e.to_string()))?
+        .replace("</", "<\\/");
+    let title = escape(timed.title);
+    let colors: String = Color::ALL
+        .iter()
+        .map(|c| format!(".{0} {{ color: {0}; font-weight: bold; }}\n", c.name()))
+        .collect();
+    let mut out = format!(
+        "<!DOCTYPE html>\n<html>\n<head>\n<meta charset=\"utf-8\">\n<title>{title}</title>\n\
+         <style>\n.line {{ opacity: 0.5; }}\n.now {{ opacity: 1; }}\n.mark {{ color: red; }}\n{colors}</style>\n\
+         </head>\n<body>\n<h1>{title}</h1>\n<button id=\"play\">Play</button>\n"
+    );
+    for line in &timed.lines {
+        out.push_str(&format!(
+            "<pre class=\"line\" data-track=\"{}\">{}\n<span class=\"mark\"></span></pre>\n",
+            line.track,
+            html_rows(line)
+        ));
+    }
+    out.push_str(&format!(
+        "<script id=\"cues\" type=\"application/json\">{cues}</script>\n<script>{SCRIPT}</script>\n</body>\n</html>\n"
+    ));
+    Ok(out)
+}
+
+// The ASCII tab lines of each track in a standalone web page for sharing, without playback,
+// where tracks can be hidden from view and for printing
+pub struct Html {
+    title: String,
+    width: usize,
+}
+
+impl Html {
+    pub fn new(title: String, width: usize) -> Self {
+        Self { title, width }
+    }
+}
+
+const TOGGLE_SCRIPT: &str = r#"
+document.querySelectorAll("nav input").forEach((input) => {
+  input.onchange = () => {
+    const track = document.getElementById("track" + input.value);
+    track.hidden = !input.checked;
+  };
+});
+"#;
+
+fn render_page(song: &Song, title: &str, width: usize) -> String {
+    let timed = render(song, title, width);
+    let header = if song.meta.is_empty() {
+        escape(title)
+    } else {
+        escape(&song.meta.header())
+    };
+    let colors: String = Color::ALL
+        .iter()
+        .map(|c| format!(".{0} {{ color: {0}; font-weight: bold; }}\n", c.name()))
+        .collect();
+    let mut out = format!(
+        "<!DOCTYPE html>\n<html>\n<head>\n<meta charset=\"utf-8\">\n<title>{}</title>\n\
+         <style>\npre {{ margin: 0 0 1em; }}\n@media print {{ nav {{ display: none; }} }}\n{colors}</style>\n\
+         </head>\n<body>\n<h1>{header}</h1>\n<p>{} bpm, {}</p>\n<nav>\n",
+        escape(timed.title),
+        song.tempo(),
+        song.time_sig
+    );
+    for (t, track) in song.tracks.iter().enumerate() {
+        out.push_str(&format!(
+            "<label><input type=\"checkbox\" value=\"{}\" checked> {}</label>\n",
+            t + 1,
+            escape(&ascii::track_heading(t, track))
+        ));
+    }
+    out.push_str("</nav>\n");
+    for (t, track) in song.tracks.iter().enumerate() {
+        out.push_str(&format!(
+            "<section id=\"track{}\">\n<h2>{}</h2>\n",
+            t + 1,
+            escape(&ascii::track_heading(t, track))
+        ));
+        for line in timed.lines.iter().filter(|l| l.track == t + 1) {
+            out.push_str(&format!("<pre>{}</pre>\n", html_rows(line)));
+        }
+        out.push_str("</section>\n");
+    }
+    out.push_str(&format!(
+        "<script>{TOGGLE_SCRIPT}</script>\n</body>\n</html>\n"
+    ));
+    out
+}
+
+impl TabFormat for Html {
+    fn name(&self) -> &'static str {
+        "html"
+    }
+
+    fn extensions(&self) -> &'static [&'static str] {
+        &["html", "htm"]
+    }
+
+    fn write(&self, song: &Song) -> Result<Vec<u8>> {
+        Ok(render_page(song, &self.title, self.width).into_bytes())
+    }
+}
+
+impl TabFormat for KaraokeHtml {
+    fn name(&self) -> &'static str {
+        "karaoke-html"
+    }
+
+    fn extensions(&self) -> &'static [&'static str] {
+        &["karaoke.html"]
+    }
+
+    fn write(&self, song: &Song) -> Result<Vec<u8>> {
+        Ok(render_html(song, &self.title, self.width)?.into_bytes())
+    }
+}
+
+impl TabFormat for Karaoke {
+    fn name(&self) -> &'static str {
+        "karaoke"
+    }
+
+    fn extensions(&self) -> &'static [&'static str] {
+        &["karaoke.json"]
+    }
+
+    fn write(&self, song: &Song) -> Result<Vec<u8>> {
+        serde_json::to_vec_pretty(&render(song, &self.title, self.width))
+            .map_err(|e| Error::FileError(e.to_string()))
+    }
+}
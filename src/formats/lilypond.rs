@@ -0,0 +1,216 @@
+use crate::{
+    error::Result,
+    formats::{dynamic, gp5, quote, TabFormat},
+    song::{Arpeggio, Beat, Finger, Note, Song, Track},
+};
+
+// LilyPond source with a TabStaff per track, for engraving with `lilypond`
+pub struct LilyPond;
+
+const VERSION: &str = "2.24.0";
+
+const NAMES: [&str; 12] = [
+    "c", "cis", "d", "dis", "e", "f", "fis", "g", "gis", "a", "ais", "b",
+];
+
+// Octaves are counted from c, the C below middle C
+fn pitch(pitch: u16) -> String {
+    let octave = pitch as i32 / 12 - 4;
+    let marks = if octave > 0 {
+        "'".repeat(octave as usize)
+    } else {
+        ",".repeat(-octave as usize)
+    };
+    format!("{}{marks}", NAMES[(pitch % 12) as usize])
+}
+
+fn finger(finger: Finger) -> &'static str {
+    match finger {
+        Finger::Thumb => "\\thumb",
+        Finger::Index => "-1",
+        Finger::Middle => "-2",
+        Finger::Ring => "-3",
+        Finger::Pinky => "-4",
+    }
+}
+
+// Dead notes have no pitch of their own, so they sit on the open string
+// Returns the note and its string and fingering marks, which go after the duration outside chords
+fn note(beat: &Beat, track: &Track, string: u16, note: &Note, tied: bool) -> (String, String) {
+    let open = track.tuning()[string as usize] as u16;
+    let name = match note {
+        Note::Fret(fret) => pitch(open + fret),
+        Note::X => format!("\\deadNote {}", pitch(open)),
+    };
+    let mut marks = format!("\\{}", string + 1);
+    if let Some(f) = beat
+        .get_marks(string)
+        .and_then(|m| m.finger)
+        .filter(|_| !tied)
+    {
+        marks.push_str(finger(f));
+    }
+    (name, marks)
+}
+
+fn write_beat(
+    out: &mut String,
+    beat: &Beat,
+    track: &Track,
+    tuplet: &mut Option<u16>,
+) -> Result<()> {
+    let notes: Vec<(u16, &Note)> = (0..track.string_count)
+        .filter_map(|s| beat.get_note(s).map(|n| (s, n)))
+        .collect();
+    let parts = gp5::gp_durations(beat.dur)?;
+    for (i, dur) in parts.iter().enumerate() {
+        let tied = i > 0;
+        // Runs of the same tuplet share a bracket
+        if dur.tuplet != *tuplet {
+            if tuplet.is_some() {
+                out.push_str("} ");
+            }
+            if let Some(n) = dur.tuplet {
+                let base = gp5::tuplet_base(n);
+                out.push_str(&format!("\\tuplet {n}/{base} {{ "));
+            }
+            *tuplet = dur.tuplet;
+        }
+        let mut length = dur.value.to_string();
+        if dur.dotted {
+            length.push('.');
+        }
+        match notes.as_slice() {
+            [] => out.push_str(&format!("r{length}")),
+            [(string, n)] => {
+                let (name, marks) = note(beat, track, *string, n, tied);
+                out.push_str(&format!("{name}{length}{marks}"));
+            }
+            _ => {
+                let chord: Vec<String> = notes
+                    .iter()
+                    .map(|(string, n)| {
+                        let (name, marks) = note(beat, track, *string, n, tied);
+                        name + &marks
+                    })
+                    .collect();
+                out.push_str(&format!("<{}>{length}", chord.join(" ")));
+            }
+        }
+        if !tied {
+            if notes.len() > 1 && beat.arpeggio.is_some() {
+                out.push_str(match beat.arpeggio {
+                    Some(Arpeggio::Up) => "\\arpeggioArrowUp\\arpeggio",
+                    _ => "\\arpeggioArrowDown\\arpeggio",
+                });
+            }
+            if let Some(velocity) = beat.velocity {
+                out.push_str(&format!("\\{}", dynamic(velocity)));
+            }
+            if let Some(text) = &beat.text {
+                out.push_str(&format!("^{}", quote(text)));
+            }
+        }
+        if i + 1 < parts.len() && !notes.is_empty() {
+            out.push('~');
+        }
+        out.push(' ');
+    }
+    Ok(())
+}
+
+fn write_track(out: &mut String, song: &Song, t: usize) -> Result<()> {
+    let track = &song.tracks[t];
+    let mut tuning: Vec<String> = track.tuning().iter().map(|p| pitch(*p as u16)).collect();
+    tuning.reverse();
+    let mut name = format!("Track {}", t + 1);
+    if track.capo > 0 {
+        name.push_str(&format!(" (capo {})", track.capo));
+    }
+    out.push_str(&format!(
+        "    \\new TabStaff \\with {{\n      stringTunings = \\stringTuning <{}>\n      instrumentName = {}\n    }} {{\n",
+        tuning.join(" "),
+        quote(&name)
+    ));
+    out.push_str(&format!(
+        "      \\time {}/{}\n",
+        song.time_sig.num, song.time_sig.den
+    ));
+    if let Some(key) = &song.key {
+        let mode = if key.minor { "minor" } else { "major" };
+        out.push_str(&format!(
+            "      \\key {} \\{mode}\n",
+            NAMES[key.tonic as usize % 12]
+        ));
+    }
+    if t == 0 {
+        out.push_str(&format!("      \\tempo 4 = {}\n", song.tempo()));
+    }
+    out.push_str("      ");
+    let mut tuplet = None;
+    let mut measure = 0;
+    for (i, beat) in track.beats.iter().enumerate() {
+        if track.measure_i[i] && i > 0 {
+            if tuplet.take().is_some() {
+                out.push_str("} ");
+            }
+            out.push_str("|\n      ");
+            measure += 1;
+            if let Some(change) = song.change_at(measure) {
+                if let Some(sig) = change.time_sig {
+                    out.push_str(&format!("\\time {}/{} ", sig.num, sig.den));
+                }
+                if let Some(tempo) = change.tempo.filter(|_| t == 0) {
+                    out.push_str(&format!("\\tempo 4 = {tempo} "));
+                }
+            }
+        }
+        write_beat(out, beat, track, &mut tuplet)?;
+    }
+    if tuplet.is_some() {
+        out.push_str("} ");
+    }
+    out.push_str("\\bar \"|.\"\n    }\n");
+    Ok(())
+}
+
+pub fn render(song: &Song) -> Result<String> {
+    let mut out = format!("\\version {}\n\n", quote(VERSION));
+    let meta = &song.meta;
+    let header: Vec<String> = [
+        ("title", &meta.title),
+        ("composer", &meta.artist),
+        ("subtitle", &meta.album),
+        ("arranger", &meta.transcriber),
+    ]
+    .into_iter()
+    .filter_map(|(field, value)| {
+        value
+            .as_ref()
+            .map(|v| format!("  {field} = {}\n", quote(v)))
+    })
+    .collect();
+    if !header.is_empty() {
+        out.push_str(&format!("\\header {{\n{}}}\n\n", header.concat()));
+    }
+    out.push_str("\\score {\n  <<\n");
+    for t in 0..song.tracks.len() {
+        write_track(&mut out, song, t)?;
+    }
+    out.push_str("  >>\n  \\layout { }\n}\n");
+    Ok(out)
+}
+
+impl TabFormat for LilyPond {
+    fn name(&self) -> &'static str {
+        "lilypond"
+    }
+
+    fn extensions(&self) -> &'static [&'static str] {
+        &["ly"]
+    }
+
+    fn write(&self, song: &Song) -> Result<Vec<u8>> {
+        Ok(render(song)?.into_bytes())
+    }
+}
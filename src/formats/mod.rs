@@ -0,0 +1,182 @@
+use crate::{
+    error::{Error, Result},
+    song::Song,
+};
+
+pub mod alphatex;
+pub mod ascii;
+pub mod gp5;
+pub mod gp7;
+pub mod json;
+pub mod karaoke;
+pub mod lilypond;
+pub mod musicxml;
+pub mod pdf;
+pub mod text;
+pub mod xml;
+
+pub trait TabFormat {
+    fn name(&self) -> &'static str;
+
+    // The first extension is used when a path has to be made up for this format
+    fn extensions(&self) -> &'static [&'static str];
+
+    fn can_read(&self) -> bool {
+        false
+    }
+
+    fn can_write(&self) -> bool {
+        true
+    }
+
+    // Whether reading back written output gives the same song, not just a similar one
+    fn lossless(&self) -> bool {
+        false
+    }
+
+    // Whether the lengths of beats are written, and not just the order of the notes
+    fn keeps_rhythm(&self) -> bool {
+        true
+    }
+
+    fn read(&self, _data: &[u8]) -> Result<Song> {
+        Err(Error::InvalidOp(format!(
+            "Cannot import from {} files",
+            self.name()
+        )))
+    }
+
+    fn write(&self, song: &Song) -> Result<Vec<u8>>;
+}
+
+pub struct Registry {
+    formats: Vec<Box<dyn TabFormat>>,
+}
+
+impl Registry {
+    pub fn new() -> Self {
+        Self {
+            formats: Vec::new(),
+        }
+    }
+
+    pub fn builtin(title: &str, text_opts: &text::TextOpts) -> Self {
+        let mut reg = Self::new();
+        reg.register(Box::new(json::Json));
+        reg.register(Box::new(json::JsonGz));
+        reg.register(Box::new(text::Text::new(title.into(), text_opts.clone())));
+        reg.register(Box::new(ascii::Ascii::new(title.into(), text_opts.width)));
+        reg.register(Box::new(karaoke::Karaoke::new(
+            title.into(),
+            text_opts.width,
+        )));
+        reg.register(Box::new(karaoke::Html::new(title.into(), text_opts.width)));
+        reg.register(Box::new(karaoke::KaraokeHtml::new(
+            title.into(),
+            text_opts.width,
+        )));
+        reg.register(Box::new(gp5::Gp5));
+        reg.register(Box::new(gp7::Gp7));
+        reg.register(Box::new(alphatex::AlphaTex));
+        reg.register(Box::new(lilypond::LilyPond));
+        reg.register(Box::new(musicxml::MusicXml));
+        reg.register(Box::new(pdf::Pdf::new(title.into(), text_opts.clone())));
+        reg
+    }
+
+    pub fn register(&mut self, format: Box<dyn TabFormat>) {
+        self.formats.push(format);
+    }
+
+    pub fn iter(&self) -> impl Iterator<Item = &dyn TabFormat> {
+        self.formats.iter().map(|f| f.as_ref())
+    }
+
+    pub fn find(&self, ext: &str) -> Result<&dyn TabFormat> {
+        let ext = ext.to_ascii_lowercase();
+        self.iter()
+            .find(|f| f.name() == ext || f.extensions().contains(&ext.as_str()))
+            .ok_or_else(|| Error::InvalidOp(format!("Unknown format '{ext}'")))
+    }
+
+    // Paths without an extension are taken to be native json songs, and of the extensions
+    // a path ends with the longest wins, so .karaoke.html is not taken for .html
+    pub fn for_path(&self, path: &str) -> Result<&dyn TabFormat> {
+        let name = path.to_ascii_lowercase();
+        let longest = self
+            .iter()
+            .flat_map(|f| f.extensions().iter().map(move |e| (e, f)))
+            .filter(|(e, _)| e.contains('.') && name.ends_with(&format!(".{e}")))
+            .max_by_key(|(e, _)| e.len());
+        if let Some((_, format)) = longest {
+            return Ok(format);
+        }
+        match std::path::Path::new(path).extension() {
+            Some(ext) => self.find(&ext.to_string_lossy()),
+            None => self.find("json"),
+        }
+    }
+
+    // Several formats may share an extension, in which case the first readable one is used
+    pub fn read_file(&self, path: &str) -> Result<Song> {
+        let format = self.for_path(path)?;
+        let format = match std::path::Path::new(path).extension() {
+            Some(ext) if !format.can_read() => self
+                .iter()
+                .find(|f| f.can_read() && f.extensions().contains(&&*ext.to_string_lossy()))
+                .unwrap_or(format),
+            _ => format,
+        };
+        if !format.can_read() {
+            return format.read(&[]);
+        }
+        let data = std::fs::read(path)
+            .map_err(|e| Error::FileError(format!("Cannot read file '{path}': {e}")))?;
+        format.read(&data).map_err(|e| match e {
+            Error::ParseError(e) => Error::ParseError(format!("Cannot parse '{path}': {e}")),
+            e => e,
+        })
+    }
+
+    pub fn write_file(&self, format: &dyn TabFormat, song: &Song, path: &str) -> Result<()> {
+        write_atomic(path, &format.write(song)?)
+    }
+}
+
+// Written beside the file and moved over it, so a failed write leaves the old file whole
+pub fn write_atomic(path: &str, data: &[u8]) -> Result<()> {
+    let target = std::fs::canonicalize(path).unwrap_or_else(|_| path.into());
+    let name = target.file_name().unwrap_or_default().to_string_lossy();
+    let temp = target.with_file_name(format!(".{name}.{}.tmp", std::process::id()));
+    let res = std::fs::write(&temp, data).and_then(|_| {
+        if let Ok(meta) = std::fs::metadata(&target) {
+            std::fs::set_permissions(&temp, meta.permissions())?;
+        }
+        std::fs::rename(&temp, &target)
+    });
+    res.map_err(|e| {
+        let _ = std::fs::remove_file(&temp);
+        Error::FileError(format!("Cannot write '{path}': {e}"))
+    })
+}
+
+const DYNAMICS: [&str; 8] = ["ppp", "pp", "p", "mp", "mf", "f", "ff", "fff"];
+
+// Velocity of a dynamic as Guitar Pro and MusicXML name them, ppp to fff in either case
+// Forte is the default loudness, so it leaves the velocity unset
+pub fn velocity(dynamic: &str) -> Option<u8> {
+    let level = DYNAMICS
+        .iter()
+        .position(|d| d.eq_ignore_ascii_case(dynamic))?;
+    (level != 5).then_some(15 + 16 * level as u8)
+}
+
+// Velocities are mapped back onto the dynamics Guitar Pro imports give them
+pub fn dynamic(velocity: u8) -> &'static str {
+    DYNAMICS[((velocity.saturating_sub(15) as usize + 8) / 16).min(DYNAMICS.len() - 1)]
+}
+
+// Double quoted string for the text based formats, with quotes and backslashes escaped
+pub fn quote(text: &str) -> String {
+    format!("\"{}\"", text.replace('\\', "\\\\").replace('"', "\\\""))
+}
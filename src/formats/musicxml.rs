@@ -0,0 +1,715 @@
+use crate::{
+    dur::Duration,
+    error::{Error, Result},
+    formats::{
+        self,
+        xml::{self, Elem},
+        TabFormat,
+    },
+    pitch::{self, Key},
+    song::{Beat, Finger, Note, NoteMarks, Song, TimeSig, Track},
+};
+use std::io::Read;
+
+pub struct MusicXml;
+
+const STEPS: [(&str, i32); 7] = [
+    ("C", 0),
+    ("D", 2),
+    ("E", 4),
+    ("F", 5),
+    ("G", 7),
+    ("A", 9),
+    ("B", 11),
+];
+
+// Compressed .mxl files are zips naming the score in META-INF/container.xml
+fn read_mxl(data: &[u8]) -> Result<String> {
+    let err = |e: zip::result::ZipError| Error::ParseError(format!("Bad mxl archive: {e}"));
+    let mut archive = zip::ZipArchive::new(std::io::Cursor::new(data)).map_err(err)?;
+    let fallback = archive
+        .file_names()
+        .find(|n| !n.starts_with("META-INF") && n.ends_with("xml"))
+        .map(str::to_owned);
+    let mut read = |name: &str| -> Result<String> {
+        let mut xml = String::new();
+        archive
+            .by_name(name)
+            .map_err(err)?
+            .read_to_string(&mut xml)
+            .map_err(|e| Error::ParseError(format!("Bad mxl archive: {e}")))?;
+        Ok(xml)
+    };
+    let root = read("META-INF/container.xml").ok().and_then(|c| {
+        let doc = xml::parse(&c).ok()?;
+        let path = doc
+            .path(&["container", "rootfiles", "rootfile"])?
+            .attr("full-path")?
+            .to_owned();
+        Some(path)
+    });
+    match root.or(fallback) {
+        Some(path) => read(&path),
+        None => Err(Error::ParseError("No score in mxl archive".into())),
+    }
+}
+
+fn gcd(a: u32, b: u32) -> u32 {
+    if b == 0 {
+        a
+    } else {
+        gcd(b, a % b)
+    }
+}
+
+// Lengths are counted in divisions of a quarter note
+fn read_duration(divs: u32, divisions: u32) -> Result<Duration> {
+    let den = divisions.max(1).checked_mul(4).ok_or_else(|| {
+        Error::ParseError(format!(
+            "Too many divisions of a quarter note ({divisions})"
+        ))
+    })?;
+    let g = gcd(divs, den).max(1);
+    Ok(match (u16::try_from(divs / g), u16::try_from(den / g)) {
+        (Ok(num), Ok(den)) if num > 0 => Duration::new(num, den),
+        _ => {
+            let n = (divs as u64 * 64 / den as u64).clamp(1, u16::MAX as u64) as u16;
+            Duration::new(n, 64)
+        }
+    })
+}
+
+fn read_pitch(elem: &Elem) -> Option<i32> {
+    let step = elem.text_of("step")?;
+    let class = STEPS.iter().find(|(s, _)| *s == step)?.1;
+    let alter: f32 = elem
+        .text_of("alter")
+        .and_then(|a| a.parse().ok())
+        .unwrap_or(0.0);
+    let octave: i32 = elem.text_of("octave")?.parse().ok()?;
+    Some((octave + 1) * 12 + class + alter.round() as i32)
+}
+
+// Key signatures are counted in sharps (or flats when negative) from C major
+fn read_key(elem: &Elem) -> Option<Key> {
+    let fifths: i32 = elem.text_of("fifths")?.parse().ok()?;
+    let minor = elem.text_of("mode") == Some("minor");
+    let major = (fifths * 7).rem_euclid(12) as u8;
+    Some(Key::new(
+        if minor { (major + 9) % 12 } else { major },
+        minor,
+    ))
+}
+
+// Signatures given as plain numbers must be ones the editor can hold, so a beat type of 0
+// makes the score unreadable, while other forms (ie 3+2) are skipped
+fn read_time_sig(attributes: &Elem) -> Result<Option<TimeSig>> {
+    let Some(time) = attributes.child("time") else {
+        return Ok(None);
+    };
+    let number = |name| time.text_of(name).and_then(|n| n.parse::<u16>().ok());
+    match (number("beats"), number("beat-type")) {
+        (Some(num), Some(den)) => format!("{num}/{den}").parse().map(Some),
+        _ => Ok(None),
+    }
+}
+
+// Tab staves carry their tuning, line 1 being the lowest string
+fn read_staff_details(track: &mut Track, details: &Elem) {
+    let mut tuning: Vec<(u16, u8)> = details
+        .children("staff-tuning")
+        .filter_map(|t| {
+            let line = t.attr("line")?.parse().ok()?;
+            let step = t.text_of("tuning-step")?;
+            let class = STEPS.iter().find(|(s, _)| *s == step)?.1;
+            let alter: i32 = t
+                .text_of("tuning-alter")
+                .and_then(|a| a.parse().ok())
+                .unwrap_or(0);
+            let octave: i32 = t.text_of("tuning-octave")?.parse().ok()?;
+            let pitch = u8::try_from((octave + 1) * 12 + class + alter).ok()?;
+            Some((line, pitch))
+        })
+        .collect();
+    tuning.sort_by_key(|(line, _)| std::cmp::Reverse(*line));
+    if !tuning.is_empty() {
+        track.string_count = tuning.len() as u16;
+        let tuning: Vec<u8> = tuning.into_iter().map(|(_, p)| p).collect();
+        track.tuning = if tuning == pitch::standard_tuning(track.string_count) {
+            Vec::new()
+        } else {
+            tuning
+        };
+    } else if let Some(lines) = details
+        .text_of("staff-lines")
+        .and_then(|l| l.parse().ok())
+        .filter(|l| *l > 0)
+    {
+        // A staff of no lines is valid MusicXML, but leaves no strings to play
+        track.string_count = lines;
+    }
+    if let Some(capo) = details.text_of("capo").and_then(|c| c.parse().ok()) {
+        track.capo = capo;
+    }
+}
+
+fn read_finger(technical: &Elem) -> Option<Finger> {
+    match technical.text_of("fingering")? {
+        "1" => Some(Finger::Index),
+        "2" => Some(Finger::Middle),
+        "3" => Some(Finger::Ring),
+        "4" => Some(Finger::Pinky),
+        "T" | "t" => Some(Finger::Thumb),
+        _ => None,
+    }
+}
+
+// A beat before its sounding pitches are given strings
+struct Pending {
+    beat: Beat,
+    pitches: Vec<(i32, Option<Finger>)>,
+}
+
+// String and fret given to a pitch, or None when it is left out
+type Placement = Option<(usize, u16)>;
+
+// Chooses a string for every pitch of a chord, preferring compact shapes near the last hand position
+struct Fretter {
+    tuning: Vec<u8>,
+    capo: u16,
+    fret_count: u16,
+    hand: u16,
+}
+
+impl Fretter {
+    fn fret(&self, pitch: i32, string: usize) -> Option<u16> {
+        let fret = u16::try_from(pitch - self.tuning[string] as i32).ok()?;
+        (fret >= self.capo && fret <= self.fret_count).then_some(fret)
+    }
+
+    // Pitches out of the instrument's range are moved by octaves until they fit
+    fn fold(&self, mut pitch: i32) -> i32 {
+        let low = *self.tuning.iter().min().unwrap() as i32 + self.capo as i32;
+        let high = *self.tuning.iter().max().unwrap() as i32 + self.fret_count as i32;
+        while pitch < low {
+            pitch += 12;
+        }
+        while pitch > high && pitch - 12 >= low {
+            pitch -= 12;
+        }
+        pitch
+    }
+
+    // Cost of the placement, split into the part that can only grow as more notes are
+    // placed and the hand's travel, which can shrink again
+    fn costs(&self, placed: &[Placement]) -> (u32, u32) {
+        let fretted: Vec<u16> = placed
+            .iter()
+            .flatten()
+            .map(|(_, f)| *f)
+            .filter(|f| *f > self.capo)
+            .collect();
+        let dropped = placed.iter().filter(|p| p.is_none()).count() as u32;
+        let (Some(low), Some(high)) = (fretted.iter().min(), fretted.iter().max()) else {
+            return (dropped * 100, 0);
+        };
+        let span = (high - low) as u32;
+        let stretch = span.saturating_sub(3) * 20;
+        let travel = low.abs_diff(self.hand) as u32;
+        (
+            dropped * 100 + stretch + span * 2 + fretted.iter().sum::<u16>() as u32 / 4,
+            travel * 2,
+        )
+    }
+
+    fn search(
+        &self,
+        pitches: &[i32],
+        used: &mut [bool],
+        chosen: &mut Vec<Placement>,
+        best: &mut Option<(u32, Vec<Placement>)>,
+    ) {
+        let (fixed, travel) = self.costs(chosen);
+        // Placing more notes never lowers the fixed part, so no better placement is left
+        if best.as_ref().is_some_and(|(c, _)| fixed >= *c) {
+            return;
+        }
+        let Some(&pitch) = pitches.get(chosen.len()) else {
+            // The travel may still make a placement past the check above the worse one
+            if best.as_ref().is_none_or(|(c, _)| fixed + travel < *c) {
+                *best = Some((fixed + travel, chosen.clone()));
+            }
+            return;
+        };
+        for string in 0..self.tuning.len() {
+            if used[string] {
+                continue;
+            }
+            if let Some(fret) = self.fret(pitch, string) {
+                used[string] = true;
+                chosen.push(Some((string, fret)));
+                self.search(pitches, used, chosen, best);
+                chosen.pop();
+                used[string] = false;
+            }
+        }
+        chosen.push(None);
+        self.search(pitches, used, chosen, best);
+        chosen.pop();
+    }
+
+    fn place(&mut self, pending: Pending) -> Beat {
+        let mut beat = pending.beat;
+        let mut used: Vec<bool> = (0..self.tuning.len() as u16)
+            .map(|s| beat.get_note(s).is_some())
+            .collect();
+        // Pitches beyond the free strings are dropped before searching
+        let free = used.iter().filter(|u| !**u).count();
+        let pitches: Vec<i32> = pending
+            .pitches
+            .iter()
+            .take(free)
+            .map(|(p, _)| self.fold(*p))
+            .collect();
+        let mut best = None;
+        self.search(&pitches, &mut used, &mut Vec::new(), &mut best);
+        let Some((_, chosen)) = best else {
+            return beat;
+        };
+        for (placed, (_, finger)) in chosen.into_iter().zip(pending.pitches) {
+            let Some((string, fret)) = placed else {
+                continue;
+            };
+            beat.set_note(string as u16, Note::Fret(fret));
+            if finger.is_some() {
+                beat.marks.push((
+                    string as u16,
+                    NoteMarks {
+                        finger,
+                        ..Default::default()
+                    },
+                ));
+            }
+        }
+        if let Some(low) = (0..self.tuning.len() as u16)
+            .filter_map(|s| match beat.get_note(s) {
+                Some(Note::Fret(f)) if *f > self.capo => Some(*f),
+                _ => None,
+            })
+            .min()
+        {
+            self.hand = low;
+        }
+        beat
+    }
+}
+
+struct Part {
+    track: Track,
+    beats: Vec<Pending>,
+}
+
+// Only the first voice of the first staff of each part is kept
+fn read_part(elem: &Elem, song: &mut Song, first: bool) -> Result<Part> {
+    let mut track = Track::new();
+    track.beats.clear();
+    let mut beats: Vec<Pending> = Vec::new();
+    let mut divisions = 1;
+    let mut transpose = 0;
+    let mut time_sig = song.time_sig;
+    let mut voice = None;
+    let mut velocity = None;
+    let mut text = None;
+    for measure in elem.children("measure") {
+        let before = beats.len();
+        for child in measure.children.iter() {
+            match child.name.as_str() {
+                "attributes" => {
+                    if let Some(d) = child.text_of("divisions").and_then(|d| d.parse().ok()) {
+                        divisions = d;
+                    }
+                    if let Some(time) = read_time_sig(child)? {
+                        if first && beats.is_empty() {
+                            song.time_sig = time;
+                        }
+                        time_sig = time;
+                    }
+                    if first && song.key.is_none() {
+                        song.key = child.child("key").and_then(read_key);
+                    }
+                    if let Some(t) = child.child("transpose") {
+                        let get = |name| t.text_of(name).and_then(|v| v.parse::<i32>().ok());
+                        transpose =
+                            get("chromatic").unwrap_or(0) + get("octave-change").unwrap_or(0) * 12;
+                    }
+                    if let Some(details) = child.child("staff-details") {
+                        read_staff_details(&mut track, details);
+                    } else if beats.is_empty()
+                        && child.path(&["clef", "sign"]).map(|s| s.text.trim()) == Some("F")
+                    {
+                        track.string_count = 4;
+                    }
+                }
+                "direction" => {
+                    if let Some(words) = child.path(&["direction-type", "words"]) {
+                        text = Some(words.text.trim().to_owned()).filter(|t| !t.is_empty());
+                    }
+                    if let Some(dynamics) = child.path(&["direction-type", "dynamics"]) {
+                        if let Some(d) = dynamics.children.first() {
+                            velocity = formats::velocity(&d.name);
+                        }
+                    }
+                }
+                "forward" => {
+                    let divs = child
+                        .text_of("duration")
+                        .and_then(|d| d.parse().ok())
+                        .unwrap_or(0);
+                    if divs > 0 && child.text_of("voice").is_none_or(|v| Some(v) == voice) {
+                        beats.push(Pending {
+                            beat: Beat::new(read_duration(divs, divisions)?),
+                            pitches: Vec::new(),
+                        });
+                    }
+                }
+                "note" => {
+                    if child.child("grace").is_some()
+                        || child.text_of("staff").is_some_and(|s| s != "1")
+                    {
+                        continue;
+                    }
+                    let v = child.text_of("voice").unwrap_or("1");
+                    if *voice.get_or_insert(v) != v {
+                        continue;
+                    }
+                    let chord = child.child("chord").is_some() && !beats.is_empty();
+                    if !chord {
+                        let divs = child
+                            .text_of("duration")
+                            .and_then(|d| d.parse().ok())
+                            .unwrap_or(0);
+                        let mut beat = Beat::new(read_duration(divs, divisions)?);
+                        beat.velocity = velocity;
+                        beat.text = text.take();
+                        beats.push(Pending {
+                            beat,
+                            pitches: Vec::new(),
+                        });
+                    }
+                    let pending = beats.last_mut().unwrap();
+                    // Held over from a tied note, so not struck again
+                    let tied = child
+                        .children("tie")
+                        .any(|t| t.attr("type") == Some("stop"))
+                        && child
+                            .children("tie")
+                            .all(|t| t.attr("type") != Some("start"));
+                    if child.child("rest").is_some() || tied {
+                        continue;
+                    }
+                    let technical = child.path(&["notations", "technical"]);
+                    let finger = technical.and_then(read_finger);
+                    let dead = child.text_of("notehead") == Some("x");
+                    let tab = technical.and_then(|t| {
+                        let string: u16 = t.text_of("string")?.parse().ok()?;
+                        let fret: u16 = t.text_of("fret")?.parse().ok()?;
+                        Some((string.checked_sub(1)?, fret))
+                    });
+                    match (tab, child.child("pitch").and_then(read_pitch)) {
+                        (Some((string, fret)), _) if string < track.string_count => {
+                            pending.beat.set_note(
+                                string,
+                                if dead {
+                                    Note::X
+                                } else {
+                                    Note::Fret(fret + track.capo)
+                                },
+                            );
+                            if finger.is_some() {
+                                pending.beat.marks.push((
+                                    string,
+                                    NoteMarks {
+                                        finger,
+                                        ..Default::default()
+                                    },
+                                ));
+                            }
+                        }
+                        (_, Some(pitch)) => pending.pitches.push((pitch + transpose, finger)),
+                        _ => (),
+                    }
+                }
+                _ => (),
+            }
+        }
+        if beats.len() == before {
+            beats.push(Pending {
+                beat: Beat::new(Duration::new(time_sig.num, time_sig.den)),
+                pitches: Vec::new(),
+            });
+        }
+    }
+    Ok(Part { track, beats })
+}
+
+pub fn parse(data: &[u8]) -> Result<Song> {
+    let text = if data.starts_with(b"PK") {
+        read_mxl(data)?
+    } else {
+        String::from_utf8_lossy(data).into_owned()
+    };
+    let doc = xml::parse(&text)?;
+    let root = match doc.children.iter().find(|c| c.name.starts_with("score-")) {
+        Some(root) if root.name == "score-partwise" => root,
+        Some(_) => {
+            return Err(Error::ParseError(
+                "Only partwise MusicXML scores can be imported".into(),
+            ))
+        }
+        None => return Err(Error::ParseError("No score in MusicXML file".into())),
+    };
+
+    let mut song = Song::new();
+    song.tracks.clear();
+    song.meta.title = root
+        .path(&["work", "work-title"])
+        .or_else(|| root.child("movement-title"))
+        .map(|t| t.text.trim().to_owned())
+        .filter(|t| !t.is_empty());
+    song.meta.artist = root
+        .child("identification")
+        .and_then(|i| {
+            i.children("creator")
+                .find(|c| c.attr("type") == Some("composer"))
+        })
+        .map(|c| c.text.trim().to_owned())
+        .filter(|t| !t.is_empty());
+    for (i, elem) in root.children("part").enumerate() {
+        let Part { mut track, beats } = read_part(elem, &mut song, i == 0)?;
+        let mut fretter = Fretter {
+            tuning: track.tuning(),
+            capo: track.capo,
+            fret_count: track.fret_count,
+            hand: track.capo,
+        };
+        track.beats = beats.into_iter().map(|p| fretter.place(p)).collect();
+        if track.beats.is_empty() {
+            track.beats.push(Beat::new(Duration::new(1, 1)));
+        }
+        song.tracks.push(track);
+    }
+    if song.tracks.is_empty() {
+        return Err(Error::ParseError("No parts in MusicXML file".into()));
+    }
+    song.update_all_measures();
+    Ok(song)
+}
+
+impl TabFormat for MusicXml {
+    fn name(&self) -> &'static str {
+        "musicxml"
+    }
+
+    fn extensions(&self) -> &'static [&'static str] {
+        &["musicxml", "mxl", "xml"]
+    }
+
+    fn can_read(&self) -> bool {
+        true
+    }
+
+    fn can_write(&self) -> bool {
+        false
+    }
+
+    fn read(&self, data: &[u8]) -> Result<Song> {
+        parse(data)
+    }
+
+    fn write(&self, _song: &Song) -> Result<Vec<u8>> {
+        Err(Error::InvalidOp("Cannot export to MusicXML files".into()))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // Drop D tab staff with a capo at 2, a chord, a tie, a rest and a second voice
+    const TAB_SCORE: &str = r#"<?xml version="1.0" encoding="UTF-8"?>
+<score-partwise version="3.1">
+  <work><work-title>Tab Test</work-title></work>
+  <part-list><score-part id="P1"><part-name>Guitar</part-name></score-part></part-list>
+  <part id="P1">
+    <measure number="1">
+      <attributes>
+        <divisions>2</divisions>
+        <time><beats>3</beats><beat-type>4</beat-type></time>
+        <clef><sign>TAB</sign><line>5</line></clef>
+        <staff-details>
+          <staff-lines>6</staff-lines>
+          <staff-tuning line="1"><tuning-step>D</tuning-step><tuning-octave>2</tuning-octave></staff-tuning>
+          <staff-tuning line="2"><tuning-step>A</tuning-step><tuning-octave>2</tuning-octave></staff-tuning>
+          <staff-tuning line="3"><tuning-step>D</tuning-step><tuning-octave>3</tuning-octave></staff-tuning>
+          <staff-tuning line="4"><tuning-step>G</tuning-step><tuning-octave>3</tuning-octave></staff-tuning>
+          <staff-tuning line="5"><tuning-step>B</tuning-step><tuning-octave>3</tuning-octave></staff-tuning>
+          <staff-tuning line="6"><tuning-step>E</tuning-step><tuning-octave>4</tuning-octave></staff-tuning>
+          <capo>2</capo>
+        </staff-details>
+      </attributes>
+      <note>
+        <pitch><step>E</step><octave>3</octave></pitch>
+        <duration>2</duration><voice>1</voice><tie type="start"/>
+        <notations><technical><string>6</string><fret>0</fret></technical></notations>
+      </note>
+      <note>
+        <chord/><pitch><step>B</step><octave>3</octave></pitch>
+        <duration>2</duration><voice>1</voice>
+        <notations><technical><string>5</string><fret>2</fret><fingering>3</fingering></technical></notations>
+      </note>
+      <note>
+        <pitch><step>C</step><octave>5</octave></pitch>
+        <duration>4</duration><voice>2</voice>
+        <notations><technical><string>1</string><fret>8</fret></technical></notations>
+      </note>
+      <note>
+        <pitch><step>E</step><octave>3</octave></pitch>
+        <duration>1</duration><voice>1</voice><tie type="stop"/>
+        <notations><technical><string>6</string><fret>0</fret></technical></notations>
+      </note>
+      <note><rest/><duration>1</duration><voice>1</voice></note>
+      <note>
+        <unpitched><display-step>E</display-step><display-octave>4</display-octave></unpitched>
+        <duration>2</duration><voice>1</voice><notehead>x</notehead>
+        <notations><technical><string>1</string><fret>0</fret></technical></notations>
+      </note>
+    </measure>
+  </part>
+</score-partwise>"#;
+
+    // Two untabbed parts, the first holding a C major chord and a run up from G3
+    const PITCHED_SCORE: &str = r#"<?xml version="1.0" encoding="UTF-8"?>
+<score-partwise version="3.1">
+  <part-list>
+    <score-part id="P1"><part-name>Guitar</part-name></score-part>
+    <score-part id="P2"><part-name>Bass</part-name></score-part>
+  </part-list>
+  <part id="P1">
+    <measure number="1">
+      <attributes>
+        <divisions>1</divisions>
+        <key><fifths>-1</fifths><mode>minor</mode></key>
+        <time><beats>4</beats><beat-type>4</beat-type></time>
+        <clef><sign>G</sign><line>2</line></clef>
+      </attributes>
+      <note><pitch><step>C</step><octave>4</octave></pitch><duration>1</duration></note>
+      <note><chord/><pitch><step>E</step><octave>4</octave></pitch><duration>1</duration></note>
+      <note><chord/><pitch><step>G</step><octave>4</octave></pitch><duration>1</duration></note>
+      <note><pitch><step>G</step><octave>3</octave></pitch><duration>1</duration></note>
+      <note><pitch><step>A</step><octave>3</octave></pitch><duration>1</duration></note>
+      <note><pitch><step>B</step><alter>-1</alter><octave>3</octave></pitch><duration>1</duration></note>
+    </measure>
+  </part>
+  <part id="P2">
+    <measure number="1">
+      <attributes>
+        <divisions>1</divisions>
+        <clef><sign>F</sign><line>4</line></clef>
+      </attributes>
+      <note><pitch><step>C</step><octave>2</octave></pitch><duration>4</duration></note>
+    </measure>
+  </part>
+</score-partwise>"#;
+
+    fn fret(beat: &Beat, string: u16) -> Option<Note> {
+        beat.get_note(string).cloned()
+    }
+
+    // Sounding pitches of a beat, lowest first
+    fn pitches(track: &Track, beat: &Beat) -> Vec<i32> {
+        let tuning = track.tuning();
+        let mut pitches: Vec<i32> = (0..track.string_count)
+            .filter_map(|s| match beat.get_note(s) {
+                Some(Note::Fret(f)) => Some(tuning[s as usize] as i32 + *f as i32),
+                _ => None,
+            })
+            .collect();
+        pitches.sort();
+        pitches
+    }
+
+    #[test]
+    fn tab_staff_score() {
+        let song = parse(TAB_SCORE.as_bytes()).unwrap();
+        assert_eq!(song.meta.title.as_deref(), Some("Tab Test"));
+        assert_eq!((song.time_sig.num, song.time_sig.den), (3, 4));
+        let track = &song.tracks[0];
+        assert_eq!(track.string_count, 6);
+        assert_eq!(track.capo, 2);
+        assert_eq!(track.tuning(), vec![64, 59, 55, 50, 45, 38]);
+        // The second voice is dropped, and the tied note holds without a fret
+        let beats = &track.beats;
+        assert_eq!(beats.len(), 4);
+        assert!(fret(&beats[0], 5) == Some(Note::Fret(2)));
+        assert!(fret(&beats[0], 4) == Some(Note::Fret(4)));
+        assert!(beats[0]
+            .marks
+            .iter()
+            .any(|(s, m)| *s == 4 && matches!(m.finger, Some(Finger::Ring))));
+        assert!(fret(&beats[1], 5).is_none());
+        assert!(fret(&beats[2], 5).is_none());
+        assert!(fret(&beats[3], 0) == Some(Note::X));
+        assert_eq!(beats[1].dur.1, 8);
+    }
+
+    #[test]
+    fn pitched_score_is_fretted() {
+        let song = parse(PITCHED_SCORE.as_bytes()).unwrap();
+        assert_eq!(song.tracks.len(), 2);
+        assert!(song.key == Some(Key::new(2, true)));
+        let guitar = &song.tracks[0];
+        let beats = &guitar.beats;
+        assert_eq!(beats.len(), 4);
+        assert_eq!(pitches(guitar, &beats[0]), vec![60, 64, 67]);
+        assert_eq!(pitches(guitar, &beats[1]), vec![55]);
+        assert_eq!(pitches(guitar, &beats[3]), vec![58]);
+        // The chord is one compact shape, and the run stays near it
+        let frets: Vec<u16> = (0..guitar.string_count)
+            .filter_map(|s| match beats[0].get_note(s) {
+                Some(Note::Fret(f)) => Some(*f),
+                _ => None,
+            })
+            .collect();
+        assert!(frets.iter().max().unwrap() - frets.iter().min().unwrap() <= 3);
+        for beat in &beats[1..] {
+            assert!(beat
+                .notes
+                .iter()
+                .all(|(_, n)| matches!(n, Note::Fret(f) if *f <= 5)));
+        }
+        // A bass clef part is taken for a four string bass, with its low C folded into range
+        let bass = &song.tracks[1];
+        assert_eq!(bass.string_count, 4);
+        assert_eq!(pitches(bass, &bass.beats[0]), vec![36]);
+    }
+
+    #[test]
+    fn bad_attributes() {
+        let no_lines = TAB_SCORE.replace(
+            "<staff-lines>6</staff-lines>",
+            "<staff-lines>0</staff-lines>",
+        );
+        let no_lines = no_lines.replace(
+            &no_lines[no_lines.find("<staff-tuning").unwrap()..no_lines.find("<capo>").unwrap()],
+            "",
+        );
+        let song = parse(no_lines.as_bytes()).unwrap();
+        assert!(song.tracks[0].string_count > 0);
+        let zero_beat =
+            PITCHED_SCORE.replace("<beat-type>4</beat-type>", "<beat-type>0</beat-type>");
+        assert!(matches!(
+            parse(zero_beat.as_bytes()),
+            Err(Error::ParseError(_))
+        ));
+    }
+}
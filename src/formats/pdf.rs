@@ -0,0 +1,209 @@
+use crate::{
+    error::Result,
+    formats::{ascii, text, text::TextOpts, TabFormat},
+    song::{Song, Track},
+};
+
+// A4 in points, text is set in Courier so the tab columns line up as in ASCII exports
+const PAGE_WIDTH: f32 = 595.0;
+const PAGE_HEIGHT: f32 = 842.0;
+const MARGIN: f32 = 40.0;
+const MAX_FONT_SIZE: f32 = 10.0;
+// Advance of every Courier glyph, as a fraction of the font size
+const CHAR_WIDTH: f32 = 0.6;
+const LEADING: f32 = 1.2;
+
+pub struct Pdf {
+    title: String,
+    opts: TextOpts,
+}
+
+impl Pdf {
+    pub fn new(title: String, opts: TextOpts) -> Self {
+        Self { title, opts }
+    }
+}
+
+// Lines of `measures` measures each, the last one holding what is left
+fn measure_lines(track: &Track, measures: usize) -> Vec<std::ops::Range<usize>> {
+    let mut starts: Vec<usize> = (0..track.beats.len())
+        .filter(|i| track.measure_i[*i])
+        .step_by(measures)
+        .collect();
+    starts.push(track.beats.len());
+    starts.windows(2).map(|w| w[0]..w[1]).collect()
+}
+
+// Blocks of lines that are kept together on a page, the heading with the first line of its track
+fn blocks(song: &Song, opts: &TextOpts) -> Vec<Vec<String>> {
+    let mut blocks = Vec::new();
+    for (t, track) in song.tracks.iter().enumerate() {
+        let lines = if opts.measures > 0 {
+            measure_lines(track, opts.measures)
+        } else {
+            let width = ascii::line_width(track, opts.width);
+            text::split_lines(track, width, |i| ascii::beat_width(track, i))
+        };
+        for (i, range) in lines.into_iter().enumerate() {
+            let mut block = Vec::new();
+            if i == 0 {
+                block.push(ascii::track_heading(t, track));
+                block.push(String::new());
+            }
+            block.extend(ascii::render_line(track, range));
+            block.push(String::new());
+            blocks.push(block);
+        }
+    }
+    blocks
+}
+
+// Courier is given the WinAnsi encoding, close enough to Latin-1 for song titles
+fn pdf_string(line: &str) -> Vec<u8> {
+    let mut out = vec![b'('];
+    for c in line.chars() {
+        match c {
+            '(' | ')' | '\\' => out.extend([b'\\', c as u8]),
+            c if (c as u32) < 256 => out.push(c as u8),
+            _ => out.push(b'?'),
+        }
+    }
+    out.push(b')');
+    out
+}
+
+struct Writer {
+    out: Vec<u8>,
+    offsets: Vec<usize>,
+}
+
+impl Writer {
+    // Objects are numbered from 1 in the order they are written
+    fn object(&mut self, body: &[u8]) {
+        self.offsets.push(self.out.len());
+        let id = self.offsets.len();
+        self.out.extend(format!("{id} 0 obj\n").as_bytes());
+        self.out.extend(body);
+        self.out.extend(b"\nendobj\n");
+    }
+
+    fn stream(&mut self, data: &[u8]) {
+        let mut body = format!("<< /Length {} >>\nstream\n", data.len()).into_bytes();
+        body.extend(data);
+        body.extend(b"\nendstream");
+        self.object(&body);
+    }
+
+    fn finish(mut self) -> Vec<u8> {
+        let xref = self.out.len();
+        let count = self.offsets.len() + 1;
+        self.out
+            .extend(format!("xref\n0 {count}\n0000000000 65535 f \n").as_bytes());
+        for offset in &self.offsets {
+            self.out
+                .extend(format!("{offset:010} 00000 n \n").as_bytes());
+        }
+        self.out.extend(
+            format!(
+                "trailer\n<< /Size {count} /Root 1 0 R /Info 4 0 R >>\nstartxref\n{xref}\n%%EOF\n"
+            )
+            .as_bytes(),
+        );
+        self.out
+    }
+}
+
+pub fn render(song: &Song, title: &str, opts: &TextOpts) -> Vec<u8> {
+    let header = if song.meta.is_empty() {
+        title.to_owned()
+    } else {
+        song.meta.header()
+    };
+    let blocks = blocks(song, opts);
+    // Lines too wide for the page make the text smaller rather than run off it
+    let columns = blocks
+        .iter()
+        .flatten()
+        .map(|l| l.chars().count())
+        .max()
+        .unwrap_or(0)
+        .max(opts.width);
+    let size = ((PAGE_WIDTH - 2.0 * MARGIN) / (columns as f32 * CHAR_WIDTH)).min(MAX_FONT_SIZE);
+    let leading = size * LEADING;
+    let body_len = (((PAGE_HEIGHT - 2.0 * MARGIN) / leading) as usize)
+        .saturating_sub(2)
+        .max(1);
+    let mut pages: Vec<Vec<String>> = vec![Vec::new()];
+    for block in blocks {
+        let page = pages.last_mut().unwrap();
+        if !page.is_empty() && page.len() + block.len() > body_len {
+            pages.push(block);
+        } else {
+            page.extend(block);
+        }
+    }
+
+    let mut w = Writer {
+        out: b"%PDF-1.4\n%\xe2\xe3\xcf\xd3\n".to_vec(),
+        offsets: Vec::new(),
+    };
+    // Catalog, page tree, font and document info come first, then each page and its text
+    let count = pages.len();
+    let kids: Vec<String> = (0..count).map(|i| format!("{} 0 R", 5 + 2 * i)).collect();
+    w.object(b"<< /Type /Catalog /Pages 2 0 R >>");
+    w.object(
+        format!(
+            "<< /Type /Pages /Kids [{}] /Count {count} >>",
+            kids.join(" ")
+        )
+        .as_bytes(),
+    );
+    w.object(b"<< /Type /Font /Subtype /Type1 /BaseFont /Courier /Encoding /WinAnsiEncoding >>");
+    let mut info = b"<< /Title ".to_vec();
+    info.extend(pdf_string(song.meta.title.as_deref().unwrap_or(title)));
+    if let Some(artist) = &song.meta.artist {
+        info.extend(b" /Author ");
+        info.extend(pdf_string(artist));
+    }
+    info.extend(b" /Creator (rust-tab) >>");
+    w.object(&info);
+    for (i, page) in pages.into_iter().enumerate() {
+        w.object(
+            format!(
+                "<< /Type /Page /Parent 2 0 R /MediaBox [0 0 {PAGE_WIDTH} {PAGE_HEIGHT}] \
+                 /Resources << /Font << /F1 3 0 R >> >> /Contents {} 0 R >>",
+                6 + 2 * i
+            )
+            .as_bytes(),
+        );
+        let num = format!("Page {}/{count}", i + 1);
+        let pad = columns.saturating_sub(header.chars().count() + num.len());
+        let top = format!("{header}{}{num}", " ".repeat(pad));
+        let mut text = format!(
+            "BT\n/F1 {size:.2} Tf\n{leading:.2} TL\n{MARGIN} {:.2} Td\n",
+            PAGE_HEIGHT - MARGIN - size
+        )
+        .into_bytes();
+        for line in [top, String::new()].iter().chain(page.iter()) {
+            text.extend(pdf_string(line));
+            text.extend(b" Tj T*\n");
+        }
+        text.extend(b"ET");
+        w.stream(&text);
+    }
+    w.finish()
+}
+
+impl TabFormat for Pdf {
+    fn name(&self) -> &'static str {
+        "pdf"
+    }
+
+    fn extensions(&self) -> &'static [&'static str] {
+        &["pdf"]
+    }
+
+    fn write(&self, song: &Song) -> Result<Vec<u8>> {
+        Ok(render(song, &self.title, &self.opts))
+    }
+}
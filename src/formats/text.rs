@@ -0,0 +1,194 @@
+use crate::{
+    draw,
+    error::Result,
+    formats::TabFormat,
+    song::{Note, Song, Track},
+};
+
+#[derive(Clone)]
+pub struct TextOpts {
+    pub width: usize,
+    pub page_len: usize,
+    // Measures per line of printed exports, 0 fitting as many as `width` allows
+    pub measures: usize,
+}
+
+impl Default for TextOpts {
+    fn default() -> Self {
+        Self {
+            width: 80,
+            page_len: 66,
+            measures: 0,
+        }
+    }
+}
+
+// Cells are laid out as in the editor, so wide frets get wide cells here as well
+fn cell(track: &Track, beat: usize, string: u16) -> String {
+    let w = draw::cell_width(&track.beats[beat], draw::CELL_WIDTH);
+    match track.beats[beat].get_note(string) {
+        Some(Note::Fret(fret)) => format!("{:-^w$}", fret),
+        Some(Note::X) => format!("{:-^w$}", 'x'),
+        None => "-".repeat(w),
+    }
+}
+
+fn beat_width(track: &Track, beat: usize) -> usize {
+    draw::cell_width(&track.beats[beat], draw::CELL_WIDTH) + 1
+}
+
+// Split a track's beats into lines no wider than `width`, breaking on barlines where possible,
+// the beat at `i` taking `beat_width(i)` columns
+pub(super) fn split_lines(
+    track: &Track,
+    width: usize,
+    beat_width: impl Fn(usize) -> usize,
+) -> Vec<std::ops::Range<usize>> {
+    let mut lines = Vec::new();
+    let mut start = 0;
+    while start < track.beats.len() {
+        let end = draw::fit_columns(
+            track.beats.len(),
+            start,
+            width.saturating_sub(1),
+            &beat_width,
+        )
+        .end;
+        let end = if end < track.beats.len() {
+            (start + 1..=end)
+                .rev()
+                .find(|i| track.measure_i[*i])
+                .unwrap_or(end)
+        } else {
+            end
+        };
+        lines.push(start..end);
+        start = end;
+    }
+    lines
+}
+
+fn render_system(track: &Track, range: std::ops::Range<usize>) -> Vec<String> {
+    let mut rows = Vec::with_capacity(track.string_count as usize + 1);
+    let mut durs = String::new();
+    for i in range.clone() {
+        let w = draw::cell_width(&track.beats[i], draw::CELL_WIDTH);
+        durs.push(if track.beam_i[i] { '~' } else { ' ' });
+        durs.push_str(&format!("{: <w$}", track.beats[i].dur.dur_icon()));
+    }
+    rows.push(durs);
+    for string in 0..track.string_count {
+        let mut row = String::new();
+        for i in range.clone() {
+            row.push(if track.measure_i[i] { '|' } else { '-' });
+            row.push_str(&cell(track, i, string));
+        }
+        row.push('|');
+        rows.push(row);
+    }
+    rows
+}
+
+// A measure whose first beat is annotated starts a section, named by the annotation
+fn section_name(track: &Track, beat: usize) -> Option<&str> {
+    if track.measure_i[beat] {
+        track.beats[beat].text.as_deref()
+    } else {
+        None
+    }
+}
+
+// Each block is kept together on one page, and each section (a list of blocks)
+// starts on a new page when it would fit on one but not in what is left of the page
+fn render_sections(song: &Song, width: usize) -> Vec<Vec<Vec<String>>> {
+    let mut sections = Vec::new();
+    for (t, track) in song.tracks.iter().enumerate() {
+        let mut section: Vec<Vec<String>> = Vec::new();
+        for range in split_lines(track, width, |i| beat_width(track, i)) {
+            // Lines are also broken where a section starts
+            let mut starts: Vec<usize> = (range.start + 1..range.end)
+                .filter(|i| section_name(track, *i).is_some())
+                .collect();
+            starts.push(range.end);
+            let mut start = range.start;
+            for end in starts {
+                let mut block = Vec::new();
+                if start == 0 {
+                    block.push(format!("Track {}", t + 1));
+                }
+                if let Some(name) = section_name(track, start) {
+                    if !section.is_empty() {
+                        sections.push(std::mem::take(&mut section));
+                    }
+                    block.push(format!("[{name}]"));
+                }
+                block.extend(render_system(track, start..end));
+                block.push(String::new());
+                section.push(block);
+                start = end;
+            }
+        }
+        sections.push(section);
+    }
+    sections
+}
+
+pub fn render_paged(song: &Song, title: &str, opts: &TextOpts) -> String {
+    let body_len = opts.page_len.saturating_sub(2).max(1);
+    let mut pages: Vec<Vec<String>> = vec![Vec::new()];
+    for section in render_sections(song, opts.width) {
+        let len: usize = section.iter().map(Vec::len).sum();
+        let page = pages.last().unwrap();
+        if !page.is_empty() && page.len() + len > body_len && len <= body_len {
+            pages.push(Vec::new());
+        }
+        for block in section {
+            let page = pages.last_mut().unwrap();
+            if !page.is_empty() && page.len() + block.len() > body_len {
+                pages.push(block);
+            } else {
+                page.extend(block);
+            }
+        }
+    }
+    let count = pages.len();
+    let mut out = String::new();
+    for (i, page) in pages.into_iter().enumerate() {
+        if i > 0 {
+            out.push('\x0c');
+        }
+        let num = format!("Page {}/{count}", i + 1);
+        let pad = opts.width.saturating_sub(title.chars().count() + num.len());
+        out.push_str(&format!("{title}{}{num}\n\n", " ".repeat(pad)));
+        for line in page {
+            out.push_str(&line);
+            out.push('\n');
+        }
+    }
+    out
+}
+
+pub struct Text {
+    title: String,
+    opts: TextOpts,
+}
+
+impl Text {
+    pub fn new(title: String, opts: TextOpts) -> Self {
+        Self { title, opts }
+    }
+}
+
+impl TabFormat for Text {
+    fn name(&self) -> &'static str {
+        "txt"
+    }
+
+    fn extensions(&self) -> &'static [&'static str] {
+        &["txt", "text"]
+    }
+
+    fn write(&self, song: &Song) -> Result<Vec<u8>> {
+        Ok(render_paged(song, &self.title, &self.opts).into_bytes())
+    }
+}
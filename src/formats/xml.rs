@@ -0,0 +1,115 @@
+use crate::error::{Error, Result};
+use quick_xml::events::{BytesStart, Event};
+
+// Just enough of a document tree to walk scores with
+#[derive(Default)]
+pub struct Elem {
+    pub name: String,
+    pub attrs: Vec<(String, String)>,
+    pub text: String,
+    pub children: Vec<Elem>,
+}
+
+impl Elem {
+    fn from_start(e: &BytesStart) -> Self {
+        Self {
+            name: String::from_utf8_lossy(e.name().as_ref()).into_owned(),
+            attrs: e
+                .attributes()
+                .flatten()
+                .map(|a| {
+                    let key = String::from_utf8_lossy(a.key.as_ref()).into_owned();
+                    let value = a
+                        .unescape_value()
+                        .map(|v| v.into_owned())
+                        .unwrap_or_default();
+                    (key, value)
+                })
+                .collect(),
+            ..Default::default()
+        }
+    }
+
+    pub fn child(&self, name: &str) -> Option<&Elem> {
+        self.children.iter().find(|c| c.name == name)
+    }
+
+    pub fn children<'a>(&'a self, name: &'a str) -> impl Iterator<Item = &'a Elem> {
+        self.children.iter().filter(move |c| c.name == name)
+    }
+
+    pub fn path(&self, path: &[&str]) -> Option<&Elem> {
+        path.iter().try_fold(self, |e, name| e.child(name))
+    }
+
+    pub fn attr(&self, name: &str) -> Option<&str> {
+        self.attrs
+            .iter()
+            .find(|(k, _)| k == name)
+            .map(|(_, v)| v.as_str())
+    }
+
+    pub fn text_of(&self, name: &str) -> Option<&str> {
+        self.child(name)
+            .map(|c| c.text.trim())
+            .filter(|t| !t.is_empty())
+    }
+
+    pub fn ids(&self, name: &str) -> Vec<i64> {
+        self.text_of(name)
+            .map(|t| {
+                t.split_whitespace()
+                    .filter_map(|n| n.parse().ok())
+                    .collect()
+            })
+            .unwrap_or_default()
+    }
+
+    // Guitar Pro properties are written as <Property name="..."> holding a single value element
+    pub fn property(&self, name: &str) -> Option<&Elem> {
+        self.child("Properties")?
+            .children("Property")
+            .find(|p| p.attr("name") == Some(name))
+    }
+
+    pub fn property_value(&self, name: &str) -> Option<&str> {
+        self.property(name)?.children.first().map(|v| v.text.trim())
+    }
+}
+
+// The returned element is the document itself, holding the root element as its child
+pub fn parse(xml: &str) -> Result<Elem> {
+    let err = |e: quick_xml::Error| Error::ParseError(format!("Bad XML: {e}"));
+    let mut reader = quick_xml::Reader::from_str(xml);
+    let mut stack = vec![Elem::default()];
+    loop {
+        match reader.read_event().map_err(err)? {
+            Event::Start(e) => stack.push(Elem::from_start(&e)),
+            Event::Empty(e) => {
+                let elem = Elem::from_start(&e);
+                stack.last_mut().unwrap().children.push(elem);
+            }
+            Event::End(_) => {
+                let elem = stack.pop().unwrap();
+                match stack.last_mut() {
+                    Some(parent) => parent.children.push(elem),
+                    None => return Err(Error::ParseError("Bad XML: unbalanced tags".into())),
+                }
+            }
+            Event::Text(t) => {
+                let text = t.unescape().map_err(err)?;
+                stack.last_mut().unwrap().text.push_str(&text);
+            }
+            Event::CData(c) => {
+                let text = String::from_utf8_lossy(&c.into_inner()).into_owned();
+                stack.last_mut().unwrap().text.push_str(&text);
+            }
+            Event::Eof => break,
+            _ => (),
+        }
+    }
+    match stack.pop() {
+        Some(doc) if stack.is_empty() => Ok(doc),
+        _ => Err(Error::ParseError("Bad XML: unclosed tags".into())),
+    }
+}
@@ -1,11 +1,15 @@
 use crate::{
     cursor::Cursor,
     dur::Duration,
-    song::{Beat, Note},
+    song::{Arpeggio, Beat, Color, Finger, Note, NoteEntry, Song, Stroke, Track, Whammy},
 };
 use std::collections::VecDeque;
 
 pub enum Action {
+    Group {
+        label: String,
+        actions: Vec<Action>,
+    },
     SetDuration {
         cur: Cursor,
         old: Duration,
@@ -13,8 +17,48 @@ pub enum Action {
     },
     SetNote {
         cur: Cursor,
-        old: Option<Note>,
-        new: Option<Note>,
+        old: Option<NoteEntry>,
+        new: Option<NoteEntry>,
+    },
+    SetFinger {
+        cur: Cursor,
+        old: Option<Finger>,
+        new: Option<Finger>,
+    },
+    SetColor {
+        cur: Cursor,
+        old: Option<Color>,
+        new: Option<Color>,
+    },
+    SetStroke {
+        cur: Cursor,
+        old: Option<Stroke>,
+        new: Option<Stroke>,
+    },
+    SetVelocity {
+        cur: Cursor,
+        old: Option<u8>,
+        new: Option<u8>,
+    },
+    SetText {
+        cur: Cursor,
+        old: Option<String>,
+        new: Option<String>,
+    },
+    SetArpeggio {
+        cur: Cursor,
+        old: Option<Arpeggio>,
+        new: Option<Arpeggio>,
+    },
+    SetWhammy {
+        cur: Cursor,
+        old: Option<Whammy>,
+        new: Option<Whammy>,
+    },
+    SetChord {
+        cur: Cursor,
+        old: Vec<(u16, Note)>,
+        new: Vec<(u16, Note)>,
     },
     ClearBeat {
         cur: Cursor,
@@ -32,10 +76,15 @@ pub enum Action {
         cur: Cursor,
         old: Vec<Beat>,
     },
+    // Empty beats put in before the cursor
+    InsertBeats {
+        cur: Cursor,
+        new: Vec<Beat>,
+    },
     PasteNote {
         cur: Cursor,
-        old: Option<Note>,
-        buf: Note,
+        old: Option<NoteEntry>,
+        buf: NoteEntry,
     },
     PasteBeat {
         cur: Cursor,
@@ -47,17 +96,66 @@ pub enum Action {
         old: Option<Beat>,
         buf: Vec<Beat>,
     },
+    // Appended after the last track
+    AddTrack {
+        track: Box<Track>,
+    },
+    // Song settings before and after, as copied by `Song::settings`
+    SetSettings {
+        label: String,
+        old: Box<Song>,
+        new: Box<Song>,
+    },
 }
 
 impl Action {
+    pub fn group(label: &str, actions: Vec<Action>) -> Self {
+        Self::Group {
+            label: label.into(),
+            actions,
+        }
+    }
+
     pub fn set_duration(cur: Cursor, old: Duration, new: Duration) -> Self {
         Self::SetDuration { cur, old, new }
     }
 
-    pub fn set_note(cur: Cursor, old: Option<Note>, new: Option<Note>) -> Self {
+    pub fn set_note(cur: Cursor, old: Option<NoteEntry>, new: Option<NoteEntry>) -> Self {
         Self::SetNote { cur, old, new }
     }
 
+    pub fn set_finger(cur: Cursor, old: Option<Finger>, new: Option<Finger>) -> Self {
+        Self::SetFinger { cur, old, new }
+    }
+
+    pub fn set_color(cur: Cursor, old: Option<Color>, new: Option<Color>) -> Self {
+        Self::SetColor { cur, old, new }
+    }
+
+    pub fn set_stroke(cur: Cursor, old: Option<Stroke>, new: Option<Stroke>) -> Self {
+        Self::SetStroke { cur, old, new }
+    }
+
+    pub fn set_velocity(cur: Cursor, old: Option<u8>, new: Option<u8>) -> Self {
+        Self::SetVelocity { cur, old, new }
+    }
+
+    pub fn set_text(cur: Cursor, old: Option<String>, new: Option<String>) -> Self {
+        Self::SetText { cur, old, new }
+    }
+
+    pub fn set_arpeggio(cur: Cursor, old: Option<Arpeggio>, new: Option<Arpeggio>) -> Self {
+        Self::SetArpeggio { cur, old, new }
+    }
+
+    pub fn set_whammy(cur: Cursor, old: Option<Whammy>, new: Option<Whammy>) -> Self {
+        Self::SetWhammy { cur, old, new }
+    }
+
+    pub fn set_chord(cur: Cursor, old: Vec<(u16, Note)>, new: Vec<(u16, Note)>) -> Self {
+        Self::SetChord { cur, old, new }
+    }
+
     pub fn clear_beat(cur: Cursor, old: Vec<(u16, Note)>) -> Self {
         Self::ClearBeat { cur, old }
     }
@@ -74,7 +172,11 @@ impl Action {
         Self::DeleteBeats { cur, old }
     }
 
-    pub fn paste_note(cur: Cursor, old: Option<Note>, buf: Note) -> Self {
+    pub fn insert_beats(cur: Cursor, new: Vec<Beat>) -> Self {
+        Self::InsertBeats { cur, new }
+    }
+
+    pub fn paste_note(cur: Cursor, old: Option<NoteEntry>, buf: NoteEntry) -> Self {
         Self::PasteNote { cur, old, buf }
     }
 
@@ -85,6 +187,20 @@ impl Action {
     pub fn paste_beats(cur: Cursor, old: Option<Beat>, buf: Vec<Beat>) -> Self {
         Self::PasteBeats { cur, old, buf }
     }
+
+    pub fn add_track(track: Track) -> Self {
+        Self::AddTrack {
+            track: Box::new(track),
+        }
+    }
+
+    pub fn set_settings(label: &str, old: Song, new: Song) -> Self {
+        Self::SetSettings {
+            label: label.into(),
+            old: Box::new(old),
+            new: Box::new(new),
+        }
+    }
 }
 
 pub struct History {
@@ -124,6 +240,11 @@ impl History {
         Some(e.to_owned())
     }
 
+    // Entries that undo would go through, most recent first
+    pub fn past(&self) -> impl Iterator<Item = &Action> {
+        self.history.iter().skip(self.future).map(|a| a.as_ref())
+    }
+
     pub fn push(&mut self, entry: std::rc::Rc<Action>) {
         self.del_future();
         if self.history.len() == self.size {
@@ -0,0 +1,110 @@
+use crate::{
+    error::{Error, Result},
+    map_io_err,
+};
+use std::{
+    fs,
+    io::Write,
+    path::{Path, PathBuf},
+};
+
+// Marks a song file as open in this process, so other instances open it read-only
+pub struct Lock {
+    file: String,
+    path: PathBuf,
+}
+
+// Lock of `song.json` is the hidden `.song.json.lock` beside it, holding the owner's pid
+pub fn lock_path(file: &str) -> PathBuf {
+    let path = Path::new(file);
+    let name = path
+        .file_name()
+        .map(|n| n.to_string_lossy().into_owned())
+        .unwrap_or_default();
+    path.with_file_name(format!(".{name}.lock"))
+}
+
+#[cfg(target_os = "linux")]
+fn is_running(pid: u32) -> bool {
+    Path::new(&format!("/proc/{pid}")).exists()
+}
+
+// Signal 0 only checks that the process exists
+#[cfg(all(unix, not(target_os = "linux")))]
+fn is_running(pid: u32) -> bool {
+    std::process::Command::new("kill")
+        .args(["-0", &pid.to_string()])
+        .stderr(std::process::Stdio::null())
+        .status()
+        .is_ok_and(|s| s.success())
+}
+
+#[cfg(windows)]
+fn is_running(pid: u32) -> bool {
+    std::process::Command::new("tasklist")
+        .args(["/NH", "/FO", "CSV", "/FI", &format!("PID eq {pid}")])
+        .output()
+        .is_ok_and(|out| String::from_utf8_lossy(&out.stdout).contains(&format!("\"{pid}\"")))
+}
+
+fn read_owner(path: &Path) -> Option<u32> {
+    fs::read_to_string(path).ok()?.trim().parse().ok()
+}
+
+impl Lock {
+    // None when a running instance (this one included) already holds the file
+    pub fn acquire(file: &str) -> Result<Option<Lock>> {
+        let path = lock_path(file);
+        let pid = std::process::id();
+        // The pid is written to a file of our own first, so the lock never exists without it
+        let mut tmp = path.clone().into_os_string();
+        tmp.push(format!(".{pid}"));
+        let tmp = PathBuf::from(tmp);
+        map_io_err!(fs::File::create(&tmp).and_then(|mut f| write!(f, "{pid}")))?;
+        let res = Self::take(&path, &tmp);
+        let _ = fs::remove_file(&tmp);
+        Ok(res?.then(|| Lock {
+            file: file.to_owned(),
+            path,
+        }))
+    }
+
+    fn take(path: &Path, tmp: &Path) -> Result<bool> {
+        let mut aside = tmp.as_os_str().to_owned();
+        aside.push(".stale");
+        for _ in 0..3 {
+            match fs::hard_link(tmp, path) {
+                Ok(()) => return Ok(true),
+                Err(e) if e.kind() == std::io::ErrorKind::AlreadyExists => (),
+                Err(e) => return Err(Error::IOError(e)),
+            }
+            let owner = read_owner(path);
+            if owner.is_some_and(is_running) {
+                return Ok(false);
+            }
+            // Left behind by an instance that did not exit cleanly, it is moved aside in one
+            // step so that only one of several instances doing this at once removes it
+            match fs::rename(path, &aside) {
+                Ok(()) if read_owner(Path::new(&aside)) != owner => {
+                    // A live lock was created in between, so it goes back
+                    map_io_err!(fs::rename(&aside, path))?;
+                    return Ok(false);
+                }
+                Ok(()) => map_io_err!(fs::remove_file(&aside))?,
+                Err(e) if e.kind() == std::io::ErrorKind::NotFound => (),
+                Err(e) => return Err(Error::IOError(e)),
+            }
+        }
+        Ok(false)
+    }
+
+    pub fn file(&self) -> &str {
+        &self.file
+    }
+}
+
+impl Drop for Lock {
+    fn drop(&mut self) {
+        let _ = fs::remove_file(&self.path);
+    }
+}
@@ -6,14 +6,30 @@ extern crate regex;
 extern crate serde;
 extern crate serde_json;
 extern crate serde_repr;
+#[cfg(unix)]
+extern crate signal_hook;
+mod analyze;
 mod app;
 mod args;
 mod buffer;
+mod calc;
+mod chords;
+mod complete;
+mod config;
 mod cursor;
 mod draw;
 mod dur;
 mod error;
+mod formats;
 mod history;
+mod lock;
+mod messages;
+mod midi_input;
+mod notation;
+mod pitch;
+mod playback;
+mod roundtrip;
+mod serve;
 mod song;
 mod window;
 
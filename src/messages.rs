@@ -0,0 +1,90 @@
+use std::{
+    collections::VecDeque,
+    time::{Duration, Instant},
+};
+
+// Messages kept for the log
+const LOG_LEN: usize = 200;
+
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub enum Level {
+    Info,
+    Warn,
+    Error,
+}
+
+impl Level {
+    // Errors stay up until the next message, so they are not missed
+    fn lifetime(&self) -> Option<Duration> {
+        match self {
+            Self::Info => Some(Duration::from_secs(5)),
+            Self::Warn => Some(Duration::from_secs(10)),
+            Self::Error => None,
+        }
+    }
+
+    pub fn name(&self) -> &'static str {
+        match self {
+            Self::Info => "info",
+            Self::Warn => "warn",
+            Self::Error => "error",
+        }
+    }
+}
+
+pub struct Message {
+    pub level: Level,
+    pub text: String,
+    pub at: Instant,
+}
+
+// Results of commands and keys, the last one shown under the status bar until it runs out
+#[derive(Default)]
+pub struct Messages {
+    log: VecDeque<Message>,
+    shown: bool,
+}
+
+impl Messages {
+    // An empty message only clears the line
+    pub fn push(&mut self, level: Level, text: impl Into<String>) {
+        let text = text.into();
+        self.shown = !text.is_empty();
+        if text.is_empty() {
+            return;
+        }
+        self.log.push_back(Message {
+            level,
+            text,
+            at: Instant::now(),
+        });
+        if self.log.len() > LOG_LEN {
+            self.log.pop_front();
+        }
+    }
+
+    pub fn current(&self) -> Option<&Message> {
+        self.log.back().filter(|_| self.shown)
+    }
+
+    pub fn text(&self) -> &str {
+        self.current().map_or("", |m| m.text.as_str())
+    }
+
+    // Newest first
+    pub fn log(&self) -> impl Iterator<Item = &Message> {
+        self.log.iter().rev()
+    }
+
+    // True once the message shown has run out, for the line to be drawn again without it
+    pub fn expire(&mut self) -> bool {
+        let expired = self
+            .current()
+            .and_then(|m| Some(m.at.elapsed() >= m.level.lifetime()?))
+            .unwrap_or(false);
+        if expired {
+            self.shown = false;
+        }
+        expired
+    }
+}
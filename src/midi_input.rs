@@ -0,0 +1,164 @@
+use crate::{
+    error::{Error, Result},
+    pitch,
+    song::{Note, Track},
+};
+use std::{
+    io::Read,
+    path::Path,
+    sync::mpsc,
+    thread,
+    time::{Duration, Instant},
+};
+
+// Notes struck within this long of each other are entered as one chord
+const CHORD_MS: u64 = 60;
+
+// Picks note ons out of a raw MIDI stream, which may leave out repeated status bytes
+#[derive(Default)]
+struct Parser {
+    status: u8,
+    data: Vec<u8>,
+}
+
+impl Parser {
+    // Pitch of the note on completed by `byte`, if any
+    fn push(&mut self, byte: u8) -> Option<u8> {
+        match byte {
+            // Clock and other real time messages can come between the bytes of another message
+            0xf8..=0xff => None,
+            0x80..=0xf7 => {
+                self.status = byte;
+                self.data.clear();
+                None
+            }
+            _ => {
+                let len = match self.status & 0xf0 {
+                    0xc0 | 0xd0 => 1,
+                    0x80..=0xe0 => 2,
+                    // System exclusive and common messages are skipped
+                    _ => return None,
+                };
+                self.data.push(byte);
+                if self.data.len() < len {
+                    return None;
+                }
+                let data = std::mem::take(&mut self.data);
+                // A note on without velocity is a note off
+                (self.status & 0xf0 == 0x90 && data[1] > 0).then_some(data[0])
+            }
+        }
+    }
+}
+
+// Reads a raw MIDI device on a thread of its own, which ends with the next message after
+// the input is dropped, as reads of the device cannot be cut short
+pub struct Input {
+    notes: mpsc::Receiver<u8>,
+    chord: Vec<u8>,
+    last: Instant,
+}
+
+impl Input {
+    pub fn open(device: &Path) -> Result<Self> {
+        let mut file = std::fs::File::open(device).map_err(|e| {
+            Error::FileError(format!(
+                "Cannot open MIDI input '{}': {e}",
+                device.display()
+            ))
+        })?;
+        let (send, notes) = mpsc::channel();
+        thread::spawn(move || {
+            let mut parser = Parser::default();
+            let mut buf = [0; 64];
+            while let Ok(n @ 1..) = file.read(&mut buf) {
+                for pitch in buf[..n].iter().filter_map(|b| parser.push(*b)) {
+                    if send.send(pitch).is_err() {
+                        return;
+                    }
+                }
+            }
+        });
+        Ok(Self {
+            notes,
+            chord: Vec::new(),
+            last: Instant::now(),
+        })
+    }
+
+    // Pitches of the chord struck last, once no more notes have joined it for a while
+    pub fn chord(&mut self) -> Option<Vec<u8>> {
+        for pitch in self.notes.try_iter() {
+            if !self.chord.contains(&pitch) {
+                self.chord.push(pitch);
+            }
+            self.last = Instant::now();
+        }
+        if self.chord.is_empty() || self.last.elapsed() < Duration::from_millis(CHORD_MS) {
+            return None;
+        }
+        Some(std::mem::take(&mut self.chord))
+    }
+}
+
+// Strings and frets for the pitches, from the highest pitch down each taking the free string
+// where it is played lowest on the neck, above the capo
+pub fn place(track: &Track, pitches: &[u8]) -> Result<Vec<(u16, Note)>> {
+    let tuning = track.tuning();
+    let mut pitches = pitches.to_vec();
+    pitches.sort_unstable_by(|a, b| b.cmp(a));
+    let mut notes: Vec<(u16, Note)> = Vec::new();
+    for p in pitches {
+        let fret_on = |s: u16| {
+            (p as u16)
+                .checked_sub(tuning[s as usize] as u16)
+                .filter(|f| (track.capo..=track.fret_count).contains(f))
+        };
+        let (string, fret) = (0..track.string_count)
+            .filter(|s| notes.iter().all(|(used, _)| used != s))
+            .filter_map(|s| Some((s, fret_on(s)?)))
+            .min_by_key(|(_, f)| *f)
+            .ok_or_else(|| {
+                Error::InvalidOp(format!("{} is out of reach on this track", pitch::name(p)))
+            })?;
+        notes.push((string, Note::Fret(fret)));
+    }
+    notes.sort_by_key(|(s, _)| *s);
+    Ok(notes)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn chords_take_the_lowest_frets() {
+        // Running status and a note on without velocity in between
+        let mut parser = Parser::default();
+        let bytes = [
+            0x90, 40, 100, 0xf8, 47, 90, 40, 0, 0x80, 47, 0, 0x91, 52, 80,
+        ];
+        let pitches: Vec<u8> = bytes.iter().filter_map(|b| parser.push(*b)).collect();
+        assert_eq!(pitches, [40, 47, 52]);
+
+        // An open E chord, and with a capo no open strings, nor notes below it
+        let mut track = Track::new();
+        let frets = |notes: Vec<(u16, Note)>| -> Vec<(u16, u16)> {
+            notes
+                .into_iter()
+                .map(|(s, n)| match n {
+                    Note::Fret(f) => (s, f),
+                    Note::X => unreachable!(),
+                })
+                .collect()
+        };
+        let chord = place(&track, &[40, 47, 52, 56, 59, 64]).unwrap();
+        assert_eq!(
+            frets(chord),
+            [(0, 0), (1, 0), (2, 1), (3, 2), (4, 2), (5, 0)]
+        );
+        track.capo = 2;
+        assert_eq!(frets(place(&track, &[59]).unwrap()), [(2, 4)]);
+        assert!(place(&track, &[39]).is_err());
+    }
+}
@@ -0,0 +1,138 @@
+use crate::{
+    error::{Error, Result},
+    pitch::{self, Key},
+    song::{Note, Track},
+};
+
+// What a notation can see of the note being entered
+pub struct Context<'a> {
+    pub track: &'a Track,
+    pub string: u16,
+    pub key: Option<&'a Key>,
+    // Pitch of the closest note before the cursor
+    pub prev: Option<u8>,
+}
+
+// Turns the number typed in edit mode into a fret on the cursor's string
+pub trait Notation {
+    fn name(&self) -> &'static str;
+
+    fn fret(&self, n: i32, ctx: &Context) -> Result<u16>;
+}
+
+pub const NAMES: [&str; 3] = ["fret", "degree", "interval"];
+
+pub fn by_name(name: &str) -> Result<Box<dyn Notation>> {
+    match name {
+        "fret" => Ok(Box::new(Fret)),
+        "degree" => Ok(Box::new(Degree)),
+        "interval" => Ok(Box::new(Interval)),
+        _ => Err(Error::InvalidOp(format!(
+            "Unknown entry '{name}' (one of {})",
+            NAMES.join(", ")
+        ))),
+    }
+}
+
+// Among the frets of the closest earlier beat with a pitched note, the one nearest the string
+pub fn previous_pitch(track: &Track, beat: usize, string: u16) -> Option<u8> {
+    let tuning = track.tuning();
+    track.beats[..beat].iter().rev().find_map(|b| {
+        b.notes
+            .iter()
+            .filter_map(|(s, note)| match note {
+                Note::Fret(f) => Some((*s, f.checked_add(*tuning.get(*s as usize)? as u16)?)),
+                Note::X => None,
+            })
+            .min_by_key(|(s, _)| s.abs_diff(string))
+            .map(|(_, p)| p.min(127) as u8)
+    })
+}
+
+fn fret_of(ctx: &Context, pitch: i32) -> Result<u16> {
+    let open = ctx.track.tuning()[ctx.string as usize] as i32;
+    u16::try_from(pitch - open)
+        .ok()
+        .filter(|f| *f >= ctx.track.capo && *f <= ctx.track.fret_count)
+        .ok_or_else(|| {
+            let name = u8::try_from(pitch).map_or_else(|_| pitch.to_string(), pitch::name);
+            Error::InvalidOp(format!("{name} is out of reach on this string"))
+        })
+}
+
+fn key_of<'a>(ctx: &Context<'a>) -> Result<&'a Key> {
+    ctx.key
+        .ok_or_else(|| Error::InvalidOp("Set a key first (:key)".into()))
+}
+
+// Numbers are frets, as usual
+pub struct Fret;
+
+impl Notation for Fret {
+    fn name(&self) -> &'static str {
+        "fret"
+    }
+
+    fn fret(&self, n: i32, _ctx: &Context) -> Result<u16> {
+        u16::try_from(n).map_err(|_| Error::InvalidOp("Frets cannot be negative".into()))
+    }
+}
+
+// Numbers are degrees of the song's key at the lowest fret they fall on, 8 being the tonic an octave up
+pub struct Degree;
+
+impl Notation for Degree {
+    fn name(&self) -> &'static str {
+        "degree"
+    }
+
+    fn fret(&self, n: i32, ctx: &Context) -> Result<u16> {
+        let key = key_of(ctx)?;
+        if n < 1 {
+            return Err(Error::InvalidOp("Scale degrees start from 1".into()));
+        }
+        let class = key.scale()[((n - 1) % 7) as usize] as i32;
+        let open = ctx.track.tuning()[ctx.string as usize] as i32 + ctx.track.capo as i32;
+        let lowest = open + (class - open).rem_euclid(12);
+        fret_of(ctx, lowest + (n - 1) / 7 * 12)
+    }
+}
+
+// Numbers are intervals from the previous note: diatonic in the song's key (3 is a third up, -3 a third down),
+// or semitones when there is no key
+pub struct Interval;
+
+impl Notation for Interval {
+    fn name(&self) -> &'static str {
+        "interval"
+    }
+
+    fn fret(&self, n: i32, ctx: &Context) -> Result<u16> {
+        let prev = ctx
+            .prev
+            .ok_or_else(|| Error::InvalidOp("No previous note to step from".into()))?
+            as i32;
+        let Some(key) = ctx.key else {
+            return fret_of(ctx, prev + n);
+        };
+        if n == 0 {
+            return Err(Error::InvalidOp("Intervals start from 1 (unison)".into()));
+        }
+        let steps = n - n.signum();
+        let scale = key.scale();
+        let tonic = key.tonic as i32;
+        // Notes outside the key count from the scale note below them
+        let mut rel = prev - tonic;
+        while !scale.contains(&((rel + tonic).rem_euclid(12) as u8)) {
+            rel -= 1;
+        }
+        let degree = scale
+            .iter()
+            .position(|c| *c as i32 == (rel + tonic).rem_euclid(12))
+            .unwrap() as i32;
+        let target = rel.div_euclid(12) * 7 + degree + steps;
+        let class = scale[target.rem_euclid(7) as usize] as i32;
+        let pitch = tonic + target.div_euclid(7) * 12 + (class - tonic).rem_euclid(12);
+        fret_of(ctx, pitch)
+    }
+}
@@ -0,0 +1,111 @@
+use crate::{
+    error::{Error, Result},
+    song::Extra,
+};
+use serde::{Deserialize, Serialize};
+
+const NAMES: [&str; 12] = [
+    "C", "C#", "D", "D#", "E", "F", "F#", "G", "G#", "A", "A#", "B",
+];
+
+pub fn class_name(pitch: u8) -> &'static str {
+    NAMES[(pitch % 12) as usize]
+}
+
+pub fn name(pitch: u8) -> String {
+    format!("{}{}", class_name(pitch), (pitch / 12) as i16 - 1)
+}
+
+pub fn parse(s: &str) -> Result<u8> {
+    let err = || Error::ParseError(format!("Unable to parse '{s}' as pitch"));
+    let mut chars = s.chars();
+    let class: i16 = match chars.next().map(|c| c.to_ascii_uppercase()) {
+        Some('C') => 0,
+        Some('D') => 2,
+        Some('E') => 4,
+        Some('F') => 5,
+        Some('G') => 7,
+        Some('A') => 9,
+        Some('B') => 11,
+        _ => return Err(err()),
+    };
+    let rest = chars.as_str();
+    let (class, octave) = if let Some(o) = rest.strip_prefix('#') {
+        (class + 1, o)
+    } else if let Some(o) = rest.strip_prefix('b') {
+        (class - 1, o)
+    } else {
+        (class, rest)
+    };
+    let octave: i16 = octave.parse().map_err(|_| err())?;
+    let pitch = (octave + 1) * 12 + class;
+    u8::try_from(pitch)
+        .ok()
+        .filter(|p| *p < 128)
+        .ok_or_else(err)
+}
+
+pub fn standard_tuning(string_count: u16) -> Vec<u8> {
+    match string_count {
+        4 => vec![43, 38, 33, 28],
+        5 => vec![43, 38, 33, 28, 23],
+        6 => vec![64, 59, 55, 50, 45, 40],
+        7 => vec![64, 59, 55, 50, 45, 40, 35],
+        8 => vec![64, 59, 55, 50, 45, 40, 35, 30],
+        n => (0..n as u32)
+            .map(|i| 64u32.saturating_sub(5 * i) as u8)
+            .collect(),
+    }
+}
+
+#[derive(Clone, PartialEq, Serialize, Deserialize)]
+pub struct Key {
+    pub tonic: u8,
+    pub minor: bool,
+    #[serde(flatten)]
+    pub extra: Extra,
+}
+
+const MAJOR_SCALE: [u8; 7] = [0, 2, 4, 5, 7, 9, 11];
+const MINOR_SCALE: [u8; 7] = [0, 2, 3, 5, 7, 8, 10];
+
+impl Key {
+    pub fn new(tonic: u8, minor: bool) -> Self {
+        Self {
+            tonic,
+            minor,
+            extra: Extra::new(),
+        }
+    }
+
+    // Pitch classes of the key's scale, from the tonic up
+    pub fn scale(&self) -> [u8; 7] {
+        let steps = if self.minor { MINOR_SCALE } else { MAJOR_SCALE };
+        steps.map(|s| (self.tonic + s) % 12)
+    }
+}
+
+impl std::fmt::Display for Key {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let mode = if self.minor { "minor" } else { "major" };
+        write!(f, "{} {mode}", class_name(self.tonic))
+    }
+}
+
+// Accepts "A minor", "Am", "F# major" or a bare tonic for major keys
+impl std::str::FromStr for Key {
+    type Err = Error;
+
+    fn from_str(s: &str) -> Result<Self> {
+        let err = || Error::ParseError(format!("Unable to parse '{s}' as key"));
+        let (name, mode) = s.trim().split_once(' ').unwrap_or((s.trim(), ""));
+        let (name, minor) = match (mode.trim().to_lowercase().as_str(), name.strip_suffix('m')) {
+            ("", Some(name)) => (name, true),
+            ("" | "major" | "maj", _) => (name, false),
+            ("minor" | "min", _) => (name, true),
+            _ => return Err(err()),
+        };
+        let tonic = parse(&format!("{name}4")).map_err(|_| err())? % 12;
+        Ok(Self::new(tonic, minor))
+    }
+}
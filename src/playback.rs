@@ -0,0 +1,452 @@
+use crate::{
+    error::{Error, Result},
+    song::{Note, Song, DEFAULT_VELOCITY},
+};
+use std::{
+    io::Write,
+    path::{Path, PathBuf},
+    sync::{
+        atomic::{AtomicBool, Ordering},
+        Arc,
+    },
+    thread,
+    time::{Duration, Instant},
+};
+
+// Raw MIDI devices of ALSA (midiC0D0...) and OSS (midi, midi1...)
+const DEVICE_DIRS: [&str; 2] = ["/dev/snd", "/dev"];
+// Channel 10 is left alone, as it plays drums
+const DRUM_CHANNEL: u8 = 9;
+const ALL_NOTES_OFF: u8 = 123;
+// Wood blocks of the drum kit, the high one on downbeats
+const CLICK_ACCENT: [u8; 2] = [76, 120];
+const CLICK: [u8; 2] = [77, 80];
+const CLICK_LEN: f64 = 0.05;
+const PREVIEW_LEN: f64 = 0.4;
+// Messages that keep other devices in time, the clock ticking 24 times a quarter
+const CLOCK: u8 = 0xf8;
+const CLOCK_TICKS: u32 = 24;
+const SONG_POSITION: u8 = 0xf2;
+const START: u8 = 0xfa;
+const CONTINUE: u8 = 0xfb;
+const STOP: u8 = 0xfc;
+
+pub struct Device {
+    pub path: PathBuf,
+    // Id of the sound card, for ALSA devices
+    pub card: Option<String>,
+}
+
+impl Device {
+    pub fn name(&self) -> String {
+        self.path
+            .file_name()
+            .map_or_else(String::new, |n| n.to_string_lossy().into_owned())
+    }
+}
+
+impl std::fmt::Display for Device {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match &self.card {
+            Some(card) => write!(f, "{} ({card})", self.name()),
+            None => write!(f, "{}", self.name()),
+        }
+    }
+}
+
+// ALSA names its devices midiC{card}D{device}, the card's id is kept apart
+fn card_id(name: &str) -> Option<String> {
+    let card = name.strip_prefix("midiC")?.split('D').next()?;
+    let id = std::fs::read_to_string(format!("/proc/asound/card{card}/id")).ok()?;
+    Some(id.trim().to_owned())
+}
+
+pub fn devices() -> Vec<Device> {
+    DEVICE_DIRS
+        .iter()
+        .flat_map(|dir| {
+            let mut paths: Vec<PathBuf> = std::fs::read_dir(dir)
+                .into_iter()
+                .flatten()
+                .filter_map(|e| e.ok())
+                .filter(|e| e.file_name().to_string_lossy().starts_with("midi"))
+                .map(|e| e.path())
+                .collect();
+            paths.sort();
+            paths
+        })
+        .map(|path| Device {
+            card: path.file_name().and_then(|n| card_id(&n.to_string_lossy())),
+            path,
+        })
+        .collect()
+}
+
+pub fn find_device() -> Option<PathBuf> {
+    devices().into_iter().next().map(|d| d.path)
+}
+
+// A device by its name or the id of its card, or any path such as that of a virtual port
+pub fn device_by_name(name: &str) -> Result<PathBuf> {
+    if name.contains('/') {
+        let path = PathBuf::from(name);
+        return match path.exists() {
+            true => Ok(path),
+            false => Err(Error::FileError(format!("No MIDI output '{name}'"))),
+        };
+    }
+    devices()
+        .into_iter()
+        .find(|d| {
+            d.name() == name
+                || d.card
+                    .as_deref()
+                    .is_some_and(|c| c.eq_ignore_ascii_case(name))
+        })
+        .map(|d| d.path)
+        .ok_or_else(|| Error::FileError(format!("No MIDI output '{name}' (:midi-out lists them)")))
+}
+
+pub struct Event {
+    // Seconds from the start of playback
+    pub time: f64,
+    pub data: Vec<u8>,
+}
+
+// Notes of the audible tracks from `start` seconds into the song, as note on and off messages
+// in time order, each track on its channel or else one of its own, after its program
+pub fn schedule(song: &Song, start: f64) -> Vec<Event> {
+    let mut events = Vec::new();
+    // Channels go by the tracks that are not archived, so muting one does not move the others
+    let tracks: Vec<usize> = song.visible_tracks().collect();
+    for (t, channel) in tracks.iter().copied().zip(song.midi_channels(&tracks)) {
+        if !song.is_audible(t) {
+            continue;
+        }
+        let track = &song.tracks[t];
+        if let Some(program) = track.program {
+            events.push(Event {
+                time: 0.0,
+                data: vec![0xc0 | channel, program.min(127)],
+            });
+        }
+        let tuning = track.tuning();
+        let times = song.beat_times(t);
+        for (i, beat) in track.beats.iter().enumerate() {
+            // Beats of other tracks at the same point may be a rounding error early
+            if times[i] < start - 1e-6 {
+                continue;
+            }
+            let velocity = beat.velocity.unwrap_or(DEFAULT_VELOCITY).min(127);
+            for (string, note) in &beat.notes {
+                let Note::Fret(fret) = note else {
+                    continue;
+                };
+                let pitch = tuning
+                    .get(*string as usize)
+                    .and_then(|open| fret.checked_add(*open as u16));
+                let Some(pitch) = pitch.filter(|p| *p <= 127) else {
+                    continue;
+                };
+                let pitch = pitch as u8;
+                events.push(Event {
+                    time: (times[i] - start).max(0.0),
+                    data: vec![0x90 | channel, pitch, velocity],
+                });
+                events.push(Event {
+                    time: times[i + 1] - start,
+                    data: vec![0x80 | channel, pitch, 0],
+                });
+            }
+        }
+    }
+    sort(&mut events);
+    events
+}
+
+// Metronome clicks on each beat of the time signature from `start` seconds into the song,
+// following the measures of `track`, with the first beat of a measure accented
+pub fn clicks(song: &Song, track: usize, start: f64) -> Vec<Event> {
+    let mut events = Vec::new();
+    let times = song.beat_times(track);
+    let track = &song.tracks[track];
+    let starts: Vec<usize> = (0..track.beats.len())
+        .filter(|i| track.measure_i[*i])
+        .collect();
+    for (measure, first) in starts.iter().enumerate() {
+        let end = times[starts
+            .get(measure + 1)
+            .copied()
+            .unwrap_or(track.beats.len())];
+        let sig = song.time_sig_at(measure);
+        let len = 60.0 * 4.0 / sig.den as f64 / song.tempo_at(measure) as f64;
+        for k in 0..sig.num {
+            let time = times[*first] + k as f64 * len;
+            if time >= end - 1e-6 {
+                break;
+            }
+            if time < start - 1e-6 {
+                continue;
+            }
+            let [pitch, velocity] = if k == 0 { CLICK_ACCENT } else { CLICK };
+            events.push(Event {
+                time: time - start,
+                data: vec![0x90 | DRUM_CHANNEL, pitch, velocity],
+            });
+            events.push(Event {
+                time: time - start + CLICK_LEN,
+                data: vec![0x80 | DRUM_CHANNEL, pitch, 0],
+            });
+        }
+    }
+    sort(&mut events);
+    events
+}
+
+// A short note on the first channel, heard as it is entered
+pub fn preview(pitch: u8, velocity: u8) -> Vec<Event> {
+    vec![
+        Event {
+            time: 0.0,
+            data: vec![0x90, pitch, velocity],
+        },
+        Event {
+            time: PREVIEW_LEN,
+            data: vec![0x80, pitch, 0],
+        },
+    ]
+}
+
+// MIDI clock from `start` seconds into the song, following the measures of `track`, from
+// the first sixteenth on; it starts devices from the top, or sets their song position and
+// continues when played from further in
+pub fn clock(song: &Song, track: usize, start: f64) -> Vec<Event> {
+    let mut events = Vec::new();
+    let times = song.beat_times(track);
+    let offsets = song.tracks[track].offsets();
+    let track = &song.tracks[track];
+    let starts: Vec<usize> = (0..track.beats.len())
+        .filter(|i| track.measure_i[*i])
+        .collect();
+    for (measure, first) in starts.iter().enumerate() {
+        let end = times[starts
+            .get(measure + 1)
+            .copied()
+            .unwrap_or(track.beats.len())];
+        let len = 60.0 / CLOCK_TICKS as f64 / song.tempo_at(measure) as f64;
+        // Position of the measure in ticks
+        let at = offsets[*first].quarters() * CLOCK_TICKS as f64;
+        for k in 0.. {
+            let time = times[*first] + k as f64 * len;
+            if time >= end - 1e-6 {
+                break;
+            }
+            if time < start - 1e-6 {
+                continue;
+            }
+            if events.is_empty() {
+                let ticks = (at + k as f64).round() as u32;
+                // Song positions count sixteenths, of six ticks
+                if !ticks.is_multiple_of(6) {
+                    continue;
+                }
+                let sixteenths = (ticks / 6).min(0x3fff);
+                let transport = match sixteenths {
+                    0 => vec![START],
+                    _ => vec![
+                        SONG_POSITION,
+                        (sixteenths & 0x7f) as u8,
+                        (sixteenths >> 7) as u8,
+                    ],
+                };
+                events.push(Event {
+                    time: time - start,
+                    data: transport,
+                });
+                if sixteenths > 0 {
+                    events.push(Event {
+                        time: time - start,
+                        data: vec![CONTINUE],
+                    });
+                }
+            }
+            events.push(Event {
+                time: time - start,
+                data: vec![CLOCK],
+            });
+        }
+    }
+    events
+}
+
+// Transport and programs go first at the same time, then note offs before note ons, so that
+// repeated notes are struck again
+pub fn sort(events: &mut [Event]) {
+    let order = |e: &Event| match e.data[0] {
+        SONG_POSITION | START | CONTINUE => 0,
+        0xc0..=0xcf => 1,
+        status => status,
+    };
+    events.sort_by(|a, b| a.time.total_cmp(&b.time).then(order(a).cmp(&order(b))));
+}
+
+// Sends the events on a thread of its own, until they run out or the player is dropped
+pub struct Player {
+    started: Instant,
+    stop: Arc<AtomicBool>,
+    thread: Option<thread::JoinHandle<()>>,
+}
+
+impl Player {
+    pub fn start(device: &Path, events: Vec<Event>) -> Result<Self> {
+        let mut out = std::fs::OpenOptions::new()
+            .write(true)
+            .open(device)
+            .map_err(|e| {
+                Error::FileError(format!(
+                    "Cannot open MIDI output '{}': {e}",
+                    device.display()
+                ))
+            })?;
+        // Devices following the clock are stopped along with the notes
+        let synced = events.iter().any(|e| e.data[0] == CLOCK);
+        let stop = Arc::new(AtomicBool::new(false));
+        let stopped = stop.clone();
+        let started = Instant::now();
+        let thread = thread::spawn(move || {
+            'events: for event in events {
+                let due = Duration::from_secs_f64(event.time);
+                // Waits in short steps, to stop soon after being asked to
+                loop {
+                    if stopped.load(Ordering::Relaxed) {
+                        break 'events;
+                    }
+                    let now = started.elapsed();
+                    if now >= due {
+                        break;
+                    }
+                    thread::sleep((due - now).min(Duration::from_millis(20)));
+                }
+                if out.write_all(&event.data).is_err() {
+                    break;
+                }
+            }
+            if synced {
+                let _ = out.write_all(&[STOP]);
+            }
+            for channel in 0..16 {
+                let _ = out.write_all(&[0xb0 | channel, ALL_NOTES_OFF, 0]);
+            }
+        });
+        Ok(Self {
+            started,
+            stop,
+            thread: Some(thread),
+        })
+    }
+
+    pub fn is_playing(&self) -> bool {
+        self.thread.as_ref().is_some_and(|t| !t.is_finished())
+    }
+
+    // Seconds since the first event was due
+    pub fn elapsed(&self) -> f64 {
+        self.started.elapsed().as_secs_f64()
+    }
+}
+
+impl Drop for Player {
+    fn drop(&mut self) {
+        self.stop.store(true, Ordering::Relaxed);
+        if let Some(thread) = self.thread.take() {
+            let _ = thread.join();
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        dur::Duration,
+        song::{Beat, TimeSig},
+    };
+
+    #[test]
+    fn notes_follow_tuning_and_tempo() {
+        let mut song = Song::new();
+        song.tracks[0].capo = 2;
+        let mut beat = Beat::new(Duration::new(1, 4));
+        beat.notes.push((5, Note::Fret(3)));
+        song.tracks[0].beats = vec![beat.clone(), beat];
+        song.update_all_measures();
+        let events = schedule(&song, 0.0);
+        // Frets count from the nut whatever the capo, so fret 3 of low E (40) is 43, and a quarter
+        // at 120 bpm lasts half a second
+        let data: Vec<(f64, [u8; 3])> = events
+            .iter()
+            .map(|e| (e.time, e.data.clone().try_into().unwrap()))
+            .collect();
+        assert_eq!(
+            data,
+            [
+                (0.0, [0x90, 43, DEFAULT_VELOCITY]),
+                (0.5, [0x80, 43, 0]),
+                (0.5, [0x90, 43, DEFAULT_VELOCITY]),
+                (1.0, [0x80, 43, 0]),
+            ]
+        );
+        assert_eq!(schedule(&song, 0.5).len(), 2);
+    }
+
+    #[test]
+    fn unplayable_notes_are_skipped() {
+        let mut song = Song::new();
+        let mut beat = Beat::new(Duration::new(1, 4));
+        beat.notes.push((0, Note::Fret(u16::MAX)));
+        beat.notes.push((1, Note::Fret(100)));
+        beat.notes.push((2, Note::Fret(3)));
+        song.tracks[0].beats = vec![beat];
+        song.update_all_measures();
+        // Only fret 3 of the G string (55) is in MIDI range, as 58
+        let ons: Vec<u8> = schedule(&song, 0.0)
+            .iter()
+            .filter(|e| e.data[0] & 0xf0 == 0x90)
+            .map(|e| e.data[1])
+            .collect();
+        assert_eq!(ons, [58]);
+    }
+
+    #[test]
+    fn clicks_follow_time_signature() {
+        let mut song = Song::new();
+        song.time_sig = TimeSig { num: 3, den: 4 };
+        song.tracks[0].beats = vec![
+            Beat::new(Duration::new(3, 4)),
+            Beat::new(Duration::new(1, 4)),
+        ];
+        song.update_all_measures();
+        // A full measure of 3/4 and the first beat of one cut short by the end of the track
+        let ons: Vec<(f64, u8)> = clicks(&song, 0, 0.0)
+            .iter()
+            .filter(|e| e.data[0] == 0x90 | DRUM_CHANNEL)
+            .map(|e| (e.time, e.data[1]))
+            .collect();
+        assert_eq!(ons, [(0.0, 76), (0.5, 77), (1.0, 77), (1.5, 76)]);
+    }
+
+    #[test]
+    fn clock_starts_or_continues() {
+        let mut song = Song::new();
+        song.tracks[0].beats = vec![Beat::new(Duration::new(1, 4)); 4];
+        song.update_all_measures();
+        let events = clock(&song, 0, 0.0);
+        assert_eq!(events[0].data, [START]);
+        assert_eq!(events.iter().filter(|e| e.data == [CLOCK]).count(), 96);
+        // From the second quarter, four sixteenths in
+        let events = clock(&song, 0, 0.5);
+        assert_eq!(events[0].data, [SONG_POSITION, 4, 0]);
+        assert_eq!(events[1].data, [CONTINUE]);
+        assert_eq!(events[2].time, 0.0);
+    }
+}
@@ -0,0 +1,161 @@
+use crate::{
+    cursor::Cursor,
+    error::{Error, Result},
+    song::Song,
+};
+use crossterm::event::{KeyCode, KeyModifiers};
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+
+// Requests are read one per line as {"id": .., "method": .., "params": ..}
+#[derive(Deserialize)]
+pub struct Request {
+    #[serde(default)]
+    pub id: Value,
+    pub method: String,
+    #[serde(default)]
+    pub params: Value,
+}
+
+#[derive(Serialize)]
+pub struct CursorState {
+    pub track: usize,
+    pub beat: usize,
+    pub string: u16,
+}
+
+impl CursorState {
+    pub fn new(cur: &Cursor) -> Self {
+        Self {
+            track: cur.track,
+            beat: cur.beat,
+            string: cur.string,
+        }
+    }
+}
+
+#[derive(Serialize)]
+pub struct State<'a> {
+    pub song: &'a Song,
+    pub path: Option<&'a str>,
+    pub dirty: bool,
+    pub cursor: CursorState,
+    pub input: String,
+    pub status: &'a str,
+}
+
+// JSON-RPC error codes, and the one used for requests the editor refuses
+pub const PARSE_ERROR: i32 = -32700;
+pub const METHOD_NOT_FOUND: i32 = -32601;
+pub const INVALID_PARAMS: i32 = -32602;
+pub const EDITOR_ERROR: i32 = 1;
+
+#[derive(Serialize)]
+pub struct ErrorState {
+    pub code: i32,
+    pub message: String,
+}
+
+impl ErrorState {
+    pub fn new(code: i32, err: Error) -> Self {
+        Self {
+            code,
+            message: format!("{err}"),
+        }
+    }
+}
+
+impl From<Error> for ErrorState {
+    fn from(err: Error) -> Self {
+        Self::new(EDITOR_ERROR, err)
+    }
+}
+
+#[derive(Serialize)]
+pub struct Response<'a> {
+    pub jsonrpc: &'static str,
+    pub id: Value,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub result: Option<State<'a>>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub error: Option<ErrorState>,
+}
+
+impl<'a> Response<'a> {
+    pub fn ok(id: Value, state: State<'a>) -> Self {
+        Self {
+            jsonrpc: "2.0",
+            id,
+            result: Some(state),
+            error: None,
+        }
+    }
+
+    pub fn err(id: Value, err: ErrorState) -> Self {
+        Self {
+            jsonrpc: "2.0",
+            id,
+            result: None,
+            error: Some(err),
+        }
+    }
+}
+
+// Keys are named as in key_bind_spec.txt, with an optional "S-" prefix for shift
+pub fn parse_key(name: &str) -> Result<(KeyCode, KeyModifiers)> {
+    let (name, modi) = match name.strip_prefix("S-") {
+        Some(rest) if !rest.is_empty() => (rest, KeyModifiers::SHIFT),
+        _ => (name, KeyModifiers::NONE),
+    };
+    let code = match name {
+        "Enter" => KeyCode::Enter,
+        "Esc" => KeyCode::Esc,
+        "Backspace" => KeyCode::Backspace,
+        "Tab" => KeyCode::Tab,
+        "Up" => KeyCode::Up,
+        "Down" => KeyCode::Down,
+        "Left" => KeyCode::Left,
+        "Right" => KeyCode::Right,
+        "Home" => KeyCode::Home,
+        "End" => KeyCode::End,
+        _ => {
+            let mut chars = name.chars();
+            match (chars.next(), chars.next(), function_key(name)) {
+                (_, _, Some(n)) => KeyCode::F(n),
+                (Some(ch), None, _) => KeyCode::Char(ch),
+                _ => return Err(Error::ParseError(format!("Unknown key '{name}'"))),
+            }
+        }
+    };
+    Ok((code, modi))
+}
+
+// "F1" to "F12"
+pub fn function_key(name: &str) -> Option<u8> {
+    name.strip_prefix('F')
+        .and_then(|n| n.parse().ok())
+        .filter(|n| (1..=12).contains(n))
+}
+
+// Inverse of parse_key, for keys that can be replayed
+pub fn key_name(code: KeyCode, modi: KeyModifiers) -> Option<String> {
+    let name = match code {
+        KeyCode::Char(ch) => return Some(ch.to_string()),
+        KeyCode::Enter => "Enter",
+        KeyCode::Esc => "Esc",
+        KeyCode::Backspace => "Backspace",
+        KeyCode::Tab => "Tab",
+        KeyCode::Up => "Up",
+        KeyCode::Down => "Down",
+        KeyCode::Left => "Left",
+        KeyCode::Right => "Right",
+        KeyCode::Home => "Home",
+        KeyCode::End => "End",
+        _ => return None,
+    };
+    if modi.contains(KeyModifiers::SHIFT) {
+        Some(format!("S-{name}"))
+    } else {
+        Some(name.to_owned())
+    }
+}
@@ -1,10 +1,14 @@
 use crate::{
-    dur::Duration,
+    chords::Shape,
+    dur::{Duration, Offset},
     error::{Error, Result},
+    pitch::{self, Key},
 };
 use serde::{Deserialize, Serialize};
 
-#[derive(Clone, Serialize, Deserialize)]
+pub mod editor;
+
+#[derive(Clone, PartialEq, Serialize, Deserialize)]
 #[serde(untagged)]
 pub enum Note {
     Fret(u16),
@@ -24,10 +28,355 @@ impl std::str::FromStr for Note {
     }
 }
 
+#[derive(Clone, Copy, Serialize, Deserialize)]
+pub enum Finger {
+    Index,
+    Middle,
+    Ring,
+    Pinky,
+    Thumb,
+}
+
+impl Finger {
+    pub fn glyph(&self) -> char {
+        match self {
+            Self::Index => '1',
+            Self::Middle => '2',
+            Self::Ring => '3',
+            Self::Pinky => '4',
+            Self::Thumb => 'T',
+        }
+    }
+}
+
+impl std::str::FromStr for Finger {
+    type Err = Error;
+    fn from_str(s: &str) -> Result<Self> {
+        match s {
+            "1" => Ok(Self::Index),
+            "2" => Ok(Self::Middle),
+            "3" => Ok(Self::Ring),
+            "4" => Ok(Self::Pinky),
+            "t" | "T" => Ok(Self::Thumb),
+            _ => Err(Error::InvalidOp(format!("Cannot parse '{s}' as finger"))),
+        }
+    }
+}
+
+// Emphasis of a note, ie the target notes of a lick in lesson material
+#[derive(Clone, Copy, PartialEq, Serialize, Deserialize)]
+pub enum Color {
+    Red,
+    Green,
+    Yellow,
+    Blue,
+    Magenta,
+    Cyan,
+}
+
+impl Color {
+    pub const ALL: [Self; 6] = [
+        Self::Red,
+        Self::Green,
+        Self::Yellow,
+        Self::Blue,
+        Self::Magenta,
+        Self::Cyan,
+    ];
+
+    pub fn name(&self) -> &'static str {
+        match self {
+            Self::Red => "red",
+            Self::Green => "green",
+            Self::Yellow => "yellow",
+            Self::Blue => "blue",
+            Self::Magenta => "magenta",
+            Self::Cyan => "cyan",
+        }
+    }
+}
+
+impl std::str::FromStr for Color {
+    type Err = Error;
+    fn from_str(s: &str) -> Result<Self> {
+        Self::ALL
+            .into_iter()
+            .find(|c| c.name().eq_ignore_ascii_case(s.trim()))
+            .ok_or_else(|| {
+                let names: Vec<&str> = Self::ALL.iter().map(|c| c.name()).collect();
+                Error::InvalidOp(format!("Unknown color '{s}' (one of {})", names.join(", ")))
+            })
+    }
+}
+
+#[derive(Clone, Copy, PartialEq, Serialize, Deserialize)]
+pub enum Pluck {
+    Slap,
+    Pop,
+}
+
+impl Pluck {
+    pub fn glyph(&self) -> char {
+        match self {
+            Self::Slap => 'S',
+            Self::Pop => 'P',
+        }
+    }
+}
+
+#[derive(Clone, Default, Serialize, Deserialize)]
+pub struct NoteMarks {
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub finger: Option<Finger>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub trill: Option<u16>,
+    #[serde(default, skip_serializing_if = "std::ops::Not::not")]
+    pub tap: bool,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub pluck: Option<Pluck>,
+    #[serde(default, skip_serializing_if = "std::ops::Not::not")]
+    pub swell: bool,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub color: Option<Color>,
+    #[serde(flatten)]
+    pub extra: Extra,
+}
+
+impl NoteMarks {
+    pub fn is_empty(&self) -> bool {
+        self.finger.is_none()
+            && self.trill.is_none()
+            && !self.tap
+            && self.pluck.is_none()
+            && !self.swell
+            && self.color.is_none()
+            && self.extra.is_empty()
+    }
+}
+
+#[derive(Clone)]
+pub struct NoteEntry {
+    pub note: Note,
+    pub marks: NoteMarks,
+}
+
+impl NoteEntry {
+    pub fn new(note: Note) -> Self {
+        Self {
+            note,
+            marks: NoteMarks::default(),
+        }
+    }
+}
+
+impl std::str::FromStr for NoteEntry {
+    type Err = Error;
+    fn from_str(s: &str) -> Result<Self> {
+        let split = s
+            .find(|c: char| c != 'x' && !c.is_ascii_digit())
+            .unwrap_or(s.len());
+        let (note, mut rest) = s.split_at(split);
+        let mut entry = Self::new(note.parse()?);
+        while !rest.is_empty() {
+            if let Some(trill) = rest.strip_prefix("tr") {
+                let len = trill
+                    .find(|c: char| !c.is_ascii_digit())
+                    .unwrap_or(trill.len());
+                entry.marks.trill = Some(trill[..len].parse().map_err(|_| {
+                    Error::InvalidOp(format!("Cannot parse '{}' as trill fret", &trill[..len]))
+                })?);
+                rest = &trill[len..];
+            } else if let Some(r) = rest.strip_prefix('t') {
+                entry.marks.tap = true;
+                rest = r;
+            } else if let Some(r) = rest.strip_prefix('v') {
+                entry.marks.swell = true;
+                rest = r;
+            } else if let Some(r) = rest.strip_prefix('s') {
+                entry.marks.pluck = Some(Pluck::Slap);
+                rest = r;
+            } else if let Some(r) = rest.strip_prefix('p') {
+                entry.marks.pluck = Some(Pluck::Pop);
+                rest = r;
+            } else {
+                return Err(Error::InvalidOp(format!(
+                    "Cannot parse '{rest}' as articulation"
+                )));
+            }
+        }
+        Ok(entry)
+    }
+}
+
+#[derive(Clone, Copy, Serialize, Deserialize)]
+pub enum Stroke {
+    Down,
+    Up,
+}
+
+impl Stroke {
+    pub fn glyph(&self) -> &'static str {
+        match self {
+            Self::Down => "∏",
+            Self::Up => "V",
+        }
+    }
+}
+
+#[derive(Clone, Copy, Serialize, Deserialize)]
+pub enum Arpeggio {
+    Up,
+    Down,
+}
+
+impl Arpeggio {
+    pub fn glyph(&self) -> char {
+        match self {
+            Self::Up => '↑',
+            Self::Down => '↓',
+        }
+    }
+}
+
+#[derive(Clone, Copy, PartialEq, Serialize, Deserialize)]
+pub enum Whammy {
+    Dive(u16),
+    Return,
+}
+
+impl Whammy {
+    pub fn glyph(&self) -> String {
+        match self {
+            Self::Dive(depth) => format!("\\{depth}"),
+            Self::Return => "/".into(),
+        }
+    }
+}
+
+impl std::str::FromStr for Whammy {
+    type Err = Error;
+    fn from_str(s: &str) -> Result<Self> {
+        match s {
+            "r" => Ok(Self::Return),
+            _ => match s.parse() {
+                Ok(depth) if depth > 0 => Ok(Self::Dive(depth)),
+                _ => Err(Error::InvalidOp(format!(
+                    "Cannot parse '{s}' as whammy depth"
+                ))),
+            },
+        }
+    }
+}
+
+// Fields written by a newer version, kept so that saving does not drop them
+pub type Extra = serde_json::Map<String, serde_json::Value>;
+
+pub const DEFAULT_VELOCITY: u8 = 96;
+pub const DEFAULT_TEMPO: u16 = 120;
+
+fn check_tempo(tempo: u16) -> Result<()> {
+    match tempo {
+        20..=400 => Ok(()),
+        _ => Err(Error::InvalidOp(format!(
+            "Tempo of {tempo} bpm is outside 20-400"
+        ))),
+    }
+}
+
 #[derive(Clone, Serialize, Deserialize)]
+#[serde(from = "RawBeat", into = "RawBeat")]
 pub struct Beat {
     pub dur: Duration,
     pub notes: Vec<(u16, Note)>,
+    pub marks: Vec<(u16, NoteMarks)>,
+    pub stroke: Option<Stroke>,
+    pub whammy: Option<Whammy>,
+    pub arpeggio: Option<Arpeggio>,
+    pub text: Option<String>,
+    pub velocity: Option<u8>,
+    // Notes of kinds written by a newer version, kept until their string gets a note here
+    pub unknown_notes: Vec<(u16, serde_json::Value)>,
+    // Whammies of unknown kinds are kept here under "whammy", as they were written
+    pub extra: Extra,
+}
+
+// A beat as written in song files, with notes and whammy left unread
+#[derive(Serialize, Deserialize)]
+struct RawBeat {
+    dur: Duration,
+    notes: Vec<(u16, serde_json::Value)>,
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    marks: Vec<(u16, NoteMarks)>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    stroke: Option<Stroke>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    whammy: Option<serde_json::Value>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    arpeggio: Option<Arpeggio>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    text: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    velocity: Option<u8>,
+    #[serde(flatten)]
+    extra: Extra,
+}
+
+impl From<RawBeat> for Beat {
+    fn from(raw: RawBeat) -> Self {
+        let mut beat = Self::new(raw.dur);
+        for (string, value) in raw.notes {
+            match serde_json::from_value(value.clone()) {
+                Ok(note) => beat.notes.push((string, note)),
+                Err(_) => beat.unknown_notes.push((string, value)),
+            }
+        }
+        beat.extra = raw.extra;
+        if let Some(value) = raw.whammy {
+            match serde_json::from_value(value.clone()) {
+                Ok(whammy) => beat.whammy = Some(whammy),
+                Err(_) => {
+                    beat.extra.insert("whammy".into(), value);
+                }
+            }
+        }
+        Self {
+            marks: raw.marks,
+            stroke: raw.stroke,
+            arpeggio: raw.arpeggio,
+            text: raw.text,
+            velocity: raw.velocity,
+            ..beat
+        }
+    }
+}
+
+impl From<Beat> for RawBeat {
+    fn from(mut beat: Beat) -> Self {
+        let unknown = beat
+            .unknown_notes
+            .into_iter()
+            .filter(|(s, _)| beat.notes.iter().all(|(n, _)| n != s))
+            .collect::<Vec<_>>();
+        if beat.whammy.is_some() {
+            beat.extra.remove("whammy");
+        }
+        Self {
+            dur: beat.dur,
+            notes: beat
+                .notes
+                .iter()
+                .map(|(s, n)| (*s, serde_json::to_value(n).unwrap()))
+                .chain(unknown)
+                .collect(),
+            marks: beat.marks,
+            stroke: beat.stroke,
+            whammy: beat.whammy.map(|w| serde_json::to_value(w).unwrap()),
+            arpeggio: beat.arpeggio,
+            text: beat.text,
+            velocity: beat.velocity,
+            extra: beat.extra,
+        }
+    }
 }
 
 impl Beat {
@@ -35,9 +384,68 @@ impl Beat {
         Self {
             dur,
             notes: Vec::new(),
+            marks: Vec::new(),
+            stroke: None,
+            whammy: None,
+            arpeggio: None,
+            text: None,
+            velocity: None,
+            unknown_notes: Vec::new(),
+            extra: Extra::new(),
         }
     }
 
+    pub fn velocity(&self) -> u8 {
+        self.velocity.unwrap_or(DEFAULT_VELOCITY)
+    }
+
+    pub fn has_decoration(&self) -> bool {
+        self.stroke.is_some()
+            || self.whammy.is_some()
+            || self.arpeggio.is_some()
+            || self.trill().is_some()
+            || self.has_tap()
+            || self.has_swell()
+            || self.pluck().is_some()
+    }
+
+    // Strings spanned by the roll line of an arpeggiated chord
+    pub fn arpeggio_span(&self) -> Option<std::ops::RangeInclusive<u16>> {
+        self.arpeggio?;
+        let lo = self.notes.iter().map(|n| n.0).min()?;
+        let hi = self.notes.iter().map(|n| n.0).max()?;
+        Some(lo..=hi)
+    }
+
+    fn any_marks(&self, f: impl Fn(&NoteMarks) -> bool) -> bool {
+        self.marks
+            .iter()
+            .any(|(s, m)| f(m) && self.get_note(*s).is_some())
+    }
+
+    // Fret trilled to by the first trilled note
+    pub fn trill(&self) -> Option<u16> {
+        self.marks
+            .iter()
+            .filter(|(s, _)| self.get_note(*s).is_some())
+            .find_map(|(_, m)| m.trill)
+    }
+
+    pub fn has_tap(&self) -> bool {
+        self.any_marks(|m| m.tap)
+    }
+
+    pub fn has_swell(&self) -> bool {
+        self.any_marks(|m| m.swell)
+    }
+
+    pub fn pluck(&self) -> Option<Pluck> {
+        self.marks
+            .iter()
+            .filter(|(s, _)| self.get_note(*s).is_some())
+            .find_map(|(_, m)| m.pluck)
+    }
+
     pub fn copy_duration(&self) -> Self {
         Self::new(self.dur)
     }
@@ -78,54 +486,1124 @@ impl Beat {
             }
         }
     }
+
+    pub fn copy_entry(&self, string: u16) -> Option<NoteEntry> {
+        Some(NoteEntry {
+            note: self.copy_note(string)?,
+            marks: self.get_marks(string).cloned().unwrap_or_default(),
+        })
+    }
+
+    pub fn set_entry(&mut self, string: u16, entry: NoteEntry) {
+        self.set_note(string, entry.note);
+        self.del_marks(string);
+        if !entry.marks.is_empty() {
+            self.marks.push((string, entry.marks));
+        }
+    }
+
+    pub fn del_entry(&mut self, string: u16) {
+        self.del_note(string);
+        self.del_marks(string);
+    }
+
+    pub fn get_marks(&self, string: u16) -> Option<&NoteMarks> {
+        for i in self.marks.iter() {
+            if i.0 == string {
+                return Some(&i.1);
+            }
+        }
+        None
+    }
+
+    pub fn marks_mut(&mut self, string: u16) -> &mut NoteMarks {
+        if let Some(i) = self.marks.iter().position(|i| i.0 == string) {
+            &mut self.marks[i].1
+        } else {
+            self.marks.push((string, NoteMarks::default()));
+            &mut self.marks.last_mut().unwrap().1
+        }
+    }
+
+    pub fn del_marks(&mut self, string: u16) {
+        for i in 0..self.marks.len() {
+            if self.marks[i].0 == string {
+                self.marks.swap_remove(i);
+                return;
+            }
+        }
+    }
+
+    pub fn tidy_marks(&mut self) {
+        self.marks.retain(|i| !i.1.is_empty());
+    }
+
+    pub fn get_finger(&self, string: u16) -> Option<Finger> {
+        self.get_marks(string)?.finger
+    }
+
+    pub fn set_finger(&mut self, string: u16, finger: Option<Finger>) {
+        self.marks_mut(string).finger = finger;
+        self.tidy_marks();
+    }
+
+    pub fn get_color(&self, string: u16) -> Option<Color> {
+        self.get_marks(string)?.color
+    }
+
+    pub fn set_color(&mut self, string: u16, color: Option<Color>) {
+        self.marks_mut(string).color = color;
+        self.tidy_marks();
+    }
 }
 
-#[derive(Serialize, Deserialize)]
+fn default_fret_count() -> u16 {
+    24
+}
+
+#[derive(Clone, Serialize, Deserialize)]
 pub struct Track {
     pub string_count: u16,
+    #[serde(default = "default_fret_count")]
+    pub fret_count: u16,
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub tuning: Vec<u8>,
+    #[serde(default)]
+    pub capo: u16,
+    #[serde(default, skip_serializing_if = "std::ops::Not::not")]
+    pub archived: bool,
+    // Left out of playback, or played alone along with the other solo tracks
+    #[serde(default, skip_serializing_if = "std::ops::Not::not")]
+    pub muted: bool,
+    #[serde(default, skip_serializing_if = "std::ops::Not::not")]
+    pub solo: bool,
+    // MIDI instrument (0-127) and channel (1-16), left to the player when not set
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub program: Option<u8>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub channel: Option<u8>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub name: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub group: Option<String>,
     pub beats: Vec<Beat>,
+    #[serde(flatten)]
+    pub extra: Extra,
     #[serde(skip)]
     pub measure_i: Vec<bool>,
+    #[serde(skip)]
+    pub beam_i: Vec<bool>,
 }
 
 impl Track {
     pub fn new() -> Self {
         Self {
             string_count: 6,
+            fret_count: default_fret_count(),
+            tuning: Vec::new(),
+            capo: 0,
+            archived: false,
+            muted: false,
+            solo: false,
+            program: None,
+            channel: None,
+            name: None,
+            group: None,
             beats: vec![Beat::new(Duration::new(1, 1))],
+            extra: Extra::new(),
             measure_i: vec![true],
+            beam_i: vec![false],
+        }
+    }
+
+    fn settings(&self) -> Self {
+        Self {
+            string_count: self.string_count,
+            fret_count: self.fret_count,
+            tuning: self.tuning.clone(),
+            capo: self.capo,
+            archived: self.archived,
+            muted: self.muted,
+            solo: self.solo,
+            program: self.program,
+            channel: self.channel,
+            name: self.name.clone(),
+            group: self.group.clone(),
+            beats: Vec::new(),
+            extra: self.extra.clone(),
+            measure_i: Vec::new(),
+            beam_i: Vec::new(),
+        }
+    }
+
+    pub fn check_note(&self, note: &Note) -> Result<()> {
+        match note {
+            Note::Fret(fret) if *fret > self.fret_count => Err(Error::InvalidOp(format!(
+                "Fret {fret} exceeds the track's {} frets",
+                self.fret_count
+            ))),
+            _ => Ok(()),
         }
     }
 
-    pub fn update_measures(&mut self) {
+    // The capo has to stay on the neck, below the last fret
+    pub fn set_fret_count(&mut self, frets: u16) -> Result<()> {
+        if frets == 0 || frets <= self.capo {
+            return Err(Error::InvalidOp(format!(
+                "{frets} frets leave no room past the capo at {}",
+                self.capo
+            )));
+        }
+        self.fret_count = frets;
+        Ok(())
+    }
+
+    pub fn has_decorations(&self) -> bool {
+        self.beats.iter().any(|b| b.has_decoration())
+    }
+
+    pub fn has_text(&self) -> bool {
+        self.beats.iter().any(|b| b.text.is_some())
+    }
+
+    pub fn offsets(&self) -> Vec<Offset> {
+        let mut sum = Offset::zero();
+        self.beats
+            .iter()
+            .map(|b| {
+                let o = sum;
+                sum = sum + b.dur;
+                o
+            })
+            .collect()
+    }
+
+    pub fn measure_of(&self, beat: usize) -> usize {
+        self.measure_i[..=beat].iter().filter(|m| **m).count() - 1
+    }
+
+    pub fn measure_start(&self, measure: usize) -> Option<usize> {
+        self.measure_i
+            .iter()
+            .enumerate()
+            .filter(|(_, m)| **m)
+            .nth(measure)
+            .map(|(i, _)| i)
+    }
+
+    pub fn tuning(&self) -> Vec<u8> {
+        if self.tuning.len() == self.string_count as usize {
+            self.tuning.clone()
+        } else {
+            pitch::standard_tuning(self.string_count)
+        }
+    }
+
+    pub fn notes_shape(&self, notes: &[(u16, Note)]) -> Result<Shape> {
+        notes
+            .iter()
+            .map(|(s, note)| match note {
+                Note::Fret(f) if *f < self.capo => Err(Error::InvalidOp(format!(
+                    "Fret {f} is behind the capo at {}",
+                    self.capo
+                ))),
+                Note::Fret(f) => Ok((*s, Note::Fret(f - self.capo))),
+                Note::X => Ok((*s, Note::X)),
+            })
+            .collect()
+    }
+
+    // The capo can push frets of the shape past the neck, so each note is checked
+    pub fn shape_notes(&self, shape: &Shape) -> Result<Vec<(u16, Note)>> {
+        shape
+            .iter()
+            .filter(|(s, _)| *s < self.string_count)
+            .map(|(s, note)| {
+                let note = match note {
+                    Note::Fret(f) => Note::Fret(f.saturating_add(self.capo)),
+                    Note::X => Note::X,
+                };
+                self.check_entry(&NoteEntry::new(note.clone()))?;
+                Ok((*s, note))
+            })
+            .collect()
+    }
+
+    pub fn check_entry(&self, entry: &NoteEntry) -> Result<()> {
+        self.check_note(&entry.note)?;
+        if let Some(trill) = entry.marks.trill {
+            self.check_note(&Note::Fret(trill))?;
+        }
+        Ok(())
+    }
+
+    // `meters` is in measure order, starting with the meter of the first measure
+    pub fn update_measures(&mut self, meters: &[Meter]) {
         self.measure_i.clear();
         self.measure_i.reserve(self.beats.len());
-        let mut total = Duration::new(1, 1);
-        let mlen = Duration::new(1, 1);
+        self.beam_i.clear();
+        self.beam_i.reserve(self.beats.len());
+        let group_of = |meter: &Meter, offset: Duration| {
+            let unit = Duration::new(1, meter.sig.den);
+            let mut end = Duration::zero();
+            for (i, g) in meter.groups.iter().enumerate() {
+                end = end + unit * *g;
+                if offset < end {
+                    return i;
+                }
+            }
+            meter.groups.len()
+        };
+        let mut meter = &meters[0];
+        let mut measure = 0;
+        let mut total = meter.sig.measure_len();
+        let mut prev: Option<(Duration, usize)> = None;
         for beat in self.beats.iter() {
+            let mlen = meter.sig.measure_len();
             if total == mlen {
                 total = Duration::new(0, 1);
                 self.measure_i.push(true);
+                prev = None;
+                meter = meters.iter().rev().find(|m| m.measure <= measure).unwrap();
+                measure += 1;
             } else if total > mlen {
                 total = total - mlen;
                 self.measure_i.push(false);
+                prev = None;
             } else {
                 self.measure_i.push(false);
             }
+            let group = group_of(meter, total);
+            self.beam_i.push(match prev {
+                Some((dur, g)) => beat.dur.beamable() && dur.beamable() && g == group,
+                None => false,
+            });
+            prev = Some((beat.dur, group));
             total = total + beat.dur;
         }
     }
 }
 
-#[derive(Serialize, Deserialize)]
+// Time signature and beaming in effect from a measure on
+pub struct Meter {
+    pub measure: usize,
+    pub sig: TimeSig,
+    pub groups: Vec<u16>,
+}
+
+// Time signature and/or tempo taking effect at the start of a measure after the first
+#[derive(Clone, PartialEq, Serialize, Deserialize)]
+pub struct Change {
+    pub measure: usize,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub time_sig: Option<TimeSig>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub tempo: Option<u16>,
+}
+
+#[derive(Clone, Copy, PartialEq, Serialize, Deserialize)]
+pub struct TimeSig {
+    pub num: u16,
+    pub den: u16,
+}
+
+impl TimeSig {
+    pub fn measure_len(&self) -> Duration {
+        Duration::new(self.num, self.den)
+    }
+
+    pub fn default_groups(&self) -> Vec<u16> {
+        if self.den >= 8 && self.num > 3 && self.num.is_multiple_of(3) {
+            vec![3; (self.num / 3) as usize]
+        } else {
+            vec![1; self.num as usize]
+        }
+    }
+}
+
+impl Default for TimeSig {
+    fn default() -> Self {
+        Self { num: 4, den: 4 }
+    }
+}
+
+impl std::fmt::Display for TimeSig {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}/{}", self.num, self.den)
+    }
+}
+
+impl std::str::FromStr for TimeSig {
+    type Err = Error;
+    fn from_str(s: &str) -> Result<Self> {
+        let parsed = s
+            .split_once('/')
+            .and_then(|(n, d)| Some((n.trim().parse().ok()?, d.trim().parse().ok()?)));
+        match parsed {
+            Some((num, den)) if num > 0 && den > 0 && u16::is_power_of_two(den) => {
+                Ok(Self { num, den })
+            }
+            _ => Err(Error::ParseError(format!(
+                "Unable to parse '{s}' as time signature"
+            ))),
+        }
+    }
+}
+
+#[derive(Clone, PartialEq, Serialize, Deserialize)]
+pub struct Beaming {
+    pub sig: TimeSig,
+    pub groups: Vec<u16>,
+}
+
+#[derive(Clone, PartialEq, Serialize, Deserialize)]
+pub struct Todo {
+    pub measure: usize,
+    pub text: String,
+    #[serde(default)]
+    pub done: bool,
+}
+
+#[derive(Clone, Default, PartialEq, Serialize, Deserialize)]
+pub struct Meta {
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub title: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub artist: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub album: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub transcriber: Option<String>,
+    #[serde(flatten)]
+    pub extra: Extra,
+}
+
+impl Meta {
+    pub fn is_empty(&self) -> bool {
+        self.title.is_none()
+            && self.artist.is_none()
+            && self.album.is_none()
+            && self.transcriber.is_none()
+            && self.extra.is_empty()
+    }
+
+    pub fn field_mut(&mut self, key: &str) -> Option<&mut Option<String>> {
+        match key {
+            "title" => Some(&mut self.title),
+            "artist" => Some(&mut self.artist),
+            "album" => Some(&mut self.album),
+            "transcriber" => Some(&mut self.transcriber),
+            _ => None,
+        }
+    }
+
+    // ie "Title - Artist (Album), tab by Transcriber"
+    pub fn header(&self) -> String {
+        let mut out = self.title.clone().unwrap_or_else(|| "Untitled".into());
+        if let Some(artist) = &self.artist {
+            out.push_str(&format!(" - {artist}"));
+        }
+        if let Some(album) = &self.album {
+            out.push_str(&format!(" ({album})"));
+        }
+        if let Some(transcriber) = &self.transcriber {
+            out.push_str(&format!(", tab by {transcriber}"));
+        }
+        out
+    }
+}
+
+#[derive(Clone, Serialize, Deserialize)]
 pub struct Song {
+    #[serde(default, skip_serializing_if = "Meta::is_empty")]
+    pub meta: Meta,
     pub tracks: Vec<Track>,
+    #[serde(default)]
+    pub time_sig: TimeSig,
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub beaming: Vec<Beaming>,
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub todos: Vec<Todo>,
+    // Groups of tracks shown as a single summary lane
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub collapsed: Vec<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub swing: Option<u16>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub key: Option<Key>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub tempo: Option<u16>,
+    // In measure order, at most one per measure
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub changes: Vec<Change>,
+    // Formats written next to the song on every save
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub exports: Vec<String>,
+    #[serde(flatten)]
+    pub extra: Extra,
 }
 
 impl Song {
     pub fn new() -> Self {
         Self {
+            meta: Meta::default(),
             tracks: vec![Track::new()],
+            time_sig: TimeSig::default(),
+            beaming: Vec::new(),
+            todos: Vec::new(),
+            collapsed: Vec::new(),
+            swing: None,
+            key: None,
+            tempo: None,
+            changes: Vec::new(),
+            exports: Vec::new(),
+            extra: Extra::new(),
+        }
+    }
+
+    pub fn beam_groups(&self) -> Vec<u16> {
+        self.groups_of(self.time_sig)
+    }
+
+    fn groups_of(&self, sig: TimeSig) -> Vec<u16> {
+        self.beaming
+            .iter()
+            .find(|b| b.sig == sig)
+            .map(|b| b.groups.clone())
+            .unwrap_or_else(|| sig.default_groups())
+    }
+
+    pub fn meters(&self) -> Vec<Meter> {
+        let first = Meter {
+            measure: 0,
+            sig: self.time_sig,
+            groups: self.beam_groups(),
+        };
+        let changes = self.changes.iter().filter_map(|c| {
+            c.time_sig.map(|sig| Meter {
+                measure: c.measure,
+                sig,
+                groups: self.groups_of(sig),
+            })
+        });
+        std::iter::once(first).chain(changes).collect()
+    }
+
+    pub fn change_at(&self, measure: usize) -> Option<&Change> {
+        self.changes.iter().find(|c| c.measure == measure)
+    }
+
+    pub fn time_sig_at(&self, measure: usize) -> TimeSig {
+        self.changes
+            .iter()
+            .rev()
+            .filter(|c| c.measure <= measure)
+            .find_map(|c| c.time_sig)
+            .unwrap_or(self.time_sig)
+    }
+
+    pub fn tempo_at(&self, measure: usize) -> u16 {
+        self.changes
+            .iter()
+            .rev()
+            .filter(|c| c.measure <= measure)
+            .find_map(|c| c.tempo)
+            .unwrap_or_else(|| self.tempo())
+    }
+
+    // Replaces the change at its measure, one changing nothing is removed
+    pub fn set_change(&mut self, change: Change) -> Result<()> {
+        if change.measure == 0 {
+            return Err(Error::InvalidOp(
+                "The first measure takes the time signature and tempo of the song".into(),
+            ));
+        }
+        if let Some(tempo) = change.tempo {
+            check_tempo(tempo)?;
+        }
+        self.changes.retain(|c| c.measure != change.measure);
+        if change.time_sig.is_some() || change.tempo.is_some() {
+            let i = self.changes.partition_point(|c| c.measure < change.measure);
+            self.changes.insert(i, change);
+        }
+        self.update_all_measures();
+        Ok(())
+    }
+
+    pub fn move_change(&mut self, from: usize, to: usize) -> Result<()> {
+        if self.change_at(to).is_some() {
+            return Err(Error::InvalidOp(format!(
+                "Measure {} already has a change",
+                to + 1
+            )));
+        }
+        let i = self
+            .changes
+            .iter()
+            .position(|c| c.measure == from)
+            .ok_or_else(|| Error::InvalidOp(format!("No change at measure {}", from + 1)))?;
+        let mut change = self.changes.remove(i);
+        change.measure = to;
+        self.set_change(change)
+    }
+
+    pub fn del_change(&mut self, measure: usize) -> Result<Change> {
+        let i = self
+            .changes
+            .iter()
+            .position(|c| c.measure == measure)
+            .ok_or_else(|| Error::InvalidOp(format!("No change at measure {}", measure + 1)))?;
+        let change = self.changes.remove(i);
+        self.update_all_measures();
+        Ok(change)
+    }
+
+    pub fn set_beam_groups(&mut self, groups: Option<Vec<u16>>) -> Result<()> {
+        if let Some(groups) = &groups {
+            if groups.contains(&0) {
+                return Err(Error::InvalidOp(
+                    "Groups must hold at least one beat".into(),
+                ));
+            }
+            // Summed wider, so that long groups cannot wrap around to the right count
+            if groups.iter().map(|g| *g as u32).sum::<u32>() != self.time_sig.num as u32 {
+                return Err(Error::InvalidOp(format!(
+                    "Groups must add up to {} for {}",
+                    self.time_sig.num, self.time_sig
+                )));
+            }
+        }
+        let sig = self.time_sig;
+        self.beaming.retain(|b| b.sig != sig);
+        if let Some(groups) = groups {
+            self.beaming.push(Beaming { sig, groups });
+        }
+        self.update_all_measures();
+        Ok(())
+    }
+
+    pub fn tempo(&self) -> u16 {
+        self.tempo.unwrap_or(DEFAULT_TEMPO)
+    }
+
+    pub fn set_tempo(&mut self, tempo: Option<u16>) -> Result<()> {
+        if let Some(tempo) = tempo {
+            check_tempo(tempo)?;
+        }
+        self.tempo = tempo;
+        Ok(())
+    }
+
+    // Offset in quarter notes with swing delaying offbeat eighths
+    fn swung(&self, quarters: f64) -> f64 {
+        let whole = quarters.floor();
+        let mut part = quarters - whole;
+        if let Some(swing) = self.swing {
+            let first = swing as f64 / 100.0;
+            part = if part < 0.5 {
+                part * 2.0 * first
+            } else {
+                first + (part - 0.5) * 2.0 * (1.0 - first)
+            };
+        }
+        whole + part
+    }
+
+    // Start time in seconds of each beat of a track, followed by the time it ends
+    pub fn beat_times(&self, track: usize) -> Vec<f64> {
+        let track = &self.tracks[track];
+        let mut offset = Offset::zero();
+        let mut times = vec![0.0];
+        // Time and offset at which the tempo in effect was set
+        let (mut since, mut since_offset, mut tempo) = (0.0, Offset::zero(), self.tempo());
+        let mut measure = 0;
+        for (i, beat) in track.beats.iter().enumerate() {
+            if track.measure_i[i] {
+                let t = self.tempo_at(measure);
+                if t != tempo {
+                    (since, since_offset, tempo) = (times[i], offset, t);
+                }
+                measure += 1;
+            }
+            offset = offset + beat.dur;
+            let quarters = self.swung(offset.quarters()) - self.swung(since_offset.quarters());
+            times.push(since + quarters * 60.0 / tempo as f64);
+        }
+        times
+    }
+
+    // Swing is the share of each beat given to its first eighth, 50 being straight
+    pub fn set_swing(&mut self, swing: Option<u16>) -> Result<()> {
+        match swing {
+            Some(s) if !(50..=80).contains(&s) => {
+                Err(Error::InvalidOp(format!("Swing of {s}% is outside 50-80%")))
+            }
+            Some(50) => {
+                self.swing = None;
+                Ok(())
+            }
+            _ => {
+                self.swing = swing;
+                Ok(())
+            }
+        }
+    }
+
+    pub fn update_measures(&mut self, track: usize) {
+        let meters = self.meters();
+        self.tracks[track].update_measures(&meters);
+    }
+
+    pub fn visible_tracks(&self) -> impl Iterator<Item = usize> + '_ {
+        (0..self.tracks.len()).filter(|t| !self.tracks[*t].archived)
+    }
+
+    // Tracks that are played, the solo ones when there are any and otherwise those not muted
+    pub fn is_audible(&self, track: usize) -> bool {
+        let any_solo = self.visible_tracks().any(|t| self.tracks[t].solo);
+        let track = &self.tracks[track];
+        !track.archived && if any_solo { track.solo } else { !track.muted }
+    }
+
+    // MIDI channel (0-15) of each of the tracks, those without one of their own taking the
+    // channels left in turn, channel 10 being kept for drums
+    pub fn midi_channels(&self, tracks: &[usize]) -> Vec<u8> {
+        let taken: Vec<u8> = tracks
+            .iter()
+            .filter_map(|t| self.tracks[*t].channel)
+            .map(|c| c.clamp(1, 16) - 1)
+            .collect();
+        let free: Vec<u8> = (0..16).filter(|c| *c != 9 && !taken.contains(c)).collect();
+        let mut auto = free.iter().cycle();
+        tracks
+            .iter()
+            .map(|t| match self.tracks[*t].channel {
+                Some(c) => c.clamp(1, 16) - 1,
+                None => auto.next().copied().unwrap_or(0),
+            })
+            .collect()
+    }
+
+    // Groups in the order of their first track, with the tracks that are not archived
+    pub fn groups(&self) -> Vec<(&str, Vec<usize>)> {
+        let mut groups: Vec<(&str, Vec<usize>)> = Vec::new();
+        for t in self.visible_tracks() {
+            let Some(name) = self.tracks[t].group.as_deref() else {
+                continue;
+            };
+            match groups.iter_mut().find(|(n, _)| *n == name) {
+                Some((_, tracks)) => tracks.push(t),
+                None => groups.push((name, vec![t])),
+            }
+        }
+        groups
+    }
+
+    pub fn is_collapsed(&self, track: usize) -> bool {
+        self.tracks[track]
+            .group
+            .as_ref()
+            .is_some_and(|g| self.collapsed.contains(g))
+    }
+
+    // Names of the fields kept in `extra` anywhere in the song, ie "track.lyrics"
+    pub fn unknown_fields(&self) -> Vec<String> {
+        let mut extras = vec![("", &self.extra), ("meta.", &self.meta.extra)];
+        if let Some(key) = &self.key {
+            extras.push(("key.", &key.extra));
+        }
+        let mut names = std::collections::BTreeSet::new();
+        for track in &self.tracks {
+            extras.push(("track.", &track.extra));
+            for beat in &track.beats {
+                extras.push(("beat.", &beat.extra));
+                extras.extend(beat.marks.iter().map(|(_, m)| ("marks.", &m.extra)));
+                if !beat.unknown_notes.is_empty() {
+                    names.insert("beat.notes".to_owned());
+                }
+            }
+        }
+        for (prefix, extra) in extras {
+            names.extend(extra.keys().map(|key| format!("{prefix}{key}")));
+        }
+        names.into_iter().collect()
+    }
+
+    // Archived tracks stay in the song file but are left out of anything shared
+    pub fn without_archived(&self) -> Result<Song> {
+        let mut song = self.clone();
+        song.tracks.retain(|t| !t.archived);
+        if song.tracks.is_empty() {
+            return Err(Error::InvalidOp("All tracks are archived".into()));
+        }
+        Ok(song)
+    }
+
+    // Copy holding measures `from` to `to` of the tracks that reach `from`,
+    // along with the meter, tempo and TODOs of those measures
+    pub fn measure_range(&self, from: usize, to: usize) -> Result<Song> {
+        let mut song = self.clone();
+        song.tracks.retain_mut(|track| {
+            let Some(start) = track.measure_start(from) else {
+                return false;
+            };
+            let end = track.measure_start(to + 1).unwrap_or(track.beats.len());
+            track.beats = track.beats[start..end].to_vec();
+            true
+        });
+        if song.tracks.is_empty() {
+            return Err(Error::InvalidOp(format!(
+                "No track reaches measure {}",
+                from + 1
+            )));
+        }
+        song.todos.retain(|t| (from..=to).contains(&t.measure));
+        for todo in song.todos.iter_mut() {
+            todo.measure -= from;
+        }
+        song.changes.retain(|c| c.measure <= to);
+        song.shift_changes(0, from);
+        Ok(song)
+    }
+
+    pub fn update_all_measures(&mut self) {
+        let meters = self.meters();
+        for track in self.tracks.iter_mut() {
+            track.update_measures(&meters);
+        }
+    }
+
+    // How the beats of each measure of the track compare to the length of its time signature,
+    // a measure that runs over taking in the next one as no barline falls inside it
+    pub fn measure_fit(&self, t: usize) -> Vec<std::cmp::Ordering> {
+        let track = &self.tracks[t];
+        let mut out = Vec::new();
+        let mut total = Duration::zero();
+        for (i, beat) in track.beats.iter().enumerate() {
+            if track.measure_i[i] && i > 0 {
+                out.push(total.cmp(&self.time_sig_at(out.len()).measure_len()));
+                total = Duration::zero();
+            }
+            total = total + beat.dur;
+        }
+        out.push(total.cmp(&self.time_sig_at(out.len()).measure_len()));
+        out
+    }
+
+    pub fn last_measure(&self) -> usize {
+        self.tracks
+            .iter()
+            .map(|t| t.measure_of(t.beats.len() - 1))
+            .max()
+            .unwrap_or(0)
+    }
+
+    pub fn add_todo(&mut self, measure: usize, text: String) -> usize {
+        self.todos.push(Todo {
+            measure,
+            text,
+            done: false,
+        });
+        self.todos.len()
+    }
+
+    pub fn todo_mut(&mut self, n: usize) -> Result<&mut Todo> {
+        n.checked_sub(1)
+            .and_then(|i| self.todos.get_mut(i))
+            .ok_or_else(|| Error::InvalidOp(format!("No TODO item {n}")))
+    }
+
+    pub fn del_todo(&mut self, n: usize) -> Result<Todo> {
+        self.todo_mut(n)?;
+        Ok(self.todos.remove(n - 1))
+    }
+
+    // Called once `count` measures are inserted before `measure` in every track, TODOs and later
+    // changes move along with their measures; a change at `measure` stays, so the new ones keep its meter
+    pub fn open_measures(&mut self, measure: usize, count: usize) {
+        for todo in &mut self.todos {
+            if todo.measure >= measure {
+                todo.measure += count;
+            }
+        }
+        for change in &mut self.changes {
+            if change.measure > measure {
+                change.measure += count;
+            }
+        }
+        self.update_all_measures();
+    }
+
+    // Called once `count` measures from `measure` are deleted, TODOs of the deleted
+    // measures move to the measure now in their place
+    pub fn shift_todos(&mut self, measure: usize, count: usize) {
+        let last = self.last_measure();
+        for todo in &mut self.todos {
+            if todo.measure >= measure + count {
+                todo.measure -= count;
+            } else if todo.measure > measure {
+                todo.measure = measure;
+            }
+            todo.measure = todo.measure.min(last);
+        }
+    }
+
+    // Called once `count` measures from `measure` are deleted, the changes of the deleted
+    // measures are merged into the measure now in their place, so what follows keeps its meter
+    pub fn shift_changes(&mut self, measure: usize, count: usize) {
+        let mut merged = Change {
+            measure,
+            time_sig: None,
+            tempo: None,
+        };
+        let mut changes = Vec::new();
+        for mut change in std::mem::take(&mut self.changes) {
+            if change.measure < measure {
+                changes.push(change);
+            } else if change.measure <= measure + count {
+                // Later changes win, the one after the deleted measures last
+                merged.time_sig = change.time_sig.or(merged.time_sig);
+                merged.tempo = change.tempo.or(merged.tempo);
+            } else {
+                change.measure -= count;
+                changes.push(change);
+            }
+        }
+        if measure == 0 {
+            self.time_sig = merged.time_sig.unwrap_or(self.time_sig);
+            self.tempo = merged.tempo.or(self.tempo);
+        } else if merged.time_sig.is_some() || merged.tempo.is_some() {
+            let i = changes.partition_point(|c| c.measure < measure);
+            changes.insert(i, merged);
+        }
+        self.changes = changes;
+        self.update_all_measures();
+    }
+
+    // Copy of everything but the beats of the tracks, to undo changes of the settings
+    pub fn settings(&self) -> Song {
+        Song {
+            meta: self.meta.clone(),
+            tracks: self.tracks.iter().map(Track::settings).collect(),
+            time_sig: self.time_sig,
+            beaming: self.beaming.clone(),
+            todos: self.todos.clone(),
+            collapsed: self.collapsed.clone(),
+            swing: self.swing,
+            key: self.key.clone(),
+            tempo: self.tempo,
+            changes: self.changes.clone(),
+            exports: self.exports.clone(),
+            extra: self.extra.clone(),
+        }
+    }
+
+    pub fn set_settings(&mut self, settings: &Song) {
+        for (track, other) in self.tracks.iter_mut().zip(&settings.tracks) {
+            track.string_count = other.string_count;
+            track.fret_count = other.fret_count;
+            track.tuning = other.tuning.clone();
+            track.capo = other.capo;
+            track.archived = other.archived;
+            track.muted = other.muted;
+            track.solo = other.solo;
+            track.program = other.program;
+            track.channel = other.channel;
+            track.name = other.name.clone();
+            track.group = other.group.clone();
+            track.extra = other.extra.clone();
+        }
+        self.meta = settings.meta.clone();
+        self.time_sig = settings.time_sig;
+        self.beaming = settings.beaming.clone();
+        self.todos = settings.todos.clone();
+        self.collapsed = settings.collapsed.clone();
+        self.swing = settings.swing;
+        self.key = settings.key.clone();
+        self.tempo = settings.tempo;
+        self.changes = settings.changes.clone();
+        self.exports = settings.exports.clone();
+        self.extra = settings.extra.clone();
+        self.update_all_measures();
+    }
+
+    // Parts of the settings that differ in `new`, ie "tempo 120 bpm -> 90 bpm"
+    pub fn settings_diff(&self, new: &Song) -> Vec<String> {
+        fn text(value: &Option<String>) -> String {
+            match value {
+                Some(value) => format!("'{value}'"),
+                None => "(none)".into(),
+            }
+        }
+        fn tuning(track: &Track) -> String {
+            let names: Vec<_> = track
+                .tuning()
+                .iter()
+                .rev()
+                .map(|p| pitch::name(*p))
+                .collect();
+            names.join(" ")
+        }
+        fn swing(swing: Option<u16>) -> String {
+            swing.map_or("straight".into(), |s| format!("{s}%"))
+        }
+        fn key(key: &Option<Key>) -> String {
+            key.as_ref().map_or("(none)".into(), |k| k.to_string())
+        }
+        fn auto(value: Option<u8>) -> String {
+            value.map_or("auto".into(), |v| v.to_string())
+        }
+        let mut out = Vec::new();
+        let mut diff = |part: &str, old: String, new: String| {
+            if old != new {
+                out.push(format!("{part} {old} -> {new}"));
+            }
+        };
+        diff("title", text(&self.meta.title), text(&new.meta.title));
+        diff("artist", text(&self.meta.artist), text(&new.meta.artist));
+        diff("album", text(&self.meta.album), text(&new.meta.album));
+        let transcriber = (&self.meta.transcriber, &new.meta.transcriber);
+        diff("transcriber", text(transcriber.0), text(transcriber.1));
+        diff(
+            "time signature",
+            self.time_sig.to_string(),
+            new.time_sig.to_string(),
+        );
+        diff(
+            "tempo",
+            format!("{} bpm", self.tempo()),
+            format!("{} bpm", new.tempo()),
+        );
+        diff("swing", swing(self.swing), swing(new.swing));
+        diff("key", key(&self.key), key(&new.key));
+        diff(
+            "TODOs",
+            self.todos.len().to_string(),
+            new.todos.len().to_string(),
+        );
+        diff(
+            "changes",
+            self.changes.len().to_string(),
+            new.changes.len().to_string(),
+        );
+        diff(
+            "collapsed",
+            self.collapsed.join(" "),
+            new.collapsed.join(" "),
+        );
+        diff("exports", self.exports.join(" "), new.exports.join(" "));
+        for (t, (old, new)) in self.tracks.iter().zip(&new.tracks).enumerate() {
+            let part = |name: &str| format!("track {} {name}", t + 1);
+            diff(&part("tuning"), tuning(old), tuning(new));
+            diff(&part("capo"), old.capo.to_string(), new.capo.to_string());
+            diff(
+                &part("frets"),
+                old.fret_count.to_string(),
+                new.fret_count.to_string(),
+            );
+            diff(
+                &part("archived"),
+                old.archived.to_string(),
+                new.archived.to_string(),
+            );
+            diff(&part("muted"), old.muted.to_string(), new.muted.to_string());
+            diff(&part("solo"), old.solo.to_string(), new.solo.to_string());
+            diff(&part("program"), auto(old.program), auto(new.program));
+            diff(&part("channel"), auto(old.channel), auto(new.channel));
+            diff(&part("name"), text(&old.name), text(&new.name));
+            diff(&part("group"), text(&old.group), text(&new.group));
+        }
+        // Edits that keep the counts above, such as rewording a TODO
+        if out.is_empty() {
+            let parts = [
+                ("beaming", self.beaming != new.beaming),
+                ("TODOs", self.todos != new.todos),
+                ("changes", self.changes != new.changes),
+            ];
+            out.extend(
+                parts
+                    .iter()
+                    .filter(|(_, d)| *d)
+                    .map(|(p, _)| format!("{p} edited")),
+            );
+        }
+        out
+    }
+
+    // Undoes the parts of the settings that differ between `old` and `new`, keeping the rest
+    pub fn revert_settings(&mut self, old: &Song, new: &Song) {
+        let mut settings = self.settings();
+        let meta = &mut settings.meta;
+        for (field, o, n) in [
+            ("title", &old.meta.title, &new.meta.title),
+            ("artist", &old.meta.artist, &new.meta.artist),
+            ("album", &old.meta.album, &new.meta.album),
+            ("transcriber", &old.meta.transcriber, &new.meta.transcriber),
+        ] {
+            if o != n {
+                *meta.field_mut(field).unwrap() = o.clone();
+            }
+        }
+        if old.time_sig != new.time_sig {
+            settings.time_sig = old.time_sig;
+        }
+        if old.tempo != new.tempo {
+            settings.tempo = old.tempo;
+        }
+        if old.swing != new.swing {
+            settings.swing = old.swing;
+        }
+        if old.key != new.key {
+            settings.key = old.key.clone();
+        }
+        if old.beaming != new.beaming {
+            settings.beaming = old.beaming.clone();
+        }
+        if old.todos != new.todos {
+            settings.todos = old.todos.clone();
+        }
+        if old.changes != new.changes {
+            settings.changes = old.changes.clone();
+        }
+        if old.collapsed != new.collapsed {
+            settings.collapsed = old.collapsed.clone();
+        }
+        if old.exports != new.exports {
+            settings.exports = old.exports.clone();
+        }
+        let tracks = settings.tracks.iter_mut().zip(&old.tracks).zip(&new.tracks);
+        for ((track, old), new) in tracks {
+            if old.tuning() != new.tuning() {
+                track.tuning = old.tuning.clone();
+            }
+            if old.capo != new.capo {
+                track.capo = old.capo;
+            }
+            if old.fret_count != new.fret_count {
+                track.fret_count = old.fret_count;
+            }
+            if old.archived != new.archived {
+                track.archived = old.archived;
+            }
+            if old.muted != new.muted {
+                track.muted = old.muted;
+            }
+            if old.solo != new.solo {
+                track.solo = old.solo;
+            }
+            if old.program != new.program {
+                track.program = old.program;
+            }
+            if old.channel != new.channel {
+                track.channel = old.channel;
+            }
+            if old.name != new.name {
+                track.name = old.name.clone();
+            }
+            if old.group != new.group {
+                track.group = old.group.clone();
+            }
         }
+        self.set_settings(&settings);
     }
 }
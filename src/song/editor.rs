@@ -0,0 +1,519 @@
+use crate::{
+    error::{Error, Result},
+    history::{Action, History},
+    song::{Arpeggio, Song, Stroke, Whammy},
+};
+use std::rc::Rc;
+
+// Owns a song together with its undo history, so every edit goes through `apply`
+pub struct Editor {
+    pub song: Song,
+    history: History,
+    // Song as it was at each open `begin`, with how many actions had been applied by then
+    txn: Vec<(Song, usize)>,
+    actions: Vec<Action>,
+}
+
+impl Editor {
+    pub fn new(song: Song) -> Self {
+        Self {
+            song,
+            history: History::new(32),
+            txn: Vec::new(),
+            actions: Vec::new(),
+        }
+    }
+
+    pub fn apply(&mut self, action: Action) -> Result<String> {
+        let res = self.apply_action(&action);
+        if res.is_ok() {
+            if self.txn.is_empty() {
+                self.history.push(Rc::new(action));
+            } else {
+                self.actions.push(action);
+            }
+        }
+        res
+    }
+
+    pub fn undo(&mut self) -> Result<String> {
+        self.check_no_txn("undo")?;
+        match self.history.undo() {
+            Some(action) => self.undo_action(&action),
+            None => Err(Error::InvalidOp("Cannot undo any further".into())),
+        }
+    }
+
+    pub fn redo(&mut self) -> Result<String> {
+        self.check_no_txn("redo")?;
+        match self.history.redo() {
+            Some(action) => self.apply_action(&action),
+            None => Err(Error::InvalidOp("Cannot redo any further".into())),
+        }
+    }
+
+    fn check_no_txn(&self, op: &str) -> Result<()> {
+        if self.txn.is_empty() {
+            Ok(())
+        } else {
+            Err(Error::InvalidOp(format!(
+                "Cannot {op} inside a transaction"
+            )))
+        }
+    }
+
+    // Changes the song apart from its beats in `f`, undone by restoring the settings from before
+    pub fn edit<R>(&mut self, label: &str, f: impl FnOnce(&mut Song) -> Result<R>) -> Result<R> {
+        let old = self.song.settings();
+        match f(&mut self.song) {
+            Ok(res) => {
+                let new = self.song.settings();
+                self.apply(Action::set_settings(label, old, new))?;
+                Ok(res)
+            }
+            Err(e) => {
+                self.song.set_settings(&old);
+                Err(e)
+            }
+        }
+    }
+
+    // Settings edits that undo would go through, each with how many undo steps back it is
+    pub fn settings_history(&self) -> Vec<(usize, &str, &Song, &Song)> {
+        self.history
+            .past()
+            .enumerate()
+            .filter_map(|(i, action)| match action {
+                Action::SetSettings { label, old, new } => {
+                    Some((i + 1, label.as_str(), &**old, &**new))
+                }
+                _ => None,
+            })
+            .collect()
+    }
+
+    // Edits applied between `begin` and `commit` become a single undo step,
+    // transactions begun inside another one are part of the outer step
+    pub fn begin(&mut self) {
+        self.txn.push((self.song.clone(), self.actions.len()));
+    }
+
+    pub fn commit(&mut self, label: &str) {
+        if self.txn.pop().is_none() || !self.txn.is_empty() {
+            return;
+        }
+        let actions = std::mem::take(&mut self.actions);
+        if !actions.is_empty() {
+            self.history.push(Rc::new(Action::Group {
+                label: label.into(),
+                actions,
+            }));
+        }
+    }
+
+    // Puts the song back as it was at the matching `begin`
+    pub fn rollback(&mut self) {
+        if let Some((song, applied)) = self.txn.pop() {
+            self.song = song;
+            self.actions.truncate(applied);
+        }
+    }
+
+    // Returns the song as it was `steps` undo steps ago, leaving the editor unchanged
+    pub fn snapshot(&mut self, steps: usize) -> Result<Song> {
+        let current = self.song.clone();
+        let mut undone = 0;
+        while undone < steps {
+            match self.history.undo() {
+                Some(action) => {
+                    undone += 1;
+                    if let Err(e) = self.undo_action(&action) {
+                        self.song = current;
+                        return Err(e);
+                    }
+                }
+                None => break,
+            }
+        }
+        for _ in 0..undone {
+            self.history.redo();
+        }
+        if undone == 0 {
+            return Err(Error::InvalidOp(
+                "Nothing to undo to compare against".into(),
+            ));
+        }
+        Ok(std::mem::replace(&mut self.song, current))
+    }
+
+    fn apply_action(&mut self, action: &Action) -> Result<String> {
+        match action {
+            Action::Group { label, actions } => {
+                // A group is applied whole or not at all
+                for (i, action) in actions.iter().enumerate() {
+                    if let Err(e) = self.apply_action(action) {
+                        for action in actions[..i].iter().rev() {
+                            self.undo_action(action)?;
+                        }
+                        return Err(e);
+                    }
+                }
+                Ok(label.clone())
+            }
+            Action::SetDuration { cur, new, .. } => {
+                cur.set_duration(&mut self.song, *new);
+                Ok(format!("Set duration {}/{}", new.0, new.1))
+            }
+            Action::SetNote { cur, new, .. } => {
+                if let Some(note) = new {
+                    cur.set_note(&mut self.song, note.to_owned());
+                    Ok("Set note".into())
+                } else {
+                    cur.clear_note(&mut self.song);
+                    Ok("Delete note".into())
+                }
+            }
+            Action::SetFinger { cur, new, .. } => {
+                cur.set_finger(&mut self.song, *new);
+                if new.is_some() {
+                    Ok("Set fingering".into())
+                } else {
+                    Ok("Clear fingering".into())
+                }
+            }
+            Action::SetColor { cur, new, .. } => {
+                cur.set_color(&mut self.song, *new);
+                match new {
+                    Some(color) => Ok(format!("Set note color {}", color.name())),
+                    None => Ok("Clear note color".into()),
+                }
+            }
+            Action::SetStroke { cur, new, .. } => {
+                cur.set_stroke(&mut self.song, *new);
+                match new {
+                    Some(Stroke::Down) => Ok("Set downstroke".into()),
+                    Some(Stroke::Up) => Ok("Set upstroke".into()),
+                    None => Ok("Clear stroke".into()),
+                }
+            }
+            Action::SetVelocity { cur, new, .. } => {
+                cur.set_velocity(&mut self.song, *new);
+                match new {
+                    Some(v) => Ok(format!("Set velocity {v}")),
+                    None => Ok("Reset velocity".into()),
+                }
+            }
+            Action::SetText { cur, new, .. } => {
+                cur.set_text(&mut self.song, new.clone());
+                if new.is_some() {
+                    Ok("Set annotation".into())
+                } else {
+                    Ok("Clear annotation".into())
+                }
+            }
+            Action::SetArpeggio { cur, new, .. } => {
+                cur.set_arpeggio(&mut self.song, *new);
+                match new {
+                    Some(Arpeggio::Up) => Ok("Set arpeggio up".into()),
+                    Some(Arpeggio::Down) => Ok("Set arpeggio down".into()),
+                    None => Ok("Clear arpeggio".into()),
+                }
+            }
+            Action::SetWhammy { cur, new, .. } => {
+                cur.set_whammy(&mut self.song, *new);
+                match new {
+                    Some(Whammy::Dive(_)) => Ok("Set whammy dive".into()),
+                    Some(Whammy::Return) => Ok("Set whammy return".into()),
+                    None => Ok("Clear whammy".into()),
+                }
+            }
+            Action::SetChord { cur, new, .. } => {
+                cur.set_notes(&mut self.song, new.clone());
+                Ok("Set chord".into())
+            }
+            Action::ClearBeat { cur, .. } => {
+                cur.clear_beat(&mut self.song);
+                Ok("Clear beat".into())
+            }
+            Action::ClearBeats { cur, old } => {
+                cur.clear_beats(&mut self.song, old.len());
+                Ok("Clear beats".into())
+            }
+            Action::DeleteBeat { cur, .. } => {
+                cur.delete_beat(&mut self.song);
+                Ok("Delete beat".into())
+            }
+            Action::DeleteBeats { cur, old } => {
+                cur.delete_beats(&mut self.song, old.len());
+                Ok("Delete beats".into())
+            }
+            Action::InsertBeats { cur, new } => {
+                cur.insert_beats(&mut self.song, false, new.clone());
+                if new.len() == 1 {
+                    Ok("Insert beat".into())
+                } else {
+                    Ok(format!("Insert {} beats", new.len()))
+                }
+            }
+            Action::PasteNote { cur, buf, .. } => {
+                cur.set_note(&mut self.song, buf.clone());
+                Ok("Paste note".into())
+            }
+            Action::PasteBeat { cur, old, buf } => {
+                cur.insert_beat(&mut self.song, old.is_some(), buf.clone());
+                Ok("Paste beat".into())
+            }
+            Action::PasteBeats { cur, old, buf } => {
+                cur.insert_beats(&mut self.song, old.is_some(), buf.clone());
+                Ok("Paste beats".into())
+            }
+            Action::AddTrack { track } => {
+                self.song.tracks.push((**track).clone());
+                self.song.update_measures(self.song.tracks.len() - 1);
+                Ok("Add track".into())
+            }
+            Action::SetSettings { label, new, .. } => {
+                self.song.set_settings(new);
+                Ok(label.clone())
+            }
+        }
+    }
+
+    fn undo_action(&mut self, action: &Action) -> Result<String> {
+        match action {
+            Action::Group { label, actions } => {
+                for action in actions.iter().rev() {
+                    self.undo_action(action)?;
+                }
+                Ok(format!("Undo {label}"))
+            }
+            Action::SetDuration { cur, old, .. } => {
+                cur.set_duration(&mut self.song, *old);
+                Ok("Undo set duration".into())
+            }
+            Action::SetNote { cur, old, new } => {
+                if let Some(note) = old {
+                    cur.set_note(&mut self.song, note.clone());
+                } else {
+                    cur.clear_note(&mut self.song);
+                }
+                if new.is_none() {
+                    Ok("Undo delete note".into())
+                } else {
+                    Ok("Undo set note".into())
+                }
+            }
+            Action::SetFinger { cur, old, .. } => {
+                cur.set_finger(&mut self.song, *old);
+                Ok("Undo set fingering".into())
+            }
+            Action::SetColor { cur, old, .. } => {
+                cur.set_color(&mut self.song, *old);
+                Ok("Undo set note color".into())
+            }
+            Action::SetStroke { cur, old, .. } => {
+                cur.set_stroke(&mut self.song, *old);
+                Ok("Undo set stroke".into())
+            }
+            Action::SetVelocity { cur, old, .. } => {
+                cur.set_velocity(&mut self.song, *old);
+                Ok("Undo set velocity".into())
+            }
+            Action::SetText { cur, old, .. } => {
+                cur.set_text(&mut self.song, old.clone());
+                Ok("Undo set annotation".into())
+            }
+            Action::SetArpeggio { cur, old, .. } => {
+                cur.set_arpeggio(&mut self.song, *old);
+                Ok("Undo set arpeggio".into())
+            }
+            Action::SetWhammy { cur, old, .. } => {
+                cur.set_whammy(&mut self.song, *old);
+                Ok("Undo set whammy".into())
+            }
+            Action::SetChord { cur, old, .. } => {
+                cur.set_notes(&mut self.song, old.clone());
+                Ok("Undo set chord".into())
+            }
+            Action::ClearBeat { cur, old } => {
+                cur.set_notes(&mut self.song, old.clone());
+                Ok("Undo clear beat".into())
+            }
+            Action::ClearBeats { cur, old } => {
+                cur.replace_beats(&mut self.song, old.clone());
+                Ok("Undo clear beats".into())
+            }
+            Action::DeleteBeat { cur, old } => {
+                cur.insert_beat(&mut self.song, false, old.clone());
+                Ok("Undo delete beat".into())
+            }
+            Action::DeleteBeats { cur, old } => {
+                cur.insert_beats(&mut self.song, false, old.clone());
+                Ok("Undo delete beats".into())
+            }
+            Action::InsertBeats { cur, new } => {
+                cur.delete_beats(&mut self.song, new.len());
+                Ok("Undo insert beats".into())
+            }
+            Action::PasteNote { cur, old, .. } => {
+                if let Some(note) = old {
+                    cur.set_note(&mut self.song, note.clone());
+                } else {
+                    cur.clear_note(&mut self.song);
+                }
+                Ok("Undo paste note".into())
+            }
+            Action::PasteBeat { cur, old, .. } => {
+                if let Some(beat) = old {
+                    cur.insert_beat(&mut self.song, true, beat.clone());
+                } else {
+                    cur.delete_beat(&mut self.song);
+                }
+                Ok("Undo paste beat".into())
+            }
+            Action::PasteBeats { cur, old, buf } => {
+                cur.delete_beats(&mut self.song, buf.len());
+                if let Some(beat) = old {
+                    cur.insert_beat(&mut self.song, false, beat.clone());
+                }
+                Ok("Undo paste beats".into())
+            }
+            Action::AddTrack { .. } => {
+                self.song.tracks.pop();
+                Ok("Undo add track".into())
+            }
+            Action::SetSettings { label, old, .. } => {
+                self.song.set_settings(old);
+                Ok(format!("Undo {label}"))
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        cursor::Cursor,
+        song::{Beat, Note, NoteEntry},
+    };
+
+    fn set_fret(editor: &mut Editor, fret: u16) -> Result<String> {
+        let old = editor.song.tracks[0].beats[0].copy_entry(0);
+        editor.apply(Action::set_note(
+            Cursor::new(),
+            old,
+            Some(NoteEntry::new(Note::Fret(fret))),
+        ))
+    }
+
+    fn fret(editor: &Editor) -> Option<Note> {
+        editor.song.tracks[0].beats[0].get_note(0).cloned()
+    }
+
+    #[test]
+    fn nested_transactions_are_one_step() {
+        let mut editor = Editor::new(Song::new());
+        editor.begin();
+        set_fret(&mut editor, 1).unwrap();
+        editor.begin();
+        set_fret(&mut editor, 2).unwrap();
+        editor.commit("inner");
+        set_fret(&mut editor, 3).unwrap();
+        editor.commit("outer");
+        assert!(fret(&editor) == Some(Note::Fret(3)));
+        editor.undo().unwrap();
+        assert!(fret(&editor).is_none());
+        assert!(editor.undo().is_err());
+    }
+
+    #[test]
+    fn undo_refused_inside_transaction() {
+        let mut editor = Editor::new(Song::new());
+        set_fret(&mut editor, 1).unwrap();
+        editor.begin();
+        assert!(editor.undo().is_err());
+        assert!(editor.redo().is_err());
+        editor.commit("empty");
+        editor.undo().unwrap();
+        assert!(fret(&editor).is_none());
+    }
+
+    #[test]
+    fn rollback_restores_song() {
+        let mut editor = Editor::new(Song::new());
+        set_fret(&mut editor, 1).unwrap();
+        editor.begin();
+        set_fret(&mut editor, 2).unwrap();
+        editor.begin();
+        set_fret(&mut editor, 3).unwrap();
+        editor.rollback();
+        assert!(fret(&editor) == Some(Note::Fret(2)));
+        editor.rollback();
+        assert!(fret(&editor) == Some(Note::Fret(1)));
+        editor.undo().unwrap();
+        assert!(fret(&editor).is_none());
+    }
+
+    #[test]
+    fn settings_edits_undo() {
+        let mut editor = Editor::new(Song::new());
+        editor
+            .edit("set tempo", |song| song.set_tempo(Some(90)))
+            .unwrap();
+        assert!(editor
+            .edit("set tempo", |song| song.set_tempo(Some(1000)))
+            .is_err());
+        assert_eq!(editor.song.tempo, Some(90));
+        editor.undo().unwrap();
+        assert_eq!(editor.song.tempo, None);
+    }
+
+    #[test]
+    fn inserted_beats_undo() {
+        let mut editor = Editor::new(Song::new());
+        set_fret(&mut editor, 1).unwrap();
+        let dur = editor.song.tracks[0].beats[0].dur;
+        editor
+            .apply(Action::insert_beats(Cursor::new(), vec![Beat::new(dur); 2]))
+            .unwrap();
+        assert_eq!(editor.song.tracks[0].beats.len(), 3);
+        assert!(fret(&editor).is_none());
+        editor.undo().unwrap();
+        assert_eq!(editor.song.tracks[0].beats.len(), 1);
+        assert!(fret(&editor) == Some(Note::Fret(1)));
+    }
+
+    #[test]
+    fn fret_count_stays_past_capo() {
+        let mut editor = Editor::new(Song::new());
+        editor.song.tracks[0].capo = 5;
+        for frets in [0, 3, 5] {
+            assert!(editor
+                .edit("set fret count", |song| song.tracks[0]
+                    .set_fret_count(frets))
+                .is_err());
+        }
+        editor
+            .edit("set fret count", |song| song.tracks[0].set_fret_count(6))
+            .unwrap();
+        assert_eq!(editor.song.tracks[0].fret_count, 6);
+    }
+
+    #[test]
+    fn beam_groups_checked() {
+        let mut editor = Editor::new(Song::new());
+        let num = editor.song.time_sig.num;
+        for groups in [vec![u16::MAX, num + 1], vec![0, num]] {
+            assert!(editor
+                .edit("set beaming", |song| song.set_beam_groups(Some(groups)))
+                .is_err());
+        }
+        editor
+            .edit("set beaming", |song| {
+                song.set_beam_groups(Some(vec![1; num as usize]))
+            })
+            .unwrap();
+        assert_eq!(editor.song.beam_groups(), vec![1; num as usize]);
+    }
+}
@@ -1,51 +1,231 @@
 use crate::{
+    config,
     error::{Error, Result},
     map_io_err,
 };
 use crossterm::{event, style, terminal};
+use std::sync::{
+    atomic::{AtomicBool, Ordering},
+    Arc,
+};
+
+const CLOSE_POLL: std::time::Duration = std::time::Duration::from_millis(50);
+
+// A column of the screen; the column after a wide character holds None
+#[derive(Clone, Copy, PartialEq)]
+struct Cell {
+    ch: Option<char>,
+    style: style::ContentStyle,
+}
+
+impl Default for Cell {
+    fn default() -> Self {
+        Self {
+            ch: Some(' '),
+            style: style::ContentStyle::default(),
+        }
+    }
+}
 
+// Rows of cells, each cut after its last non blank cell
+type Screen = Vec<Vec<Cell>>;
+
+fn trimmed(row: &[Cell]) -> &[Cell] {
+    let len = row
+        .iter()
+        .rposition(|c| *c != Cell::default())
+        .map_or(0, |i| i + 1);
+    &row[..len]
+}
+
+// Output is drawn into `screen` and only the cells that differ from what the terminal shows
+// are written on update, so a keypress redraws the few cells it changed rather than every lane
 pub struct Window {
     stdout: std::io::Stdout,
+    close_req: Arc<AtomicBool>,
+    title: String,
+    screen: Screen,
+    shown: Screen,
+    col: u16,
+    row: u16,
+    // Rows of the terminal, output below them is dropped rather than scrolling the screen
+    height: u16,
+    mouse: bool,
+    // Characters outside of ASCII are printed as their stand-ins
+    ascii: bool,
 }
 
 impl Window {
     pub fn new() -> Result<Self> {
         let mut o = Self {
             stdout: std::io::stdout(),
+            close_req: Arc::new(AtomicBool::new(false)),
+            title: String::new(),
+            screen: Vec::new(),
+            shown: Vec::new(),
+            col: 0,
+            row: 0,
+            height: u16::MAX,
+            mouse: false,
+            ascii: false,
         };
+        o.register_signals()?;
         map_io_err!(crossterm::terminal::enable_raw_mode())?;
         o.queue(crossterm::cursor::Hide)?
-            .queue(crossterm::terminal::SetTitle("Tab"))?
+            .set_title("Tab")?
+            .queue(event::EnableFocusChange)?
             .update()?;
         Ok(o)
     }
 
+    #[cfg(unix)]
+    fn register_signals(&self) -> Result<()> {
+        use signal_hook::{consts, flag};
+        for sig in [consts::SIGHUP, consts::SIGTERM] {
+            map_io_err!(flag::register(sig, self.close_req.clone()))?;
+        }
+        Ok(())
+    }
+
+    #[cfg(not(unix))]
+    fn register_signals(&self) -> Result<()> {
+        Ok(())
+    }
+
+    pub fn close_requested(&self) -> bool {
+        self.close_req.load(Ordering::Relaxed)
+    }
+
+    pub fn set_title(&mut self, title: &str) -> Result<&mut Self> {
+        if self.title != title {
+            self.title = title.to_owned();
+            self.queue(crossterm::terminal::SetTitle(title))?;
+        }
+        Ok(self)
+    }
+
+    // Mouse events are only captured while something on screen uses them,
+    // so the terminal's own selection keeps working otherwise
+    pub fn set_mouse_capture(&mut self, on: bool) -> Result<&mut Self> {
+        if self.mouse == on {
+            return Ok(self);
+        }
+        self.mouse = on;
+        if on {
+            self.queue(event::EnableMouseCapture)
+        } else {
+            self.queue(event::DisableMouseCapture)
+        }
+    }
+
+    pub fn set_height(&mut self, height: u16) -> &mut Self {
+        self.height = height;
+        self
+    }
+
+    pub fn set_ascii(&mut self, ascii: bool) -> &mut Self {
+        self.ascii = ascii;
+        self
+    }
+
     pub fn moveto(&mut self, x: u16, y: u16) -> Result<&mut Self> {
-        self.queue(crossterm::cursor::MoveTo(x, y))
+        self.col = x;
+        self.row = y;
+        Ok(self)
+    }
+
+    pub fn row(&self) -> u16 {
+        self.row
+    }
+
+    // Zero width characters, such as combining marks, have no cell of their own and are dropped
+    fn put(&mut self, text: &str, style: style::ContentStyle) {
+        if self.row >= self.height {
+            return;
+        }
+        let text = if self.ascii {
+            config::to_ascii(text)
+        } else {
+            text.to_owned()
+        };
+        let y = self.row as usize;
+        if self.screen.len() <= y {
+            self.screen.resize(y + 1, Vec::new());
+        }
+        let row = &mut self.screen[y];
+        for ch in text.chars() {
+            let width = config::char_width(ch);
+            if width == 0 {
+                continue;
+            }
+            let x = self.col as usize;
+            if row.len() < x + width {
+                row.resize(x + width, Cell::default());
+            }
+            // A wide character partly written over is cleared
+            if row[x].ch.is_none() && x > 0 {
+                row[x - 1] = Cell::default();
+            }
+            if let Some(Cell { ch: None, .. }) = row.get(x + width) {
+                row[x + width] = Cell::default();
+            }
+            row[x] = Cell {
+                ch: Some(ch),
+                style,
+            };
+            if width == 2 {
+                row[x + 1] = Cell { ch: None, style };
+            }
+            self.col += width as u16;
+        }
     }
 
     pub fn print_styled<D: std::fmt::Display>(
         &mut self,
         content: style::StyledContent<D>,
     ) -> Result<&mut Self> {
-        self.queue(style::PrintStyledContent(content))
+        self.put(&content.content().to_string(), *content.style());
+        Ok(self)
     }
 
     pub fn print<T: std::fmt::Display>(&mut self, text: T) -> Result<&mut Self> {
-        self.queue(style::Print(text))
+        self.put(&text.to_string(), style::ContentStyle::default());
+        Ok(self)
+    }
+
+    pub fn bell(&mut self) -> Result<&mut Self> {
+        self.queue(style::Print('\x07'))
     }
 
+    // The terminal is cleared at once, and everything on screen is written again on update
     pub fn clear(&mut self) -> Result<&mut Self> {
+        self.screen.clear();
+        self.shown.clear();
         self.queue(terminal::Clear(terminal::ClearType::All))
     }
 
-    pub fn clear_eoline(&mut self) -> Result<&mut Self> {
-        self.queue(terminal::Clear(terminal::ClearType::UntilNewLine))
+    fn clear_line(&mut self) {
+        if let Some(row) = self.screen.get_mut(self.row as usize) {
+            row.truncate(self.col as usize);
+        }
+    }
+
+    pub fn clear_below(&mut self) -> Result<&mut Self> {
+        self.clear_line();
+        self.screen.truncate(self.row as usize + 1);
+        Ok(self)
     }
 
     pub fn next_line(&mut self) -> Result<&mut Self> {
-        self.queue(terminal::Clear(terminal::ClearType::UntilNewLine))?
-            .queue(crossterm::cursor::MoveToNextLine(1))
+        if self.row < self.height {
+            self.clear_line();
+        }
+        self.row += 1;
+        // On the last row the cursor stays put, as output below it is dropped
+        if self.row < self.height {
+            self.col = 0;
+        }
+        Ok(self)
     }
 
     pub fn queue<C>(&mut self, command: C) -> Result<&mut Self>
@@ -59,16 +239,85 @@ impl Window {
         Ok(self)
     }
 
+    // Writes the cells of each row from the first to the last that changed, clearing the rest
+    // of the row when it ends before what was shown; cells past the terminal's width are dropped
+    fn write_changes(&mut self) -> Result<()> {
+        let (width, _) = map_io_err!(terminal::size())?;
+        let rows = self.screen.len().max(self.shown.len());
+        for y in 0..rows {
+            let new = trimmed(self.screen.get(y).map_or(&[], |r| r.as_slice()));
+            let old = trimmed(self.shown.get(y).map_or(&[], |r| r.as_slice()));
+            let Some(mut start) =
+                (0..new.len().max(old.len())).find(|x| new.get(*x) != old.get(*x))
+            else {
+                continue;
+            };
+            let end = (0..new.len().max(old.len()))
+                .rev()
+                .find(|x| new.get(*x) != old.get(*x))
+                .map_or(start, |x| x + 1)
+                .min(new.len());
+            while start > 0 && new.get(start).is_some_and(|c| c.ch.is_none()) {
+                start -= 1;
+            }
+            if start >= width as usize {
+                continue;
+            }
+            let clear = end >= new.len() && old.len() > new.len();
+            let end = end.min(width as usize);
+            let mut out: Vec<(style::ContentStyle, String)> = Vec::new();
+            for cell in new.iter().take(end).skip(start) {
+                let Some(ch) = cell.ch else { continue };
+                match out.last_mut() {
+                    Some((style, text)) if *style == cell.style => text.push(ch),
+                    _ => out.push((cell.style, ch.to_string())),
+                }
+            }
+            self.queue(crossterm::cursor::MoveTo(start as u16, y as u16))?;
+            for (style, text) in out {
+                self.queue(style::PrintStyledContent(style::StyledContent::new(
+                    style, text,
+                )))?;
+            }
+            if clear {
+                self.queue(terminal::Clear(terminal::ClearType::UntilNewLine))?;
+            }
+        }
+        self.shown.clone_from(&self.screen);
+        Ok(())
+    }
+
     pub fn update(&mut self) -> Result<()> {
+        self.write_changes()?;
         map_io_err!(std::io::Write::flush(&mut self.stdout))
     }
 
-    pub fn get_event(&mut self) -> Result<event::Event> {
-        let poll = map_io_err!(event::poll(std::time::Duration::from_millis(100)))?;
-        if poll {
-            map_io_err!(event::read())
-        } else {
-            Err(Error::NoEvent)
+    // Polls in short slices so a SIGHUP or SIGTERM is noticed without waiting out the timeout
+    pub fn get_event(&mut self, timeout: std::time::Duration) -> Result<event::Event> {
+        let end = std::time::Instant::now() + timeout;
+        loop {
+            if self.close_requested() {
+                return Err(Error::NoEvent);
+            }
+            let left = end.saturating_duration_since(std::time::Instant::now());
+            if map_io_err!(event::poll(left.min(CLOSE_POLL)))? {
+                return map_io_err!(event::read());
+            }
+            if left <= CLOSE_POLL {
+                return Err(Error::NoEvent);
+            }
         }
     }
 }
+
+// Also runs when closing on a signal, so the shell is left usable
+impl Drop for Window {
+    fn drop(&mut self) {
+        let _ = self
+            .queue(event::DisableMouseCapture)
+            .and_then(|w| w.queue(event::DisableFocusChange))
+            .and_then(|w| w.queue(crossterm::cursor::Show))
+            .and_then(|w| w.update());
+        let _ = crossterm::terminal::disable_raw_mode();
+    }
+}